@@ -1,11 +1,15 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
 use glob::Pattern;
 
 #[cfg(feature = "coveralls")]
 use crate::config::Ci;
-use crate::config::{Color, Mode, OutputFile, RunType, TraceEngine};
+use crate::config::{
+    Color, DryRun, HtmlTheme, LogicalLines, MarkdownStyle, Mode, OutputFile, ProfrawCleanup,
+    RunReportFormat, RunType, TraceEngine,
+};
+use crate::event_query::EventFilter;
 
 #[derive(Debug, Parser)]
 #[command(name = "cargo-tarpaulin")]
@@ -35,6 +39,59 @@ pub struct TarpaulinCli {
     pub print_flags: PrintFlagsArgs,
     #[clap(flatten)]
     pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub action: Option<Action>,
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Action {
+    /// Remove the profraws, event logs and reports tarpaulin stores between runs without cleaning the rest of the target directory
+    Clean,
+    /// Merge the run reports from target/tarpaulin of other runs, which can be on other OSes, and report the combined coverage
+    Merge {
+        /// Run reports to merge
+        #[arg(required = true, value_name = "REPORT")]
+        reports: Vec<PathBuf>,
+    },
+    /// Summarise a --dump-traces event log: the slowest binaries, time spent in each phase of the state machine and signals received
+    Events {
+        /// Event log to read, either the json or jsonl log
+        #[arg(value_name = "LOG")]
+        log: PathBuf,
+        /// Only include events matching KEY=VALUE, where the key is binary, config, pid or signal. Can be given more than once
+        #[arg(long, value_name = "KEY=VALUE")]
+        filter: Vec<EventFilter>,
+        /// Only include events from this many seconds after the run started
+        #[arg(long, value_name = "SECONDS")]
+        since: Option<u64>,
+    },
+    /// Raise fail-under to the coverage stored by the last run when it's above the threshold by at least the margin
+    Ratchet {
+        /// Percentage points coverage has to exceed fail-under by before it's raised
+        #[arg(long, value_name = "PERCENT", default_value_t = 1.0)]
+        margin: f64,
+        /// Write the new threshold to the config file instead of printing it
+        #[arg(long)]
+        write: bool,
+    },
+    /// List the tests recorded with --test-impact as covering a source line, given as FILE:LINE, or any line of a file
+    WhichTests {
+        /// Source location to find the tests of, such as src/lib.rs:120
+        #[arg(value_name = "FILE[:LINE]")]
+        location: String,
+        /// Commit to use the recorded coverage of, defaults to HEAD or the latest commit recorded
+        #[arg(long, value_name = "REV")]
+        commit: Option<String>,
+    },
+    /// Convert a run report stored in target/tarpaulin in the compact format to json
+    Convert {
+        /// Run report to convert
+        #[arg(value_name = "REPORT")]
+        report: PathBuf,
+        /// Where to write the json, defaults to the report with a json extension
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug, Clone, Args)]
@@ -68,6 +125,9 @@ pub struct ConfigArgs {
     /// Build artefacts with the specified profile
     #[arg(long, value_name = "NAME")]
     pub profile: Option<String>,
+    /// Don't merge physical lines into a single logical line for these constructs
+    #[arg(long, value_enum, value_name = "KIND", num_args = 1.., ignore_case = true)]
+    pub no_logical_lines: Vec<LogicalLines>,
     /// Ignore lines of test functions when collecting coverage (default)
     #[arg(long)]
     pub ignore_tests: bool,
@@ -77,15 +137,30 @@ pub struct ConfigArgs {
     /// Include lines of test functions when collecting coverage
     #[arg(long)]
     pub include_tests: bool,
+    /// Include the support code in the tests directory such as tests/common without its test functions
+    #[arg(long)]
+    pub include_test_helpers: bool,
+    /// Include test functions and #[cfg(test)] modules when collecting coverage
+    #[arg(long)]
+    pub include_test_functions: bool,
     /// Ignore panic macros in tests
     #[arg(long)]
     pub ignore_panics: bool,
+    /// Report how many `Err` handling paths and `panic!` calls outside of tests the tests ran
+    #[arg(long)]
+    pub error_path_coverage: bool,
+    /// Ignore const blocks and const fns only used in const contexts as they run at compile time
+    #[arg(long)]
+    pub ignore_const_fns: bool,
     /// Counts the number of hits during coverage
     #[arg(long)]
     pub count: bool,
     /// Run ignored tests as well
     #[arg(long, short)]
     pub ignored: bool,
+    /// Only run ignored tests
+    #[arg(long, conflicts_with = "ignored")]
+    pub only_ignored: bool,
     /// Line coverage
     #[arg(long, short)]
     pub line: bool,
@@ -98,7 +173,17 @@ pub struct ConfigArgs {
     /// Sets a percentage threshold for failure ranging from 0-100, if coverage is below exit with a non-zero code
     #[arg(long, value_name = "PERCENTAGE")]
     pub fail_under: Option<f64>,
-    /// Branch coverage: NOT IMPLEMENTED
+    /// List public functions with no coverage and fail if there are more than COUNT of them
+    #[arg(long, value_name = "COUNT")]
+    pub fail_on_uncovered_public: Option<usize>,
+    /// Most uncovered lines to print, past this the uncovered lines are only counted
+    #[arg(long, value_name = "COUNT")]
+    pub max_uncovered_lines_printed: Option<usize>,
+    /// Only print the uncovered lines of files changed in the diff given with --diff-file, and only
+    /// list the lines it adds with `--out Quickfix`
+    #[arg(long)]
+    pub uncovered_diff_only: bool,
+    /// Branch coverage: currently only the early return from `?` with the llvm engine
     #[arg(long, short)]
     pub branch: bool,
     /// Forwards unexpected signals to test. This is now the default behaviour
@@ -110,6 +195,28 @@ pub struct ConfigArgs {
     /// URI to send report to, only used if the option --coveralls is used
     #[arg(long, value_name = "URI")]
     pub report_uri: Option<String>,
+    /// Proxy URL for uploads to online services, otherwise HTTPS_PROXY and HTTP_PROXY are used
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+    /// PEM file of the CA certificates to trust for uploads to online services, in place of the system ones
+    #[arg(long, value_name = "FILE")]
+    pub ca_cert: Option<PathBuf>,
+    #[cfg(feature = "github")]
+    /// Pull request to post the coverage of its changes on as a comment, updated on later runs. The repository is read from GITHUB_REPOSITORY
+    #[arg(long, value_name = "NUMBER")]
+    pub github_pr: Option<u64>,
+    #[cfg(feature = "github")]
+    /// Environment variable holding the GitHub token used with --github-pr, defaults to GITHUB_TOKEN
+    #[arg(long, value_name = "VAR")]
+    pub github_token_env: Option<String>,
+    #[cfg(feature = "codecov")]
+    /// Upload the coverage to Codecov, the commit and branch are read from the CI environment or git
+    #[arg(long)]
+    pub codecov: bool,
+    #[cfg(feature = "codecov")]
+    /// Environment variable holding the Codecov upload token, defaults to CODECOV_TOKEN
+    #[arg(long, value_name = "VAR")]
+    pub codecov_token_env: Option<String>,
     /// Do not include default features
     #[arg(long)]
     pub no_default_features: bool,
@@ -128,6 +235,12 @@ pub struct ConfigArgs {
     /// Package id specifications for which package should be build. See cargo help pkgid for more info
     #[arg(long, short, alias = "package", value_name = "PACKAGE", num_args = 0..)]
     pub packages: Vec<String>,
+    /// Test the workspace members with names matching the globs, e.g. 'service-*'
+    #[arg(long, value_name = "GLOB", num_args = 1..)]
+    pub packages_glob: Vec<String>,
+    /// Test only the default members of the workspace
+    #[arg(long)]
+    pub only_default_members: bool,
     /// Package id specifications to exclude from coverage. See cargo help pkgid for more info
     #[arg(long, short, value_name = "PACKAGE", num_args = 0..)]
     pub exclude: Vec<String>,
@@ -137,12 +250,37 @@ pub struct ConfigArgs {
     /// Include only given files in coverage results. Can have a * wildcard
     #[arg(long, value_name = "FILE", num_args = 0..)]
     pub include_files: Vec<Pattern>,
+    /// Only analyse and map coverage for the given files, skipping the work for the rest of the
+    /// project. Can have a * wildcard
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    pub focus: Vec<Pattern>,
     /// Integer for the maximum time in seconds without response from test before timeout (default is 1 minute).
     #[arg(long, short, value_name = "SECONDS")]
     pub timeout: Option<u64>,
     /// Delay after test to collect coverage profiles
     #[arg(long, value_name = "SECONDS")]
     pub post_test_delay: Option<u64>,
+    /// Directory to write profraw files to, relative paths are joined to target/tarpaulin (LLVM only)
+    #[arg(long, value_name = "PATH")]
+    pub profraw_dir: Option<PathBuf>,
+    /// When to remove profraw files from the profraw directory (LLVM only)
+    #[arg(long, value_enum, value_name = "WHEN", ignore_case = true)]
+    pub profraw_cleanup: Option<ProfrawCleanup>,
+    /// Format of the run report stored in target/tarpaulin, Compact is much smaller for large workspaces and can be converted back with `cargo tarpaulin convert`
+    #[arg(long, value_enum, value_name = "FORMAT", ignore_case = true)]
+    pub run_report_format: Option<RunReportFormat>,
+    /// Warn if the profraw files take up more than this many MiB (LLVM only)
+    #[arg(long, value_name = "MIB")]
+    pub profraw_size_limit: Option<u64>,
+    /// Don't lock the target directory so other tarpaulin runs can use it at the same time
+    #[arg(long)]
+    pub allow_concurrent: bool,
+    /// Number of event logs and stored coverage reports to keep, older ones are removed when tarpaulin starts
+    #[arg(long, value_name = "N")]
+    pub keep_reports: Option<usize>,
+    /// Remove event logs and stored reports older than this many days when tarpaulin starts
+    #[arg(long, value_name = "DAYS")]
+    pub max_report_age: Option<u64>,
     /// Follow executed processes capturing coverage information if they're part of your project.
     #[arg(long)]
     pub follow_exec: bool,
@@ -152,6 +290,16 @@ pub struct ConfigArgs {
     /// Compile tests but don't run coverage
     #[arg(long)]
     pub no_run: bool,
+    /// Print the build commands, flags, test binaries, source files and reports for the run without running any tests. `Build` also builds the tests to list the test binaries
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "Plan",
+        ignore_case = true
+    )]
+    pub dry_run: Option<DryRun>,
     /// 'Don't supply an explicit `--test-threads` argument to test executable. By default tarpaulin will infer the default rustc would pick if not ran via tarpaulin and set it
     #[arg(long)]
     pub implicit_test_threads: bool,
@@ -173,6 +321,27 @@ pub struct ConfigArgs {
     /// Remove --cfg=tarpaulin from the RUSTFLAG
     #[arg(long)]
     pub avoid_cfg_tarpaulin: bool,
+    /// Add --cfg=tarpaulin_include to the RUSTFLAG, independent of --avoid-cfg-tarpaulin
+    #[arg(long)]
+    pub cfg_tarpaulin_include: bool,
+    /// Don't add --check-cfg allowances for tarpaulin's cfgs to the RUSTFLAGS
+    #[arg(long)]
+    pub no_check_cfg: bool,
+    /// Extra cfg to set when building, code gated on #[cfg(not(NAME))] is excluded from coverage like #[cfg(not(tarpaulin))]
+    #[arg(long, value_name = "NAME", num_args = 1..)]
+    pub cfg: Vec<String>,
+    /// Regex matched against the source of each line, matching lines are excluded from coverage
+    #[arg(long, value_name = "REGEX", num_args = 1..)]
+    pub ignore_line_patterns: Vec<String>,
+    /// Skip source analysis of files with more lines than this, skipped files are left out of coverage
+    #[arg(long, value_name = "LINES")]
+    pub analysis_max_lines: Option<usize>,
+    /// Skip source analysis of files taking longer than this to analyse, skipped files are left out of coverage
+    #[arg(long, value_name = "SECONDS")]
+    pub analysis_timeout: Option<u64>,
+    /// Keep files skipped by source analysis in coverage, counting every line with debug info
+    #[arg(long)]
+    pub include_skipped_files: bool,
     /// Number of parallel jobs, defaults to # of CPUs
     #[arg(long, short, value_name = "N")]
     pub jobs: Option<usize>,
@@ -191,9 +360,66 @@ pub struct ConfigArgs {
     /// Coverage tracing backend to use
     #[arg(long, value_enum, value_name = "ENGINE", ignore_case = true)]
     pub engine: Option<TraceEngine>,
+    /// Run the tests with both ptrace and llvm and write a report of the lines only one of them hit
+    #[arg(long)]
+    pub compare_engines: bool,
+    /// Run each test in its own process to isolate global state (llvm only), large test suites are batched
+    #[arg(long)]
+    pub process_per_test: bool,
+    /// Record which lines each test hits in a database in target/tarpaulin for `which-tests`, most precise with --process-per-test
+    #[arg(long)]
+    pub test_impact: bool,
+    /// Run the test binaries in a pseudo-terminal for tests which need a tty, their output is still captured (llvm only, Linux x86/x86_64)
+    #[arg(long)]
+    pub allocate_pty: bool,
+    /// Run the test binaries in a new network namespace with only loopback, so they can't reach other hosts and the ports they bind can't clash (Linux only)
+    #[arg(long)]
+    pub isolate_network: bool,
+    /// Give each test process its own block of SIZE free ports, the first is in TARPAULIN_PORT_BASE and the number of them in TARPAULIN_PORT_COUNT
+    #[arg(long, value_name = "SIZE")]
+    pub port_block: Option<u16>,
+    /// Leave source files modified after the tests were built out of the reports, they're only warned about otherwise
+    #[arg(long)]
+    pub exclude_stale_files: bool,
+    /// Upload the coverage stored in target/tarpaulin by the last run to coveralls or Codecov without running the tests, to retry a failed upload
+    #[arg(long)]
+    pub upload_only: bool,
+    /// Only rerun the test binaries, or the tests in them, which failed in the last run and merge their coverage over the run stored in target/tarpaulin
+    #[arg(long)]
+    pub rerun_failed: bool,
+    /// Report the coverage of callgrind output files instead of running the tests, the cost of each line is used as its hits
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    pub from_callgrind: Vec<PathBuf>,
     /// Specify a custom directory to write report files
     #[arg(long, value_name = "PATH")]
     pub output_dir: Option<PathBuf>,
+    /// Open the HTML report in the default browser after the run
+    #[arg(long)]
+    pub open: bool,
+    /// Print the size of the coverage instrumentation and debug info of each test binary and the time spent collecting its coverage
+    #[arg(long)]
+    pub show_overhead: bool,
+    /// Base name for the report files, e.g. `--report-name cov` writes cov.xml, cov.html and cov.json
+    #[arg(long, value_name = "BASENAME")]
+    pub report_name: Option<String>,
+    /// Tera template rendered with the coverage for `--out Template`, written to the output directory without its .tera extension
+    #[arg(long, value_name = "FILE")]
+    pub template: Option<PathBuf>,
+    /// Layout of `--out Markdown`, PrComment adds the change since the previous run and collapsible uncovered lines for posting on pull requests
+    #[arg(long, value_enum, value_name = "STYLE", ignore_case = true)]
+    pub markdown_style: Option<MarkdownStyle>,
+    /// Colour scheme of `--out Html`, Auto follows the light or dark preference of the browser
+    #[arg(long, value_enum, value_name = "THEME", ignore_case = true)]
+    pub html_theme: Option<HtmlTheme>,
+    /// Stylesheet added to the HTML report after its own, to restyle it for a dashboard or portal
+    #[arg(long, value_name = "FILE")]
+    pub html_css: Option<PathBuf>,
+    /// Unified diff of the changes being tested, e.g. from `git diff`, formats which support it only report the changed lines
+    #[arg(long, value_name = "FILE")]
+    pub diff_file: Option<PathBuf>,
+    /// Write the units built by cargo along with their features, flags and artifacts to a json file
+    #[arg(long, value_name = "FILE")]
+    pub emit_build_plan: Option<PathBuf>,
     /// cargo subcommand to run. So far only test and build are supported
     #[arg(long, value_enum, value_name = "CMD", ignore_case = true)]
     pub command: Option<Mode>,
@@ -203,6 +429,9 @@ pub struct ConfigArgs {
     /// Path to Cargo.toml
     #[arg(long, value_name = "PATH")]
     pub manifest_path: Option<PathBuf>,
+    /// Workspace root for resolving paths, used in place of the cargo metadata and when it's unavailable
+    #[arg(long, value_name = "DIR")]
+    pub workspace_root: Option<PathBuf>,
     #[cfg(feature = "coveralls")]
     /// CI server being used, if unspecified tarpaulin may automatically infer for coveralls uploads
     #[arg(long, value_name = "SERVICE")]
@@ -210,6 +439,9 @@ pub struct ConfigArgs {
     /// Option to fail immediately after a single test fails
     #[arg(long)]
     pub fail_immediately: bool,
+    /// On failure write target/tarpaulin/failure-report.json with the error, config, environment and recent logs
+    #[arg(long)]
+    pub failure_report: bool,
     /// Arguments to be passed to the test executables can be used to filter or skip certain tests
     #[arg(last = true)]
     pub args: Vec<String>,
@@ -226,7 +458,7 @@ pub struct LoggingArgs {
     /// Show extra output
     #[arg(long, short)]
     pub verbose: bool,
-    /// Log tracing events and save to a json file. Also, enabled when --debug is used
+    /// Log tracing events to a jsonl file as they happen, converted to json on exit. Also, enabled when --debug is used
     #[arg(long)]
     pub dump_traces: bool,
     /// Print tarpaulin logs to stderr instead - test output will still be printed to stdout
@@ -0,0 +1,261 @@
+//! Imports coverage from the output of valgrind's callgrind tool with `--from-callgrind`, for
+//! projects which already run their tests under valgrind on targets neither engine supports.
+//! Callgrind records the cost of the events it collects on each source line rather than how many
+//! times the line ran, the cost of the first event (normally instructions executed) is used as the
+//! hits of the line. Lines which never ran aren't in the profile so the coverable lines are found
+//! in the debug info of the profiled objects built in the project where it can be read.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::source_analysis::{get_analysis, SourceAnalysisQuery};
+use crate::test_loader::generate_tracemap;
+use crate::traces::{CoverageStat, Trace, TraceMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Costs of the source lines in a callgrind profile
+#[derive(Debug, Default, PartialEq)]
+struct Profile {
+    /// Cost of the first event on each line of each source file
+    lines: BTreeMap<PathBuf, BTreeMap<u64, u64>>,
+    /// Objects the costs were collected from
+    objects: BTreeSet<PathBuf>,
+}
+
+/// Names given to ids by callgrind's name compression, `fl=(1) src/lib.rs` followed by `fl=(1)`
+#[derive(Default)]
+struct Names(HashMap<String, String>);
+
+impl Names {
+    fn resolve(&mut self, spec: &str) -> String {
+        let spec = spec.trim();
+        if let Some(rest) = spec.strip_prefix('(') {
+            if let Some((id, name)) = rest.split_once(')') {
+                let name = name.trim();
+                if name.is_empty() {
+                    return self.0.get(id).cloned().unwrap_or_default();
+                }
+                self.0.insert(id.to_string(), name.to_string());
+                return name.to_string();
+            }
+        }
+        spec.to_string()
+    }
+}
+
+fn invalid(number: usize, line: &str) -> RunError {
+    RunError::Parse(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid callgrind line {number}: {line}"),
+    ))
+}
+
+/// Parses a position, which can be relative to the last one or `*` for the same position
+fn parse_position(token: &str, last: u64) -> Option<u64> {
+    let absolute = |x: &str| match x.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => x.parse::<u64>().ok(),
+    };
+    match token.as_bytes().first()? {
+        b'*' => Some(last),
+        b'+' => Some(last + absolute(&token[1..])?),
+        b'-' => last.checked_sub(absolute(&token[1..])?),
+        _ => absolute(token),
+    }
+}
+
+fn parse_profile(contents: &str, root: &Path) -> Result<Profile, RunError> {
+    let mut profile = Profile::default();
+    let (mut files, mut objects) = (Names::default(), Names::default());
+    let mut positions = vec!["line".to_string()];
+    let mut last = vec![0; 1];
+    // The file of the function and the file of the cost lines, which inlined code changes
+    let (mut function_file, mut file) = (String::new(), String::new());
+    // Lines following `calls=` or `jump=` are the inclusive cost of the call or the jump target
+    let mut skip_cost = false;
+    for (number, line) in (1..).zip(contents.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            match key {
+                "fl" => {
+                    function_file = files.resolve(value);
+                    file = function_file.clone();
+                }
+                "fi" | "fe" => file = files.resolve(value),
+                "fn" => file = function_file.clone(),
+                "cfi" | "cfl" => {
+                    files.resolve(value);
+                }
+                "ob" | "cob" => {
+                    let object = objects.resolve(value);
+                    if !object.is_empty() && object != "???" {
+                        profile.objects.insert(PathBuf::from(object));
+                    }
+                }
+                "calls" | "jump" | "jcnd" => skip_cost = true,
+                _ => {}
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                if key == "positions" {
+                    positions = value.split_whitespace().map(str::to_string).collect();
+                    last = vec![0; positions.len()];
+                }
+                continue;
+            }
+        }
+        let mut tokens = line.split_whitespace();
+        for last in last.iter_mut() {
+            let token = tokens.next().ok_or_else(|| invalid(number, line))?;
+            *last = parse_position(token, *last).ok_or_else(|| invalid(number, line))?;
+        }
+        let cost = match tokens.next() {
+            Some(cost) => cost.parse::<u64>().map_err(|_| invalid(number, line))?,
+            None => 0,
+        };
+        if std::mem::take(&mut skip_cost) || cost == 0 || file.is_empty() || file == "???" {
+            continue;
+        }
+        let source_line = match positions.iter().position(|x| x == "line") {
+            Some(i) if last[i] > 0 => last[i],
+            _ => continue,
+        };
+        *profile
+            .lines
+            .entry(root.join(&file))
+            .or_default()
+            .entry(source_line)
+            .or_default() += cost;
+    }
+    Ok(profile)
+}
+
+/// Coverage of the project from the callgrind profiles given with `--from-callgrind`
+pub(crate) fn import_callgrind(config: &Config) -> Result<TraceMap, RunError> {
+    let root = config.root();
+    let mut profile = Profile::default();
+    for path in &config.from_callgrind {
+        info!("Importing {}", path.display());
+        let contents = fs::read_to_string(path)?;
+        let mut part = parse_profile(&contents, &root)?;
+        profile.objects.append(&mut part.objects);
+        for (file, lines) in part.lines {
+            let costs = profile.lines.entry(file).or_default();
+            for (line, cost) in lines {
+                *costs.entry(line).or_default() += cost;
+            }
+        }
+    }
+
+    let analysis = get_analysis(config);
+    let mut result = TraceMap::new();
+    result.set_functions(analysis.create_function_map());
+    result.set_feature_gates(analysis.create_feature_gate_map());
+    result.set_code_regions(analysis.create_region_map());
+    let analysis = analysis.lines;
+    let target_dir = config.target_dir();
+    for object in &profile.objects {
+        if !(object.starts_with(&root) || object.starts_with(&target_dir)) || !object.exists() {
+            continue;
+        }
+        match generate_tracemap(object, &analysis, config) {
+            Ok(traces) => result.merge(&traces),
+            Err(e) => debug!("Couldn't read traces from {}: {}", object.display(), e),
+        }
+    }
+    result.dedup();
+
+    for (file, lines) in &profile.lines {
+        if !file.starts_with(&root) || config.exclude_path(file) || !config.include_path(file) {
+            continue;
+        }
+        for (line, cost) in lines {
+            if analysis.should_ignore(file, &(*line as usize)) {
+                continue;
+            }
+            let (file, line) = analysis.normalise(file, *line as usize);
+            let line = line as u64;
+            if !result.contains_location(&file, line) {
+                result.add_trace(&file, Trace::new_stub(line));
+            }
+            if let Some(traces) = result.file_traces_mut(&file) {
+                for trace in traces.iter_mut().filter(|x| x.line == line) {
+                    if let CoverageStat::Line(ref mut hits) = trace.stats {
+                        *hits += cost;
+                    }
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_costs_read_from_profile() {
+        let contents = "# callgrind format
+version: 1
+creator: callgrind-3.22.0
+positions: line
+events: Ir Dr
+summary: 40
+
+ob=(1) /project/target/debug/deps/foo-1234
+fl=(1) src/lib.rs
+fn=(1) foo::add
+3 5 2
++1 4
+* 1
+fi=(2) src/util.rs
+10 7
+fe=(1)
+-2 2
+cfl=(3) /rustc/library/core/src/ops.rs
+cfn=(2) core::ops::add
+calls=1 20
++1 100
+fn=(3) foo::sub
+fl=(2)
+12 3
+ob=(2) /usr/lib/libc.so.6
+fl=(4) ???
+fn=(4) malloc
+0 50
+";
+        let profile = parse_profile(contents, Path::new("/project")).unwrap();
+        assert_eq!(
+            profile.lines,
+            BTreeMap::from([
+                (
+                    PathBuf::from("/project/src/lib.rs"),
+                    BTreeMap::from([(3, 5), (4, 5), (8, 2)])
+                ),
+                (
+                    PathBuf::from("/project/src/util.rs"),
+                    BTreeMap::from([(10, 7), (12, 3)])
+                ),
+            ])
+        );
+        assert_eq!(
+            profile.objects,
+            BTreeSet::from([
+                PathBuf::from("/project/target/debug/deps/foo-1234"),
+                PathBuf::from("/usr/lib/libc.so.6"),
+            ])
+        );
+
+        assert_eq!(parse_position("0x1f", 0), Some(31));
+        assert_eq!(parse_position("-4", 2), None);
+        assert!(parse_profile("3 five", Path::new("/project")).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::config::*;
 use crate::errors::RunError;
 use crate::path_utils::{fix_unc_path, get_source_walker};
+use crate::reproduce::write_scripts;
 use cargo_metadata::{diagnostic::DiagnosticLevel, CargoOpt, Message, Metadata, MetadataCommand};
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -8,7 +9,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
-use std::fs::{read_dir, read_to_string, remove_dir_all, remove_file, File};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, File};
 use std::io;
 use std::io::{BufRead, BufReader};
 use std::path::{Component, Path, PathBuf};
@@ -21,11 +22,11 @@ const BUILD_PROFRAW: &str = "build_rs_cov.profraw";
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "windows")] {
-        pub const LD_PATH_VAR: &'static str ="PATH";
+        pub const LD_PATH_VAR: &str ="PATH";
     } else if #[cfg(any(target_os = "macos", target_os = "ios"))] {
-        pub const LD_PATH_VAR: &'static str = "DYLD_LIBRARY_PATH";
+        pub const LD_PATH_VAR: &str = "DYLD_LIBRARY_PATH";
     } else {
-        pub const LD_PATH_VAR: &'static str =  "LD_LIBRARY_PATH";
+        pub const LD_PATH_VAR: &str =  "LD_LIBRARY_PATH";
     }
 }
 
@@ -47,6 +48,10 @@ impl CargoVersionInfo {
     fn supports_llvm_cov(&self) -> bool {
         (self.minor >= 50 && self.channel == Channel::Nightly) || self.minor >= 60
     }
+
+    fn supports_check_cfg(&self) -> bool {
+        self.minor >= 80
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -56,17 +61,64 @@ pub struct CargoOutput {
     /// This covers binaries we don't want to run explicitly but may be called as part of tracing
     /// execution of other processes.
     pub binaries: Vec<PathBuf>,
+    /// Every unit cargo reported building, used for `--emit-build-plan`
+    pub build_plan: BuildPlan,
+}
+
+/// The units compiled during the tarpaulin build along with the flags they were built with. This
+/// is to help diagnose why a crate was or wasn't rebuilt/instrumented.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BuildPlan {
+    pub rustflags: String,
+    pub rustdocflags: String,
+    pub units: Vec<BuildUnit>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BuildUnit {
+    pub package_id: String,
+    pub target: String,
+    pub kind: Vec<String>,
+    pub run_type: Option<RunType>,
+    pub features: Vec<String>,
+    pub test: bool,
+    /// True if cargo didn't have to rebuild this unit
+    pub fresh: bool,
+    pub filenames: Vec<PathBuf>,
+    pub executable: Option<PathBuf>,
+}
+
+impl BuildPlan {
+    pub fn write(&self, path: &Path) -> Result<(), RunError> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        info!("Build plan written to {}", path.display());
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct TestBinary {
     path: PathBuf,
     ty: Option<RunType>,
+    /// Name of the cargo target the binary was built from
+    target: Option<String>,
+    /// Kind of cargo target the binary was built from, `RunType::Tests` being an integration test
+    target_kind: Option<RunType>,
     cargo_dir: Option<PathBuf>,
     pkg_name: Option<String>,
     pkg_version: Option<String>,
     pkg_authors: Option<Vec<String>>,
     should_panic: bool,
+    /// Whether the binary was built with the libtest harness, so it takes libtest's arguments
+    #[serde(default)]
+    libtest: bool,
+    /// Set when the binary is being run as one of the configured scenarios rather than with the
+    /// usual test arguments
+    scenario: Option<Scenario>,
     /// Linker paths used when linking the binary, this should be accessed via
     /// `Self::has_linker_paths` and `Self::ld_library_path` as there may be interaction with
     /// current environment. It's only made pub(crate) for the purpose of testing.
@@ -84,15 +136,31 @@ impl TestBinary {
         Self {
             path,
             ty,
+            target: None,
+            target_kind: None,
             pkg_name: None,
             pkg_version: None,
             pkg_authors: None,
             cargo_dir: None,
             should_panic: false,
+            libtest: false,
+            scenario: None,
             linker_paths: vec![],
         }
     }
 
+    /// Creates a copy of the binary to be run for the given scenario
+    pub fn with_scenario(&self, scenario: &Scenario) -> Self {
+        Self {
+            scenario: Some(scenario.clone()),
+            ..self.clone()
+        }
+    }
+
+    pub fn scenario(&self) -> Option<&Scenario> {
+        self.scenario.as_ref()
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -101,6 +169,12 @@ impl TestBinary {
         self.ty
     }
 
+    /// The kind of tests in the binary, this separates the library and binary unit tests and the
+    /// integration tests built for `RunType::Tests`
+    pub fn coverage_kind(&self) -> Option<RunType> {
+        self.target_kind.or(self.ty)
+    }
+
     pub fn manifest_dir(&self) -> &Option<PathBuf> {
         &self.cargo_dir
     }
@@ -121,6 +195,10 @@ impl TestBinary {
         !self.linker_paths.is_empty()
     }
 
+    pub fn uses_libtest(&self) -> bool {
+        self.libtest
+    }
+
     pub fn is_test_type(&self) -> bool {
         matches!(self.ty, None | Some(RunType::Tests))
     }
@@ -163,6 +241,27 @@ impl TestBinary {
             .map(|x| x.to_string_lossy().to_string())
             .unwrap_or_default()
     }
+
+    /// Name of the cargo target the binary was built from. If cargo didn't report one the file
+    /// name is used without the hash cargo appends to test binaries.
+    pub fn target_name(&self) -> String {
+        if let Some(target) = self.target.as_ref() {
+            return target.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match stem.rsplit_once('-') {
+            Some((name, hash))
+                if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                name.to_string()
+            }
+            _ => stem,
+        }
+    }
 }
 
 impl DocTestBinaryMeta {
@@ -235,30 +334,138 @@ pub fn get_tests(config: &Config) -> Result<CargoOutput, RunError> {
             }
         }
     }
+    if !config.packages_glob.is_empty() || config.only_default_members {
+        let packages = selected_packages(config)?;
+        info!("Testing packages: {}", packages.join(", "));
+    }
     let man_binding = config.manifest();
     let manifest = man_binding.as_path().to_str().unwrap_or("Cargo.toml");
     let metadata = MetadataCommand::new()
         .manifest_path(manifest)
         .features(CargoOpt::AllFeatures)
+        .other_options(config.cargo_network_flags())
         .exec()
         .map_err(|e| RunError::Cargo(e.to_string()))?;
 
-    for ty in &config.run_types {
-        run_cargo(&metadata, manifest, config, Some(*ty), &mut result)?;
+    let script = match write_scripts(config, &build_commands(config)) {
+        Ok(script) => Some(script),
+        Err(e) => {
+            warn!("Couldn't write the scripts to reproduce the build: {}", e);
+            None
+        }
+    };
+    for ty in build_run_types(config) {
+        if let Err(e) = run_cargo(&metadata, manifest, config, ty, &mut result) {
+            if let Some(script) = script.as_ref() {
+                info!(
+                    "The build can be reproduced without tarpaulin by running {}",
+                    script.display()
+                );
+            }
+            return Err(e);
+        }
+    }
+    // Only matters for llvm cov and who knows, one day may not be needed
+    let _ = remove_file(config.root().join(BUILD_PROFRAW));
+    if let Some(path) = config.emit_build_plan() {
+        result.build_plan.rustflags = rust_flags(config);
+        result.build_plan.rustdocflags = rustdoc_flags(config);
+        result.build_plan.write(&path)?;
     }
+    Ok(result)
+}
+
+/// The run types cargo is invoked with to build the tests, `None` builds the named targets or
+/// the default targets for the command
+fn build_run_types(config: &Config) -> Vec<Option<RunType>> {
+    let mut result = config
+        .run_types
+        .iter()
+        .copied()
+        .map(Some)
+        .collect::<Vec<_>>();
     if config.has_named_tests() {
-        run_cargo(&metadata, manifest, config, None, &mut result)?;
+        result.push(None);
     } else if config.run_types.is_empty() {
         let ty = if config.command == Mode::Test {
             Some(RunType::Tests)
         } else {
             None
         };
-        run_cargo(&metadata, manifest, config, ty, &mut result)?;
+        result.push(ty);
     }
-    // Only matters for llvm cov and who knows, one day may not be needed
-    let _ = remove_file(config.root().join(BUILD_PROFRAW));
-    Ok(result)
+    result
+}
+
+/// Workspace members with a name matching any of the globs, in the order of `members`. A glob
+/// that matches nothing is an error so a typo doesn't silently fall back to the default package.
+fn match_members(members: &[String], globs: &[String]) -> Result<Vec<String>, RunError> {
+    let mut matched = vec![];
+    for glob in globs {
+        let pattern = glob::Pattern::new(glob)
+            .map_err(|e| RunError::Packages(format!("invalid package glob '{glob}': {e}")))?;
+        let mut found = false;
+        for member in members.iter().filter(|x| pattern.matches(x)) {
+            found = true;
+            if !matched.contains(member) {
+                matched.push(member.clone());
+            }
+        }
+        if !found {
+            return Err(RunError::Packages(format!(
+                "no workspace members match '{glob}'"
+            )));
+        }
+    }
+    Ok(matched)
+}
+
+/// The packages to build, those given with `--packages` along with the workspace members
+/// matched by `--packages-glob` and the default members with `--only-default-members`
+pub(crate) fn selected_packages(config: &Config) -> Result<Vec<String>, RunError> {
+    let mut packages = config.packages.clone();
+    if config.packages_glob.is_empty() && !config.only_default_members {
+        return Ok(packages);
+    }
+    let meta = config.get_metadata();
+    let meta = meta.as_ref().ok_or_else(|| {
+        RunError::Packages("selecting packages needs the project metadata".to_string())
+    })?;
+    let mut selected = vec![];
+    if config.only_default_members {
+        if cargo_metadata::workspace_default_members_is_missing(&meta.workspace_default_members) {
+            return Err(RunError::Packages(
+                "--only-default-members needs cargo 1.71 or newer".to_string(),
+            ));
+        }
+        selected.extend(
+            meta.workspace_default_packages()
+                .iter()
+                .map(|x| x.name.clone()),
+        );
+    }
+    let members = meta
+        .workspace_packages()
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<Vec<_>>();
+    selected.extend(match_members(&members, &config.packages_glob)?);
+    for package in selected {
+        if !packages.contains(&package) && !config.exclude.contains(&package) {
+            packages.push(package);
+        }
+    }
+    Ok(packages)
+}
+
+/// The cargo commands `get_tests` runs to build the tests
+pub(crate) fn build_commands(config: &Config) -> Vec<Command> {
+    let man_binding = config.manifest();
+    let manifest = man_binding.as_path().to_str().unwrap_or("Cargo.toml");
+    build_run_types(config)
+        .into_iter()
+        .map(|ty| create_command(manifest, config, ty))
+        .collect()
 }
 
 fn run_cargo(
@@ -287,14 +494,34 @@ fn run_cargo(
         for msg in Message::parse_stream(reader) {
             match msg {
                 Ok(Message::CompilerArtifact(art)) => {
+                    result.build_plan.units.push(BuildUnit {
+                        package_id: art.package_id.repr.clone(),
+                        target: art.target.name.clone(),
+                        kind: art.target.kind.iter().map(|k| k.to_string()).collect(),
+                        run_type: ty,
+                        features: art.features.clone(),
+                        test: art.profile.test,
+                        fresh: art.fresh,
+                        filenames: art
+                            .filenames
+                            .iter()
+                            .map(|x| fix_unc_path(x.as_std_path()))
+                            .collect(),
+                        executable: art
+                            .executable
+                            .as_ref()
+                            .map(|x| fix_unc_path(x.as_std_path())),
+                    });
                     if let Some(path) = art.executable.as_ref() {
                         if !art.profile.test && config.command == Mode::Test {
                             result.binaries.push(PathBuf::from(path));
                             continue;
                         }
-                        result
-                            .test_binaries
-                            .push(TestBinary::new(fix_unc_path(path.as_std_path()), ty));
+                        let mut binary = TestBinary::new(fix_unc_path(path.as_std_path()), ty);
+                        binary.target = Some(art.target.name.clone());
+                        binary.target_kind = target_run_type(&art.target.kind);
+                        binary.libtest = art.profile.test;
+                        result.test_binaries.push(binary);
                         package_ids.push(Some(art.package_id.clone()));
                     }
                 }
@@ -423,6 +650,20 @@ fn run_cargo(
     Ok(())
 }
 
+/// The run type matching the kinds of a cargo target
+fn target_run_type(kinds: &[impl ToString]) -> Option<RunType> {
+    kinds
+        .iter()
+        .find_map(|kind| match kind.to_string().as_str() {
+            "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => Some(RunType::Lib),
+            "bin" => Some(RunType::Bins),
+            "test" => Some(RunType::Tests),
+            "example" => Some(RunType::Examples),
+            "bench" => Some(RunType::Benchmarks),
+            _ => None,
+        })
+}
+
 fn convert_to_prefix(p: &Path) -> Option<String> {
     // Need to go from directory after last one with Cargo.toml
     let convert_name = |p: &Path| {
@@ -627,7 +868,9 @@ fn init_args(test_cmd: &mut Command, config: &Config) {
     if config.release {
         test_cmd.arg("--release");
     }
-    config.packages.iter().for_each(|package| {
+    // Selection errors are reported by `get_tests` before any commands are ran
+    let packages = selected_packages(config).unwrap_or_else(|_| config.packages.clone());
+    packages.iter().for_each(|package| {
         test_cmd.arg("--package");
         test_cmd.arg(package);
     });
@@ -679,7 +922,7 @@ fn clean_doctest_folder<P: AsRef<Path>>(doctest_dir: P) {
 }
 
 fn handle_llvm_flags(value: &mut String, config: &Config) {
-    if config.engine() == TraceEngine::Llvm {
+    if config.uses_llvm() {
         value.push_str(llvm_coverage_rustflag());
     }
     if cfg!(not(windows)) && !config.no_dead_code {
@@ -783,9 +1026,8 @@ pub fn rust_flags(config: &Config) -> String {
     let mut value = config.rustflags.clone().unwrap_or_default();
     value.push_str(" -Cdebuginfo=2 ");
     value.push_str("-Cstrip=none ");
-    if !config.avoid_cfg_tarpaulin {
-        value.push_str("--cfg=tarpaulin ");
-    }
+    value.push_str(&cfg_flags(config));
+    value.push_str(&check_cfg_flags(config));
     if config.release {
         value.push_str("-Cdebug-assertions=off ");
     }
@@ -811,12 +1053,14 @@ pub fn rust_flags(config: &Config) -> String {
 
 pub fn rustdoc_flags(config: &Config) -> String {
     const RUSTDOC: &str = "RUSTDOCFLAGS";
-    let common_opts = " -Cdebuginfo=2 --cfg=tarpaulin -Cstrip=none ";
+    let common_opts = " -Cdebuginfo=2 -Cstrip=none ";
     let mut value = format!(
         "{} --persist-doctests {} -Zunstable-options ",
         common_opts,
         config.doctest_dir().display()
     );
+    value.push_str(&cfg_flags(config));
+    value.push_str(&check_cfg_flags(config));
     if let Ok(vtemp) = env::var(RUSTDOC) {
         if !vtemp.contains("--persist-doctests") {
             value.push_str(vtemp.as_ref());
@@ -829,28 +1073,70 @@ pub fn rustdoc_flags(config: &Config) -> String {
     deduplicate_flags(&value)
 }
 
+/// The cfgs tarpaulin injects (or that users commonly gate on for tarpaulin) trigger the
+/// `unexpected_cfgs` lint, which fails builds using `-Dwarnings`. Declaring them as expected
+/// avoids that without needing `--avoid-cfg-tarpaulin`.
+fn check_cfg_flags(config: &Config) -> String {
+    if !config.no_check_cfg && supports_check_cfg() {
+        let mut names = vec!["tarpaulin", "tarpaulin_include"];
+        let mut values = String::new();
+        for cfg in &config.cfgs {
+            match split_cfg(cfg) {
+                (key, Some(value)) => {
+                    values.push_str(&format!(" --check-cfg=cfg({key},values({value})) "))
+                }
+                (name, None) => names.push(name),
+            }
+        }
+        format!(" --check-cfg=cfg({}) {}", names.join(","), values)
+    } else {
+        " ".to_string()
+    }
+}
+
+/// `--cfg=tarpaulin` unless it's avoided, `--cfg=tarpaulin_include` if it's enabled and any cfgs
+/// the user added. The two tarpaulin cfgs are toggled independently so the attribute recognition
+/// of `tarpaulin_include` can be used without changing what `cfg(tarpaulin)` code is built.
+fn cfg_flags(config: &Config) -> String {
+    let mut value = String::new();
+    if !config.avoid_cfg_tarpaulin {
+        value.push_str(" --cfg=tarpaulin ");
+    }
+    for cfg in config.coverage_cfgs() {
+        value.push_str(&format!(" --cfg={cfg} "));
+    }
+    value
+}
+
 fn deduplicate_flags(flags: &str) -> String {
     lazy_static! {
         static ref CFG_FLAG: Regex = Regex::new(r#"\--cfg\s+"#).unwrap();
+        static ref CHECK_CFG_FLAG: Regex = Regex::new(r#"\--check-cfg\s+"#).unwrap();
         static ref C_FLAG: Regex = Regex::new(r#"\-C\s+"#).unwrap();
         static ref Z_FLAG: Regex = Regex::new(r#"\-Z\s+"#).unwrap();
         static ref W_FLAG: Regex = Regex::new(r#"\-W\s+"#).unwrap();
         static ref A_FLAG: Regex = Regex::new(r#"\-A\s+"#).unwrap();
         static ref D_FLAG: Regex = Regex::new(r#"\-D\s+"#).unwrap();
+        static ref REMAP_FLAG: Regex = Regex::new(r#"\--remap-path-prefix\s+"#).unwrap();
     }
 
     // Going to remove the excess spaces to make it easier to filter things.
     let res = CFG_FLAG.replace_all(flags, "--cfg=");
+    let res = CHECK_CFG_FLAG.replace_all(&res, "--check-cfg=");
     let res = C_FLAG.replace_all(&res, "-C");
     let res = Z_FLAG.replace_all(&res, "-Z");
     let res = W_FLAG.replace_all(&res, "-W");
     let res = A_FLAG.replace_all(&res, "-A");
     let res = D_FLAG.replace_all(&res, "-D");
+    let res = REMAP_FLAG.replace_all(&res, "--remap-path-prefix=");
 
     let mut flag_set = HashSet::new();
     let mut result = vec![];
     for val in res.split_whitespace() {
-        if val.starts_with("--cfg") {
+        if val.starts_with("--cfg")
+            || val.starts_with("--check-cfg")
+            || val.starts_with("--remap-path-prefix")
+        {
             if !flag_set.contains(&val) {
                 result.push(val);
                 flag_set.insert(val);
@@ -901,6 +1187,13 @@ pub fn supports_llvm_coverage() -> bool {
     }
 }
 
+fn supports_check_cfg() -> bool {
+    CARGO_VERSION_INFO
+        .as_ref()
+        .map(|v| v.supports_check_cfg())
+        .unwrap_or(false)
+}
+
 pub fn llvm_coverage_rustflag() -> &'static str {
     match CARGO_VERSION_INFO.as_ref() {
         Some(v) if v.minor >= 60 => " -Cinstrument-coverage ",
@@ -913,6 +1206,47 @@ mod tests {
     use super::*;
     use toml::toml;
 
+    #[test]
+    fn package_globs_matched_against_members() {
+        let members = [
+            "service-auth",
+            "service-api",
+            "client",
+            "service-api-macros",
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+        let globs = vec!["service-*".to_string(), "client".to_string()];
+        assert_eq!(
+            match_members(&members, &globs).unwrap(),
+            vec![
+                "service-auth",
+                "service-api",
+                "service-api-macros",
+                "client"
+            ]
+        );
+        let globs = vec!["service-api*".to_string(), "*-api".to_string()];
+        assert_eq!(
+            match_members(&members, &globs).unwrap(),
+            vec!["service-api", "service-api-macros"]
+        );
+        assert!(match_members(&members, &["server-*".to_string()]).is_err());
+        assert!(match_members(&members, &["[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn target_name_without_hash() {
+        let test = TestBinary::new(
+            PathBuf::from("target/debug/deps/integration_api-0123456789abcdef"),
+            None,
+        );
+        assert_eq!(test.target_name(), "integration_api");
+        let bin = TestBinary::new(PathBuf::from("target/debug/my-cli"), None);
+        assert_eq!(bin.target_name(), "my-cli");
+    }
+
     #[test]
     #[cfg(not(windows))]
     fn check_dead_code_flags() {
@@ -981,6 +1315,65 @@ mod tests {
         assert!(!version.supports_llvm_cov());
     }
 
+    #[test]
+    fn check_cfg_flags_toggle() {
+        let mut config = Config::default();
+        if supports_check_cfg() {
+            assert!(rust_flags(&config).contains("--check-cfg=cfg(tarpaulin,tarpaulin_include)"));
+            assert!(rustdoc_flags(&config).contains("--check-cfg=cfg(tarpaulin,tarpaulin_include)"));
+        }
+        config.no_check_cfg = true;
+        assert!(!rust_flags(&config).contains("--check-cfg=cfg(tarpaulin"));
+        assert!(!rustdoc_flags(&config).contains("--check-cfg=cfg(tarpaulin"));
+    }
+
+    #[test]
+    fn user_cfgs_passed_through() {
+        let mut config = Config::default();
+        config.avoid_cfg_tarpaulin = true;
+        config.cfgs = vec!["coverage_run".to_string(), "mode=\"cov\"".to_string()];
+        for flags in [rust_flags(&config), rustdoc_flags(&config)] {
+            assert!(!flags.contains("--cfg=tarpaulin "));
+            assert!(flags.contains("--cfg=coverage_run"));
+            assert!(flags.contains("--cfg=mode=\"cov\""));
+            if supports_check_cfg() {
+                assert!(flags.contains("--check-cfg=cfg(tarpaulin,tarpaulin_include,coverage_run)"));
+                assert!(flags.contains("--check-cfg=cfg(mode,values(\"cov\"))"));
+            }
+        }
+    }
+
+    #[test]
+    fn tarpaulin_cfgs_toggled_independently() {
+        let mut config = Config::default();
+        let flags = rust_flags(&config);
+        assert!(flags.contains("--cfg=tarpaulin "));
+        assert!(!flags.contains("--cfg=tarpaulin_include"));
+
+        config.cfg_tarpaulin_include = true;
+        for flags in [rust_flags(&config), rustdoc_flags(&config)] {
+            assert!(flags.contains("--cfg=tarpaulin "));
+            assert!(flags.contains("--cfg=tarpaulin_include"));
+        }
+
+        config.avoid_cfg_tarpaulin = true;
+        for flags in [rust_flags(&config), rustdoc_flags(&config)] {
+            assert!(!flags.contains("--cfg=tarpaulin "));
+            assert!(flags.contains("--cfg=tarpaulin_include"));
+        }
+        assert_eq!(config.coverage_cfgs(), vec!["tarpaulin_include"]);
+    }
+
+    #[test]
+    fn check_cfg_flags_not_merged() {
+        assert_eq!(
+            deduplicate_flags(
+                "--check-cfg=cfg(tarpaulin) --check-cfg cfg(foo) --check-cfg=cfg(tarpaulin)"
+            ),
+            "--check-cfg=cfg(tarpaulin) --check-cfg=cfg(foo)"
+        );
+    }
+
     #[test]
     fn no_duplicate_flags() {
         assert_eq!(
@@ -1004,5 +1397,9 @@ mod tests {
             deduplicate_flags("--cfg=tarpaulin --cfg tarpauline --cfg=tarp"),
             "--cfg=tarpaulin --cfg=tarpauline --cfg=tarp"
         );
+        assert_eq!(
+            deduplicate_flags("--remap-path-prefix /a=/b --remap-path-prefix=/c=/d"),
+            "--remap-path-prefix=/a=/b --remap-path-prefix=/c=/d"
+        );
     }
 }
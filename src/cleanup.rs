@@ -0,0 +1,233 @@
+//! Management of the artifacts tarpaulin leaves behind in the target and output directories. These
+//! are the previous run reports, profraws and event logs, none of which are removed by cargo
+//! unless the whole target directory is cleaned.
+use crate::config::Config;
+use crate::path_utils::get_profile_walker;
+use crate::run_dir::remove_stale_runs;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{read_dir, remove_file};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// A file owned by tarpaulin along with when it was last modified
+struct Artifact {
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+impl Artifact {
+    fn file_name(&self) -> Cow<'_, str> {
+        self.path.file_name().unwrap_or_default().to_string_lossy()
+    }
+}
+
+fn list_files(dir: &Path, filter: impl Fn(&str) -> bool) -> Vec<Artifact> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|x| x.path().is_file())
+        .filter(|x| filter(&x.file_name().to_string_lossy()))
+        .filter_map(|x| {
+            let modified = x.metadata().and_then(|m| m.modified()).ok()?;
+            Some(Artifact {
+                path: x.path(),
+                modified,
+            })
+        })
+        .collect()
+}
+
+fn is_event_log(name: &str) -> bool {
+    lazy_static! {
+        static ref EVENT_LOG: Regex = Regex::new(r"^tarpaulin_\d{14}\.jsonl?$").unwrap();
+    }
+    EVENT_LOG.is_match(name)
+}
+
+/// Event logs from `--dump-traces` in the output directory, newest first
+fn event_logs(config: &Config) -> Vec<Artifact> {
+    let mut logs = list_files(&config.output_dir(), is_event_log);
+    logs.sort_by_key(|log| Reverse(log.modified));
+    logs
+}
+
+/// The run a `--dump-traces` event log belongs to, the `.json` and `.jsonl` logs of a run share it
+fn event_log_run(name: &str) -> &str {
+    name.trim_end_matches(".jsonl").trim_end_matches(".json")
+}
+
+/// Name shared by the files of a stored coverage report, `None` for the other stored files
+fn coverage_report_name(name: &str) -> Option<&str> {
+    ["coverage.history.json", "coverage.json", "coverage.bin"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+}
+
+/// Reports and metadata tarpaulin stores between runs in `target/tarpaulin`
+fn stored_reports(config: &Config) -> Vec<Artifact> {
+    list_files(&config.target_dir().join("tarpaulin"), |name| {
+        coverage_report_name(name).is_some()
+            || name == "failure-report.json"
+            || name == "metadata.json"
+            || name == "test-impact.json"
+    })
+}
+
+/// Groups the artifacts by the name they share, the group with the most recently modified
+/// artifact comes first
+fn newest_first(artifacts: Vec<Artifact>, name: impl Fn(&str) -> &str) -> Vec<Vec<Artifact>> {
+    let mut groups = HashMap::<String, Vec<Artifact>>::new();
+    for artifact in artifacts {
+        let key = name(&artifact.file_name()).to_string();
+        groups.entry(key).or_default().push(artifact);
+    }
+    let mut groups = groups.into_values().collect::<Vec<_>>();
+    groups.sort_by_key(|x| Reverse(x.iter().map(|x| x.modified).max()));
+    groups
+}
+
+fn remove(path: &Path) {
+    if let Err(e) = remove_file(path) {
+        warn!("Unable to remove {}: {}", path.display(), e);
+    }
+}
+
+/// Removes the profraw files in the profraw directory. The directory itself is left alone as it
+/// may be user provided and contain other files.
+pub(crate) fn remove_profraws(config: &Config) {
+    for profraw in get_profile_walker(config) {
+        remove(profraw.path());
+    }
+}
+
+/// Applies the `--keep-reports` and `--max-report-age` retention policy removing any event logs
+/// and stored reports which fall outside of it. Only the coverage reports count towards the
+/// number kept, the other stored files are only removed once they're too old.
+pub fn apply_retention(config: &Config) {
+    if config.keep_reports.is_none() && config.max_report_age.is_none() {
+        return;
+    }
+    let expired = |artifact: &Artifact| match config.max_report_age {
+        Some(days) => SystemTime::now()
+            .duration_since(artifact.modified)
+            .map(|age| age > Duration::from_secs(days * SECONDS_PER_DAY))
+            .unwrap_or(false),
+        None => false,
+    };
+    let keep = config.keep_reports.unwrap_or(usize::MAX);
+    for (i, logs) in newest_first(event_logs(config), event_log_run)
+        .iter()
+        .enumerate()
+    {
+        for log in logs.iter().filter(|x| i >= keep || expired(x)) {
+            info!("Removing old event log {}", log.path.display());
+            remove(&log.path);
+        }
+    }
+    let (coverage, other): (Vec<_>, Vec<_>) = stored_reports(config)
+        .into_iter()
+        .partition(|x| coverage_report_name(&x.file_name()).is_some());
+    let coverage = newest_first(coverage, |name| coverage_report_name(name).unwrap_or(name));
+    let outside_policy = coverage
+        .iter()
+        .enumerate()
+        .flat_map(|(i, reports)| reports.iter().filter(move |x| i >= keep || expired(x)))
+        .chain(other.iter().filter(|x| expired(x)));
+    for report in outside_policy {
+        info!("Removing old report {}", report.path.display());
+        remove(&report.path);
+    }
+}
+
+/// Removes every artifact tarpaulin has created leaving the rest of the target directory
+/// untouched. Generated coverage reports such as the html or lcov and the build plan are left
+/// alone as they're written to user chosen locations.
+pub fn clean_artifacts(config: &Config) -> io::Result<()> {
+    info!("Removing tarpaulin artifacts");
+    remove_profraws(config);
+    remove_stale_runs(config);
+    for artifact in event_logs(config)
+        .iter()
+        .chain(stored_reports(config).iter())
+    {
+        remove_file(&artifact.path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log_names() {
+        assert!(is_event_log("tarpaulin_20240101123000.json"));
+        assert!(is_event_log("tarpaulin_20240101123000.jsonl"));
+        assert!(!is_event_log("tarpaulin_2024.json"));
+        assert!(!is_event_log("tarpaulin-report.json"));
+        assert!(!is_event_log("my_tarpaulin_20240101123000.json"));
+    }
+
+    #[test]
+    fn artifacts_kept_per_run() {
+        let artifact = |name: &str, secs: u64| Artifact {
+            path: PathBuf::from("target/tarpaulin").join(name),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        };
+        let names = |groups: Vec<Vec<Artifact>>| {
+            groups
+                .iter()
+                .map(|x| {
+                    let mut names = x
+                        .iter()
+                        .map(|x| x.file_name().to_string())
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    names
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let logs = vec![
+            artifact("tarpaulin_20240101123000.json", 1),
+            artifact("tarpaulin_20240102123000.jsonl", 3),
+            artifact("tarpaulin_20240101123000.jsonl", 2),
+        ];
+        assert_eq!(
+            names(newest_first(logs, event_log_run)),
+            vec![
+                vec!["tarpaulin_20240102123000.jsonl"],
+                vec![
+                    "tarpaulin_20240101123000.json",
+                    "tarpaulin_20240101123000.jsonl"
+                ],
+            ]
+        );
+
+        let reports = vec![
+            artifact("foo-1234-coverage.json", 1),
+            artifact("coverage.bin", 4),
+            artifact("foo-1234-coverage.history.json", 2),
+        ];
+        let groups = newest_first(reports, |name| coverage_report_name(name).unwrap_or(name));
+        assert_eq!(
+            names(groups),
+            vec![
+                vec!["coverage.bin"],
+                vec!["foo-1234-coverage.history.json", "foo-1234-coverage.json"],
+            ]
+        );
+        assert_eq!(coverage_report_name("metadata.json"), None);
+    }
+}
@@ -1,26 +1,48 @@
 use self::parse::*;
 pub use self::types::*;
+use crate::args::ConfigArgs;
+use crate::cargo::{supports_llvm_coverage, TestBinary};
+use crate::errors::RunError;
 use crate::path_utils::fix_unc_path;
-use crate::{args::ConfigArgs, cargo::supports_llvm_coverage};
+use crate::source_analysis::{AnalysisConfig, LinePattern};
 use cargo_metadata::{Metadata, MetadataCommand};
+use chrono::offset::Local;
 #[cfg(feature = "coveralls")]
 use coveralls_api::CiService;
 use glob::Pattern;
 use humantime_serde::deserialize as humantime_serde;
 use indexmap::IndexMap;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::cell::{Ref, RefCell};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fs;
-use std::io::{Error, ErrorKind};
+use std::io::{self, Error, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 mod parse;
 pub mod types;
 
+/// File in `target/tarpaulin` the workspace metadata is cached in
+const METADATA_CACHE: &str = "metadata.json";
+
+/// Test harnesses which isolate tests by re-executing the test binary, with ptrace the coverage
+/// of the child processes is lost unless execs are followed
+const FORK_HARNESSES: &[&str] = &["rusty-fork", "procspawn", "mitosis"];
+
+lazy_static! {
+    /// Identifies this run of tarpaulin, used to name the run directory
+    static ref RUN_ID: String = format!(
+        "{}-{}",
+        Local::now().format("%Y%m%d%H%M%S"),
+        std::process::id()
+    );
+}
+
 #[derive(Debug)]
 pub struct ConfigWrapper(pub Vec<Config>);
 
@@ -39,9 +61,18 @@ pub struct Config {
     /// Flag to also run tests with the ignored attribute
     #[serde(rename = "ignored")]
     pub run_ignored: bool,
+    /// Flag to only run tests with the ignored attribute
+    #[serde(rename = "only-ignored")]
+    pub only_ignored: bool,
     /// Ignore panic macros in code.
     #[serde(rename = "ignore-panics")]
     pub ignore_panics: bool,
+    /// Report how many `Err` handling paths and `panic!` calls outside of tests ran
+    #[serde(rename = "error-path-coverage")]
+    pub error_path_coverage: bool,
+    /// Ignore const blocks and const fns which are only used in const contexts
+    #[serde(rename = "ignore-const-fns")]
+    pub ignore_const_fns: bool,
     /// Flag to add a clean step when preparing the target project
     #[serde(rename = "force-clean")]
     force_clean: bool,
@@ -63,9 +94,34 @@ pub struct Config {
     /// Flag specifying to run branch coverage
     #[serde(rename = "branch")]
     pub branch_coverage: bool,
+    /// Branch coverage was asked for with `--branch` or in a config file, rather than being on
+    /// because `--line` wasn't given
+    #[serde(skip_deserializing, skip_serializing)]
+    pub branch_requested: bool,
     /// Directory to write output files
     #[serde(rename = "output-dir")]
     pub output_directory: Option<PathBuf>,
+    /// Base name used for the report files instead of their default names
+    #[serde(rename = "report-name")]
+    pub report_name: Option<String>,
+    /// Tera template rendered for `--out Template`
+    pub template: Option<PathBuf>,
+    /// Layout of the markdown report
+    #[serde(rename = "markdown-style")]
+    markdown_style: Option<MarkdownStyle>,
+    /// Colour scheme of the HTML report
+    #[serde(rename = "html-theme")]
+    html_theme: Option<HtmlTheme>,
+    /// Stylesheet added to the HTML report after its own
+    #[serde(rename = "html-css")]
+    pub html_css: Option<PathBuf>,
+    /// Unified diff of the changes being tested, reports which support it only include the
+    /// changed lines
+    #[serde(rename = "diff-file")]
+    pub diff_file: Option<PathBuf>,
+    /// File to write the build plan (units compiled and flags used) to
+    #[serde(rename = "emit-build-plan")]
+    emit_build_plan: Option<PathBuf>,
     /// Key relating to coveralls service or repo
     pub coveralls: Option<String>,
     /// Enum representing CI tool used.
@@ -77,6 +133,22 @@ pub struct Config {
     /// instead.
     #[serde(rename = "report-uri")]
     pub report_uri: Option<String>,
+    /// Proxy used by uploads to online services
+    pub proxy: Option<String>,
+    /// PEM file of the CA certificates trusted by uploads to online services
+    #[serde(rename = "ca-cert")]
+    pub ca_cert: Option<PathBuf>,
+    /// Pull request to comment the coverage of its changes on
+    #[serde(rename = "github-pr")]
+    pub github_pr: Option<u64>,
+    /// Environment variable holding the token used to comment on the pull request
+    #[serde(rename = "github-token-env")]
+    pub github_token_env: Option<String>,
+    /// Upload the coverage to Codecov
+    pub codecov: bool,
+    /// Environment variable holding the Codecov upload token
+    #[serde(rename = "codecov-token-env")]
+    pub codecov_token_env: Option<String>,
     /// Forward unexpected signals back to the tracee. Used for tests which
     /// rely on signals to work.
     #[serde(rename = "forward")]
@@ -110,6 +182,10 @@ pub struct Config {
     /// Directory for generated artifacts
     #[serde(rename = "target-dir")]
     target_dir: Option<PathBuf>,
+    /// Workspace root to use instead of the one from the cargo metadata, it's also used to find
+    /// the target directory if the metadata is unavailable
+    #[serde(rename = "workspace-root")]
+    pub workspace_root: Option<PathBuf>,
     /// Run tarpaulin on project without accessing the network
     pub offline: bool,
     /// Cargo subcommand to run. So far only test and build are supported
@@ -117,8 +193,17 @@ pub struct Config {
     /// Types of tests for tarpaulin to collect coverage on
     #[serde(rename = "run-types")]
     pub run_types: Vec<RunType>,
+    /// Constructs to not merge into a single logical line in source analysis
+    #[serde(rename = "no-logical-lines")]
+    pub no_logical_lines: Vec<LogicalLines>,
     /// Packages to include when building the target project
     pub packages: Vec<String>,
+    /// Globs matched against workspace member names to add to `packages`
+    #[serde(rename = "packages-glob")]
+    pub packages_glob: Vec<String>,
+    /// Only build the workspace default members
+    #[serde(rename = "only-default-members")]
+    pub only_default_members: bool,
     /// Packages to exclude from testing
     pub exclude: Vec<String>,
     /// Files to exclude from testing in their compiled form
@@ -133,6 +218,12 @@ pub struct Config {
     /// Files to include in testing in uncompiled form (for serde)
     #[serde(rename = "include-files")]
     included_files_raw: Vec<String>,
+    /// Files source analysis and coverage mapping are limited to in their compiled form
+    #[serde(skip_deserializing, skip_serializing)]
+    focus_files: RefCell<Vec<glob::Pattern>>,
+    /// Files source analysis and coverage mapping are limited to in uncompiled form (for serde)
+    #[serde(rename = "focus")]
+    focus_files_raw: Vec<String>,
     /// Varargs to be forwarded to the test executables.
     #[serde(rename = "args")]
     pub varargs: Vec<String>,
@@ -164,11 +255,46 @@ pub struct Config {
     /// returns a non-zero code if coverage is below the threshold
     #[serde(rename = "fail-under")]
     pub fail_under: Option<f64>,
+    /// Number of public functions allowed to have no coverage, also enables listing them
+    #[serde(rename = "fail-on-uncovered-public")]
+    pub fail_on_uncovered_public: Option<usize>,
+    /// Most uncovered lines to print, the rest are only counted
+    #[serde(rename = "max-uncovered-lines-printed")]
+    pub max_uncovered_lines_printed: Option<usize>,
+    /// Only print the uncovered lines of files changed in the `diff_file`, and only list the lines
+    /// it adds in the quickfix output
+    #[serde(rename = "uncovered-diff-only")]
+    pub uncovered_diff_only: bool,
     /// Result of cargo_metadata ran on the crate
     #[serde(skip_deserializing, skip_serializing)]
     pub metadata: RefCell<Option<Metadata>>,
     /// Don't pass --cfg=tarpaulin to the 'RUSTFLAG'
+    #[serde(alias = "avoid-cfg-tarpaulin")]
     pub avoid_cfg_tarpaulin: bool,
+    /// Pass --cfg=tarpaulin_include in the 'RUSTFLAG'
+    #[serde(rename = "cfg-tarpaulin-include")]
+    pub cfg_tarpaulin_include: bool,
+    /// Don't declare tarpaulin's cfgs with --check-cfg
+    #[serde(rename = "no-check-cfg")]
+    pub no_check_cfg: bool,
+    /// Extra cfgs set when building, code gated on `not` one of these is excluded from coverage
+    #[serde(rename = "cfg")]
+    pub cfgs: Vec<String>,
+    /// Regexes matched against the source of each line, matching lines aren't coverable
+    #[serde(rename = "ignore-line-patterns")]
+    pub ignore_line_patterns: Vec<String>,
+    /// Source files with more lines than this are skipped by source analysis
+    #[serde(rename = "analysis-max-lines")]
+    pub analysis_max_lines: Option<usize>,
+    /// Source files taking longer than this to analyse are skipped
+    #[serde(deserialize_with = "humantime_serde", rename = "analysis-timeout")]
+    pub analysis_timeout: Option<Duration>,
+    /// Keep files skipped by source analysis in coverage without their lines being filtered
+    #[serde(rename = "include-skipped-files")]
+    pub include_skipped_files: bool,
+    /// Write a json report with the error, config and environment if tarpaulin fails
+    #[serde(rename = "failure-report")]
+    pub failure_report: bool,
     /// Colouring of logging
     pub color: Color,
     /// Follow traced executables down
@@ -180,20 +306,105 @@ pub struct Config {
     #[serde(rename = "implicit-test-threads")]
     pub implicit_test_threads: bool,
     /// Engine to use to collect coverage
+    #[serde(skip_deserializing)]
     engine: RefCell<TraceEngine>,
+    /// Engine as written in a config file, resolved into `engine` and `run_type_engines` when
+    /// the file is parsed
+    #[serde(rename = "engine", skip_serializing)]
+    engine_selection: Option<EngineSelection>,
+    /// Run the tests with both ptrace and llvm and report where their coverage differs
+    #[serde(rename = "compare-engines")]
+    pub compare_engines: bool,
+    /// Name of an engine registered with `engine::register_engine` to run the tests with instead
+    /// of the selected engine
+    #[serde(rename = "custom-engine")]
+    pub custom_engine: Option<String>,
+    /// Run each test in its own process (llvm only)
+    #[serde(rename = "process-per-test")]
+    pub process_per_test: bool,
+    /// Record which lines each test hits in the test impact database in target/tarpaulin
+    #[serde(rename = "test-impact")]
+    pub test_impact: bool,
+    /// Run the test binaries in a pseudo-terminal (llvm only)
+    #[serde(rename = "allocate-pty")]
+    pub allocate_pty: bool,
+    /// Run the test binaries in a network namespace with only loopback (Linux only)
+    #[serde(rename = "isolate-network")]
+    pub isolate_network: bool,
+    /// Number of ports given to each test process in `TARPAULIN_PORT_BASE`
+    #[serde(rename = "port-block")]
+    pub port_block: Option<u16>,
+    /// Leave source files modified after the tests were built out of the reports
+    #[serde(rename = "exclude-stale-files")]
+    pub exclude_stale_files: bool,
+    /// Upload the run report stored by the last run instead of running the tests
+    #[serde(rename = "upload-only")]
+    pub upload_only: bool,
+    /// Only rerun the test binaries which failed in the run report stored by the last run
+    #[serde(rename = "rerun-failed")]
+    pub rerun_failed: bool,
+    /// Open the HTML report in the default browser once it's written
+    pub open: bool,
+    /// Print the binary sizes and collection time of each test binary in the summary
+    #[serde(rename = "show-overhead")]
+    pub show_overhead: bool,
+    /// Engines used for specific run types instead of `engine`
+    #[serde(skip_deserializing, rename = "run-type-engines")]
+    run_type_engines: BTreeMap<RunType, TraceEngine>,
     /// Specifying per-config rust flags
     pub rustflags: Option<String>,
     /// Flag to include test functions in coverage statistics
     #[serde(rename = "include-tests")]
     include_tests: bool,
+    /// Include the code in the `tests` directory which isn't a test function
+    #[serde(rename = "include-test-helpers")]
+    include_test_helpers: bool,
+    /// Include test functions and test modules in coverage statistics
+    #[serde(rename = "include-test-functions")]
+    include_test_functions: bool,
     #[serde(rename = "post-test-delay")]
     /// Delay after test to collect instrumentation files (LLVM only)
     pub post_test_delay: Option<Duration>,
     /// Other objects that should be included to get counter values from for instrumentation
     /// coverage
     objects: Vec<PathBuf>,
-    /// Joined to target/tarpaulin to store profraws
-    profraw_folder: PathBuf,
+    /// Callgrind output files to report the coverage of instead of running the tests
+    #[serde(rename = "from-callgrind")]
+    pub from_callgrind: Vec<PathBuf>,
+    /// Joined to target/tarpaulin to store profraws, unless absolute
+    #[serde(rename = "profraw-dir", alias = "profraw_folder")]
+    profraw_folder: Option<PathBuf>,
+    /// When to remove profraws from the profraw directory
+    #[serde(rename = "profraw-cleanup")]
+    profraw_cleanup: Option<ProfrawCleanup>,
+    /// Format of the run report stored in target/tarpaulin
+    #[serde(rename = "run-report-format")]
+    run_report_format: Option<RunReportFormat>,
+    /// Print what would be done instead of running the tests
+    #[serde(rename = "dry-run")]
+    pub dry_run: Option<DryRun>,
+    /// Number of event logs to keep, older ones are removed at startup
+    #[serde(rename = "keep-reports")]
+    pub keep_reports: Option<usize>,
+    /// Age in days after which event logs and stored reports are removed at startup
+    #[serde(rename = "max-report-age")]
+    pub max_report_age: Option<u64>,
+    /// Size in MiB the profraws can grow to before a warning is emitted
+    #[serde(rename = "profraw-size-limit")]
+    pub profraw_size_limit: Option<u64>,
+    /// Don't lock the target directory, allowing other runs to use it at the same time
+    #[serde(rename = "allow-concurrent")]
+    pub allow_concurrent: bool,
+    /// Scenarios to run binaries under, these come from the `[scenarios]` table of a config file
+    #[serde(skip_deserializing)]
+    pub scenarios: Vec<Scenario>,
+    /// Overrides for test binaries, these come from the `[binaries]` table of a config file
+    #[serde(skip_deserializing)]
+    pub binaries: Vec<BinaryOverride>,
+    /// Timeouts for known long running tests, these come from the `[timeouts]` table of a config
+    /// file
+    #[serde(skip_deserializing)]
+    pub test_timeouts: Vec<TestTimeout>,
     /// Option to fail immediately after a single test fails
     pub fail_immediately: bool,
     /// Log to stderr instead
@@ -210,12 +421,18 @@ impl Default for Config {
             name: String::new(),
             command: Mode::Test,
             run_types: vec![],
+            no_logical_lines: vec![],
             manifest: default_manifest(),
             config: None,
             root: Default::default(),
             run_ignored: false,
+            only_ignored: false,
             include_tests: false,
+            include_test_helpers: false,
+            include_test_functions: false,
             ignore_panics: false,
+            error_path_coverage: false,
+            ignore_const_fns: false,
             force_clean: true,
             skip_clean: false,
             no_dead_code: false,
@@ -229,23 +446,41 @@ impl Default for Config {
             count: false,
             line_coverage: true,
             branch_coverage: false,
+            branch_requested: false,
             generate: vec![],
             output_directory: Default::default(),
+            report_name: None,
+            template: None,
+            markdown_style: None,
+            html_theme: None,
+            html_css: None,
+            diff_file: None,
+            emit_build_plan: None,
             coveralls: None,
             #[cfg(feature = "coveralls")]
             ci_tool: None,
             report_uri: None,
+            proxy: None,
+            ca_cert: None,
+            github_pr: None,
+            github_token_env: None,
+            codecov: false,
+            codecov_token_env: None,
             forward_signals: true,
             no_default_features: false,
             features: None,
             unstable_features: vec![],
             all: false,
             packages: vec![],
+            packages_glob: vec![],
+            only_default_members: false,
             exclude: vec![],
             excluded_files: RefCell::new(vec![]),
             excluded_files_raw: vec![],
             included_files: RefCell::new(vec![]),
             included_files_raw: vec![],
+            focus_files: RefCell::new(vec![]),
+            focus_files_raw: vec![],
             varargs: vec![],
             test_timeout: default_test_timeout(),
             release: false,
@@ -256,6 +491,7 @@ impl Default for Config {
             implicit_test_threads: false,
             target: None,
             target_dir: None,
+            workspace_root: None,
             offline: false,
             test_names: HashSet::new(),
             example_names: HashSet::new(),
@@ -264,15 +500,51 @@ impl Default for Config {
             no_fail_fast: false,
             profile: None,
             fail_under: None,
+            fail_on_uncovered_public: None,
+            max_uncovered_lines_printed: None,
+            uncovered_diff_only: false,
             metadata: RefCell::new(None),
             avoid_cfg_tarpaulin: false,
+            cfg_tarpaulin_include: false,
+            no_check_cfg: false,
+            cfgs: vec![],
+            ignore_line_patterns: vec![],
+            analysis_max_lines: None,
+            analysis_timeout: None,
+            include_skipped_files: false,
+            failure_report: false,
             jobs: None,
             color: Color::Auto,
             engine: RefCell::default(),
+            engine_selection: None,
+            compare_engines: false,
+            custom_engine: None,
+            process_per_test: false,
+            test_impact: false,
+            allocate_pty: false,
+            isolate_network: false,
+            port_block: None,
+            exclude_stale_files: false,
+            upload_only: false,
+            rerun_failed: false,
+            open: false,
+            show_overhead: false,
+            run_type_engines: BTreeMap::new(),
             rustflags: None,
             post_test_delay: Some(Duration::from_secs(1)),
             objects: vec![],
-            profraw_folder: PathBuf::from("profraws"),
+            from_callgrind: vec![],
+            profraw_folder: None,
+            profraw_cleanup: None,
+            run_report_format: None,
+            dry_run: None,
+            profraw_size_limit: None,
+            allow_concurrent: false,
+            keep_reports: None,
+            max_report_age: None,
+            scenarios: vec![],
+            binaries: vec![],
+            test_timeouts: vec![],
             fail_immediately: false,
             stderr: false,
         }
@@ -305,15 +577,35 @@ impl From<ConfigArgs> for ConfigWrapper {
             config: None,
             root: args.root,
             engine: RefCell::new(args.engine.unwrap_or_default()),
+            engine_selection: None,
+            compare_engines: args.compare_engines,
+            custom_engine: None,
+            process_per_test: args.process_per_test,
+            test_impact: args.test_impact,
+            allocate_pty: args.allocate_pty,
+            isolate_network: args.isolate_network,
+            port_block: args.port_block,
+            exclude_stale_files: args.exclude_stale_files,
+            upload_only: args.upload_only,
+            rerun_failed: args.rerun_failed,
+            open: args.open,
+            show_overhead: args.show_overhead,
+            run_type_engines: BTreeMap::new(),
             command: args.command.unwrap_or(Mode::Test),
             verbose: args.logging.verbose || args.logging.debug,
             debug: args.logging.debug,
             dump_traces: args.logging.debug || args.logging.dump_traces,
             color: args.logging.color.unwrap_or(Color::Auto),
             run_types: args.run_types.collect(),
+            no_logical_lines: args.no_logical_lines,
             run_ignored: args.ignored,
+            only_ignored: args.only_ignored,
             include_tests: args.include_tests,
+            include_test_helpers: args.include_test_helpers,
+            include_test_functions: args.include_test_functions,
             ignore_panics: args.ignore_panics,
+            error_path_coverage: args.error_path_coverage,
+            ignore_const_fns: args.ignore_const_fns,
             no_dead_code: args.no_dead_code,
             force_clean,
             skip_clean: !force_clean,
@@ -322,12 +614,38 @@ impl From<ConfigArgs> for ConfigWrapper {
             count: args.count,
             line_coverage: args.line || !args.branch,
             branch_coverage: args.branch || !args.line,
+            branch_requested: args.branch,
             generate: args.out,
             output_directory: args.output_dir,
+            report_name: args.report_name,
+            template: args.template.map(canonicalize_path),
+            markdown_style: args.markdown_style,
+            html_theme: args.html_theme,
+            html_css: args.html_css.map(canonicalize_path),
+            diff_file: args.diff_file.map(canonicalize_path),
+            emit_build_plan: args.emit_build_plan,
             coveralls: args.coveralls,
             #[cfg(feature = "coveralls")]
             ci_tool: args.ciserver.map(|c| c.0),
             report_uri: args.report_uri,
+            proxy: args.proxy,
+            ca_cert: args.ca_cert.map(canonicalize_path),
+            #[cfg(feature = "github")]
+            github_pr: args.github_pr,
+            #[cfg(not(feature = "github"))]
+            github_pr: None,
+            #[cfg(feature = "github")]
+            github_token_env: args.github_token_env,
+            #[cfg(not(feature = "github"))]
+            github_token_env: None,
+            #[cfg(feature = "codecov")]
+            codecov: args.codecov,
+            #[cfg(not(feature = "codecov"))]
+            codecov: false,
+            #[cfg(feature = "codecov")]
+            codecov_token_env: args.codecov_token_env,
+            #[cfg(not(feature = "codecov"))]
+            codecov_token_env: None,
             forward_signals: true, // No longer an option
             all_features: args.all_features,
             no_default_features: args.no_default_features,
@@ -335,11 +653,15 @@ impl From<ConfigArgs> for ConfigWrapper {
             unstable_features: args.unstable_features,
             all: args.all | args.workspace,
             packages: args.packages,
+            packages_glob: args.packages_glob,
+            only_default_members: args.only_default_members,
             exclude: args.exclude,
             excluded_files_raw: args.exclude_files.iter().map(Pattern::to_string).collect(),
             excluded_files: RefCell::new(args.exclude_files),
             included_files_raw: args.include_files.iter().map(Pattern::to_string).collect(),
             included_files: RefCell::new(args.include_files),
+            focus_files_raw: args.focus.iter().map(Pattern::to_string).collect(),
+            focus_files: RefCell::new(args.focus),
             varargs: args.args,
             test_timeout: Duration::from_secs(args.timeout.unwrap_or(60)),
             release: args.release,
@@ -348,21 +670,44 @@ impl From<ConfigArgs> for ConfigWrapper {
             frozen: args.frozen,
             target: args.target,
             target_dir: process_target_dir(args.target_dir),
+            workspace_root: args.workspace_root.map(canonicalize_path),
             offline: args.offline,
             test_names: args.test.into_iter().collect(),
             bin_names: args.bin.into_iter().collect(),
             bench_names: args.bench.into_iter().collect(),
             example_names: args.example.into_iter().collect(),
             fail_under: args.fail_under,
+            fail_on_uncovered_public: args.fail_on_uncovered_public,
+            max_uncovered_lines_printed: args.max_uncovered_lines_printed,
+            uncovered_diff_only: args.uncovered_diff_only,
             jobs: args.jobs,
             profile: args.profile,
             metadata: RefCell::new(None),
             avoid_cfg_tarpaulin: args.avoid_cfg_tarpaulin,
+            cfg_tarpaulin_include: args.cfg_tarpaulin_include,
+            no_check_cfg: args.no_check_cfg,
+            cfgs: args.cfg,
+            ignore_line_patterns: args.ignore_line_patterns,
+            analysis_max_lines: args.analysis_max_lines,
+            analysis_timeout: args.analysis_timeout.map(Duration::from_secs),
+            include_skipped_files: args.include_skipped_files,
+            failure_report: args.failure_report,
             implicit_test_threads: args.implicit_test_threads,
             rustflags: args.rustflags,
             post_test_delay: args.post_test_delay.map(Duration::from_secs),
             objects: canonicalize_paths(args.objects),
-            profraw_folder: PathBuf::from("profraws"),
+            from_callgrind: canonicalize_paths(args.from_callgrind),
+            profraw_folder: args.profraw_dir,
+            profraw_cleanup: args.profraw_cleanup,
+            run_report_format: args.run_report_format,
+            dry_run: args.dry_run,
+            profraw_size_limit: args.profraw_size_limit,
+            allow_concurrent: args.allow_concurrent,
+            keep_reports: args.keep_reports,
+            max_report_age: args.max_report_age,
+            scenarios: vec![],
+            binaries: vec![],
+            test_timeouts: vec![],
             fail_immediately: args.fail_immediately,
             stderr: args.logging.stderr,
         };
@@ -394,6 +739,7 @@ impl Config {
         let engine = *self.engine.borrow();
         match engine {
             TraceEngine::Auto | TraceEngine::Llvm if supports_llvm_coverage() => TraceEngine::Llvm,
+            TraceEngine::Perf => TraceEngine::Perf,
             engine => {
                 if engine == TraceEngine::Llvm {
                     error!("unable to utilise llvm coverage, due to compiler support. Falling back to Ptrace");
@@ -408,6 +754,39 @@ impl Config {
         self.engine.replace(engine);
     }
 
+    /// The engine selected for the given run type, falling back to `Config::engine` if it
+    /// doesn't have one of its own
+    pub fn run_type_engine(&self, run_type: RunType) -> TraceEngine {
+        match self.run_type_engines.get(&run_type) {
+            Some(TraceEngine::Auto | TraceEngine::Llvm) if supports_llvm_coverage() => {
+                TraceEngine::Llvm
+            }
+            Some(TraceEngine::Perf) => TraceEngine::Perf,
+            Some(_) => TraceEngine::Ptrace,
+            None => self.engine(),
+        }
+    }
+
+    /// A copy of the config which runs every test binary with the given engine
+    pub fn with_engine(&self, engine: TraceEngine) -> Config {
+        let mut config = self.clone();
+        config.set_engine(engine);
+        config.run_type_engines.clear();
+        config.custom_engine = None;
+        config
+    }
+
+    /// Whether any of the tests will run with the llvm engine, in which case the project needs
+    /// to be built with llvm coverage instrumentation
+    pub fn uses_llvm(&self) -> bool {
+        self.compare_engines
+            || self.engine() == TraceEngine::Llvm
+            || self
+                .run_type_engines
+                .keys()
+                .any(|x| self.run_type_engine(*x) == TraceEngine::Llvm)
+    }
+
     pub fn set_clean(&mut self, clean: bool) {
         self.force_clean = clean;
         self.skip_clean = !clean;
@@ -421,6 +800,11 @@ impl Config {
         self.include_tests
     }
 
+    /// Whether the code in the `tests` directory is included
+    pub fn include_test_helpers(&self) -> bool {
+        self.include_tests || self.include_test_helpers
+    }
+
     pub fn force_clean(&self) -> bool {
         // default is force clean true skip clean false. So if one isn't default we pick that one
         // as precedence.
@@ -434,8 +818,9 @@ impl Config {
             match *self.get_metadata() {
                 Some(ref meta) => PathBuf::from(meta.target_directory.clone()),
                 _ => self
-                    .manifest
-                    .parent()
+                    .workspace_root
+                    .as_deref()
+                    .or_else(|| self.manifest.parent())
                     .map(fix_unc_path)
                     .unwrap_or_default()
                     .join("target"),
@@ -444,21 +829,49 @@ impl Config {
         fix_unc_path(&res)
     }
 
-    /// Get directory profraws are stored in
+    /// Directory for the files generated during this run, `target/tarpaulin/runs/<id>`
+    pub fn run_dir(&self) -> PathBuf {
+        self.target_dir()
+            .join("tarpaulin")
+            .join("runs")
+            .join(RUN_ID.as_str())
+    }
+
+    /// Get directory profraws are stored in. A profraw directory given by the user is namespaced
+    /// by the run when concurrent runs are allowed.
     pub fn profraw_dir(&self) -> PathBuf {
-        if self.profraw_folder.is_relative() {
-            self.target_dir()
-                .join("tarpaulin")
-                .join(&self.profraw_folder)
+        let dir = match &self.profraw_folder {
+            Some(folder) if folder.is_absolute() => folder.clone(),
+            Some(folder) => self.target_dir().join("tarpaulin").join(folder),
+            None => return self.run_dir().join("profraws"),
+        };
+        if self.allow_concurrent {
+            dir.join(RUN_ID.as_str())
         } else {
-            self.profraw_folder.clone()
+            dir
         }
     }
 
     /// If a relative directory is joined to `$TARGET_DIR/tarpaulin/` otherwise is placed at
     /// absolute directory location
     pub fn set_profraw_folder(&mut self, path: PathBuf) {
-        self.profraw_folder = path;
+        self.profraw_folder = Some(path);
+    }
+
+    pub fn profraw_cleanup(&self) -> ProfrawCleanup {
+        self.profraw_cleanup.unwrap_or_default()
+    }
+
+    pub fn run_report_format(&self) -> RunReportFormat {
+        self.run_report_format.unwrap_or_default()
+    }
+
+    pub fn markdown_style(&self) -> MarkdownStyle {
+        self.markdown_style.unwrap_or_default()
+    }
+
+    pub fn html_theme(&self) -> HtmlTheme {
+        self.html_theme.unwrap_or_default()
     }
 
     /// Sets the target dir explicitly
@@ -473,26 +886,134 @@ impl Config {
         result
     }
 
-    pub(crate) fn get_metadata(&self) -> Ref<Option<Metadata>> {
+    /// Flags controlling whether cargo can access the network or update `Cargo.lock`
+    pub(crate) fn cargo_network_flags(&self) -> Vec<String> {
+        let mut flags = vec![];
+        if self.offline {
+            flags.push("--offline".to_string());
+        }
+        if self.locked {
+            flags.push("--locked".to_string());
+        }
+        if self.frozen {
+            flags.push("--frozen".to_string());
+        }
+        flags
+    }
+
+    fn run_metadata(&self, flags: Vec<String>) -> cargo_metadata::Result<Metadata> {
+        MetadataCommand::new()
+            .manifest_path(&self.manifest)
+            .no_deps()
+            .other_options(flags)
+            .exec()
+    }
+
+    /// Workspace metadata, this is ran with `--no-deps` so it never resolves dependencies or
+    /// touches the network or lock file. If that fails, for example from a `patch` section cargo
+    /// can't load, it's retried offline without `--locked` or `--frozen`. If cargo still can't
+    /// provide it the metadata cached by a previous run is used instead so report only runs can
+    /// work without cargo.
+    pub(crate) fn get_metadata(&self) -> Ref<'_, Option<Metadata>> {
         if self.metadata.borrow().is_none() {
-            match MetadataCommand::new().manifest_path(&self.manifest).exec() {
+            let offline = vec!["--offline".to_string()];
+            let meta = self.run_metadata(self.cargo_network_flags()).or_else(|e| {
+                if self.cargo_network_flags() == offline {
+                    return Err(e);
+                }
+                debug!("cargo metadata failed ({}), retrying offline", e);
+                self.run_metadata(offline)
+            });
+            match meta {
                 Ok(meta) => {
+                    self.cache_metadata(&meta);
                     self.metadata.replace(Some(meta));
                 }
-                Err(e) => warn!("Couldn't get project metadata {}", e),
+                Err(e) => match self.cached_metadata() {
+                    Some(meta) => {
+                        info!(
+                            "Couldn't get project metadata ({}), using cached metadata",
+                            e
+                        );
+                        self.metadata.replace(Some(meta));
+                    }
+                    None => {
+                        let root = self.fallback_root();
+                        warn!(
+                            "Couldn't get project metadata: {}. Paths are resolved from the workspace root {}, if this is wrong set it with --workspace-root",
+                            e,
+                            root.display()
+                        );
+                    }
+                },
             }
         }
         self.metadata.borrow()
     }
 
-    pub fn root(&self) -> PathBuf {
-        let res = match *self.get_metadata() {
-            Some(ref meta) => PathBuf::from(meta.workspace_root.clone()),
-            _ => self
-                .manifest
+    /// Target directory to use for the metadata cache without needing to run cargo
+    fn metadata_cache_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![];
+        if let Some(dir) = self.target_dir.as_ref() {
+            dirs.push(dir.clone());
+        }
+        if let Some(dir) = env::var_os("CARGO_TARGET_DIR") {
+            dirs.push(PathBuf::from(dir));
+        }
+        if let Some(dir) = self.workspace_root.as_ref() {
+            dirs.push(dir.join("target"));
+        }
+        if let Some(dir) = self.manifest.parent() {
+            dirs.push(dir.join("target"));
+        }
+        dirs.into_iter()
+            .map(|dir| dir.join("tarpaulin").join(METADATA_CACHE))
+            .collect()
+    }
+
+    fn cache_metadata(&self, meta: &Metadata) {
+        let path = meta
+            .target_directory
+            .as_std_path()
+            .join("tarpaulin")
+            .join(METADATA_CACHE);
+        let written = path
+            .parent()
+            .map(fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| fs::File::create(&path))
+            .and_then(|file| serde_json::to_writer(file, meta).map_err(Error::from));
+        if let Err(e) = written {
+            debug!("Unable to cache project metadata: {}", e);
+        }
+    }
+
+    fn cached_metadata(&self) -> Option<Metadata> {
+        self.metadata_cache_dirs().iter().find_map(|path| {
+            let file = fs::File::open(path).ok()?;
+            serde_json::from_reader(io::BufReader::new(file)).ok()
+        })
+    }
+
+    /// Workspace root used when there's no metadata
+    fn fallback_root(&self) -> PathBuf {
+        match self.workspace_root.as_ref() {
+            Some(root) => root.clone(),
+            None => find_workspace_root(&self.manifest)
                 .parent()
                 .map(Path::to_path_buf)
                 .unwrap_or_default(),
+        }
+    }
+
+    pub fn root(&self) -> PathBuf {
+        let res = if let Some(root) = &self.workspace_root {
+            root.clone()
+        } else {
+            match *self.get_metadata() {
+                Some(ref meta) => PathBuf::from(meta.workspace_root.clone()),
+                _ => self.fallback_root(),
+            }
         };
         fix_unc_path(&res)
     }
@@ -518,6 +1039,16 @@ impl Config {
         fix_unc_path(&path)
     }
 
+    pub fn emit_build_plan(&self) -> Option<PathBuf> {
+        self.emit_build_plan.as_ref().map(|path| {
+            if path.is_relative() {
+                fix_unc_path(&self.root().join(path))
+            } else {
+                fix_unc_path(path)
+            }
+        })
+    }
+
     pub fn get_config_vec(file_configs: std::io::Result<Vec<Self>>, backup: Self) -> ConfigWrapper {
         if let Ok(mut confs) = file_configs {
             for c in &mut confs {
@@ -581,20 +1112,91 @@ impl Config {
                 if let Some(root) = c.target_dir.as_mut() {
                     *root = make_absolute_with_parent(&root, &parent);
                 }
+                if let Some(root) = c.workspace_root.as_mut() {
+                    *root = make_absolute_with_parent(&root, &parent);
+                }
+                if let Some(diff) = c.diff_file.as_mut() {
+                    *diff = make_absolute_with_parent(&diff, &parent);
+                }
+                if let Some(cert) = c.ca_cert.as_mut() {
+                    *cert = make_absolute_with_parent(&cert, &parent);
+                }
+                if let Some(template) = c.template.as_mut() {
+                    *template = make_absolute_with_parent(&template, &parent);
+                }
+                if let Some(css) = c.html_css.as_mut() {
+                    *css = make_absolute_with_parent(&css, &parent);
+                }
+                if let Some(plan) = c.emit_build_plan.as_mut() {
+                    *plan = make_absolute_with_parent(&plan, &parent);
+                }
+                for profile in c.from_callgrind.iter_mut() {
+                    *profile = make_absolute_with_parent(&profile, &parent);
+                }
             }
         }
         res
     }
 
     pub fn parse_config_toml(buffer: &str) -> std::io::Result<Vec<Self>> {
-        let mut map: IndexMap<String, Self> = toml::from_str(buffer).map_err(|e| {
+        /// The scenarios and binaries tables are shared by every config in the file
+        #[derive(Deserialize)]
+        struct Shared {
+            #[serde(default)]
+            scenarios: IndexMap<String, Scenario>,
+            #[serde(default)]
+            binaries: IndexMap<String, BinaryOverride>,
+            #[serde(default)]
+            timeouts: IndexMap<String, humantime_serde::Serde<Duration>>,
+        }
+
+        let invalid = |e: toml::de::Error| {
             error!("Invalid config file {}", e);
             Error::new(ErrorKind::InvalidData, format!("{e}"))
-        })?;
+        };
+        let shared = toml::from_str::<Shared>(buffer).map_err(invalid)?;
+        let scenarios = shared
+            .scenarios
+            .into_iter()
+            .map(|(name, mut scenario)| {
+                scenario.name = name;
+                scenario
+            })
+            .collect::<Vec<_>>();
+        let mut binaries = vec![];
+        for (pattern, mut binary) in shared.binaries {
+            if let Err(e) = Pattern::new(&pattern) {
+                error!("Invalid binaries pattern {}: {}", pattern, e);
+                return Err(Error::new(ErrorKind::InvalidData, format!("{e}")));
+            }
+            binary.pattern = pattern;
+            binaries.push(binary);
+        }
+        let test_timeouts = shared
+            .timeouts
+            .into_iter()
+            .map(|(test, timeout)| TestTimeout {
+                test,
+                timeout: timeout.into_inner(),
+            })
+            .collect::<Vec<_>>();
+        let mut map: IndexMap<String, Self> = toml::from_str(buffer).map_err(invalid)?;
+        map.shift_remove("scenarios");
+        map.shift_remove("binaries");
+        map.shift_remove("timeouts");
 
         let mut result = Vec::new();
         for (name, conf) in map.iter_mut() {
             conf.name = name.to_string();
+            conf.branch_requested = conf.branch_coverage;
+            match conf.engine_selection.take() {
+                Some(EngineSelection::Single(engine)) => conf.set_engine(engine),
+                Some(EngineSelection::PerRunType(engines)) => conf.run_type_engines = engines,
+                None => {}
+            }
+            conf.scenarios = scenarios.clone();
+            conf.binaries = binaries.clone();
+            conf.test_timeouts = test_timeouts.clone();
             result.push(conf.clone());
         }
         if result.is_empty() {
@@ -616,16 +1218,39 @@ impl Config {
         self.no_run |= other.no_run;
         self.no_default_features |= other.no_default_features;
         self.ignore_panics |= other.ignore_panics;
+        self.error_path_coverage |= other.error_path_coverage;
+        self.ignore_const_fns |= other.ignore_const_fns;
         // Since true is the default
         self.forward_signals |= other.forward_signals;
         self.run_ignored |= other.run_ignored;
+        self.only_ignored |= other.only_ignored;
         self.release |= other.release;
         self.no_dead_code |= other.no_dead_code;
+        self.no_check_cfg |= other.no_check_cfg;
+        self.avoid_cfg_tarpaulin |= other.avoid_cfg_tarpaulin;
+        self.cfg_tarpaulin_include |= other.cfg_tarpaulin_include;
+        self.failure_report |= other.failure_report;
         self.count |= other.count;
         self.all_features |= other.all_features;
         self.implicit_test_threads |= other.implicit_test_threads;
+        self.compare_engines |= other.compare_engines;
+        self.process_per_test |= other.process_per_test;
+        self.test_impact |= other.test_impact;
+        self.allocate_pty |= other.allocate_pty;
+        self.isolate_network |= other.isolate_network;
+        self.port_block = Config::pick_optional_config(&self.port_block, &other.port_block);
+        self.exclude_stale_files |= other.exclude_stale_files;
+        self.upload_only |= other.upload_only;
+        self.rerun_failed |= other.rerun_failed;
+        self.include_skipped_files |= other.include_skipped_files;
+        self.only_default_members |= other.only_default_members;
+        self.uncovered_diff_only |= other.uncovered_diff_only;
+        self.open |= other.open;
+        self.show_overhead |= other.show_overhead;
+        self.allow_concurrent |= other.allow_concurrent;
         self.line_coverage |= other.line_coverage;
         self.branch_coverage |= other.branch_coverage;
+        self.branch_requested |= other.branch_requested;
         self.dump_traces |= other.dump_traces;
         self.offline |= other.offline;
         self.stderr |= other.stderr;
@@ -637,6 +1262,11 @@ impl Config {
                 self.objects.push(obj.clone());
             }
         }
+        for profile in &other.from_callgrind {
+            if !self.from_callgrind.contains(profile) {
+                self.from_callgrind.push(profile.clone());
+            }
+        }
         self.root = Config::pick_optional_config(&self.root, &other.root);
         self.coveralls = Config::pick_optional_config(&self.coveralls, &other.coveralls);
 
@@ -647,10 +1277,73 @@ impl Config {
         }
 
         self.report_uri = Config::pick_optional_config(&self.report_uri, &other.report_uri);
+        self.proxy = Config::pick_optional_config(&self.proxy, &other.proxy);
+        self.ca_cert = Config::pick_optional_config(&self.ca_cert, &other.ca_cert);
+        self.github_pr = Config::pick_optional_config(&self.github_pr, &other.github_pr);
+        self.github_token_env =
+            Config::pick_optional_config(&self.github_token_env, &other.github_token_env);
+        self.codecov |= other.codecov;
+        self.codecov_token_env =
+            Config::pick_optional_config(&self.codecov_token_env, &other.codecov_token_env);
         self.target = Config::pick_optional_config(&self.target, &other.target);
         self.target_dir = Config::pick_optional_config(&self.target_dir, &other.target_dir);
+        self.workspace_root =
+            Config::pick_optional_config(&self.workspace_root, &other.workspace_root);
         self.output_directory =
             Config::pick_optional_config(&self.output_directory, &other.output_directory);
+        self.report_name = Config::pick_optional_config(&self.report_name, &other.report_name);
+        self.template = Config::pick_optional_config(&self.template, &other.template);
+        self.markdown_style =
+            Config::pick_optional_config(&self.markdown_style, &other.markdown_style);
+        self.html_theme = Config::pick_optional_config(&self.html_theme, &other.html_theme);
+        self.html_css = Config::pick_optional_config(&self.html_css, &other.html_css);
+        self.analysis_max_lines =
+            Config::pick_optional_config(&self.analysis_max_lines, &other.analysis_max_lines);
+        self.analysis_timeout =
+            Config::pick_optional_config(&self.analysis_timeout, &other.analysis_timeout);
+        self.diff_file = Config::pick_optional_config(&self.diff_file, &other.diff_file);
+        self.custom_engine =
+            Config::pick_optional_config(&self.custom_engine, &other.custom_engine);
+        self.emit_build_plan =
+            Config::pick_optional_config(&self.emit_build_plan, &other.emit_build_plan);
+        self.profraw_folder =
+            Config::pick_optional_config(&self.profraw_folder, &other.profraw_folder);
+        self.profraw_cleanup =
+            Config::pick_optional_config(&self.profraw_cleanup, &other.profraw_cleanup);
+        self.run_report_format =
+            Config::pick_optional_config(&self.run_report_format, &other.run_report_format);
+        self.dry_run = Config::pick_optional_config(&self.dry_run, &other.dry_run);
+        self.profraw_size_limit =
+            Config::pick_optional_config(&self.profraw_size_limit, &other.profraw_size_limit);
+        self.keep_reports = Config::pick_optional_config(&self.keep_reports, &other.keep_reports);
+        self.max_report_age =
+            Config::pick_optional_config(&self.max_report_age, &other.max_report_age);
+        self.fail_on_uncovered_public = Config::pick_optional_config(
+            &self.fail_on_uncovered_public,
+            &other.fail_on_uncovered_public,
+        );
+        self.max_uncovered_lines_printed = Config::pick_optional_config(
+            &self.max_uncovered_lines_printed,
+            &other.max_uncovered_lines_printed,
+        );
+        for scenario in &other.scenarios {
+            if !self.scenarios.iter().any(|x| x.name == scenario.name) {
+                self.scenarios.push(scenario.clone());
+            }
+        }
+        if self.run_type_engines.is_empty() {
+            self.run_type_engines = other.run_type_engines.clone();
+        }
+        for binary in &other.binaries {
+            if !self.binaries.iter().any(|x| x.pattern == binary.pattern) {
+                self.binaries.push(binary.clone());
+            }
+        }
+        for timeout in &other.test_timeouts {
+            if !self.test_timeouts.iter().any(|x| x.test == timeout.test) {
+                self.test_timeouts.push(timeout.clone());
+            }
+        }
         self.all |= other.all;
         self.frozen |= other.frozen;
         self.locked |= other.locked;
@@ -659,6 +1352,8 @@ impl Config {
         self.force_clean &= other.force_clean;
         self.skip_clean |= other.skip_clean;
         self.include_tests |= other.include_tests;
+        self.include_test_helpers |= other.include_test_helpers;
+        self.include_test_functions |= other.include_test_functions;
         self.no_fail_fast |= other.no_fail_fast;
 
         let end_delay = match (self.post_test_delay, other.post_test_delay) {
@@ -702,12 +1397,12 @@ impl Config {
         if self.profile.is_none() && other.profile.is_some() {
             self.profile = other.profile.clone();
         }
-        if other.features.is_some() {
-            if self.features.is_none() {
-                self.features = other.features.clone();
-            } else if let Some(features) = self.features.as_mut() {
+        if let Some(other_features) = other.features.as_ref() {
+            if let Some(features) = self.features.as_mut() {
                 features.push(' ');
-                features.push_str(other.features.as_ref().unwrap());
+                features.push_str(other_features);
+            } else {
+                self.features = Some(other_features.clone());
             }
         }
 
@@ -718,6 +1413,11 @@ impl Config {
             .cloned()
             .collect::<Vec<String>>();
         self.packages.extend(additional_packages);
+        for glob in &other.packages_glob {
+            if !self.packages_glob.contains(glob) {
+                self.packages_glob.push(glob.clone());
+            }
+        }
 
         let additional_outs = other
             .generate
@@ -777,6 +1477,21 @@ impl Config {
                 self.run_types.push(*ty);
             }
         }
+        for kind in &other.no_logical_lines {
+            if !self.no_logical_lines.contains(kind) {
+                self.no_logical_lines.push(*kind);
+            }
+        }
+        for cfg in &other.cfgs {
+            if !self.cfgs.contains(cfg) {
+                self.cfgs.push(cfg.clone());
+            }
+        }
+        for pattern in &other.ignore_line_patterns {
+            if !self.ignore_line_patterns.contains(pattern) {
+                self.ignore_line_patterns.push(pattern.clone());
+            }
+        }
 
         if !other.excluded_files_raw.is_empty() {
             self.excluded_files_raw
@@ -795,6 +1510,15 @@ impl Config {
             let mut included_files = self.included_files.borrow_mut();
             included_files.clear();
         }
+
+        if !other.focus_files_raw.is_empty() {
+            self.focus_files_raw
+                .extend_from_slice(&other.focus_files_raw);
+
+            // Now invalidated the compiled regex cache so clear it
+            let mut focus_files = self.focus_files.borrow_mut();
+            focus_files.clear();
+        }
     }
 
     pub fn pick_optional_config<T: Clone>(
@@ -824,6 +1548,182 @@ impl Config {
         self.coveralls.is_some()
     }
 
+    /// The longest of the `[timeouts]` entries matching any of the tests in the target, `None` if
+    /// none of them match
+    pub fn test_timeout_for(&self, target: &str, tests: &[String]) -> Option<Duration> {
+        self.test_timeouts
+            .iter()
+            .filter(|x| tests.iter().any(|test| x.matches(target, test)))
+            .map(|x| x.timeout)
+            .max()
+    }
+
+    /// Combines the `[binaries]` overrides matching the target name, when several match the
+    /// later tables take priority
+    pub fn binary_override(&self, target: &str) -> Option<BinaryOverride> {
+        self.binaries.iter().filter(|x| x.matches(target)).fold(
+            None,
+            |acc: Option<BinaryOverride>, x| {
+                let mut acc = acc.unwrap_or_default();
+                acc.merge(x);
+                Some(acc)
+            },
+        )
+    }
+
+    /// The fork based test harness the package depends on, if any
+    fn fork_harness(&self, package: &str) -> Option<String> {
+        let meta = self.get_metadata();
+        let package = meta
+            .as_ref()?
+            .workspace_packages()
+            .into_iter()
+            .find(|x| x.name == package)?;
+        package
+            .dependencies
+            .iter()
+            .find(|x| FORK_HARNESSES.contains(&x.name.as_str()))
+            .map(|x| x.name.clone())
+    }
+
+    /// The config to run a test binary with, applying the engine for its run type and the
+    /// timeout and engine from any matching `[binaries]` overrides. With ptrace, execs are
+    /// followed for packages using a fork based test harness.
+    pub fn for_binary(&self, test: &TestBinary) -> Cow<'_, Config> {
+        let run_type = test.run_type().unwrap_or(RunType::Tests);
+        let mut engine = self.run_type_engine(run_type);
+        let target = test.target_name();
+        let binary = self.binary_override(&target).unwrap_or_default();
+        match binary.engine {
+            Some(TraceEngine::Llvm) if !self.uses_llvm() => {
+                warn!(
+                    "{} can't use the llvm engine as the project wasn't built with it",
+                    target
+                );
+            }
+            Some(e) => engine = e,
+            None => {}
+        }
+        let harness = match test.pkg_name() {
+            Some(package) if !self.follow_exec && engine == TraceEngine::Ptrace => {
+                self.fork_harness(package)
+            }
+            _ => None,
+        };
+        if binary.timeout.is_none() && engine == self.engine() && harness.is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut config = self.clone();
+        if let Some(timeout) = binary.timeout {
+            config.test_timeout = timeout;
+        }
+        if let Some(harness) = harness {
+            info!("Following execs for {} as it uses {}", target, harness);
+            config.follow_exec = true;
+        }
+        config.set_engine(engine);
+        Cow::Owned(config)
+    }
+
+    /// Describes every setting which changes the coverage collected from running a test binary,
+    /// two configs with the same settings get the same coverage from the same binary
+    pub(crate) fn run_settings(&self, target: &str) -> String {
+        format!(
+            "{:?} {:?} {:?} {} {} {} {} {} {} {} {} {:?} {:?} {:?} {:?} {:?} {:?}",
+            self.engine(),
+            self.custom_engine,
+            self.varargs,
+            self.process_per_test,
+            self.test_impact,
+            self.allocate_pty,
+            self.isolate_network,
+            self.follow_exec,
+            self.forward_signals,
+            self.branch_coverage,
+            self.count,
+            self.root(),
+            self.excluded_files_raw,
+            self.included_files_raw,
+            self.focus_files_raw,
+            self.analysis_config(),
+            self.binary_override(target),
+        )
+    }
+
+    /// The subset of the config used by source analysis
+    pub fn analysis_config(&self) -> AnalysisConfig {
+        AnalysisConfig {
+            include_tests: self.include_tests(),
+            include_test_helpers: self.include_test_helpers,
+            include_test_functions: self.include_test_functions,
+            run_ignored: self.run_ignored || self.only_ignored,
+            include_examples: self.run_types.contains(&RunType::Examples),
+            ignore_panics: self.ignore_panics,
+            ignore_const_fns: self.ignore_const_fns,
+            no_logical_lines: self.no_logical_lines.clone(),
+            avoid_cfg_tarpaulin: self.avoid_cfg_tarpaulin,
+            coverage_cfgs: self.coverage_cfgs(),
+            ignore_line_patterns: self.line_patterns(),
+            max_file_lines: self.analysis_max_lines,
+            analysis_timeout: self.analysis_timeout,
+            include_skipped_files: self.include_skipped_files,
+        }
+    }
+
+    /// The cfgs set when building other than `tarpaulin`, which is controlled separately by
+    /// `avoid_cfg_tarpaulin`. These are `tarpaulin_include` when it's enabled and the user's cfgs.
+    pub fn coverage_cfgs(&self) -> Vec<String> {
+        let include = self
+            .cfg_tarpaulin_include
+            .then(|| "tarpaulin_include".to_string());
+        include
+            .into_iter()
+            .chain(
+                self.cfgs
+                    .iter()
+                    .filter(|x| *x != "tarpaulin_include")
+                    .cloned(),
+            )
+            .collect()
+    }
+
+    /// Checks the settings which can't be checked when parsing the args or config file
+    pub fn validate(&self) -> Result<(), RunError> {
+        if let Some(cfg) = self.cfgs.iter().find(|x| !is_valid_cfg(x)) {
+            return Err(RunError::Config(format!(
+                "--cfg {cfg} isn't a cfg name or key=\"value\" pair"
+            )));
+        }
+        for pattern in &self.ignore_line_patterns {
+            if let Err(e) = LinePattern::new(pattern) {
+                return Err(RunError::Config(format!(
+                    "Invalid ignore-line-pattern '{pattern}': {e}"
+                )));
+            }
+        }
+        if self.generate.contains(&OutputFile::Template) {
+            match &self.template {
+                Some(template) => {
+                    crate::report::template::load_template(template)?;
+                }
+                None => {
+                    return Err(RunError::Config(
+                        "`--out Template` needs a template given with --template".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The compiled `ignore_line_patterns`, invalid patterns are rejected by `validate`
+    fn line_patterns(&self) -> Vec<LinePattern> {
+        self.ignore_line_patterns
+            .iter()
+            .filter_map(|pattern| LinePattern::new(pattern).ok())
+            .collect()
+    }
+
     #[inline]
     pub fn exclude_path(&self, path: &Path) -> bool {
         if self.excluded_files.borrow().len() != self.excluded_files_raw.len() {
@@ -842,6 +1742,9 @@ impl Config {
 
     #[inline]
     pub fn include_path(&self, path: &Path) -> bool {
+        if !self.focus_path(path) {
+            return false;
+        }
         if self.included_files.borrow().len() != self.included_files_raw.len() {
             let mut included_files = self.included_files.borrow_mut();
             let mut compiled = globs_from_excluded(&self.included_files_raw);
@@ -862,6 +1765,26 @@ impl Config {
             .any(|x| x.matches_path(&project))
     }
 
+    /// Whether the file is in focus, files outside it are skipped by source analysis and never
+    /// have coverage mapped to them. Everything is in focus if `--focus` isn't given
+    pub fn focus_path(&self, path: &Path) -> bool {
+        if self.focus_files_raw.is_empty() {
+            return true;
+        }
+        if self.focus_files.borrow().len() != self.focus_files_raw.len() {
+            let mut focus_files = self.focus_files.borrow_mut();
+            let mut compiled = globs_from_excluded(&self.focus_files_raw);
+            focus_files.clear();
+            focus_files.append(&mut compiled);
+        }
+        let project = self.strip_base_dir(path);
+
+        self.focus_files
+            .borrow()
+            .iter()
+            .any(|x| x.matches_path(&project))
+    }
+
     /// returns the relative path from the base_dir
     /// uses root if set, else env::current_dir()
     #[inline]
@@ -892,6 +1815,36 @@ impl Config {
     }
 }
 
+/// Whether the cfg is a name or a `key="value"` pair. Values can't contain whitespace as the cfgs
+/// are passed in the space separated RUSTFLAGS.
+fn is_valid_cfg(cfg: &str) -> bool {
+    let is_ident = |x: &str| {
+        let mut chars = x.chars();
+        x != "_"
+            && chars.next().is_some_and(|c| c == '_' || c.is_alphabetic())
+            && chars.all(|c| c == '_' || c.is_alphanumeric())
+    };
+    match split_cfg(cfg) {
+        (key, Some(value)) => {
+            is_ident(key)
+                && value.len() >= 2
+                && value.starts_with('"')
+                && value.ends_with('"')
+                && !value[1..value.len() - 1]
+                    .contains(|c: char| c == '"' || c == '\\' || c.is_whitespace())
+        }
+        (name, None) => is_ident(name),
+    }
+}
+
+/// Splits a cfg into its name and quoted value if it's a `key="value"` pair
+pub(crate) fn split_cfg(cfg: &str) -> (&str, Option<&str>) {
+    match cfg.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (cfg, None),
+    }
+}
+
 fn make_absolute_with_parent(path: impl AsRef<Path>, parent: impl AsRef<Path>) -> PathBuf {
     let path = path.as_ref();
     if path.is_relative() {
@@ -961,6 +1914,22 @@ mod tests {
         assert!(conf[0].root().is_absolute());
     }
 
+    #[test]
+    fn branch_requested_explicitly() {
+        let args = TarpaulinCli::parse_from(vec!["tarpaulin"]);
+        let conf = ConfigWrapper::from(args.config).0;
+        assert!(conf[0].branch_coverage);
+        assert!(!conf[0].branch_requested);
+
+        let args = TarpaulinCli::parse_from(vec!["tarpaulin", "--branch"]);
+        let conf = ConfigWrapper::from(args.config).0;
+        assert!(conf[0].branch_requested);
+
+        let conf = Config::parse_config_toml("[a]\nbranch = true\n[b]\n").unwrap();
+        assert!(conf[0].branch_requested);
+        assert!(!conf[1].branch_requested);
+    }
+
     #[test]
     fn features_args() {
         let args = TarpaulinCli::parse_from(vec![
@@ -1041,6 +2010,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn focus_paths() {
+        let args = TarpaulinCli::parse_from(vec!["tarpaulin", "--focus", "src/engine/**"]);
+        let conf = ConfigWrapper::from(args.config).0;
+        assert_eq!(conf.len(), 1);
+        assert!(conf[0].focus_path(Path::new("src/engine/mod.rs")));
+        assert!(conf[0].focus_path(Path::new("src/engine/parser/lexer.rs")));
+        assert!(!conf[0].focus_path(Path::new("src/lib.rs")));
+        assert!(!conf[0].include_path(Path::new("src/lib.rs")));
+        assert!(conf[0].include_path(Path::new("src/engine/mod.rs")));
+
+        let toml = r#"focus = ["src/lib.rs"]"#;
+        let conf: Config = toml::from_str(toml).unwrap();
+        assert!(conf.focus_path(Path::new("src/lib.rs")));
+        assert!(!conf.focus_path(Path::new("src/main.rs")));
+    }
+
     #[test]
     fn no_exclusions() {
         let args = TarpaulinCli::parse_from(vec!["tarpaulin"]);
@@ -1159,6 +2145,84 @@ mod tests {
         assert_eq!(configs[0].excluded_files_raw.len(), 1);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn profraw_dir_options() {
+        let toml = r#"[absolute]
+        profraw-dir = "/dev/shm/profraws"
+        profraw-cleanup = "Always"
+        profraw-size-limit = 512
+        [relative]
+        profraw_folder = "my_profraws"
+        "#;
+
+        let mut configs = Config::parse_config_toml(toml).unwrap();
+        let absolute = configs.remove(0);
+        assert_eq!(absolute.profraw_dir(), Path::new("/dev/shm/profraws"));
+        assert_eq!(absolute.profraw_cleanup(), ProfrawCleanup::Always);
+        assert_eq!(absolute.profraw_size_limit, Some(512));
+
+        let mut relative = configs.remove(0);
+        assert_eq!(
+            relative.profraw_dir(),
+            relative.target_dir().join("tarpaulin").join("my_profraws")
+        );
+        assert_eq!(relative.profraw_cleanup(), ProfrawCleanup::Start);
+
+        relative.merge(&absolute);
+        assert_eq!(relative.profraw_dir(), Path::new("/dev/shm/profraws"));
+        assert_eq!(relative.profraw_cleanup(), ProfrawCleanup::Always);
+
+        let default = Config::default();
+        assert_eq!(default.profraw_dir(), default.run_dir().join("profraws"));
+
+        relative.allow_concurrent = true;
+        assert!(relative
+            .profraw_dir()
+            .starts_with(Path::new("/dev/shm/profraws")));
+        assert_ne!(relative.profraw_dir(), Path::new("/dev/shm/profraws"));
+    }
+
+    #[test]
+    fn metadata_falls_back_to_cache() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/simple_project");
+        let mut config = Config::default();
+        config.set_manifest(project.join("Cargo.toml"));
+        let target = config.target_dir();
+        assert!(config.get_metadata().is_some());
+
+        let cache = target.join("tarpaulin").join(METADATA_CACHE);
+        assert!(cache.exists());
+
+        // No manifest so cargo metadata will fail
+        let missing = env::temp_dir().join("tarpaulin_metadata_cache");
+        let mut offline = Config::default();
+        offline.set_manifest(missing.join("Cargo.toml"));
+        offline.set_target_dir(target);
+        assert!(offline.get_metadata().is_some());
+        assert_eq!(offline.root(), config.root());
+    }
+
+    #[test]
+    fn workspace_root_without_metadata() {
+        let root = env::temp_dir().join("tarpaulin_workspace_root");
+        let mut config = Config::default();
+        config.set_manifest(root.join("missing/Cargo.toml"));
+        config.workspace_root = Some(root.clone());
+        assert!(config.get_metadata().is_none());
+        assert_eq!(config.root(), root);
+        assert_eq!(config.target_dir(), root.join("target"));
+    }
+
+    #[test]
+    fn network_flags() {
+        let mut config = Config::default();
+        assert!(config.cargo_network_flags().is_empty());
+        config.offline = true;
+        config.frozen = true;
+        assert_eq!(config.cargo_network_flags(), vec!["--offline", "--frozen"]);
+    }
+
     #[test]
     fn target_merge() {
         let toml_a = r#""#;
@@ -1456,4 +2520,188 @@ mod tests {
         assert!(config.example_names.contains("example"));
         assert!(config.bench_names.contains("bench"));
     }
+
+    #[test]
+    fn scenarios_shared_by_configs() {
+        let toml = r#"[smoke]
+        command = "Build"
+
+        [with_features]
+        command = "Build"
+        features = "extra"
+
+        [scenarios.help]
+        bin = "cli"
+        args = ["--help"]
+
+        [scenarios.bad-input]
+        bin = "cli"
+        args = ["parse", "-"]
+        stdin = "not valid"
+        timeout = "10s"
+        expected-exit = 2
+        env = { RUST_LOG = "debug" }
+        "#;
+        let configs = Config::parse_config_toml(toml).unwrap();
+        assert_eq!(configs.len(), 2);
+        for config in &configs {
+            assert_eq!(config.scenarios.len(), 2);
+            let help = &config.scenarios[0];
+            assert_eq!(help.name, "help");
+            assert_eq!(help.bin, "cli");
+            assert_eq!(help.args, vec!["--help".to_string()]);
+            assert_eq!(help.expected_exit, 0);
+            assert_eq!(help.timeout, None);
+
+            let bad = &config.scenarios[1];
+            assert_eq!(bad.name, "bad-input");
+            assert_eq!(bad.stdin.as_deref(), Some("not valid"));
+            assert_eq!(bad.timeout, Some(Duration::from_secs(10)));
+            assert_eq!(bad.expected_exit, 2);
+            assert_eq!(bad.env.get("RUST_LOG").map(String::as_str), Some("debug"));
+        }
+    }
+
+    #[test]
+    fn timeouts_matched_by_test_path() {
+        let toml = r#"[cov]
+        timeout = "60s"
+
+        [timeouts]
+        "integration::big_sim" = "600s"
+        "sim::slow" = "5m"
+        "parser::fuzz_corpus" = "120s"
+        "#;
+        let configs = Config::parse_config_toml(toml).unwrap();
+        let config = &configs[0];
+        assert_eq!(config.test_timeouts.len(), 3);
+        assert_eq!(config.test_timeout, Duration::from_secs(60));
+
+        let tests = |names: &[&str]| names.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        // Prefixed with the target name or the test's own module path
+        assert_eq!(
+            config.test_timeout_for("integration", &tests(&["big_sim"])),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(
+            config.test_timeout_for("unit", &tests(&["sim::slow::orbit", "parser::fuzz_corpus"])),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            config.test_timeout_for(
+                "unit",
+                &tests(&["sim::slower", "integration::big_simulation"])
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn binary_overrides_matched_by_glob() {
+        let toml = r#"[cov]
+        timeout = "60s"
+
+        [binaries."integration_*"]
+        timeout = "5m"
+        args = ["--nocapture"]
+        env = { LOG = "info" }
+
+        [binaries.integration_slow]
+        engine = "Ptrace"
+        env = { LOG = "debug" }
+        "#;
+        let configs = Config::parse_config_toml(toml).unwrap();
+        assert_eq!(configs.len(), 1);
+        let config = &configs[0];
+        assert_eq!(config.binaries.len(), 2);
+
+        assert!(config.binary_override("unit").is_none());
+        let fast = config.binary_override("integration_fast").unwrap();
+        assert_eq!(fast.timeout, Some(Duration::from_secs(300)));
+        assert_eq!(fast.engine, None);
+
+        let slow = config.binary_override("integration_slow").unwrap();
+        assert_eq!(slow.args, vec!["--nocapture".to_string()]);
+        assert_eq!(slow.env.get("LOG").map(String::as_str), Some("debug"));
+        assert_eq!(slow.timeout, Some(Duration::from_secs(300)));
+        assert_eq!(slow.engine, Some(TraceEngine::Ptrace));
+
+        let unit = TestBinary::new(PathBuf::from("deps/unit-0123456789abcdef"), None);
+        assert_eq!(
+            config.for_binary(&unit).test_timeout,
+            Duration::from_secs(60)
+        );
+        let fast = TestBinary::new(
+            PathBuf::from("deps/integration_fast-0123456789abcdef"),
+            None,
+        );
+        assert_eq!(
+            config.for_binary(&fast).test_timeout,
+            Duration::from_secs(300)
+        );
+    }
+
+    #[test]
+    fn engine_per_run_type() {
+        let toml = r#"[single]
+        engine = "Ptrace"
+
+        [mixed]
+        engine = { tests = "llvm", examples = "ptrace" }
+        "#;
+        let configs = Config::parse_config_toml(toml).unwrap();
+        let single = configs.iter().find(|x| x.name == "single").unwrap();
+        assert_eq!(*single.engine.borrow(), TraceEngine::Ptrace);
+        assert!(single.run_type_engines.is_empty());
+
+        let mixed = configs.iter().find(|x| x.name == "mixed").unwrap();
+        assert_eq!(
+            mixed.run_type_engines.get(&RunType::Tests),
+            Some(&TraceEngine::Llvm)
+        );
+        assert_eq!(
+            mixed.run_type_engine(RunType::Examples),
+            TraceEngine::Ptrace
+        );
+        assert_eq!(mixed.run_type_engine(RunType::Doctests), mixed.engine());
+    }
+
+    #[test]
+    fn cfgs_validated() {
+        let mut config = Config {
+            cfgs: vec!["coverage_run".to_string(), "mode=\"cov\"".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        for cfg in [
+            "",
+            "_",
+            "1cfg",
+            "a b",
+            "mode=cov",
+            "mode=\"a b\"",
+            "mode=\"a\"b\"",
+        ] {
+            config.cfgs = vec![cfg.to_string()];
+            assert!(
+                matches!(config.validate(), Err(RunError::Config(_))),
+                "{}",
+                cfg
+            );
+        }
+    }
+
+    #[test]
+    fn line_patterns_validated() {
+        let mut config = Config {
+            ignore_line_patterns: vec![r"^\s*debug!\(".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.line_patterns().len(), 1);
+
+        config.ignore_line_patterns.push("unclosed(".to_string());
+        assert!(matches!(config.validate(), Err(RunError::Config(_))));
+    }
 }
@@ -5,10 +5,11 @@ use coveralls_api::CiService;
 use serde::de::{self, Deserializer};
 use std::env;
 use std::fmt;
-use std::fs::create_dir_all;
-use std::path::PathBuf;
+use std::fs::{create_dir_all, read_to_string};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tracing::error;
+use toml::Value;
+use tracing::{debug, error};
 
 pub(super) fn globs_from_excluded(strs: &[String]) -> Vec<glob::Pattern> {
     let mut files = vec![];
@@ -30,20 +31,111 @@ pub(super) fn process_manifest(
         return canonicalize_path(path);
     }
 
-    let mut manifest = env::current_dir().unwrap();
+    let mut dir = env::current_dir().unwrap();
     if let Some(path) = opt_root {
-        manifest.push(path);
+        dir.push(path);
+    }
+    match find_manifest(&dir) {
+        Some(manifest) => canonicalize_path(manifest),
+        None => canonicalize_path(dir.join("Cargo.toml")),
     }
-    manifest.push("Cargo.toml");
-    canonicalize_path(manifest)
 }
 
 pub(super) fn default_manifest() -> PathBuf {
-    let mut manifest = env::current_dir().unwrap();
-    manifest.push("Cargo.toml");
+    let dir = env::current_dir().unwrap();
+    let manifest = find_manifest(&dir).unwrap_or_else(|| dir.join("Cargo.toml"));
     fix_unc_path(&manifest.canonicalize().unwrap_or(manifest))
 }
 
+/// Like cargo, find the closest `Cargo.toml` in the directory or any of its parents.
+pub(super) fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+}
+
+fn read_manifest(manifest: &Path) -> Option<Value> {
+    let contents = read_to_string(manifest).ok()?;
+    match toml::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            debug!("Couldn't parse {}: {}", manifest.display(), e);
+            None
+        }
+    }
+}
+
+/// Checks whether the workspace defined in `root` claims the package manifest. Mirrors cargo in
+/// that a package is only excluded if it's in `exclude` and not explicitly listed as a member
+/// (globs in `members` don't count as explicit).
+fn is_workspace_member(root: &Path, workspace: &Value, manifest: &Path) -> bool {
+    let root_dir = match root.parent() {
+        Some(dir) => dir,
+        None => return false,
+    };
+    let package_dir = match manifest.parent() {
+        Some(dir) => dir,
+        None => return false,
+    };
+    let paths = |key: &str| -> Vec<String> {
+        workspace
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|list| {
+                list.iter()
+                    .filter_map(Value::as_str)
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let explicit_member = paths("members")
+        .iter()
+        .any(|member| package_dir.starts_with(root_dir.join(member)));
+    let excluded = paths("exclude")
+        .iter()
+        .any(|ex| package_dir.starts_with(root_dir.join(ex)));
+    !excluded || explicit_member
+}
+
+/// Finds the manifest of the workspace root for a given package manifest. This follows cargo's
+/// rules: a manifest with a `[workspace]` table is its own root, `package.workspace` points to
+/// the root explicitly and otherwise we walk up looking for a workspace which doesn't exclude
+/// the package. If no workspace is found the package is its own root.
+pub(crate) fn find_workspace_root(manifest: &Path) -> PathBuf {
+    let value = match read_manifest(manifest) {
+        Some(v) => v,
+        None => return manifest.to_path_buf(),
+    };
+    if value.get("workspace").is_some() {
+        return manifest.to_path_buf();
+    }
+    let parent = manifest.parent().unwrap_or_else(|| Path::new(""));
+    if let Some(explicit) = value
+        .get("package")
+        .and_then(|p| p.get("workspace"))
+        .and_then(Value::as_str)
+    {
+        let root = parent.join(explicit).join("Cargo.toml");
+        return fix_unc_path(&root.canonicalize().unwrap_or(root));
+    }
+    for dir in parent.ancestors().skip(1) {
+        let candidate = dir.join("Cargo.toml");
+        if !candidate.is_file() {
+            continue;
+        }
+        if let Some(workspace) = read_manifest(&candidate)
+            .as_ref()
+            .and_then(|v| v.get("workspace"))
+        {
+            if is_workspace_member(&candidate, workspace, manifest) {
+                return candidate;
+            }
+        }
+    }
+    manifest.to_path_buf()
+}
+
 pub(super) fn process_target_dir(opt_path: Option<PathBuf>) -> Option<PathBuf> {
     let path = if let Some(path) = opt_path {
         path
@@ -116,4 +208,48 @@ mod tests {
             PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/lib.rs")
         );
     }
+
+    fn nested_workspace() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/nested_workspace")
+    }
+
+    #[test]
+    fn manifest_from_subdirectory() {
+        let root = nested_workspace();
+        assert_eq!(
+            find_manifest(&root.join("crates/member/src")),
+            Some(root.join("crates/member/Cargo.toml"))
+        );
+        assert_eq!(find_manifest(&root), Some(root.join("Cargo.toml")));
+    }
+
+    #[test]
+    fn virtual_workspace_root() {
+        let root = nested_workspace();
+        assert_eq!(
+            find_workspace_root(&root.join("Cargo.toml")),
+            root.join("Cargo.toml")
+        );
+        assert_eq!(
+            find_workspace_root(&root.join("crates/member/Cargo.toml")),
+            root.join("Cargo.toml")
+        );
+    }
+
+    #[test]
+    fn excluded_member_is_own_root() {
+        let root = nested_workspace();
+        let excluded = root.join("crates/excluded/Cargo.toml");
+        assert_eq!(find_workspace_root(&excluded), excluded);
+    }
+
+    #[test]
+    fn explicit_package_workspace() {
+        let root = nested_workspace();
+        let expected = root.join("Cargo.toml");
+        assert_eq!(
+            find_workspace_root(&root.join("detached/inner/Cargo.toml")),
+            fix_unc_path(&expected.canonicalize().unwrap())
+        );
+    }
 }
@@ -2,8 +2,11 @@ use clap::ValueEnum;
 #[cfg(feature = "coveralls")]
 use coveralls_api::CiService;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
+pub use tarpaulin_analysis::LogicalLines;
 
 #[derive(
     Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize, ValueEnum,
@@ -41,18 +44,25 @@ impl fmt::Display for Color {
 )]
 #[value(rename_all = "PascalCase")]
 pub enum TraceEngine {
+    #[serde(alias = "auto")]
     Auto,
     #[cfg_attr(ptrace_supported, default)]
+    #[serde(alias = "ptrace")]
     Ptrace,
     #[cfg_attr(not(ptrace_supported), default)]
+    #[serde(alias = "llvm")]
     Llvm,
+    // Experimental, samples the tests with `perf record` instead of tracing them so binaries
+    // which can't be ptraced still get approximate coverage
+    #[serde(alias = "perf")]
+    Perf,
 }
 
 impl TraceEngine {
     pub const fn supported() -> &'static [TraceEngine] {
         cfg_if::cfg_if! {
             if #[cfg(ptrace_supported)] {
-                &[TraceEngine::Ptrace, TraceEngine::Llvm]
+                &[TraceEngine::Ptrace, TraceEngine::Llvm, TraceEngine::Perf]
             } else {
                 &[TraceEngine::Llvm]
             }
@@ -60,6 +70,205 @@ impl TraceEngine {
     }
 }
 
+/// When profraw files are removed from the profraw directory
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Deserialize,
+    Serialize,
+    ValueEnum,
+)]
+#[value(rename_all = "PascalCase")]
+pub enum ProfrawCleanup {
+    /// Remove profraws left over from previous runs before running the tests
+    #[default]
+    Start,
+    /// Remove profraws before running the tests and once coverage has been collected
+    Always,
+    /// Never remove profraws
+    Never,
+}
+
+/// Format of the run report stored in `target/tarpaulin` between runs
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Deserialize,
+    Serialize,
+    ValueEnum,
+)]
+#[value(rename_all = "PascalCase")]
+pub enum RunReportFormat {
+    /// Plain json
+    #[default]
+    Json,
+    /// Gzipped json with a versioned header, much smaller for large workspaces
+    Compact,
+}
+
+/// Layout of the markdown report
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Deserialize,
+    Serialize,
+    ValueEnum,
+)]
+#[value(rename_all = "PascalCase")]
+pub enum MarkdownStyle {
+    /// Total coverage and the coverage of each file
+    #[default]
+    #[serde(alias = "summary")]
+    Summary,
+    /// Changes since the previous run and collapsible uncovered lines, for pull request comments
+    #[value(alias = "pr-comment")]
+    #[serde(alias = "pr-comment")]
+    PrComment,
+}
+
+/// Colour scheme of the HTML report
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Deserialize,
+    Serialize,
+    ValueEnum,
+)]
+#[value(rename_all = "PascalCase")]
+pub enum HtmlTheme {
+    /// Follows the light or dark preference of the browser
+    #[default]
+    #[serde(alias = "auto")]
+    Auto,
+    #[serde(alias = "light")]
+    Light,
+    #[serde(alias = "dark")]
+    Dark,
+}
+
+/// What a dry run does before printing the plan
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize, ValueEnum,
+)]
+#[value(rename_all = "PascalCase")]
+pub enum DryRun {
+    /// Only print the plan, the test binaries aren't listed as they'd need building
+    Plan,
+    /// Build the tests so the test binaries can be listed
+    Build,
+}
+
+/// A black-box run of one of the project binaries defined in the `[scenarios]` table of a config
+/// file. Each scenario is executed after the build and contributes to the coverage results.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scenario {
+    /// Name of the scenario, taken from the table key
+    #[serde(skip_deserializing)]
+    pub name: String,
+    /// Name of the binary target to run
+    pub bin: String,
+    /// Arguments passed to the binary
+    pub args: Vec<String>,
+    /// Extra environment variables set for the binary
+    pub env: BTreeMap<String, String>,
+    /// Text written to the binary's stdin
+    pub stdin: Option<String>,
+    /// Time the binary is allowed to run for before it's killed
+    #[serde(with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Exit code the binary should finish with
+    #[serde(rename = "expected-exit")]
+    pub expected_exit: i32,
+}
+
+/// Settings from the `[binaries]` table of a config file, applied to every test binary whose
+/// target name matches the glob used as the table key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BinaryOverride {
+    /// Glob matched against the target name, taken from the table key
+    #[serde(skip_deserializing)]
+    pub pattern: String,
+    /// Arguments passed to the binary after the usual test arguments
+    pub args: Vec<String>,
+    /// Extra environment variables set for the binary
+    pub env: BTreeMap<String, String>,
+    /// Replaces the test timeout for the binary
+    #[serde(with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Engine used to collect the binary's coverage
+    pub engine: Option<TraceEngine>,
+}
+
+/// An entry of the `[timeouts]` table of a config file, giving tests with a known long runtime
+/// more time than the test timeout
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Serialize)]
+pub struct TestTimeout {
+    /// Test path, either the name libtest lists the test as or prefixed with the target name.
+    /// A module path applies to every test in the module.
+    pub test: String,
+    pub timeout: Duration,
+}
+
+impl TestTimeout {
+    /// Whether the entry applies to the test in the target
+    pub fn matches(&self, target: &str, test: &str) -> bool {
+        let matches_path = |path: &str| {
+            path == self.test
+                || path
+                    .strip_prefix(self.test.as_str())
+                    .is_some_and(|rest| rest.starts_with("::"))
+        };
+        matches_path(test) || matches_path(&format!("{target}::{test}"))
+    }
+}
+
+impl BinaryOverride {
+    pub fn matches(&self, target: &str) -> bool {
+        glob::Pattern::new(&self.pattern)
+            .map(|x| x.matches(target))
+            .unwrap_or(false)
+    }
+
+    /// Applies another override on top of this one, settings in `other` take priority
+    pub fn merge(&mut self, other: &BinaryOverride) {
+        self.args.extend_from_slice(&other.args);
+        self.env
+            .extend(other.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.timeout = other.timeout.or(self.timeout);
+        self.engine = other.engine.or(self.engine);
+    }
+}
+
 #[derive(
     Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize, ValueEnum,
 )]
@@ -74,15 +283,31 @@ pub enum Mode {
 )]
 #[value(rename_all = "PascalCase")]
 pub enum RunType {
+    #[serde(alias = "tests")]
     Tests,
+    #[serde(alias = "doctests")]
     Doctests,
+    #[serde(alias = "benchmarks")]
     Benchmarks,
+    #[serde(alias = "examples")]
     Examples,
+    #[serde(alias = "lib")]
     Lib,
+    #[serde(alias = "bins")]
     Bins,
+    #[serde(alias = "all-targets")]
     AllTargets,
 }
 
+/// The `engine` entry of a config file, either one engine for everything or an engine for each
+/// run type
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum EngineSelection {
+    Single(TraceEngine),
+    PerRunType(BTreeMap<RunType, TraceEngine>),
+}
+
 #[derive(
     Debug,
     Default,
@@ -106,6 +331,15 @@ pub enum OutputFile {
     Xml,
     Html,
     Lcov,
+    Stats,
+    Sarif,
+    Template,
+    Sonar,
+    Jacoco,
+    Gcov,
+    Quickfix,
+    Badge,
+    Markdown,
 }
 
 #[cfg(feature = "coveralls")]
@@ -0,0 +1,328 @@
+//! Engines launch the test binaries and follow them to collect their coverage. The ptrace, llvm and
+//! perf engines are selected with `--engine`, other engines can be provided by library users with
+//! [`register_engine`] and selected by name with `custom-engine` in a config file. The tests are
+//! still built for the engine selected with `--engine`, so an engine which doesn't use llvm's
+//! instrumentation should be paired with `engine = "Ptrace"`.
+use crate::cargo::TestBinary;
+use crate::config::{Config, TraceEngine};
+use crate::errors::RunError;
+use crate::event_log::EventLog;
+use crate::process_handling::execute_test;
+#[cfg(ptrace_supported)]
+use crate::process_handling::linux;
+use crate::source_analysis::LineAnalysis;
+use crate::statemachine::{instrumented, StateData, TestState};
+#[cfg(ptrace_supported)]
+use crate::test_loader::generate_tracemap;
+use crate::traces::TraceMap;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+#[cfg(not(ptrace_supported))]
+use tracing::error;
+
+pub use crate::process_handling::{ProcessHandle, RunningProcessHandle, TestHandle};
+
+lazy_static! {
+    static ref ENGINES: RwLock<Vec<Arc<dyn Engine>>> = RwLock::new(vec![]);
+}
+
+/// A way of running test binaries and collecting their coverage
+pub trait Engine: Send + Sync {
+    /// Name the engine is selected by with `custom-engine`
+    fn name(&self) -> &str;
+
+    /// Traces of the test binary before it runs. Engines which find the coverable locations up
+    /// front fill them in here for the state machine to count the hits of.
+    fn prepare_traces(
+        &self,
+        _test: &Path,
+        _analysis: &HashMap<PathBuf, LineAnalysis>,
+        _config: &Config,
+    ) -> Result<TraceMap, RunError> {
+        Ok(TraceMap::new())
+    }
+
+    /// Whether the coverage recorded before a test crashes or times out is kept with
+    /// `--no-fail-fast`
+    fn keeps_partial_coverage(&self) -> bool {
+        false
+    }
+
+    /// Launches the test binary, `None` if there's nothing to run. When the binary has to run its
+    /// tests on fewer threads the number of threads is given. With `--port-block` the first port
+    /// of the block given to the process is passed in `port_base`.
+    #[allow(clippy::too_many_arguments)]
+    fn launch(
+        &self,
+        test: &TestBinary,
+        other_binaries: &[PathBuf],
+        config: &Config,
+        ignored: bool,
+        tests: &[String],
+        threads: Option<usize>,
+        port_base: Option<u16>,
+    ) -> Result<Option<TestHandle>, RunError>;
+
+    /// State machine following the launched test until it finishes, recording its coverage in
+    /// the traces
+    fn state_machine<'a>(
+        &self,
+        test: TestHandle,
+        traces: &'a mut TraceMap,
+        analysis: &'a HashMap<PathBuf, LineAnalysis>,
+        config: &'a Config,
+        event_log: &'a Option<EventLog>,
+    ) -> (TestState, Box<dyn StateData + 'a>);
+}
+
+/// Runs the tests as child processes built with `-Cinstrument-coverage`, the counters they write
+/// are collected once they exit
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LlvmEngine;
+
+impl Engine for LlvmEngine {
+    fn name(&self) -> &str {
+        "llvm"
+    }
+
+    fn launch(
+        &self,
+        test: &TestBinary,
+        other_binaries: &[PathBuf],
+        config: &Config,
+        ignored: bool,
+        tests: &[String],
+        threads: Option<usize>,
+        port_base: Option<u16>,
+    ) -> Result<Option<TestHandle>, RunError> {
+        execute_test(
+            test,
+            other_binaries,
+            ignored,
+            config,
+            threads,
+            tests,
+            port_base,
+        )
+        .map(Some)
+    }
+
+    fn state_machine<'a>(
+        &self,
+        test: TestHandle,
+        _traces: &'a mut TraceMap,
+        _analysis: &'a HashMap<PathBuf, LineAnalysis>,
+        config: &'a Config,
+        event_log: &'a Option<EventLog>,
+    ) -> (TestState, Box<dyn StateData + 'a>) {
+        let (state, machine) = instrumented::create_state_machine(test, config, event_log);
+        (state, Box::new(machine))
+    }
+}
+
+/// Forks the tests under ptrace, setting breakpoints on the coverable lines found in the debug
+/// info
+#[cfg(ptrace_supported)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PtraceEngine;
+
+#[cfg(ptrace_supported)]
+impl Engine for PtraceEngine {
+    fn name(&self) -> &str {
+        "ptrace"
+    }
+
+    fn prepare_traces(
+        &self,
+        test: &Path,
+        analysis: &HashMap<PathBuf, LineAnalysis>,
+        config: &Config,
+    ) -> Result<TraceMap, RunError> {
+        Ok(generate_tracemap(test, analysis, config)?)
+    }
+
+    fn keeps_partial_coverage(&self) -> bool {
+        true
+    }
+
+    fn launch(
+        &self,
+        test: &TestBinary,
+        _other_binaries: &[PathBuf],
+        config: &Config,
+        ignored: bool,
+        _tests: &[String],
+        _threads: Option<usize>,
+        port_base: Option<u16>,
+    ) -> Result<Option<TestHandle>, RunError> {
+        linux::get_test_coverage(test, config, ignored, port_base)
+    }
+
+    fn state_machine<'a>(
+        &self,
+        test: TestHandle,
+        traces: &'a mut TraceMap,
+        analysis: &'a HashMap<PathBuf, LineAnalysis>,
+        config: &'a Config,
+        event_log: &'a Option<EventLog>,
+    ) -> (TestState, Box<dyn StateData + 'a>) {
+        let (state, machine) = crate::statemachine::linux::create_state_machine(
+            test, traces, analysis, config, event_log,
+        );
+        (state, Box::new(machine))
+    }
+}
+
+/// Runs the tests under `perf record`, adding the source lines of the sampled instructions to the
+/// traces found in the debug info. Nothing is traced or instrumented, so it works on binaries
+/// ptrace can't follow but the coverage is only approximate and marked as sampled.
+#[cfg(ptrace_supported)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PerfEngine;
+
+#[cfg(ptrace_supported)]
+impl Engine for PerfEngine {
+    fn name(&self) -> &str {
+        "perf"
+    }
+
+    fn prepare_traces(
+        &self,
+        test: &Path,
+        analysis: &HashMap<PathBuf, LineAnalysis>,
+        config: &Config,
+    ) -> Result<TraceMap, RunError> {
+        Ok(generate_tracemap(test, analysis, config)?)
+    }
+
+    fn launch(
+        &self,
+        test: &TestBinary,
+        other_binaries: &[PathBuf],
+        config: &Config,
+        ignored: bool,
+        tests: &[String],
+        threads: Option<usize>,
+        port_base: Option<u16>,
+    ) -> Result<Option<TestHandle>, RunError> {
+        execute_test(
+            test,
+            other_binaries,
+            ignored,
+            config,
+            threads,
+            tests,
+            port_base,
+        )
+        .map(Some)
+    }
+
+    fn state_machine<'a>(
+        &self,
+        test: TestHandle,
+        traces: &'a mut TraceMap,
+        analysis: &'a HashMap<PathBuf, LineAnalysis>,
+        config: &'a Config,
+        _event_log: &'a Option<EventLog>,
+    ) -> (TestState, Box<dyn StateData + 'a>) {
+        let (state, machine) =
+            crate::statemachine::perf::create_state_machine(test, traces, analysis, config);
+        (state, Box::new(machine))
+    }
+}
+
+/// Registers an engine so configs can select it with `custom-engine`. An engine with the same
+/// name as one registered earlier replaces it.
+pub fn register_engine(engine: impl Engine + 'static) {
+    let mut engines = ENGINES.write().unwrap_or_else(|e| e.into_inner());
+    engines.retain(|x| x.name() != engine.name());
+    engines.push(Arc::new(engine));
+}
+
+/// The engine the config runs the tests with
+pub fn engine_for(config: &Config) -> Result<Arc<dyn Engine>, RunError> {
+    if let Some(name) = config.custom_engine.as_deref() {
+        let engines = ENGINES.read().unwrap_or_else(|e| e.into_inner());
+        return engines
+            .iter()
+            .find(|x| x.name() == name)
+            .cloned()
+            .ok_or_else(|| RunError::Engine(format!("No engine called {name} is registered")));
+    }
+    match config.engine() {
+        TraceEngine::Ptrace => {
+            cfg_if::cfg_if! {
+                if #[cfg(ptrace_supported)] {
+                    Ok(Arc::new(PtraceEngine))
+                } else {
+                    error!("Ptrace is not supported on this platform");
+                    Err(RunError::TestCoverage("Unsupported OS".to_string()))
+                }
+            }
+        }
+        TraceEngine::Perf => {
+            cfg_if::cfg_if! {
+                if #[cfg(ptrace_supported)] {
+                    Ok(Arc::new(PerfEngine))
+                } else {
+                    error!("The perf engine is not supported on this platform");
+                    Err(RunError::TestCoverage("Unsupported OS".to_string()))
+                }
+            }
+        }
+        TraceEngine::Llvm | TraceEngine::Auto => Ok(Arc::new(LlvmEngine)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoopEngine;
+
+    impl Engine for NoopEngine {
+        fn name(&self) -> &str {
+            "noop"
+        }
+
+        fn launch(
+            &self,
+            _test: &TestBinary,
+            _other_binaries: &[PathBuf],
+            _config: &Config,
+            _ignored: bool,
+            _tests: &[String],
+            _threads: Option<usize>,
+            _port_base: Option<u16>,
+        ) -> Result<Option<TestHandle>, RunError> {
+            Ok(None)
+        }
+
+        fn state_machine<'a>(
+            &self,
+            test: TestHandle,
+            traces: &'a mut TraceMap,
+            analysis: &'a HashMap<PathBuf, LineAnalysis>,
+            config: &'a Config,
+            event_log: &'a Option<EventLog>,
+        ) -> (TestState, Box<dyn StateData + 'a>) {
+            LlvmEngine.state_machine(test, traces, analysis, config, event_log)
+        }
+    }
+
+    #[test]
+    fn custom_engines_selected_by_name() {
+        let mut config = Config::default();
+        config.set_engine(TraceEngine::Llvm);
+        config.custom_engine = Some("noop".to_string());
+        assert!(engine_for(&config).is_err());
+
+        register_engine(NoopEngine);
+        assert_eq!(engine_for(&config).unwrap().name(), "noop");
+
+        config.custom_engine = None;
+        assert_ne!(engine_for(&config).unwrap().name(), "noop");
+    }
+}
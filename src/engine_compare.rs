@@ -0,0 +1,192 @@
+//! Developer mode which collects coverage with both the ptrace and llvm engines and reports the
+//! lines only one of them saw being hit. This helps narrow down whether a coverage discrepancy is
+//! down to the engine or the source analysis.
+use crate::cargo::supports_llvm_coverage;
+use crate::config::{Config, TraceEngine};
+use crate::errors::RunError;
+use crate::event_log::EventLog;
+use crate::launch_tarpaulin;
+use crate::traces::{CoverageStat, Trace, TraceMap};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+const DIVERGENCE_REPORT: &str = "tarpaulin-engine-divergence.json";
+
+/// Number of files listed in the summary printed after the comparison
+const SUMMARY_FILES: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileDivergence {
+    pub path: PathBuf,
+    /// Lines hit when running with ptrace and not with llvm
+    pub ptrace_only: Vec<u64>,
+    /// Lines hit when running with llvm and not with ptrace
+    pub llvm_only: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DivergenceReport {
+    /// Percentage coverage from 0-100 using the ptrace engine
+    pub ptrace_coverage: f64,
+    /// Percentage coverage from 0-100 using the llvm engine
+    pub llvm_coverage: f64,
+    pub ptrace_only: usize,
+    pub llvm_only: usize,
+    /// Files where the engines disagree, files where they agree are left out
+    pub files: Vec<FileDivergence>,
+}
+
+fn is_hit(trace: &Trace) -> bool {
+    match &trace.stats {
+        CoverageStat::Line(hits) => *hits > 0,
+        CoverageStat::Branch(x) => x.been_true || x.been_false,
+        CoverageStat::Condition(x) => x.iter().any(|x| x.been_true || x.been_false),
+    }
+}
+
+fn hit_lines(traces: &TraceMap) -> BTreeMap<&PathBuf, BTreeSet<u64>> {
+    traces
+        .iter()
+        .map(|(path, traces)| {
+            let lines = traces.iter().filter(|x| is_hit(x)).map(|x| x.line);
+            (path, lines.collect())
+        })
+        .collect()
+}
+
+/// Compares the lines hit in the two trace maps
+pub fn compare(ptrace: &TraceMap, llvm: &TraceMap) -> DivergenceReport {
+    let ptrace_hits = hit_lines(ptrace);
+    let llvm_hits = hit_lines(llvm);
+    let empty = BTreeSet::new();
+    let paths = ptrace_hits
+        .keys()
+        .chain(llvm_hits.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    let mut report = DivergenceReport {
+        ptrace_coverage: 100.0 * ptrace.coverage_percentage(),
+        llvm_coverage: 100.0 * llvm.coverage_percentage(),
+        ..Default::default()
+    };
+    for path in paths {
+        let ptrace_lines = ptrace_hits.get(path).unwrap_or(&empty);
+        let llvm_lines = llvm_hits.get(path).unwrap_or(&empty);
+        let file = FileDivergence {
+            path: path.clone(),
+            ptrace_only: ptrace_lines.difference(llvm_lines).copied().collect(),
+            llvm_only: llvm_lines.difference(ptrace_lines).copied().collect(),
+        };
+        if !(file.ptrace_only.is_empty() && file.llvm_only.is_empty()) {
+            report.ptrace_only += file.ptrace_only.len();
+            report.llvm_only += file.llvm_only.len();
+            report.files.push(file);
+        }
+    }
+    report
+}
+
+fn print_summary(report: &DivergenceReport) {
+    info!(
+        "Engine comparison: ptrace {:.2}% llvm {:.2}%, {} lines only hit with ptrace, {} lines only hit with llvm",
+        report.ptrace_coverage, report.llvm_coverage, report.ptrace_only, report.llvm_only
+    );
+    let mut files = report.files.iter().collect::<Vec<_>>();
+    files.sort_by_key(|x| std::cmp::Reverse(x.ptrace_only.len() + x.llvm_only.len()));
+    for file in files.iter().take(SUMMARY_FILES) {
+        info!(
+            "{}: ptrace only {:?}, llvm only {:?}",
+            file.path.display(),
+            file.ptrace_only,
+            file.llvm_only
+        );
+    }
+}
+
+/// Runs the tests once with each engine, writing the divergence report to the output directory.
+/// The coverage from both runs is merged and returned along with the combined return code.
+pub(crate) fn compare_engines(
+    config: &Config,
+    logger: &Option<EventLog>,
+) -> Result<(TraceMap, i32), RunError> {
+    if !cfg!(ptrace_supported) || !supports_llvm_coverage() {
+        return Err(RunError::Engine(
+            "--compare-engines needs both the ptrace and llvm engines to be available".to_string(),
+        ));
+    }
+    info!("Collecting coverage with ptrace");
+    let (ptrace, ptrace_ret) = launch_tarpaulin(&config.with_engine(TraceEngine::Ptrace), logger)?;
+    info!("Collecting coverage with llvm");
+    let (llvm, llvm_ret) = launch_tarpaulin(&config.with_engine(TraceEngine::Llvm), logger)?;
+    if ptrace_ret != llvm_ret {
+        warn!(
+            "Tests returned {} with ptrace and {} with llvm",
+            ptrace_ret, llvm_ret
+        );
+    }
+
+    let report = compare(&ptrace, &llvm);
+    print_summary(&report);
+    let path = config.output_dir().join(DIVERGENCE_REPORT);
+    serde_json::to_writer_pretty(File::create(&path)?, &report)?;
+    info!("Engine divergence report written to {}", path.display());
+
+    let mut result = ptrace;
+    result.merge(&llvm);
+    Ok((result, ptrace_ret | llvm_ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn tracemap(lines: &[(&str, u64, u64)]) -> TraceMap {
+        let mut map = TraceMap::new();
+        for (file, line, hits) in lines {
+            let mut trace = Trace::new_stub(*line);
+            trace.stats = CoverageStat::Line(*hits);
+            map.add_trace(Path::new(file), trace);
+        }
+        map
+    }
+
+    #[test]
+    fn lines_hit_by_one_engine() {
+        let ptrace = tracemap(&[
+            ("src/lib.rs", 1, 1),
+            ("src/lib.rs", 2, 1),
+            ("src/lib.rs", 3, 0),
+            ("src/same.rs", 1, 2),
+        ]);
+        let llvm = tracemap(&[
+            ("src/lib.rs", 1, 4),
+            ("src/lib.rs", 2, 0),
+            ("src/lib.rs", 3, 1),
+            ("src/same.rs", 1, 1),
+            ("src/other.rs", 7, 1),
+        ]);
+        let report = compare(&ptrace, &llvm);
+        assert_eq!(report.ptrace_only, 1);
+        assert_eq!(report.llvm_only, 2);
+        assert_eq!(
+            report.files,
+            vec![
+                FileDivergence {
+                    path: PathBuf::from("src/lib.rs"),
+                    ptrace_only: vec![2],
+                    llvm_only: vec![3],
+                },
+                FileDivergence {
+                    path: PathBuf::from("src/other.rs"),
+                    ptrace_only: vec![],
+                    llvm_only: vec![7],
+                },
+            ]
+        );
+    }
+}
@@ -1,7 +1,27 @@
 #![cfg(not(tarpaulin_include))]
 use crate::report::cobertura;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 
+/// Broad classification of where an error came from, so tools embedding tarpaulin can handle
+/// build failures differently to test or report failures.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ErrorCategory {
+    /// Problems with the project or tarpaulin configuration
+    Config,
+    /// Building the project failed
+    Build,
+    /// Running or tracing the tests failed
+    Runtime,
+    /// Coverage was collected but generating a report failed
+    Report,
+    /// Coverage was collected and reported but didn't meet the threshold
+    Threshold,
+    /// A bug in tarpaulin
+    Internal,
+}
+
 /// Error states that could be returned from tarpaulin
 #[derive(Debug)]
 pub enum RunError {
@@ -36,8 +56,113 @@ pub enum RunError {
     Internal,
     /// Tuple of actual coverage and threshold
     BelowThreshold(f64, f64),
+    /// Tuple of the number of uncovered public functions and the number allowed
+    UncoveredPublicApi(usize, usize),
+    /// Tuple of the number of coverage goals failed and the number of goals
+    GoalsNotMet(usize, usize),
     /// Error relating to tracing engine selected
     Engine(String),
+    /// Another tarpaulin run holds the lock on the target directory
+    ConcurrentRun(String),
+    /// A setting in the tarpaulin config is invalid
+    Config(String),
+}
+
+impl RunError {
+    /// A stable identifier for the error variant, this won't change between releases even if the
+    /// error message does.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Manifest(_) => "manifest",
+            Self::Cargo(_) => "cargo",
+            Self::Packages(_) => "packages",
+            Self::TestLaunch(_) => "test-launch",
+            Self::TestCompile(_) => "test-compile",
+            Self::TestRuntime(_) => "test-runtime",
+            Self::TestFailed => "test-failed",
+            Self::Parse(_) => "parse",
+            Self::TestCoverage(_) => "test-coverage",
+            Self::Trace(_) => "trace",
+            Self::CovReport(_) => "coverage-report",
+            Self::OutFormat(_) => "output-format",
+            Self::IO(_) => "io",
+            Self::StateMachine(_) => "state-machine",
+            #[cfg(ptrace_supported)]
+            Self::NixError(_) => "nix",
+            Self::Html(_) => "html-report",
+            Self::XML(_) => "xml-report",
+            Self::Lcov(_) => "lcov-report",
+            Self::Json(_) => "json-report",
+            Self::Internal => "internal",
+            Self::BelowThreshold(_, _) => "below-threshold",
+            Self::UncoveredPublicApi(_, _) => "uncovered-public-api",
+            Self::GoalsNotMet(_, _) => "goals-not-met",
+            Self::Engine(_) => "engine",
+            Self::ConcurrentRun(_) => "concurrent-run",
+            Self::Config(_) => "config",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Manifest(_)
+            | Self::Packages(_)
+            | Self::Parse(_)
+            | Self::Engine(_)
+            | Self::Config(_) => ErrorCategory::Config,
+            Self::Cargo(_) | Self::TestCompile(_) => ErrorCategory::Build,
+            Self::TestLaunch(_)
+            | Self::TestRuntime(_)
+            | Self::TestFailed
+            | Self::TestCoverage(_)
+            | Self::Trace(_)
+            | Self::StateMachine(_)
+            | Self::ConcurrentRun(_) => ErrorCategory::Runtime,
+            #[cfg(ptrace_supported)]
+            Self::NixError(_) => ErrorCategory::Runtime,
+            Self::CovReport(_)
+            | Self::OutFormat(_)
+            | Self::Html(_)
+            | Self::XML(_)
+            | Self::Lcov(_)
+            | Self::Json(_) => ErrorCategory::Report,
+            Self::IO(_) => ErrorCategory::Runtime,
+            Self::BelowThreshold(_, _)
+            | Self::UncoveredPublicApi(_, _)
+            | Self::GoalsNotMet(_, _) => ErrorCategory::Threshold,
+            Self::Internal => ErrorCategory::Internal,
+        }
+    }
+
+    /// Whether running tarpaulin again without changes could plausibly succeed. This covers
+    /// things like timeouts, interrupted syscalls and failures spawning processes, not failing
+    /// tests or compilation errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::IO(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+            ),
+            #[cfg(ptrace_supported)]
+            Self::NixError(e) => matches!(e, nix::Error::EINTR | nix::Error::EAGAIN),
+            Self::TestLaunch(_) | Self::StateMachine(_) | Self::ConcurrentRun(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(e) | Self::IO(e) => Some(e),
+            #[cfg(ptrace_supported)]
+            Self::NixError(e) => Some(e),
+            Self::XML(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl Display for RunError {
@@ -71,7 +196,16 @@ impl Display for RunError {
                     "Coverage is below the failure threshold {a:.2}% < {e:.2}%"
                 )
             }
+            Self::UncoveredPublicApi(a, e) => {
+                write!(
+                    f,
+                    "{a} public functions have no coverage, only {e} are allowed"
+                )
+            }
+            Self::GoalsNotMet(a, e) => write!(f, "{a} of {e} coverage goals weren't met"),
             Self::Engine(s) => write!(f, "Engine error: {s}"),
+            Self::ConcurrentRun(s) => write!(f, "{s}"),
+            Self::Config(s) => write!(f, "Invalid config: {s}"),
         }
     }
 }
@@ -100,3 +234,89 @@ impl From<serde_json::error::Error> for RunError {
         RunError::Json(e.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::io::{self, ErrorKind};
+
+    #[test]
+    fn error_codes_and_categories() {
+        use ErrorCategory::*;
+        use RunError as E;
+
+        let msg = || "msg".to_string();
+        let io_error = || io::Error::new(ErrorKind::NotFound, "missing");
+        let cases = vec![
+            (E::Manifest(msg()), "manifest", Config, false),
+            (E::Cargo(msg()), "cargo", Build, false),
+            (E::Packages(msg()), "packages", Config, false),
+            (E::TestLaunch(msg()), "test-launch", Runtime, true),
+            (E::TestCompile(msg()), "test-compile", Build, false),
+            (E::TestRuntime(msg()), "test-runtime", Runtime, false),
+            (E::TestFailed, "test-failed", Runtime, false),
+            (E::Parse(io_error()), "parse", Config, false),
+            (E::TestCoverage(msg()), "test-coverage", Runtime, false),
+            (E::Trace(msg()), "trace", Runtime, false),
+            (E::CovReport(msg()), "coverage-report", Report, false),
+            (E::OutFormat(msg()), "output-format", Report, false),
+            (E::IO(io_error()), "io", Runtime, false),
+            (E::StateMachine(msg()), "state-machine", Runtime, true),
+            (E::Html(msg()), "html-report", Report, false),
+            (
+                E::XML(cobertura::Error::Unknown),
+                "xml-report",
+                Report,
+                false,
+            ),
+            (E::Lcov(msg()), "lcov-report", Report, false),
+            (E::Json(msg()), "json-report", Report, false),
+            (E::Internal, "internal", Internal, false),
+            (
+                E::BelowThreshold(50.0, 80.0),
+                "below-threshold",
+                Threshold,
+                false,
+            ),
+            (
+                E::UncoveredPublicApi(3, 1),
+                "uncovered-public-api",
+                Threshold,
+                false,
+            ),
+            (E::GoalsNotMet(1, 2), "goals-not-met", Threshold, false),
+            (E::Engine(msg()), "engine", Config, false),
+            (E::ConcurrentRun(msg()), "concurrent-run", Runtime, true),
+            (E::Config(msg()), "config", Config, false),
+        ];
+        let mut codes = HashSet::new();
+        for (error, code, category, retryable) in cases {
+            assert_eq!(error.code(), code, "{error:?}");
+            assert_eq!(error.category(), category, "{error:?}");
+            assert_eq!(error.is_retryable(), retryable, "{error:?}");
+            assert!(codes.insert(code), "{} is used twice", code);
+        }
+    }
+
+    #[test]
+    fn io_errors_retryable_by_kind() {
+        let retryable = |kind| RunError::IO(io::Error::new(kind, "io")).is_retryable();
+        assert!(retryable(ErrorKind::Interrupted));
+        assert!(retryable(ErrorKind::TimedOut));
+        assert!(retryable(ErrorKind::WouldBlock));
+        assert!(!retryable(ErrorKind::NotFound));
+        assert!(!retryable(ErrorKind::PermissionDenied));
+    }
+
+    #[test]
+    #[cfg(ptrace_supported)]
+    fn nix_errors_retryable_by_errno() {
+        let error = RunError::NixError(nix::Error::EINTR);
+        assert_eq!(error.code(), "nix");
+        assert_eq!(error.category(), ErrorCategory::Runtime);
+        assert!(error.is_retryable());
+        assert!(RunError::NixError(nix::Error::EAGAIN).is_retryable());
+        assert!(!RunError::NixError(nix::Error::EPERM).is_retryable());
+    }
+}
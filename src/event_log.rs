@@ -15,17 +15,22 @@ use nix::libc::*;
 #[cfg(ptrace_supported)]
 use nix::sys::{signal::Signal, wait::WaitStatus};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
-use std::fs::File;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
 #[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Event {
     ConfigLaunch(String),
     BinaryLaunch(TestBinary),
+    /// Launch of a binary to run only its ignored tests
+    IgnoredBinaryLaunch(TestBinary),
+    /// The tests in a binary were forced to run on one thread, with the reason why
+    TestsSerialised(String),
     Trace(TraceEvent),
     Marker(Option<()>),
 }
@@ -33,9 +38,9 @@ pub enum Event {
 #[derive(Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct EventWrapper {
     #[serde(flatten)]
-    event: Event,
+    pub(crate) event: Event,
     // The time this was created in seconds
-    created: f64,
+    pub(crate) created: f64,
 }
 
 impl EventWrapper {
@@ -47,13 +52,13 @@ impl EventWrapper {
 
 #[derive(Clone, Default, Eq, PartialEq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct TraceEvent {
-    pid: Option<i64>,
-    child: Option<i64>,
-    signal: Option<String>,
+    pub(crate) pid: Option<i64>,
+    pub(crate) child: Option<i64>,
+    pub(crate) signal: Option<String>,
     addr: Option<u64>,
     return_val: Option<i64>,
     location: Option<Location>,
-    description: String,
+    pub(crate) description: String,
 }
 
 impl TraceEvent {
@@ -163,73 +168,210 @@ impl TraceEvent {
     }
 }
 
+/// Events are flushed to disk once this many have been written since the last flush
+const FLUSH_EVENTS: usize = 64;
+/// Or once this long has passed since the last flush
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First line of the jsonl log, every line after it is an `EventWrapper`
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
-pub struct EventLog {
-    events: RefCell<Vec<EventWrapper>>,
-    #[serde(skip)]
-    start: Option<Instant>,
+struct LogHeader {
     manifest_paths: HashSet<PathBuf>,
-    #[serde(skip)]
-    output_folder: PathBuf,
+}
+
+/// The complete debug log, this is the json format the log is rendered to once tarpaulin is done
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventLogFile {
+    pub events: Vec<EventWrapper>,
+    pub manifest_paths: HashSet<PathBuf>,
+}
+
+impl EventLogFile {
+    /// Reads a jsonl log. A partially written last line, from tarpaulin being killed mid-write,
+    /// is skipped.
+    pub fn from_jsonl(reader: impl BufRead) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header: LogHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "empty event log",
+                ))
+            }
+        };
+        let mut events = vec![];
+        for line in lines {
+            match serde_json::from_str(&line?) {
+                Ok(event) => events.push(event),
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Self {
+            events,
+            manifest_paths: header.manifest_paths,
+        })
+    }
+}
+
+/// Renders the jsonl log at the path into the json log format, written next to it with a json
+/// extension. Returns the path of the json log.
+pub fn jsonl_to_json(path: &Path) -> io::Result<PathBuf> {
+    let log = EventLogFile::from_jsonl(BufReader::new(File::open(path)?))?;
+    let output = path.with_extension("json");
+    serde_json::to_writer(BufWriter::new(File::create(&output)?), &log)?;
+    Ok(output)
+}
+
+/// Appends events to a jsonl file as they happen so a hang or crash doesn't lose the log
+pub struct EventLog {
+    writer: RefCell<Option<BufWriter<File>>>,
+    path: PathBuf,
+    start: Instant,
+    last_flush: Cell<Instant>,
+    unflushed: Cell<usize>,
+    last_was_marker: Cell<bool>,
 }
 
 impl EventLog {
     pub fn new(manifest_paths: HashSet<PathBuf>, config: &Config) -> Self {
+        let output_folder = config.output_dir();
+        let fname = format!("tarpaulin_{}.jsonl", Local::now().format("%Y%m%d%H%M%S"));
+        let path = output_folder.join(fname);
+        info!("Writing tarpaulin debug log to {}", path.display());
+        let writer = create_dir_all(&output_folder)
+            .and_then(|_| File::create(&path))
+            .map(BufWriter::new)
+            .and_then(|mut writer| {
+                serde_json::to_writer(&mut writer, &LogHeader { manifest_paths })?;
+                writeln!(writer)?;
+                writer.flush()?;
+                Ok(writer)
+            });
+        let writer = match writer {
+            Ok(writer) => Some(writer),
+            Err(e) => {
+                warn!("Failed to create log file: {e}");
+                None
+            }
+        };
+        let start = Instant::now();
         Self {
-            events: RefCell::new(vec![]),
-            start: Some(Instant::now()),
-            manifest_paths,
-            output_folder: config.output_dir(),
+            writer: RefCell::new(writer),
+            path,
+            start,
+            last_flush: Cell::new(start),
+            unflushed: Cell::new(0),
+            last_was_marker: Cell::new(false),
         }
     }
 
-    pub fn push_binary(&self, binary: TestBinary) {
-        self.events.borrow_mut().push(EventWrapper::new(
-            Event::BinaryLaunch(binary),
-            self.start.unwrap(),
-        ));
+    fn push(&self, event: Event, flush: bool) {
+        self.last_was_marker.set(matches!(event, Event::Marker(_)));
+        let mut writer = self.writer.borrow_mut();
+        let output = match writer.as_mut() {
+            Some(output) => output,
+            None => return,
+        };
+        let event = EventWrapper::new(event, self.start);
+        let res = serde_json::to_writer(&mut *output, &event)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(output));
+        self.unflushed.set(self.unflushed.get() + 1);
+        let res = res.and_then(|_| {
+            if flush
+                || self.unflushed.get() >= FLUSH_EVENTS
+                || self.last_flush.get().elapsed() >= FLUSH_INTERVAL
+            {
+                self.unflushed.set(0);
+                self.last_flush.set(Instant::now());
+                output.flush()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = res {
+            warn!("Failed to write to log file, no more events will be logged: {e}");
+            *writer = None;
+        }
+    }
+
+    pub fn push_binary(&self, binary: TestBinary, ignored: bool) {
+        let event = if ignored {
+            Event::IgnoredBinaryLaunch(binary)
+        } else {
+            Event::BinaryLaunch(binary)
+        };
+        self.push(event, true);
+    }
+
+    pub fn push_serialised(&self, reason: String) {
+        self.push(Event::TestsSerialised(reason), false);
     }
 
     pub fn push_trace(&self, event: TraceEvent) {
-        self.events
-            .borrow_mut()
-            .push(EventWrapper::new(Event::Trace(event), self.start.unwrap()));
+        self.push(Event::Trace(event), false);
     }
 
     pub fn push_config(&self, name: String) {
-        self.events.borrow_mut().push(EventWrapper::new(
-            Event::ConfigLaunch(name),
-            self.start.unwrap(),
-        ));
+        self.push(Event::ConfigLaunch(name), true);
     }
 
     pub fn push_marker(&self) {
         // Prevent back to back markers when we spend a lot of time waiting on events
-        if self
-            .events
-            .borrow()
-            .last()
-            .filter(|x| matches!(x.event, Event::Marker(_)))
-            .is_none()
-        {
-            self.events
-                .borrow_mut()
-                .push(EventWrapper::new(Event::Marker(None), self.start.unwrap()));
+        if !self.last_was_marker.get() {
+            self.push(Event::Marker(None), false);
         }
     }
 }
 
 impl Drop for EventLog {
     fn drop(&mut self) {
-        let fname = format!("tarpaulin_{}.json", Local::now().format("%Y%m%d%H%M%S"));
-        let path = self.output_folder.join(fname);
-        info!("Serializing tarpaulin debug log to {}", path.display());
-        if let Ok(output) = File::create(path) {
-            if let Err(e) = serde_json::to_writer(output, self) {
-                warn!("Failed to serialise or write result: {e}");
-            }
-        } else {
-            warn!("Failed to create log file");
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return,
+        };
+        if let Err(e) = writer.flush() {
+            warn!("Failed to write log file: {e}");
+            return;
+        }
+        match jsonl_to_json(&self.path) {
+            Ok(path) => info!("Serialized tarpaulin debug log to {}", path.display()),
+            Err(e) => warn!("Failed to convert log to json: {e}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn jsonl_log_renders_to_json() {
+        let output = env::temp_dir().join("tarpaulin_event_log");
+        let mut config = Config::default();
+        config.output_directory = Some(output.clone());
+        let log = EventLog::new(HashSet::from([PathBuf::from("/project")]), &config);
+        log.push_config("default".to_string());
+        log.push_marker();
+        log.push_marker();
+        log.push_serialised("reason".to_string());
+        let jsonl = log.path.clone();
+        drop(log);
+
+        let mut contents = fs::read_to_string(&jsonl).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+        // A crash mid-write leaves a truncated last line
+        contents.push_str("{\"Marker\":nu");
+        let log = EventLogFile::from_jsonl(contents.as_bytes()).unwrap();
+        assert_eq!(log.events.len(), 3);
+        assert!(log.manifest_paths.contains(Path::new("/project")));
+
+        let json: EventLogFile =
+            serde_json::from_reader(File::open(jsonl.with_extension("json")).unwrap()).unwrap();
+        assert!(json.events == log.events);
+        let _ = fs::remove_dir_all(output);
+    }
+}
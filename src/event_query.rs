@@ -0,0 +1,269 @@
+//! Answers the usual questions about a `--dump-traces` event log: which binaries took longest,
+//! where the state machine spent its time and which signals the tests received.
+use crate::errors::RunError;
+use crate::event_log::{Event, EventLogFile, EventWrapper};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How many of the slowest binaries are listed
+const SLOWEST_BINARIES: usize = 10;
+
+/// Descriptions of the events recording what the tracer did rather than what it saw, signals on
+/// these are being passed on to the test so they'd be counted twice
+const TRACER_ACTIONS: [&str; 5] = [
+    "Try continue child",
+    "Continue child",
+    "Step child",
+    "Detach child",
+    "Do nothing",
+];
+
+/// An event along with what was running when it happened
+struct Annotated<'a> {
+    event: &'a Event,
+    created: f64,
+    /// Time until the next event in the log
+    elapsed: f64,
+    config: Option<&'a str>,
+    binary: Option<String>,
+}
+
+/// A `key=value` filter given to the events command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Events while a binary with the name in its path was running
+    Binary(String),
+    /// Events while the named config was running
+    Config(String),
+    /// Trace events of the process or its new child
+    Pid(i64),
+    /// Trace events with the signal
+    Signal(String),
+}
+
+impl FromStr for EventFilter {
+    type Err = String;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid event filter '{filter}', expected KEY=VALUE");
+        let (key, value) = filter.split_once('=').ok_or_else(invalid)?;
+        match key {
+            "binary" => Ok(Self::Binary(value.to_string())),
+            "config" => Ok(Self::Config(value.to_string())),
+            "pid" => value.parse().map(Self::Pid).map_err(|_| invalid()),
+            "signal" => Ok(Self::Signal(value.to_string())),
+            _ => Err(format!(
+                "unknown event filter '{key}', expected one of binary, config, pid or signal"
+            )),
+        }
+    }
+}
+
+impl EventFilter {
+    fn matches(&self, event: &Annotated) -> bool {
+        match self {
+            Self::Binary(name) => event.binary.as_ref().is_some_and(|x| x.contains(name)),
+            Self::Config(name) => event.config == Some(name.as_str()),
+            Self::Pid(pid) => match event.event {
+                Event::Trace(t) => t.pid == Some(*pid) || t.child == Some(*pid),
+                _ => false,
+            },
+            Self::Signal(signal) => match event.event {
+                Event::Trace(t) => t.signal.as_deref() == Some(signal.as_str()),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Name of the state machine phase the event starts
+fn phase(event: &Event) -> &str {
+    match event {
+        Event::ConfigLaunch(_) => "Config launch",
+        Event::BinaryLaunch(_) | Event::IgnoredBinaryLaunch(_) => "Binary launch",
+        Event::TestsSerialised(_) => "Tests serialised",
+        Event::Trace(t) => &t.description,
+        Event::Marker(_) => "Waiting",
+    }
+}
+
+fn annotate(events: &[EventWrapper]) -> Vec<Annotated<'_>> {
+    let mut config = None;
+    let mut binary = None;
+    let mut result = Vec::with_capacity(events.len());
+    for (i, wrapper) in events.iter().enumerate() {
+        match &wrapper.event {
+            Event::ConfigLaunch(name) => {
+                config = Some(name.as_str());
+                binary = None;
+            }
+            Event::BinaryLaunch(b) | Event::IgnoredBinaryLaunch(b) => {
+                binary = Some(b.path().display().to_string());
+            }
+            _ => {}
+        }
+        let elapsed = events
+            .get(i + 1)
+            .map(|next| (next.created - wrapper.created).max(0.0))
+            .unwrap_or_default();
+        result.push(Annotated {
+            event: &wrapper.event,
+            created: wrapper.created,
+            elapsed,
+            config,
+            binary: binary.clone(),
+        });
+    }
+    result
+}
+
+/// Summary of the events matching the query
+#[derive(Clone, Debug, Default, PartialEq)]
+struct EventSummary {
+    /// Binaries and the seconds spent in them, slowest first
+    binaries: Vec<(String, f64)>,
+    /// Seconds spent in each phase, the most time first
+    phases: Vec<(String, f64)>,
+    /// Signals the tests received and how many times
+    signals: BTreeMap<String, usize>,
+}
+
+fn sorted_by_time(times: BTreeMap<String, f64>) -> Vec<(String, f64)> {
+    let mut times = times.into_iter().collect::<Vec<_>>();
+    times.sort_by(|a, b| b.1.total_cmp(&a.1));
+    times
+}
+
+fn summarise(events: &[Annotated], filters: &[EventFilter], since: Option<f64>) -> EventSummary {
+    let mut binaries = BTreeMap::<String, f64>::new();
+    let mut phases = BTreeMap::<String, f64>::new();
+    let mut signals = BTreeMap::new();
+    for event in events
+        .iter()
+        .filter(|x| since.is_none_or(|since| x.created >= since))
+        .filter(|x| filters.iter().all(|f| f.matches(x)))
+    {
+        if let Some(binary) = &event.binary {
+            *binaries.entry(binary.clone()).or_default() += event.elapsed;
+        }
+        *phases.entry(phase(event.event).to_string()).or_default() += event.elapsed;
+        if let Event::Trace(t) = event.event {
+            if let Some(signal) = &t.signal {
+                if !TRACER_ACTIONS.contains(&t.description.as_str()) {
+                    *signals.entry(signal.clone()).or_default() += 1;
+                }
+            }
+        }
+    }
+    EventSummary {
+        binaries: sorted_by_time(binaries),
+        phases: sorted_by_time(phases),
+        signals,
+    }
+}
+
+fn read_log(path: &Path) -> Result<EventLogFile, RunError> {
+    let reader = BufReader::new(File::open(path)?);
+    let log = if path.extension().is_some_and(|x| x == "jsonl") {
+        EventLogFile::from_jsonl(reader)?
+    } else {
+        serde_json::from_reader(reader)?
+    };
+    Ok(log)
+}
+
+/// Prints the slowest binaries, time per phase and signals received for the events in the log
+/// matching every filter. `since` is in seconds from the start of the run.
+pub fn query_events(
+    log: &Path,
+    filters: &[EventFilter],
+    since: Option<u64>,
+) -> Result<(), RunError> {
+    let log = read_log(log)?;
+    let summary = summarise(&annotate(&log.events), filters, since.map(|x| x as f64));
+
+    println!("Slowest binaries:");
+    for (binary, time) in summary.binaries.iter().take(SLOWEST_BINARIES) {
+        println!("  {time:.3}s {binary}");
+    }
+    println!("Time per phase:");
+    for (phase, time) in &summary.phases {
+        println!("  {time:.3}s {phase}");
+    }
+    println!("Signals received:");
+    for (signal, count) in &summary.signals {
+        println!("  {signal}: {count}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cargo::TestBinary;
+    use crate::event_log::TraceEvent;
+    use std::path::PathBuf;
+
+    fn trace(pid: i64, signal: Option<&str>, description: &str) -> Event {
+        let mut event = TraceEvent::default();
+        event.pid = Some(pid);
+        event.signal = signal.map(String::from);
+        event.description = description.to_string();
+        Event::Trace(event)
+    }
+
+    fn events() -> Vec<EventWrapper> {
+        let binary = |name: &str| Event::BinaryLaunch(TestBinary::new(PathBuf::from(name), None));
+        vec![
+            (Event::ConfigLaunch("default".to_string()), 0.0),
+            (binary("target/debug/deps/foo-123"), 1.0),
+            (trace(10, Some("SIGSEGV"), "Non-trace stop"), 2.0),
+            (trace(10, Some("SIGSEGV"), "Continue child"), 2.5),
+            (Event::Marker(None), 3.0),
+            (binary("target/debug/deps/bar-456"), 6.0),
+            (trace(20, None, "Exited"), 7.0),
+        ]
+        .into_iter()
+        .map(|(event, created)| EventWrapper { event, created })
+        .collect()
+    }
+
+    #[test]
+    fn time_attributed_to_binaries_and_phases() {
+        let events = events();
+        let annotated = annotate(&events);
+        let summary = summarise(&annotated, &[], None);
+        assert_eq!(
+            summary.binaries,
+            vec![
+                ("target/debug/deps/foo-123".to_string(), 5.0),
+                ("target/debug/deps/bar-456".to_string(), 1.0)
+            ]
+        );
+        assert_eq!(summary.phases[0], ("Waiting".to_string(), 3.0));
+        assert_eq!(summary.signals["SIGSEGV"], 1);
+
+        let filters = ["binary=bar".parse().unwrap()];
+        let summary = summarise(&annotated, &filters, None);
+        assert_eq!(summary.binaries.len(), 1);
+        assert!(summary.signals.is_empty());
+
+        let summary = summarise(&annotated, &[EventFilter::Pid(10)], Some(2.2));
+        assert_eq!(summary.phases, vec![("Continue child".to_string(), 0.5)]);
+    }
+
+    #[test]
+    fn filters_parsed() {
+        assert_eq!("pid=3".parse(), Ok(EventFilter::Pid(3)));
+        assert_eq!(
+            "signal=SIGILL".parse(),
+            Ok(EventFilter::Signal("SIGILL".to_string()))
+        );
+        assert!("pid=abc".parse::<EventFilter>().is_err());
+        assert!("binary".parse::<EventFilter>().is_err());
+        assert!("thread=1".parse::<EventFilter>().is_err());
+    }
+}
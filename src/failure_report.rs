@@ -0,0 +1,170 @@
+//! When tarpaulin fails it can write out a json file containing the error, the configuration used
+//! and some information about the environment along with the most recent log lines. This is
+//! intended to be attached to bug reports or used by CI to annotate failures.
+use crate::config::{Config, TraceEngine};
+use crate::errors::{ErrorCategory, RunError};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::env;
+use std::fs::{create_dir_all, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Number of log lines kept for the failure report
+const LOG_HISTORY: usize = 200;
+
+const FAILURE_REPORT: &str = "failure-report.json";
+
+lazy_static! {
+    static ref RECENT_LOGS: Mutex<VecDeque<String>> =
+        Mutex::new(VecDeque::with_capacity(LOG_HISTORY));
+}
+
+/// Set when a failure report may be written, until then the log lines aren't kept
+static KEEP_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Starts keeping the most recent log lines so they can be added to the failure report
+pub(crate) fn keep_recent_logs() {
+    KEEP_LOGS.store(true, Ordering::Relaxed);
+}
+
+/// Writer used for tarpaulin's logging, forwards everything to stdout or stderr while also
+/// retaining the last few lines so they can be added to the failure report when it's enabled.
+pub(crate) struct LogWriter {
+    stderr: bool,
+}
+
+impl LogWriter {
+    pub(crate) fn new(stderr: bool) -> Self {
+        Self { stderr }
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lazy_static! {
+            static ref ANSI_ESCAPE: Regex = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        }
+        let written = if self.stderr {
+            io::stderr().write(buf)?
+        } else {
+            io::stdout().write(buf)?
+        };
+        if !KEEP_LOGS.load(Ordering::Relaxed) {
+            return Ok(written);
+        }
+        if let Ok(mut logs) = RECENT_LOGS.lock() {
+            let text = String::from_utf8_lossy(&buf[..written]);
+            for line in text.lines().filter(|x| !x.trim().is_empty()) {
+                if logs.len() == LOG_HISTORY {
+                    logs.pop_front();
+                }
+                logs.push_back(ANSI_ESCAPE.replace_all(line, "").into_owned());
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.stderr {
+            io::stderr().flush()
+        } else {
+            io::stdout().flush()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Environment {
+    os: &'static str,
+    arch: &'static str,
+    cargo_version: Option<String>,
+    rustc_version: Option<String>,
+    engines: Vec<TraceEngine>,
+    ptrace_supported: bool,
+    variables: BTreeMap<String, String>,
+}
+
+impl Environment {
+    fn probe(configs: &[Config]) -> Self {
+        let version = |program: &str| {
+            Command::new(program)
+                .arg("--version")
+                .output()
+                .ok()
+                .filter(|x| x.status.success())
+                .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+        };
+        let variables = env::vars()
+            .filter(|(k, _)| {
+                k.starts_with("CARGO")
+                    || k.starts_with("RUST")
+                    || k.starts_with("LLVM")
+                    || k == "TARPAULIN"
+            })
+            .filter(|(k, _)| !k.contains("TOKEN"))
+            .collect();
+        Self {
+            os: env::consts::OS,
+            arch: env::consts::ARCH,
+            cargo_version: version("cargo"),
+            rustc_version: version("rustc"),
+            engines: configs.iter().map(|c| c.engine()).collect(),
+            ptrace_supported: cfg!(ptrace_supported),
+            variables,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FailureReport {
+    tarpaulin_version: &'static str,
+    code: &'static str,
+    category: ErrorCategory,
+    message: String,
+    environment: Environment,
+    configs: Vec<Config>,
+    recent_logs: Vec<String>,
+}
+
+/// Writes `failure-report.json` to the tarpaulin folder in the target directory, returning the
+/// path written to.
+pub fn write_failure_report(configs: &[Config], error: &RunError) -> io::Result<PathBuf> {
+    let mut path = configs.first().map(|c| c.target_dir()).unwrap_or_default();
+    path.push("tarpaulin");
+    create_dir_all(&path)?;
+    path.push(FAILURE_REPORT);
+
+    let configs = configs
+        .iter()
+        .cloned()
+        .map(|mut c| {
+            // Don't want to leak the coveralls token into an artifact people will share
+            if c.coveralls.is_some() {
+                c.coveralls = Some("<redacted>".to_string());
+            }
+            c
+        })
+        .collect::<Vec<_>>();
+
+    let report = FailureReport {
+        tarpaulin_version: env!("CARGO_PKG_VERSION"),
+        code: error.code(),
+        category: error.category(),
+        message: error.to_string(),
+        environment: Environment::probe(&configs),
+        configs,
+        recent_logs: RECENT_LOGS
+            .lock()
+            .map(|x| x.iter().cloned().collect())
+            .unwrap_or_default(),
+    };
+    let file = File::create(&path)?;
+    serde_json::to_writer_pretty(file, &report)?;
+    Ok(path)
+}
@@ -0,0 +1,181 @@
+//! Coverage goals for areas of a project from a `.tarpaulin-goals` file in the project root. Each
+//! line is a path glob relative to the root followed by the minimum coverage percentage for the
+//! files it matches, and like CODEOWNERS the last matching line owns a file:
+//!
+//! ```text
+//! # Everything needs at least 60%
+//! **           60
+//! src/parser/  90
+//! src/cli/*.rs 40
+//! ```
+//!
+//! A pattern ending in `/` matches everything under that directory.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::portable_path;
+use crate::traces::TraceMap;
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::Path;
+use tracing::{error, info};
+
+pub const GOALS_FILE: &str = ".tarpaulin-goals";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Goal {
+    pattern: Pattern,
+    /// Minimum coverage percentage
+    minimum: f64,
+}
+
+/// Coverage of the files owned by one goal
+#[derive(Debug, Clone, PartialEq)]
+struct GoalResult {
+    pattern: String,
+    minimum: f64,
+    files: usize,
+    covered: usize,
+    coverable: usize,
+}
+
+impl GoalResult {
+    fn coverage(&self) -> f64 {
+        if self.coverable == 0 {
+            100.0
+        } else {
+            100.0 * self.covered as f64 / self.coverable as f64
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.coverage() >= self.minimum
+    }
+}
+
+fn parse_goals(contents: &str) -> Result<Vec<Goal>, String> {
+    let mut goals = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |reason: String| format!("{} line {}: {}", GOALS_FILE, i + 1, reason);
+        let (pattern, minimum) = line
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| invalid("expected a path glob and a percentage".to_string()))?;
+        let minimum = minimum
+            .trim_end_matches('%')
+            .parse::<f64>()
+            .map_err(|e| invalid(format!("invalid percentage '{minimum}': {e}")))?;
+        let mut pattern = pattern.trim().trim_start_matches("./").to_string();
+        if pattern.ends_with('/') {
+            pattern.push_str("**");
+        }
+        let pattern = Pattern::new(&pattern).map_err(|e| invalid(e.to_string()))?;
+        goals.push(Goal { pattern, minimum });
+    }
+    Ok(goals)
+}
+
+fn check_goals(goals: &[Goal], traces: &TraceMap, root: &Path) -> Vec<GoalResult> {
+    let mut results = goals
+        .iter()
+        .map(|goal| GoalResult {
+            pattern: goal.pattern.to_string(),
+            minimum: goal.minimum,
+            files: 0,
+            covered: 0,
+            coverable: 0,
+        })
+        .collect::<Vec<_>>();
+    for file in traces.files() {
+        let relative = portable_path(file, root);
+        let owner = goals
+            .iter()
+            .rposition(|x| x.pattern.matches_path_with(&relative, MATCH_OPTIONS));
+        if let Some(owner) = owner {
+            let result = &mut results[owner];
+            result.files += 1;
+            result.covered += traces.covered_in_path(file);
+            result.coverable += traces.coverable_in_path(file);
+        }
+    }
+    results
+}
+
+/// Checks the coverage goals in the `.tarpaulin-goals` file of the project if there is one,
+/// printing how each goal did and failing if any weren't met
+pub(crate) fn check_coverage_goals(config: &Config, traces: &TraceMap) -> Result<(), RunError> {
+    let root = config.root();
+    let contents = match fs::read_to_string(root.join(GOALS_FILE)) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(()),
+    };
+    let goals = parse_goals(&contents).map_err(RunError::Manifest)?;
+    let results = check_goals(&goals, traces, &root);
+    info!("Coverage goals:");
+    for result in &results {
+        let status = if result.passed() { "pass" } else { "FAIL" };
+        info!(
+            "{} {} {:.2}% (minimum {}%) over {} files",
+            status,
+            result.pattern,
+            result.coverage(),
+            result.minimum,
+            result.files
+        );
+    }
+    let failed = results.iter().filter(|x| !x.passed()).count();
+    if failed > 0 {
+        let error = RunError::GoalsNotMet(failed, results.len());
+        error!("{}", error);
+        Err(error)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    #[test]
+    fn goals_parsed() {
+        let goals = parse_goals("# comment\n** 60\n\nsrc/parser/ 90% # strict\n").unwrap();
+        assert_eq!(goals.len(), 2);
+        assert_eq!(goals[1].pattern.as_str(), "src/parser/**");
+        assert_eq!(goals[1].minimum, 90.0);
+        assert!(parse_goals("src/").is_err());
+        assert!(parse_goals("src/ lots").is_err());
+    }
+
+    #[test]
+    fn last_matching_goal_owns_file() {
+        let mut traces = TraceMap::new();
+        for (file, hits) in [
+            ("/project/src/lib.rs", 1),
+            ("/project/src/parser/mod.rs", 0),
+            ("/project/src/parser/lexer.rs", 1),
+        ] {
+            let mut trace = Trace::new_stub(1);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(Path::new(file), trace);
+        }
+        let goals = parse_goals("** 60\nsrc/parser/ 90\ntests/ 10").unwrap();
+        let results = check_goals(&goals, &traces, Path::new("/project"));
+        assert_eq!(results[0].files, 1);
+        assert!(results[0].passed());
+        assert_eq!(results[1].files, 2);
+        assert_eq!(results[1].coverage(), 50.0);
+        assert!(!results[1].passed());
+        assert_eq!(results[2].files, 0);
+        assert!(results[2].passed());
+    }
+}
@@ -0,0 +1,105 @@
+//! Network settings shared by every integration which talks to an online service. The clients
+//! are built here with `--proxy` and `--ca-cert` applied, without a `--proxy` the proxies in the
+//! standard environment variables are used.
+use crate::config::Config;
+use crate::errors::RunError;
+use std::borrow::Cow;
+#[cfg(any(feature = "coveralls", feature = "github", feature = "codecov"))]
+use tracing::info;
+
+/// The proxy URL with any username and password replaced, so it can be logged
+#[cfg_attr(
+    not(any(feature = "coveralls", feature = "github", feature = "codecov")),
+    allow(dead_code)
+)]
+fn redact_credentials(url: &str) -> Cow<'_, str> {
+    let authority_start = url.find("://").map(|x| x + 3).unwrap_or(0);
+    let authority_end = url[authority_start..]
+        .find('/')
+        .map_or(url.len(), |x| authority_start + x);
+    match url[authority_start..authority_end].rfind('@') {
+        Some(at) => Cow::Owned(format!(
+            "{}***{}",
+            &url[..authority_start],
+            &url[authority_start + at..]
+        )),
+        None => Cow::Borrowed(url),
+    }
+}
+
+/// Checks `--proxy` and `--ca-cert` before anything is sent
+#[cfg_attr(
+    not(any(feature = "coveralls", feature = "github", feature = "codecov")),
+    allow(dead_code)
+)]
+fn validate(config: &Config) -> Result<(), RunError> {
+    if let Some(proxy) = &config.proxy {
+        if !proxy.contains("://") {
+            let proxy = redact_credentials(proxy);
+            return Err(RunError::OutFormat(format!(
+                "Proxy {proxy} should be a URL including the scheme, such as http://{proxy}"
+            )));
+        }
+    }
+    if let Some(ca_cert) = &config.ca_cert {
+        if !ca_cert.is_file() {
+            return Err(RunError::OutFormat(format!(
+                "CA certificate {} doesn't exist",
+                ca_cert.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// HTTP client for the online services with the proxy and CA certificates from the config
+#[cfg(any(feature = "coveralls", feature = "github", feature = "codecov"))]
+pub(crate) fn client(config: &Config) -> Result<reqwest::blocking::Client, RunError> {
+    validate(config)?;
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent(concat!("cargo-tarpaulin/", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = &config.proxy {
+        let redacted = redact_credentials(proxy);
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .map_err(|e| RunError::OutFormat(format!("Invalid proxy {redacted}: {e}")))?;
+        info!("Using proxy {}", redacted);
+        builder = builder.proxy(proxy);
+    }
+    if let Some(ca_cert) = &config.ca_cert {
+        let invalid = |e: &dyn std::fmt::Display| {
+            RunError::OutFormat(format!("Invalid CA certificate {}: {e}", ca_cert.display()))
+        };
+        let pem = std::fs::read(ca_cert).map_err(|e| invalid(&e))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| invalid(&e))?;
+        if certs.is_empty() {
+            return Err(invalid(&"no certificates found"));
+        }
+        info!("Using CA certificate {}", ca_cert.display());
+        builder = certs
+            .into_iter()
+            .fold(builder.tls_built_in_root_certs(false), |builder, cert| {
+                builder.add_root_certificate(cert)
+            });
+    }
+    builder
+        .build()
+        .map_err(|e| RunError::OutFormat(format!("Couldn't create the HTTP client: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_credentials_redacted() {
+        assert_eq!(
+            redact_credentials("http://proxy.corp:3128"),
+            "http://proxy.corp:3128"
+        );
+        assert_eq!(
+            redact_credentials("http://user:p@ss@proxy.corp:3128/path@x"),
+            "http://***@proxy.corp:3128/path@x"
+        );
+        assert_eq!(redact_credentials("user:pass@proxy"), "***@proxy");
+    }
+}
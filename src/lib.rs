@@ -1,30 +1,62 @@
 use crate::cargo::TestBinary;
+use crate::cleanup::{apply_retention, remove_profraws};
 use crate::config::*;
+use crate::engine_compare::compare_engines;
 use crate::errors::*;
 use crate::event_log::*;
+use crate::failure_report::{keep_recent_logs, write_failure_report, LogWriter};
 use crate::path_utils::*;
 use crate::process_handling::*;
-use crate::report::report_coverage;
-use crate::source_analysis::{LineAnalysis, SourceAnalysis};
+use crate::report::upload::upload_stored_report;
+use crate::report::{get_previous_result, html, record_history, report_coverage};
+use crate::run_cache::{RunCache, RunKey};
+use crate::run_dir::RunGuard;
+use crate::source_analysis::{get_analysis, LineAnalysis};
+use crate::stale_sources::check_stale_sources;
 use crate::test_loader::*;
 use crate::traces::*;
-use std::ffi::OsString;
-use std::fs::{create_dir_all, remove_dir_all};
-use std::io;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::thread::{self, Scope};
+use std::time::Instant;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{filter::LevelFilter, EnvFilter};
 
 pub mod args;
+mod callgrind;
 pub mod cargo;
+pub mod cleanup;
 pub mod config;
+pub mod engine;
+mod engine_compare;
 pub mod errors;
 pub mod event_log;
+pub mod event_query;
+pub mod failure_report;
+mod goals;
+mod http;
+mod objects;
+pub mod patch;
 pub mod path_utils;
+mod plan;
 mod process_handling;
+pub mod ratchet;
 pub mod report;
+mod reproduce;
+mod rerun;
+mod run_cache;
+mod run_dir;
+pub mod run_report;
 pub mod source_analysis;
+mod stale_sources;
 pub mod statemachine;
+pub mod test_impact;
 pub mod test_loader;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod toolchain;
 pub mod traces;
 
 const RUST_LOG_ENV: &str = "RUST_LOG";
@@ -35,12 +67,15 @@ pub fn setup_logging(color: Color, debug: bool, verbose: bool, stderr: bool) {
     let base_exceptions = |env: EnvFilter| {
         if debug {
             env.add_directive("cargo_tarpaulin=trace".parse().unwrap())
+                .add_directive("tarpaulin_analysis=trace".parse().unwrap())
                 .add_directive("llvm_profparser=trace".parse().unwrap())
         } else if verbose {
             env.add_directive("cargo_tarpaulin=debug".parse().unwrap())
+                .add_directive("tarpaulin_analysis=debug".parse().unwrap())
                 .add_directive("llvm_profparser=warn".parse().unwrap())
         } else {
             env.add_directive("cargo_tarpaulin=info".parse().unwrap())
+                .add_directive("tarpaulin_analysis=info".parse().unwrap())
                 .add_directive("llvm_profparser=error".parse().unwrap())
         }
         .add_directive(LevelFilter::INFO.into())
@@ -72,11 +107,9 @@ pub fn setup_logging(color: Color, debug: bool, verbose: bool, stderr: bool) {
         .with_env_filter(filter)
         .with_ansi(with_ansi);
 
-    let res = if stderr {
-        builder.with_writer(io::stderr).try_init()
-    } else {
-        builder.try_init()
-    };
+    let res = builder
+        .with_writer(move || LogWriter::new(stderr))
+        .try_init();
 
     if let Err(e) = res {
         eprintln!("Logging may be misconfigured: {e}");
@@ -92,6 +125,14 @@ pub fn trace(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
     let mut fail_fast_ret = 0;
     let mut tarpaulin_result = Ok(());
     let mut bad_threshold = Ok(());
+    let mut cache = RunCache::default();
+    // With several configs the HTML index links a report of each one's own coverage
+    let html_config = configs
+        .iter()
+        .find(|c| c.name == "report")
+        .or_else(|| configs.first())
+        .filter(|c| c.generate.contains(&OutputFile::Html));
+    let run_configs = configs.iter().filter(|c| c.name != "report").count();
 
     for config in configs.iter() {
         if config.name == "report" {
@@ -105,7 +146,12 @@ pub fn trace(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
 
         create_target_dir(config);
 
-        match launch_tarpaulin(config, &logger) {
+        let result = if config.compare_engines {
+            compare_engines(config, &logger)
+        } else {
+            launch_with_cache(config, &logger, &mut cache)
+        };
+        match result {
             Ok((t, r)) => {
                 if config.no_fail_fast {
                     fail_fast_ret |= r;
@@ -116,6 +162,12 @@ pub fn trace(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
                     // Otherwise threshold is a global one and we'll let the caller handle it
                     bad_threshold = check_fail_threshold(&t, config);
                 }
+                if let Some(report_config) = html_config.filter(|_| run_configs > 1) {
+                    let name = config_name(config);
+                    if let Err(e) = html::export_config(&t, &name, report_config) {
+                        warn!("Failed to write the HTML report of {}: {}", name, e);
+                    }
+                }
                 tracemap.merge(&t);
             }
             Err(e) => {
@@ -130,6 +182,7 @@ pub fn trace(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
     // It's OK that bad_threshold, tarpaulin_result may be overwritten in a loop
     if let Err(bad_limit) = bad_threshold {
         // Failure threshold probably more important than reporting failing
+        let _ = record_history(&configs[0], &tracemap);
         let _ = report_coverage(&configs[0], &tracemap);
         Err(bad_limit)
     } else if ret == 0 {
@@ -179,24 +232,93 @@ fn check_fail_threshold(traces: &TraceMap, config: &Config) -> Result<(), RunErr
         Some(limit) if percent < *limit => {
             let error = RunError::BelowThreshold(percent, *limit);
             error!("{}", error);
-            Err(error)
+            return Err(error);
         }
-        _ => Ok(()),
+        _ => {}
     }
+    if let Some(limit) = config.fail_on_uncovered_public {
+        let uncovered = traces.uncovered_public_functions().len();
+        if uncovered > limit {
+            let error = RunError::UncoveredPublicApi(uncovered, limit);
+            error!("{}", error);
+            return Err(error);
+        }
+    }
+    Ok(())
 }
 
 pub fn run(configs: &[Config]) -> Result<(), RunError> {
-    if configs.iter().any(|x| x.engine() == TraceEngine::Llvm) {
+    for config in configs {
+        config.validate()?;
+    }
+    let failure_report = configs.iter().any(|c| c.failure_report);
+    if failure_report {
+        keep_recent_logs();
+    }
+    let result = run_coverage(configs);
+    if let Err(e) = result.as_ref() {
+        if failure_report {
+            match write_failure_report(configs, e) {
+                Ok(path) => info!("Failure report written to {}", path.display()),
+                Err(e) => warn!("Unable to write failure report: {}", e),
+            }
+        }
+    }
+    result
+}
+
+fn run_coverage(configs: &[Config]) -> Result<(), RunError> {
+    if configs.iter().any(|c| c.dry_run.is_some()) {
+        return plan::print_plan(configs);
+    }
+    if configs.iter().any(|c| c.upload_only) {
+        return configs
+            .iter()
+            .find(|c| c.is_coveralls() || c.codecov)
+            .or(configs.first())
+            .map_or(Ok(()), upload_stored_report);
+    }
+    if let Some(config) = configs.iter().find(|c| !c.from_callgrind.is_empty()) {
+        let mut tracemap = callgrind::import_callgrind(config)?;
+        for dir in get_source_walker(config) {
+            tracemap.add_file(dir.path());
+        }
+        return report_tracemap(configs, tracemap);
+    }
+    let _run_guard = match configs.first() {
+        Some(config) => {
+            apply_retention(config);
+            Some(RunGuard::acquire(config)?)
+        }
+        None => None,
+    };
+    let llvm = configs.iter().any(|x| x.uses_llvm());
+    if llvm {
+        if configs[0].profraw_cleanup() != ProfrawCleanup::Never {
+            remove_profraws(&configs[0]);
+        }
         let profraw_dir = configs[0].profraw_dir();
-        let _ = remove_dir_all(&profraw_dir);
         if let Err(e) = create_dir_all(&profraw_dir) {
             warn!(
-                "Unable to create profraw directory in tarpaulin's target folder: {}",
+                "Unable to create profraw directory {}: {}",
+                profraw_dir.display(),
                 e
             );
         }
     }
-    let (tracemap, ret) = collect_tracemap(configs)?;
+    // Cleaned up before checking the result so a failed run doesn't leave the profraws behind
+    let collected = collect_tracemap(configs);
+    if llvm {
+        check_profraw_size(&configs[0]);
+        if configs[0].profraw_cleanup() == ProfrawCleanup::Always {
+            remove_profraws(&configs[0]);
+        }
+    }
+    let (tracemap, ret) = collected?;
+    // Recorded before reporting so the HTML report's trend includes this run
+    for c in report_configs(configs) {
+        record_history(c, &tracemap)?;
+    }
     report_tracemap(configs, tracemap)?;
     if ret != 0 {
         // So we had a test fail in a way where we still want to report coverage so since we've now
@@ -207,8 +329,34 @@ pub fn run(configs: &[Config]) -> Result<(), RunError> {
     }
 }
 
+fn check_profraw_size(config: &Config) {
+    if let Some(limit) = config.profraw_size_limit {
+        let size = get_profile_walker(config)
+            .filter_map(|x| x.metadata().ok())
+            .map(|x| x.len())
+            .sum::<u64>();
+        let size = size / (1024 * 1024);
+        if size > limit {
+            warn!(
+                "Profraw files in {} use {}MiB which exceeds the limit of {}MiB. Consider moving them to faster storage with --profraw-dir or using --profraw-cleanup Always",
+                config.profraw_dir().display(),
+                size,
+                limit
+            );
+        }
+    }
+}
+
 fn collect_tracemap(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
     let (mut tracemap, ret) = trace(configs)?;
+    let previous = configs
+        .iter()
+        .find(|c| c.rerun_failed)
+        .and_then(get_previous_result);
+    if let Some(mut previous) = previous {
+        previous.merge_rerun(&tracemap);
+        tracemap = previous;
+    }
     if !configs.is_empty() {
         // Assumption: all configs are for the same project
         for dir in get_source_walker(&configs[0]) {
@@ -219,80 +367,405 @@ fn collect_tracemap(configs: &[Config]) -> Result<(TraceMap, i32), RunError> {
     Ok((tracemap, ret))
 }
 
-pub fn report_tracemap(configs: &[Config], tracemap: TraceMap) -> Result<(), RunError> {
-    let mut reported = false;
-    for c in configs.iter() {
-        if c.no_run || c.name != "report" {
-            continue;
-        }
+/// The configs the merged coverage is reported with, the `report` table or the first config
+fn report_configs(configs: &[Config]) -> Vec<&Config> {
+    let reports = configs
+        .iter()
+        .filter(|c| !c.no_run && c.name == "report")
+        .collect::<Vec<_>>();
+    if reports.is_empty() {
+        configs.first().filter(|c| !c.no_run).into_iter().collect()
+    } else {
+        reports
+    }
+}
 
+pub fn report_tracemap(configs: &[Config], tracemap: TraceMap) -> Result<(), RunError> {
+    for c in report_configs(configs) {
         report_coverage_with_check(c, &tracemap)?;
-        reported = true;
     }
+    Ok(())
+}
 
-    if !reported && !configs.is_empty() && !configs[0].no_run {
-        report_coverage_with_check(&configs[0], &tracemap)?;
+/// Merges the run reports written to `target/tarpaulin` by other runs, such as the jobs of a CI
+/// matrix on different OSes, and reports the combined coverage
+pub fn merge_reports(configs: &[Config], reports: &[PathBuf]) -> Result<(), RunError> {
+    for config in configs {
+        config.validate()?;
     }
-
-    Ok(())
+    let root = configs.first().map(Config::root).unwrap_or_default();
+    let mut tracemap = TraceMap::new();
+    for report in reports {
+        info!("Merging {}", report.display());
+        let mut traces = run_report::read_run_report(report)?;
+        traces.make_native(&root);
+        tracemap.merge(&traces);
+    }
+    // The same line may have been traced at different addresses in each report
+    tracemap.dedup();
+    report_tracemap(configs, tracemap)
 }
 
 fn report_coverage_with_check(c: &Config, tracemap: &TraceMap) -> Result<(), RunError> {
     report_coverage(c, tracemap)?;
-    check_fail_threshold(tracemap, c)
+    check_fail_threshold(tracemap, c)?;
+    // Goals cover parts of the whole project so they're only checked on the merged coverage
+    goals::check_coverage_goals(c, tracemap)
+}
+
+/// Finds the binary a scenario should run, binaries built for running are preferred as they carry
+/// the package information.
+fn find_scenario_binary(executables: &cargo::CargoOutput, bin: &str) -> Option<TestBinary> {
+    let is_bin = |path: &Path| path.file_stem() == Some(OsStr::new(bin));
+    executables
+        .test_binaries
+        .iter()
+        .find(|x| is_bin(x.path()))
+        .cloned()
+        .or_else(|| {
+            executables
+                .binaries
+                .iter()
+                .find(|x| is_bin(x))
+                .map(|x| TestBinary::new(x.clone(), None))
+        })
+}
+
+/// Coverage of the test runs, the llvm counters of the latest run are collected while the next
+/// binary runs so collecting them doesn't hold up the tests
+struct RunPipeline<'scope, 'a> {
+    config: &'a Config,
+    cache: &'a mut RunCache,
+    result: TraceMap,
+    /// The latest run, with the key to cache its coverage under once it's collected
+    latest: Option<(PendingCoverage<'scope>, Option<(RunKey, i32)>)>,
+}
+
+impl<'scope, 'a> RunPipeline<'scope, 'a> {
+    fn new(config: &'a Config, cache: &'a mut RunCache, result: TraceMap) -> Self {
+        Self {
+            config,
+            cache,
+            result,
+            latest: None,
+        }
+    }
+
+    /// Adds the coverage of a run, waiting for the coverage of the run before it to be collected
+    fn push(
+        &mut self,
+        coverage: PendingCoverage<'scope>,
+        cache_key: Option<(RunKey, i32)>,
+        return_code: &mut i32,
+    ) -> Result<(), RunError> {
+        match self.latest.replace((coverage, cache_key)) {
+            Some(previous) => self.collect(previous, return_code),
+            None => Ok(()),
+        }
+    }
+
+    fn collect(
+        &mut self,
+        (coverage, cache_key): (PendingCoverage<'scope>, Option<(RunKey, i32)>),
+        return_code: &mut i32,
+    ) -> Result<(), RunError> {
+        match coverage.wait() {
+            Ok(traces) => {
+                self.result.merge(&traces);
+                if let Some((key, code)) = cache_key {
+                    self.cache.insert(key, traces, code);
+                }
+                Ok(())
+            }
+            Err(e) if self.config.no_fail_fast => {
+                error!("{}", e);
+                *return_code = 101;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds the coverage cached for the key to the result, returning the run's return code if
+    /// there was coverage cached
+    fn reuse(&mut self, key: &RunKey) -> Option<i32> {
+        let (traces, code) = self.cache.get(key)?;
+        self.result.merge(traces);
+        Some(code)
+    }
+
+    /// Waits for the last run's coverage to be collected
+    fn finish(mut self, return_code: &mut i32) -> Result<TraceMap, RunError> {
+        if let Some(latest) = self.latest.take() {
+            self.collect(latest, return_code)?;
+        }
+        Ok(self.result)
+    }
+}
+
+/// Runs the test binary unless a previous config already ran an identical binary with the same
+/// settings, in which case its cached coverage is added to the pipeline's result and the returned
+/// coverage is empty. The key to cache the coverage under is returned when the binary is ran.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn get_cached_coverage<'scope, 'env>(
+    test: &TestBinary,
+    other_binaries: &[PathBuf],
+    analysis: &'env HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+    ignored: bool,
+    logger: &Option<EventLog>,
+    pipeline: &mut RunPipeline<'scope, '_>,
+    scope: &'scope Scope<'scope, 'env>,
+) -> Result<Option<(PendingCoverage<'scope>, i32, Option<RunKey>)>, RunError> {
+    let key = RunCache::key(test, other_binaries, config, ignored);
+    if let Some(code) = key.as_ref().and_then(|k| pipeline.reuse(k)) {
+        info!(
+            "Reusing coverage of {} collected for a previous config",
+            test.path().display()
+        );
+        return Ok(Some((PendingCoverage::ready(TraceMap::new()), code, None)));
+    }
+    let coverage = get_test_coverage(
+        test,
+        other_binaries,
+        analysis,
+        config,
+        ignored,
+        logger,
+        scope,
+    )?;
+    Ok(coverage.map(|(coverage, code)| (coverage, code, key)))
+}
+
+/// The recorded failed runs of the test binaries which were built
+fn failed_runs(
+    runs: Vec<rerun::FailedRun>,
+    executables: &[TestBinary],
+) -> Vec<(TestBinary, rerun::FailedRun)> {
+    let failed = runs
+        .into_iter()
+        .filter_map(|run| {
+            let exe = executables.iter().find(|x| x.path() == run.binary)?;
+            Some((exe.clone(), run))
+        })
+        .collect::<Vec<_>>();
+    info!("Rerunning {} failed test runs", failed.len());
+    failed
 }
 
 /// Launches tarpaulin with the given configuration.
 pub fn launch_tarpaulin(
     config: &Config,
     logger: &Option<EventLog>,
+) -> Result<(TraceMap, i32), RunError> {
+    launch_with_cache(config, logger, &mut RunCache::default())
+}
+
+fn launch_with_cache(
+    config: &Config,
+    logger: &Option<EventLog>,
+    cache: &mut RunCache,
 ) -> Result<(TraceMap, i32), RunError> {
     if !config.name.is_empty() {
         info!("Running config {}", config.name);
     }
 
     info!("Running Tarpaulin");
+    if config.process_per_test && config.engine() != TraceEngine::Llvm {
+        warn!("--process-per-test is only supported with the llvm engine, running tests normally");
+    }
+    if config.allocate_pty && (config.engine() != TraceEngine::Llvm || !cfg!(ptrace_supported)) {
+        warn!("--allocate-pty is only supported with the llvm engine on Linux x86 and x86_64, running tests without a terminal");
+    }
+    if config.isolate_network && !cfg!(ptrace_supported) {
+        return Err(RunError::TestCoverage(
+            "--isolate-network is only supported on Linux".to_string(),
+        ));
+    }
+
+    // Read before building as the record is stale if the tests were built again since
+    let recorded = if config.rerun_failed {
+        Some(rerun::recorded(config)?)
+    } else {
+        None
+    };
+    if !config.no_run {
+        rerun::clear(config);
+    }
 
     let mut result = TraceMap::new();
     let mut return_code = 0i32;
     info!("Building project");
+    let build_start = Instant::now();
     let executables = cargo::get_tests(config)?;
+    let build_time = build_start.elapsed();
     if !config.no_run {
-        let project_analysis = SourceAnalysis::get_analysis(config);
+        let project_analysis = get_analysis(config);
         result.set_functions(project_analysis.create_function_map());
+        result.set_feature_gates(project_analysis.create_feature_gate_map());
+        result.set_code_regions(project_analysis.create_region_map());
+        if config.branch_requested {
+            result.set_branch_arms(project_analysis.create_branch_arm_map());
+        }
+        if config.run_types.contains(&RunType::Doctests) {
+            result.set_doc_examples(project_analysis.create_doc_example_map());
+        }
         let project_analysis = project_analysis.lines;
         let mut other_bins = config.objects().to_vec();
         other_bins.extend(executables.binaries.iter().cloned());
-        for exe in &executables.test_binaries {
-            if exe.should_panic() {
-                info!("Running a test executable that is expected to panic");
+        let failed = recorded.map(|runs| failed_runs(runs, &executables.test_binaries));
+        // Rerunning failures replaces every other run, and when building binaries the scenarios
+        // replace the default run of each binary
+        let plain_runs: &[TestBinary] = if failed.is_some()
+            || (config.command == Mode::Build && !config.scenarios.is_empty())
+        {
+            &[]
+        } else {
+            executables.test_binaries.as_slice()
+        };
+        let scenarios: &[Scenario] = if failed.is_some() {
+            &[]
+        } else {
+            &config.scenarios
+        };
+        let project_analysis = &project_analysis;
+        let other_bins = other_bins.as_slice();
+        result = thread::scope(|scope| {
+            let mut pipeline = RunPipeline::new(config, cache, result);
+            for (exe, run) in failed.iter().flatten() {
+                let coverage = rerun_test_coverage(
+                    exe,
+                    other_bins,
+                    project_analysis,
+                    config,
+                    run.ignored,
+                    logger,
+                    &run.tests,
+                    scope,
+                );
+                let coverage = match coverage {
+                    Ok(coverage) => coverage,
+                    Err(run_error) => {
+                        if config.no_fail_fast {
+                            return_code = 101;
+                            None
+                        } else {
+                            return Err(run_error);
+                        }
+                    }
+                };
+                if let Some((coverage, code)) = coverage {
+                    pipeline.push(coverage, None, &mut return_code)?;
+                    return_code |= if exe.should_panic() {
+                        (code == 0).into()
+                    } else {
+                        code
+                    };
+                }
+                if config.fail_immediately && return_code != 0 {
+                    return Err(RunError::TestFailed);
+                }
             }
-            let coverage =
-                get_test_coverage(exe, &other_bins, &project_analysis, config, false, logger);
-
-            let coverage = match coverage {
-                Ok(coverage) => coverage,
-                Err(run_error) => {
-                    if config.no_fail_fast {
-                        info!("No failing fast!");
-                        return_code = 101;
-                        None
+            for exe in plain_runs {
+                if !config.only_ignored {
+                    if exe.should_panic() {
+                        info!("Running a test executable that is expected to panic");
+                    }
+                    let coverage = get_cached_coverage(
+                        exe,
+                        other_bins,
+                        project_analysis,
+                        config,
+                        false,
+                        logger,
+                        &mut pipeline,
+                        scope,
+                    );
+
+                    let coverage = match coverage {
+                        Ok(coverage) => coverage,
+                        Err(run_error) => {
+                            if config.no_fail_fast {
+                                info!("No failing fast!");
+                                return_code = 101;
+                                None
+                            } else {
+                                return Err(run_error);
+                            }
+                        }
+                    };
+                    if let Some((coverage, code, key)) = coverage {
+                        pipeline.push(coverage, key.map(|k| (k, code)), &mut return_code)?;
+                        return_code |= if exe.should_panic() {
+                            (code == 0).into()
+                        } else {
+                            code
+                        };
+                    }
+                }
+                if config.run_ignored || config.only_ignored {
+                    if has_ignored_tests(exe, config) {
+                        let coverage = get_cached_coverage(
+                            exe,
+                            other_bins,
+                            project_analysis,
+                            config,
+                            true,
+                            logger,
+                            &mut pipeline,
+                            scope,
+                        );
+                        let coverage = match coverage {
+                            Ok(coverage) => coverage,
+                            Err(run_error) => {
+                                if config.no_fail_fast {
+                                    return_code = 101;
+                                    None
+                                } else {
+                                    return Err(run_error);
+                                }
+                            }
+                        };
+                        if let Some((coverage, code, key)) = coverage {
+                            pipeline.push(coverage, key.map(|k| (k, code)), &mut return_code)?;
+                            return_code |= code;
+                        }
                     } else {
-                        return Err(run_error);
+                        info!(
+                            "Skipping ignored run of {}, it has no ignored tests",
+                            exe.path().display()
+                        );
                     }
                 }
-            };
-            if let Some(res) = coverage {
-                result.merge(&res.0);
-                return_code |= if exe.should_panic() {
-                    (res.1 == 0).into()
-                } else {
-                    res.1
-                };
+
+                if config.fail_immediately && return_code != 0 {
+                    return Err(RunError::TestFailed);
+                }
             }
-            if config.run_ignored {
-                let coverage =
-                    get_test_coverage(exe, &other_bins, &project_analysis, config, true, logger);
+            for scenario in scenarios {
+                let exe = match find_scenario_binary(&executables, &scenario.bin) {
+                    Some(exe) => exe.with_scenario(scenario),
+                    None => {
+                        warn!(
+                            "Couldn't find binary {} for scenario {}",
+                            scenario.bin, scenario.name
+                        );
+                        continue;
+                    }
+                };
+                let mut scenario_config = config.clone();
+                if let Some(timeout) = scenario.timeout {
+                    scenario_config.test_timeout = timeout;
+                }
+                let coverage = get_test_coverage(
+                    &exe,
+                    other_bins,
+                    project_analysis,
+                    &scenario_config,
+                    false,
+                    logger,
+                    scope,
+                );
                 let coverage = match coverage {
                     Ok(coverage) => coverage,
                     Err(run_error) => {
@@ -304,17 +777,171 @@ pub fn launch_tarpaulin(
                         }
                     }
                 };
-                if let Some(res) = coverage {
-                    result.merge(&res.0);
-                    return_code |= res.1;
+                if let Some((coverage, code)) = coverage {
+                    pipeline.push(coverage, None, &mut return_code)?;
+                    if code != scenario.expected_exit {
+                        error!(
+                            "Scenario {} exited with {} expected {}",
+                            scenario.name, code, scenario.expected_exit
+                        );
+                        return_code |= 1;
+                    }
+                }
+                if config.fail_immediately && return_code != 0 {
+                    return Err(RunError::TestFailed);
                 }
             }
-
-            if config.fail_immediately && return_code != 0 {
-                return Err(RunError::TestFailed);
-            }
+            pipeline.finish(&mut return_code)
+        })?;
+        check_stale_sources(&mut result, &executables.test_binaries, config);
+        let duplicated = result.merge_aliases(&config.root(), canonical_path);
+        if duplicated > 0 {
+            info!(
+                "{} source files were reached through more than one path, counting each once",
+                duplicated
+            );
         }
         result.dedup();
     }
+    let summary = ConfigSummary::new(config_name(config), build_time, &result);
+    result.add_config_summary(summary);
     Ok((result, return_code))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn ready<'scope>(binary: &str) -> PendingCoverage<'scope> {
+        let mut traces = TraceMap::new();
+        traces.add_run(TestRun {
+            binary: PathBuf::from(binary),
+            target: String::new(),
+            package: None,
+            run_type: None,
+            duration: 1.0,
+            collection: 0.0,
+            size: None,
+            return_code: 0,
+            ignored: false,
+            tests: vec![],
+            results: vec![],
+        });
+        PendingCoverage::ready(traces)
+    }
+
+    fn failed<'scope>(scope: &'scope Scope<'scope, '_>) -> PendingCoverage<'scope> {
+        PendingCoverage::collecting(
+            scope.spawn(|| Err(RunError::TestCoverage("no profraws".to_string()))),
+        )
+    }
+
+    fn binaries(traces: &TraceMap) -> Vec<&str> {
+        traces
+            .runs()
+            .iter()
+            .map(|x| x.binary.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn pipeline_keeps_run_order() {
+        let config = Config::default();
+        let mut cache = RunCache::default();
+        let mut return_code = 0;
+        let result = thread::scope(|scope| {
+            let mut pipeline = RunPipeline::new(&config, &mut cache, TraceMap::new());
+            let slow = PendingCoverage::collecting(scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                ready("first").wait()
+            }));
+            pipeline.push(slow, None, &mut return_code)?;
+            pipeline.push(ready("second"), None, &mut return_code)?;
+            pipeline.push(ready("third"), None, &mut return_code)?;
+            pipeline.finish(&mut return_code)
+        })
+        .unwrap();
+        assert_eq!(binaries(&result), vec!["first", "second", "third"]);
+        assert_eq!(return_code, 0);
+    }
+
+    #[test]
+    fn pipeline_collection_errors() {
+        let config = Config::default();
+        let mut cache = RunCache::default();
+        let mut return_code = 0;
+        let result = thread::scope(|scope| {
+            let mut pipeline = RunPipeline::new(&config, &mut cache, TraceMap::new());
+            pipeline.push(failed(scope), None, &mut return_code)?;
+            pipeline.push(ready("next"), None, &mut return_code)?;
+            pipeline.finish(&mut return_code)
+        });
+        assert!(matches!(result, Err(RunError::TestCoverage(_))));
+
+        let mut config = Config::default();
+        config.no_fail_fast = true;
+        let mut return_code = 0;
+        let result = thread::scope(|scope| {
+            let mut pipeline = RunPipeline::new(&config, &mut cache, TraceMap::new());
+            pipeline.push(ready("first"), None, &mut return_code)?;
+            pipeline.push(failed(scope), None, &mut return_code)?;
+            pipeline.push(ready("last"), None, &mut return_code)?;
+            pipeline.finish(&mut return_code)
+        })
+        .unwrap();
+        assert_eq!(binaries(&result), vec!["first", "last"]);
+        assert_eq!(return_code, 101);
+    }
+
+    #[test]
+    fn pipeline_caches_collected_runs() {
+        let exe = TestBinary::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"),
+            None,
+        );
+        let config = Config::default();
+        let key = RunCache::key(&exe, &[], &config, false).unwrap();
+        let other = RunCache::key(&exe, &[], &config, true).unwrap();
+        let mut cache = RunCache::default();
+        let mut return_code = 0;
+        let result = thread::scope(|scope| {
+            let mut pipeline = RunPipeline::new(&config, &mut cache, TraceMap::new());
+            pipeline.push(ready("cached"), Some((key.clone(), 0)), &mut return_code)?;
+            // Only the latest run is held, it's cached once it's been collected
+            assert!(pipeline.cache.get(&key).is_none());
+            pipeline.push(failed(scope), Some((other.clone(), 0)), &mut return_code)?;
+            assert!(pipeline.cache.get(&key).is_some());
+            pipeline.finish(&mut return_code)
+        });
+        assert!(result.is_err());
+        let (cached, code) = cache.get(&key).unwrap();
+        assert_eq!(binaries(cached), vec!["cached"]);
+        assert_eq!(code, 0);
+        assert!(cache.get(&other).is_none());
+    }
+
+    #[test]
+    fn report_table_reports_merged_coverage() {
+        let config = |name: &str| {
+            let mut config = Config::default();
+            config.name = name.to_string();
+            config
+        };
+        let names = |configs: &[Config]| {
+            report_configs(configs)
+                .into_iter()
+                .map(|x| x.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&[config("unit"), config("docs")]), vec!["unit"]);
+        assert_eq!(
+            names(&[config("unit"), config("report"), config("docs")]),
+            vec!["report"]
+        );
+        let mut no_run = config("unit");
+        no_run.no_run = true;
+        assert!(names(&[no_run]).is_empty());
+        assert!(names(&[]).is_empty());
+    }
+}
@@ -1,8 +1,13 @@
 #![cfg(not(tarpaulin_include))]
-use cargo_tarpaulin::args::CargoTarpaulinCli;
+use cargo_tarpaulin::args::{Action, CargoTarpaulinCli};
 use cargo_tarpaulin::cargo::{rust_flags, rustdoc_flags};
+use cargo_tarpaulin::cleanup::clean_artifacts;
 use cargo_tarpaulin::config::{Color, Config, ConfigWrapper};
-use cargo_tarpaulin::{run, setup_logging};
+use cargo_tarpaulin::event_query::query_events;
+use cargo_tarpaulin::ratchet::ratchet;
+use cargo_tarpaulin::run_report::convert_run_report;
+use cargo_tarpaulin::test_impact::which_tests;
+use cargo_tarpaulin::{merge_reports, run, setup_logging};
 use std::collections::HashMap;
 use tracing::{info, trace};
 
@@ -37,6 +42,28 @@ fn main() -> Result<(), String> {
 
     trace!("Config vector: {:#?}", config);
 
+    if args.action == Some(Action::Clean) {
+        for config in &config.0 {
+            clean_artifacts(config).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+    if let Some(Action::Merge { reports }) = &args.action {
+        return merge_reports(&config.0, reports).map_err(|e| e.to_string());
+    }
+    if let Some(Action::Events { log, filter, since }) = &args.action {
+        return query_events(log, filter, *since).map_err(|e| e.to_string());
+    }
+    if let Some(Action::Ratchet { margin, write }) = &args.action {
+        return ratchet(&config.0, *margin, *write).map_err(|e| e.to_string());
+    }
+    if let Some(Action::WhichTests { location, commit }) = &args.action {
+        return which_tests(&config.0, location, commit.as_deref()).map_err(|e| e.to_string());
+    }
+    if let Some(Action::Convert { report, output }) = &args.action {
+        return convert_run_report(report, output.as_deref()).map_err(|e| e.to_string());
+    }
+
     let print_flags_args = args.print_flags;
     if print_flags_args.print_rust_flags {
         print_flags(&config, rust_flags, "RUSTFLAGS");
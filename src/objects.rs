@@ -0,0 +1,197 @@
+//! Preparing binaries for reading their llvm coverage maps on every platform. The coverage
+//! mapping lives in `__llvm_covmap` and `__llvm_covfun` sections for ELF, in the `__LLVM_COV`
+//! segment with the same section names for Mach-O, and in `.lcovmap$M` and `.lcovfun$M` for
+//! COFF which the linker merges into `.lcovmap` and `.lcovfun` in a PE image. macOS universal
+//! binaries bundle a Mach-O binary for each architecture, only the one for the host is ran so
+//! that slice is extracted and read on its own.
+use crate::config::Config;
+use object::read::macho::{FatArch, MachOFatFile32, MachOFatFile64};
+use object::read::ReadCache;
+use object::{Architecture, BinaryFormat, FileKind, Object, ObjectSection, ReadRef};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Whether the section named `name` in a binary of the given format holds coverage mapping
+fn is_coverage_section(format: BinaryFormat, name: &str) -> bool {
+    match format {
+        BinaryFormat::Coff | BinaryFormat::Pe => {
+            let name = name.split('$').next().unwrap_or_default();
+            name == ".lcovmap" || name == ".lcovfun"
+        }
+        _ => name == "__llvm_covmap" || name == "__llvm_covfun",
+    }
+}
+
+fn host_architecture() -> Architecture {
+    match std::env::consts::ARCH {
+        "x86_64" => Architecture::X86_64,
+        "x86" => Architecture::I386,
+        "aarch64" => Architecture::Aarch64,
+        "arm" => Architecture::Arm,
+        "powerpc" => Architecture::PowerPc,
+        "powerpc64" => Architecture::PowerPc64,
+        _ => Architecture::Unknown,
+    }
+}
+
+fn find_slice<'data, Fat: FatArch, R: ReadRef<'data>>(
+    arches: &[Fat],
+    data: R,
+    arch: Architecture,
+) -> Result<&'data [u8], String> {
+    let slice = arches
+        .iter()
+        .find(|x| x.architecture() == arch)
+        .ok_or_else(|| format!("no slice for {arch:?}"))?;
+    slice.data(data).map_err(|e| e.to_string())
+}
+
+/// The slice of a universal binary for the architecture, `None` if the binary isn't universal
+fn universal_slice<'data, R: ReadRef<'data>>(
+    data: R,
+    arch: Architecture,
+) -> Result<Option<&'data [u8]>, String> {
+    match FileKind::parse(data) {
+        Ok(FileKind::MachOFat32) => {
+            let fat = MachOFatFile32::parse(data).map_err(|e| e.to_string())?;
+            find_slice(fat.arches(), data, arch).map(Some)
+        }
+        Ok(FileKind::MachOFat64) => {
+            let fat = MachOFatFile64::parse(data).map_err(|e| e.to_string())?;
+            find_slice(fat.arches(), data, arch).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn has_coverage_map<'data, R: ReadRef<'data>>(data: R) -> bool {
+    match object::File::parse(data) {
+        Ok(obj) => obj.sections().any(|section| {
+            section
+                .name()
+                .is_ok_and(|name| is_coverage_section(obj.format(), name))
+        }),
+        Err(_) => false,
+    }
+}
+
+/// The file to read the coverage map of the binary from, extracting the host slice of a
+/// universal binary into `dir`. `None` if there's no coverage map to read.
+fn coverage_object(binary: &Path, dir: &Path) -> io::Result<Option<PathBuf>> {
+    let cache = ReadCache::new(File::open(binary)?);
+    let slice = universal_slice(&cache, host_architecture())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    match slice {
+        Some(slice) if has_coverage_map(slice) => {
+            let name = binary.file_name().unwrap_or_default().to_string_lossy();
+            let extracted = dir.join(format!("{}-{}", name, std::env::consts::ARCH));
+            fs::create_dir_all(dir)?;
+            fs::write(&extracted, slice)?;
+            debug!(
+                "Extracted {} slice of {} to {}",
+                std::env::consts::ARCH,
+                binary.display(),
+                extracted.display()
+            );
+            Ok(Some(extracted))
+        }
+        None if has_coverage_map(&cache) => Ok(Some(binary.to_path_buf())),
+        _ => Ok(None),
+    }
+}
+
+/// The objects to read coverage maps from for the binaries, binaries without a coverage map are
+/// left out with a warning
+pub(crate) fn coverage_objects(binaries: &[PathBuf], config: &Config) -> Vec<PathBuf> {
+    let dir = config.run_dir().join("objects");
+    binaries
+        .iter()
+        .filter_map(|binary| match coverage_object(binary, &dir) {
+            Ok(Some(object)) => Some(object),
+            Ok(None) => {
+                warn!(
+                    "{} has no llvm coverage map, it wasn't built with coverage instrumentation",
+                    config.strip_base_dir(binary).display()
+                );
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "Couldn't read the coverage map of {}: {}",
+                    config.strip_base_dir(binary).display(),
+                    e
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::macho::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, FAT_MAGIC};
+
+    /// A universal binary with a slice for x86_64 and aarch64, the slices aren't real binaries
+    fn universal_fixture() -> Vec<u8> {
+        let slices: [(u32, &[u8]); 2] = [
+            (CPU_TYPE_X86_64, b"x86_64 slice"),
+            (CPU_TYPE_ARM64, b"aarch64 slice"),
+        ];
+        let mut data = vec![];
+        data.extend(FAT_MAGIC.to_be_bytes());
+        data.extend((slices.len() as u32).to_be_bytes());
+        let mut offset = 8 + 20 * slices.len() as u32;
+        for (cputype, slice) in &slices {
+            for field in [*cputype, 0, offset, slice.len() as u32, 0] {
+                data.extend(field.to_be_bytes());
+            }
+            offset += slice.len() as u32;
+        }
+        for (_, slice) in &slices {
+            data.extend_from_slice(slice);
+        }
+        data
+    }
+
+    #[test]
+    fn coverage_sections_per_format() {
+        assert!(is_coverage_section(BinaryFormat::Elf, "__llvm_covmap"));
+        assert!(is_coverage_section(BinaryFormat::Elf, "__llvm_covfun"));
+        assert!(is_coverage_section(BinaryFormat::MachO, "__llvm_covfun"));
+        assert!(is_coverage_section(BinaryFormat::Coff, ".lcovmap$M"));
+        assert!(is_coverage_section(BinaryFormat::Pe, ".lcovfun"));
+        assert!(!is_coverage_section(BinaryFormat::Pe, "__llvm_covmap"));
+        assert!(!is_coverage_section(BinaryFormat::Elf, ".lcovmap"));
+        assert!(!is_coverage_section(BinaryFormat::Elf, "__llvm_prf_cnts"));
+    }
+
+    #[test]
+    fn host_slice_extracted_from_universal_binary() {
+        let data = universal_fixture();
+        assert_eq!(
+            universal_slice(data.as_slice(), Architecture::X86_64),
+            Ok(Some(&b"x86_64 slice"[..]))
+        );
+        assert_eq!(
+            universal_slice(data.as_slice(), Architecture::Aarch64),
+            Ok(Some(&b"aarch64 slice"[..]))
+        );
+        assert!(universal_slice(data.as_slice(), Architecture::PowerPc).is_err());
+        assert!(!has_coverage_map(data.as_slice()));
+    }
+
+    #[test]
+    fn thin_binaries_read_directly() {
+        let elf = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0".to_vec();
+        assert_eq!(
+            universal_slice(elf.as_slice(), Architecture::X86_64),
+            Ok(None)
+        );
+        let current = std::env::current_exe().unwrap();
+        let cache = ReadCache::new(File::open(current).unwrap());
+        assert_eq!(universal_slice(&cache, host_architecture()), Ok(None));
+    }
+}
@@ -0,0 +1,284 @@
+//! Coverage of the lines added or changed by a patch. This takes the unified diff as a string so
+//! bots and review tools can use it with whatever produced the diff, without tarpaulin needing
+//! to run git.
+use crate::config::Config;
+use crate::traces::{amount_covered, TraceMap};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Coverage of the added lines in one hunk of the diff
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HunkCoverage {
+    /// First line of the hunk in the new file
+    pub start: u64,
+    /// Number of lines in the hunk in the new file
+    pub len: u64,
+    /// Added lines which are coverable and were hit
+    pub covered: Vec<u64>,
+    /// Added lines which are coverable and weren't hit
+    pub uncovered: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FilePatchCoverage {
+    /// Path of the file as it's given in the diff
+    pub path: PathBuf,
+    /// Traced file the path in the diff was matched to, if there isn't one every hunk is empty
+    pub source: Option<PathBuf>,
+    pub hunks: Vec<HunkCoverage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PatchCoverage {
+    pub files: Vec<FilePatchCoverage>,
+    /// Coverable lines added by the patch
+    pub coverable: usize,
+    /// Coverable lines added by the patch which were hit
+    pub covered: usize,
+}
+
+impl PatchCoverage {
+    /// Coverage of the added lines ranging from 0.0-1.0, a patch without any coverable lines is
+    /// fully covered
+    pub fn coverage_percentage(&self) -> f64 {
+        if self.coverable == 0 {
+            1.0
+        } else {
+            self.covered as f64 / self.coverable as f64
+        }
+    }
+}
+
+/// A hunk parsed from the diff with the line numbers added in the new file
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Hunk {
+    start: u64,
+    len: u64,
+    added: Vec<u64>,
+}
+
+/// Parses the `+c,d` part of a hunk header `@@ -a,b +c,d @@`, returning the start line in the
+/// new file, its length and the length in the old file
+fn parse_hunk_header(line: &str) -> Option<(u64, u64, u64)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split_whitespace();
+    let range = |x: &str| -> Option<(u64, u64)> {
+        match x.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((x.parse().ok()?, 1)),
+        }
+    };
+    let (_, old_len) = range(ranges.next()?.strip_prefix('-')?)?;
+    let (start, len) = range(ranges.next()?.strip_prefix('+')?)?;
+    Some((start, len, old_len))
+}
+
+/// Path of the new file from a `+++ b/path` line, `None` for deleted files
+fn parse_new_path(line: &str) -> Option<PathBuf> {
+    let path = line.strip_prefix("+++ ")?;
+    // Git may add a tab followed by a timestamp
+    let path = path.split('\t').next()?.trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path.strip_prefix("b/").unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+/// The added lines of each file in a unified diff, in the order the files appear
+fn parse_diff(diff: &str) -> Vec<(PathBuf, Vec<Hunk>)> {
+    let mut files: Vec<(PathBuf, Vec<Hunk>)> = vec![];
+    let mut current: Option<usize> = None;
+    let mut old_remaining = 0;
+    let mut new_remaining = 0;
+    let mut line_number = 0;
+    for line in diff.lines() {
+        if old_remaining > 0 || new_remaining > 0 {
+            let hunk = current.and_then(|x| files[x].1.last_mut());
+            match line.chars().next() {
+                Some('+') => {
+                    if let Some(hunk) = hunk {
+                        hunk.added.push(line_number);
+                    }
+                    line_number += 1;
+                    new_remaining -= 1;
+                }
+                Some('-') => old_remaining -= 1,
+                Some('\\') => {}
+                _ => {
+                    line_number += 1;
+                    old_remaining -= 1;
+                    new_remaining -= 1;
+                }
+            }
+            // A malformed hunk shouldn't swallow the rest of the diff
+            old_remaining = old_remaining.max(0);
+            new_remaining = new_remaining.max(0);
+        } else if line.starts_with("+++ ") {
+            current = parse_new_path(line).map(|path| {
+                files.push((path, vec![]));
+                files.len() - 1
+            });
+        } else if let Some((start, len, old_len)) = parse_hunk_header(line) {
+            if let Some(index) = current {
+                files[index].1.push(Hunk {
+                    start,
+                    len,
+                    added: vec![],
+                });
+            }
+            line_number = start;
+            new_remaining = len as i64;
+            old_remaining = old_len as i64;
+        }
+    }
+    files
+}
+
+/// Finds the traced file the path in the diff refers to. Diff paths are relative to the
+/// repository so any traced file ending with the path matches, the shortest is used if several
+/// do
+fn find_traced_file<'a>(traces: &'a TraceMap, path: &Path) -> Option<&'a PathBuf> {
+    traces
+        .files()
+        .into_iter()
+        .filter(|x| x.ends_with(path))
+        .min_by_key(|x| x.components().count())
+}
+
+/// The diff given with `--diff-file`, `None` if there isn't one or it can't be read
+pub(crate) fn read_diff(config: &Config) -> Option<String> {
+    let path = config.diff_file.as_ref()?;
+    match fs::read_to_string(path) {
+        Ok(diff) => Some(diff),
+        Err(e) => {
+            warn!("Couldn't read diff {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Works out which of the lines added by the unified diff are covered
+pub fn coverage_for_patch(traces: &TraceMap, diff: &str) -> PatchCoverage {
+    let mut result = PatchCoverage::default();
+    for (path, hunks) in parse_diff(diff) {
+        let traced = find_traced_file(traces, &path);
+        // Lines can have several traces, a line is covered if any of them were hit
+        let mut lines = BTreeMap::<u64, bool>::new();
+        if let Some(file) = traced {
+            for trace in traces.get_child_traces(file) {
+                *lines.entry(trace.line).or_default() |= amount_covered(std::iter::once(trace)) > 0;
+            }
+        }
+        let hunks = hunks
+            .into_iter()
+            .map(|hunk| {
+                let mut coverage = HunkCoverage {
+                    start: hunk.start,
+                    len: hunk.len,
+                    ..Default::default()
+                };
+                for line in hunk.added {
+                    match lines.get(&line) {
+                        Some(true) => coverage.covered.push(line),
+                        Some(false) => coverage.uncovered.push(line),
+                        None => {}
+                    }
+                }
+                result.covered += coverage.covered.len();
+                result.coverable += coverage.covered.len() + coverage.uncovered.len();
+                coverage
+            })
+            .collect();
+        result.files.push(FilePatchCoverage {
+            path,
+            source: traced.cloned(),
+            hunks,
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+index 1234567..89abcde 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,4 +1,5 @@
+ fn foo() {
+-    old();
++    covered();
++    uncovered();
++    // comment
+ }
+-- not a header
+@@ -20,2 +22,3 @@ fn bar() {
+ fn bar() {
++    baz();
+ }
+diff --git a/README.md b/README.md
+deleted file mode 100644
+--- a/README.md
++++ /dev/null
+@@ -1 +0,0 @@
+-# Readme
+diff --git a/src/new.rs b/src/new.rs
+new file mode 100644
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1 @@
++fn new() {}
+";
+
+    #[test]
+    fn diff_hunks_parsed() {
+        let files = parse_diff(DIFF);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, Path::new("src/lib.rs"));
+        assert_eq!(
+            files[0].1,
+            vec![
+                Hunk {
+                    start: 1,
+                    len: 5,
+                    added: vec![2, 3, 4]
+                },
+                Hunk {
+                    start: 22,
+                    len: 3,
+                    added: vec![23]
+                }
+            ]
+        );
+        assert_eq!(files[1].0, Path::new("src/new.rs"));
+        assert_eq!(files[1].1[0].added, vec![1]);
+    }
+
+    #[test]
+    fn added_lines_covered() {
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 1), (2, 1), (3, 0), (23, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(Path::new("/project/src/lib.rs"), trace);
+        }
+        let coverage = coverage_for_patch(&traces, DIFF);
+        assert_eq!(coverage.coverable, 3);
+        assert_eq!(coverage.covered, 1);
+        let lib = &coverage.files[0];
+        assert_eq!(
+            lib.source.as_deref(),
+            Some(Path::new("/project/src/lib.rs"))
+        );
+        assert_eq!(lib.hunks[0].covered, vec![2]);
+        assert_eq!(lib.hunks[0].uncovered, vec![3]);
+        assert_eq!(lib.hunks[1].uncovered, vec![23]);
+        assert!(coverage.files[1].source.is_none());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::config::Config;
 use std::env::var;
 use std::ffi::OsStr;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
 /// On windows removes the `\\?\\` prefix to UNC paths. For other operation systems just turns the
@@ -98,15 +98,219 @@ pub fn get_source_walker(config: &Config) -> impl Iterator<Item = DirEntry> + '_
         .filter(is_source_file)
 }
 
+/// Pattern for `LLVM_PROFILE_FILE` when running the binary. Processes the binary spawns inherit
+/// it so their profraws are attributed to the binary, and `%p` gives each process its own file
+/// so tests which re-execute the binary for isolation don't collide.
+pub fn profraw_pattern(binary: &Path) -> String {
+    let name = binary
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{name}_%m-%p.profraw")
+}
+
+/// Whether the profraw was written by the binary or one of the processes it spawned, based on the
+/// name given by `profraw_pattern`
+pub fn is_profraw_for(profraw: &Path, binary: &Path) -> bool {
+    let (profraw, binary) = match (profraw.file_name(), binary.file_name()) {
+        (Some(p), Some(b)) => (p.to_string_lossy(), b.to_string_lossy()),
+        _ => return false,
+    };
+    // `%m` expands to digits and underscores which stops `foo` claiming profraws from `foo_bar`
+    profraw
+        .strip_prefix(binary.as_ref())
+        .and_then(|x| x.strip_prefix('_'))
+        .and_then(|x| x.split_once('-'))
+        .is_some_and(|(signature, _)| signature.chars().all(|c| c.is_ascii_digit() || c == '_'))
+}
+
 pub fn get_profile_walker(config: &Config) -> impl Iterator<Item = DirEntry> {
     let walker = WalkDir::new(config.profraw_dir()).into_iter();
     walker.filter_map(Result::ok).filter(is_profraw_file)
 }
 
+/// Whether the path is absolute on any platform, so a path written on Windows is still seen as
+/// absolute when read on unix and vice versa
+fn is_absolute_anywhere(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || path.starts_with('\\')
+        || (bytes.len() > 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && (bytes[2] == b'/' || bytes[2] == b'\\'))
+}
+
+/// Platform neutral form of a path for storing in reports. Paths in the project are made
+/// relative to the root and use `/` as the separator, other paths are left as they are.
+pub fn portable_path(path: &Path, root: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative) => PathBuf::from(
+            relative
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        ),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Converts a path from `portable_path` back into a native path under the root. The separator
+/// in relative paths could be either `/` or `\` depending on where the report was written.
+pub fn native_path(path: &Path, root: &Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    if is_absolute_anywhere(&path_str) || path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let mut native = root.to_path_buf();
+    native.extend(path_str.split(['/', '\\']).filter(|x| !x.is_empty()));
+    native
+}
+
+/// The path a source file is identified by, so a file reached through symlinks or `..` from
+/// several packages is the same file. Paths which don't exist are left as they are.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        Ok(canonical) => fix_unc_path(&canonical),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Longest file name most filesystems allow, in bytes
+const MAX_FILE_NAME: usize = 255;
+
+/// Paths this long can't be opened on windows without the `\\?\` prefix
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Short hash of a path for telling files from different workspaces apart. This is FNV-1a rather
+/// than `DefaultHasher` as the names need to stay the same between tarpaulin builds.
+pub fn path_hash(path: &Path) -> String {
+    let hash = path
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    format!("{:08x}", hash as u32)
+}
+
+/// Shortens a file name longer than filesystems allow. The end of the name is kept so the
+/// extension is unchanged and the part removed is replaced by a hash of the full name so
+/// shortened names don't clash.
+pub fn fit_file_name(name: &str) -> String {
+    if name.len() <= MAX_FILE_NAME {
+        return name.to_string();
+    }
+    let hash = path_hash(Path::new(name));
+    let mut keep = MAX_FILE_NAME - hash.len() - 1;
+    while !name.is_char_boundary(name.len() - keep) {
+        keep -= 1;
+    }
+    format!("{}-{}", hash, &name[name.len() - keep..])
+}
+
+/// On windows absolute paths longer than `MAX_PATH` are given the `\\?\` prefix so they can
+/// still be created, other paths are left as they are
+pub fn long_path(path: PathBuf) -> PathBuf {
+    if !cfg!(windows) || !path.is_absolute() || path.as_os_str().len() < WINDOWS_MAX_PATH {
+        return path;
+    }
+    let normalised = path
+        .components()
+        .all(|x| !matches!(x, Component::CurDir | Component::ParentDir));
+    // UNC and already verbatim paths start with `\\` and are left alone
+    if normalised && !path.to_string_lossy().starts_with(r"\\") {
+        PathBuf::from(format!(
+            r"\\?\{}",
+            path.to_string_lossy().replace('/', "\\")
+        ))
+    } else {
+        path
+    }
+}
+
+/// The `--remap-path-prefix FROM=TO` mappings in the rustflags, in the order given
+pub fn remapped_prefixes(rustflags: &str) -> Vec<(PathBuf, PathBuf)> {
+    const FLAG: &str = "--remap-path-prefix";
+    let mut remaps = vec![];
+    let mut flags = rustflags.split_whitespace();
+    while let Some(flag) = flags.next() {
+        let remap = if flag == FLAG {
+            flags.next()
+        } else {
+            flag.strip_prefix(FLAG).and_then(|x| x.strip_prefix('='))
+        };
+        // rustc splits on the last `=` so the original path can contain one
+        if let Some((from, to)) = remap.and_then(|x| x.rsplit_once('=')) {
+            remaps.push((PathBuf::from(from), PathBuf::from(to)));
+        }
+    }
+    remaps
+}
+
+/// Inverts `--remap-path-prefix` for a path embedded in a binary so it points to the source on
+/// disk. rustc applies the last matching mapping so the same one is undone here.
+pub fn unmap_path(path: &Path, remaps: &[(PathBuf, PathBuf)]) -> PathBuf {
+    remaps
+        .iter()
+        .rev()
+        .find_map(|(from, to)| path.strip_prefix(to).ok().map(|rest| from.join(rest)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn long_file_names_shortened() {
+        assert_eq!(fit_file_name("lcov.info"), "lcov.info");
+        assert_eq!(
+            path_hash(Path::new("/a/project")),
+            path_hash(Path::new("/a/project"))
+        );
+        assert_ne!(
+            path_hash(Path::new("/a/project")),
+            path_hash(Path::new("/b/project"))
+        );
+
+        let long = format!("{}-coverage.json", "é".repeat(200));
+        let fitted = fit_file_name(&long);
+        assert!(fitted.len() <= MAX_FILE_NAME);
+        assert!(fitted.ends_with("é-coverage.json"));
+        let other = format!("x{long}");
+        assert_ne!(fit_file_name(&other), fitted);
+    }
+
+    #[test]
+    fn remapped_paths_restored() {
+        let remaps = remapped_prefixes(
+            "-Cdebuginfo=2 --remap-path-prefix /home/ferris/project=/build --remap-path-prefix=/home/ferris/.cargo=/cargo",
+        );
+        assert_eq!(
+            remaps,
+            vec![
+                (
+                    PathBuf::from("/home/ferris/project"),
+                    PathBuf::from("/build")
+                ),
+                (
+                    PathBuf::from("/home/ferris/.cargo"),
+                    PathBuf::from("/cargo")
+                ),
+            ]
+        );
+        assert_eq!(
+            unmap_path(Path::new("/build/src/lib.rs"), &remaps),
+            PathBuf::from("/home/ferris/project/src/lib.rs")
+        );
+        assert_eq!(
+            unmap_path(Path::new("/buildings/src/lib.rs"), &remaps),
+            PathBuf::from("/buildings/src/lib.rs")
+        );
+    }
+
     #[test]
     #[cfg(unix)]
     fn system_headers_not_coverable() {
@@ -141,6 +345,43 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn portable_paths_round_trip() {
+        let root = std::env::current_dir().unwrap();
+        let file = root.join("src").join("lib.rs");
+        let portable = portable_path(&file, &root);
+        assert_eq!(portable, Path::new("src/lib.rs"));
+        assert_eq!(native_path(&portable, &root), file);
+        assert_eq!(native_path(Path::new("src\\lib.rs"), &root), file);
+
+        let outside = Path::new("/elsewhere/lib.rs");
+        assert_eq!(portable_path(outside, &root), outside);
+        assert_eq!(native_path(outside, &root), outside);
+        let windows = Path::new("C:\\Users\\ferris\\lib.rs");
+        assert_eq!(native_path(windows, &root), windows);
+    }
+
+    #[test]
+    fn profraws_attributed_to_binary() {
+        let binary = Path::new("target/debug/deps/foo");
+        assert!(is_profraw_for(
+            Path::new("profraws/foo_1234567890_0-42.profraw"),
+            binary
+        ));
+        assert!(is_profraw_for(
+            Path::new("profraws/foo_1234567890-43.profraw"),
+            binary
+        ));
+        assert!(!is_profraw_for(
+            Path::new("profraws/foo_bar_1234567890_0-42.profraw"),
+            binary
+        ));
+        assert!(!is_profraw_for(
+            Path::new("profraws/bar_1234567890_0-42.profraw"),
+            binary
+        ));
+    }
+
     #[test]
     fn is_hidden_check() {
         // From issue#682
@@ -0,0 +1,110 @@
+//! Dry runs print what tarpaulin would do for each config instead of running the tests, this is
+//! mainly useful for debugging how configs and CI settings are being resolved.
+use crate::cargo::{self, build_commands, rust_flags, rustdoc_flags, CargoOutput};
+use crate::config::{Config, DryRun};
+use crate::errors::RunError;
+use crate::path_utils::get_source_walker;
+use crate::report::report_paths;
+use std::io::{self, Write};
+use std::process::Command;
+
+/// The command as it would be typed in a shell, without the environment
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|x| x.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_plan(
+    config: &Config,
+    executables: Option<&CargoOutput>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let name = if config.name.is_empty() {
+        "<anonymous>"
+    } else {
+        config.name.as_str()
+    };
+    writeln!(w, "Config {name}:")?;
+    writeln!(w, "  Engine: {:?}", config.engine())?;
+    writeln!(w, "  RUSTFLAGS: {}", rust_flags(config))?;
+    writeln!(w, "  RUSTDOCFLAGS: {}", rustdoc_flags(config))?;
+    writeln!(w, "  Build commands:")?;
+    for cmd in build_commands(config) {
+        writeln!(w, "    {}", command_line(&cmd))?;
+    }
+    match executables {
+        Some(executables) => {
+            writeln!(w, "  Test binaries:")?;
+            for exe in &executables.test_binaries {
+                let exe_config = config.for_binary(exe);
+                writeln!(
+                    w,
+                    "    {} ({:?}, timeout {:?})",
+                    config.strip_base_dir(exe.path()).display(),
+                    exe_config.engine(),
+                    exe_config.test_timeout
+                )?;
+            }
+            for scenario in &config.scenarios {
+                writeln!(w, "    scenario {} runs {}", scenario.name, scenario.bin)?;
+            }
+        }
+        None => writeln!(
+            w,
+            "  Test binaries: not built, use `--dry-run Build` to list them"
+        )?,
+    }
+    if !config.varargs.is_empty() {
+        writeln!(w, "  Test arguments: {}", config.varargs.join(" "))?;
+    }
+    let sources = get_source_walker(config).collect::<Vec<_>>();
+    writeln!(w, "  Source files ({}):", sources.len())?;
+    for source in &sources {
+        writeln!(w, "    {}", config.strip_base_dir(source.path()).display())?;
+    }
+    Ok(())
+}
+
+/// Prints the plan for each config, building the tests first if the dry run asks for it
+pub(crate) fn print_plan(configs: &[Config]) -> Result<(), RunError> {
+    let mut w: Box<dyn Write> = if configs.iter().any(|c| c.stderr) {
+        Box::new(io::stderr().lock())
+    } else {
+        Box::new(io::stdout().lock())
+    };
+    for config in configs.iter().filter(|c| c.name != "report") {
+        let executables = if config.dry_run == Some(DryRun::Build) {
+            Some(cargo::get_tests(config)?)
+        } else {
+            None
+        };
+        write_plan(config, executables.as_ref(), &mut w)?;
+    }
+    let report_config = configs
+        .iter()
+        .find(|c| c.name == "report")
+        .or_else(|| configs.first());
+    if let Some(config) = report_config {
+        writeln!(w, "Reports:")?;
+        for path in report_paths(config) {
+            writeln!(w, "  {}", path.display())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_without_environment() {
+        let mut cmd = Command::new("cargo");
+        cmd.args(["test", "--no-run"])
+            .env("RUSTFLAGS", "-Cdebuginfo=2");
+        assert_eq!(command_line(&cmd), "cargo test --no-run");
+    }
+}
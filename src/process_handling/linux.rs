@@ -1,15 +1,18 @@
 use crate::config::types::Mode;
 use crate::errors::*;
-use crate::process_handling::execute_test;
+use crate::process_handling::{execute_test, write_stdin};
 use crate::ptrace_control::*;
 use crate::Config;
 use crate::TestBinary;
 use crate::TestHandle;
 use lazy_static::lazy_static;
+use nix::libc;
 use nix::sched::*;
 use nix::sys::personality;
 use nix::unistd::*;
 use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::os::fd::AsRawFd;
 use std::path::Path;
 use tracing::{info, warn};
 
@@ -22,6 +25,7 @@ pub fn get_test_coverage(
     test: &TestBinary,
     config: &Config,
     ignored: bool,
+    port_base: Option<u16>,
 ) -> Result<Option<TestHandle>, RunError> {
     if !test.path().exists() {
         warn!("Test at {} doesn't exist", test.path().display());
@@ -35,16 +39,34 @@ pub fn get_test_coverage(
         warn!("Failed to set processor affinity {}", e);
     }
 
+    // The scenario's stdin is written through a pipe once the test is running
+    let stdin = match test.scenario().and_then(|x| x.stdin.clone()) {
+        Some(input) => Some((pipe()?, input)),
+        None => None,
+    };
+
     unsafe {
         match fork() {
-            Ok(ForkResult::Parent { child }) => Ok(Some(TestHandle::Id(child))),
+            Ok(ForkResult::Parent { child }) => {
+                if let Some(((_, write), input)) = stdin {
+                    write_stdin(File::from(write), input);
+                }
+                Ok(Some(TestHandle::Id(child)))
+            }
             Ok(ForkResult::Child) => {
+                if let Some(((read, _), _)) = stdin {
+                    if libc::dup2(read.as_raw_fd(), libc::STDIN_FILENO) < 0 {
+                        return Err(RunError::TestRuntime(
+                            "Failed to redirect the scenario stdin".to_string(),
+                        ));
+                    }
+                }
                 let bin_type = match config.command {
                     Mode::Test => "test",
                     Mode::Build => "binary",
                 };
                 info!("Launching {}", bin_type);
-                execute_test(test, &[], ignored, config, Some(threads))?;
+                execute_test(test, &[], ignored, config, Some(threads), &[], port_base)?;
                 Ok(None)
             }
             Err(err) => Err(RunError::TestCoverage(format!(
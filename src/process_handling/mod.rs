@@ -1,16 +1,34 @@
+use self::ports::PortBlock;
+use self::test_output::TestOutput;
 use crate::cargo::{rust_flags, LD_PATH_VAR};
-use crate::config::Color;
-use crate::generate_tracemap;
-use crate::path_utils::get_profile_walker;
-use crate::statemachine::{create_state_machine, TestState};
+use crate::config::{BinaryOverride, Color, RunType};
+use crate::engine::engine_for;
+use crate::path_utils::{get_profile_walker, profraw_pattern};
+use crate::rerun;
+#[cfg(ptrace_supported)]
+use crate::statemachine::perf::samples_path;
+use crate::statemachine::{CounterJob, TestState};
+use crate::test_loader::binary_size;
 use crate::traces::*;
 use crate::{Config, EventLog, LineAnalysis, RunError, TestBinary, TraceEngine};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command};
-use tracing::{debug, error, info, trace_span};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, Scope, ScopedJoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace_span, warn};
+
+mod ports;
+mod test_output;
+
+/// Most processes started for a test binary with `--process-per-test`. Past this the tests are
+/// batched so a binary with thousands of tests doesn't spend most of its time starting processes
+/// and writing profraws.
+const MAX_TEST_PROCESSES: usize = 256;
 
 /// Handle to a test currently either PID or a `std::process::Child`
 pub enum TestHandle {
@@ -30,6 +48,28 @@ pub struct RunningProcessHandle {
     pub(crate) extra_binaries: Vec<PathBuf>,
     /// The flag showing if it should panic
     pub(crate) should_panic: bool,
+    /// Whether the process runs a scenario, a scenario's exit code is compared to the one it's
+    /// expected to finish with once its coverage is collected
+    pub(crate) scenario: bool,
+    /// How long the process can run before it's killed, only set for scenarios
+    pub(crate) timeout: Option<Duration>,
+    /// Stdout of the process if it's captured to read the test results from
+    pub(crate) output: Option<TestOutput>,
+    /// File `perf record` writes the samples of the process to for the perf engine
+    pub(crate) samples: Option<PathBuf>,
+    /// Forwards SIGINT to the process while it runs in its own session with `--allocate-pty`
+    #[cfg(ptrace_supported)]
+    pub(crate) session: Option<pty::Session>,
+}
+
+/// Writes a scenario's stdin from another thread so a binary which doesn't read all of its input
+/// can't block us
+pub(crate) fn write_stdin(mut stdin: impl Write + Send + 'static, input: String) {
+    thread::spawn(move || {
+        if let Err(e) = stdin.write_all(input.as_bytes()) {
+            debug!("Failed to write scenario stdin: {}", e);
+        }
+    });
 }
 
 impl RunningProcessHandle {
@@ -42,7 +82,17 @@ impl RunningProcessHandle {
         let existing_profraws = get_profile_walker(config)
             .map(|x| x.path().to_path_buf())
             .collect();
-        let child = cmd.spawn()?;
+        let scenario = test.scenario();
+        if scenario.and_then(|x| x.stdin.as_ref()).is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+        let mut child = cmd.spawn()?;
+        let output = child.stdout.take().map(TestOutput::capture);
+        if let Some(input) = scenario.and_then(|x| x.stdin.clone()) {
+            if let Some(stdin) = child.stdin.take() {
+                write_stdin(stdin, input);
+            }
+        }
 
         Ok(Self {
             path: test.path().to_path_buf(),
@@ -50,8 +100,57 @@ impl RunningProcessHandle {
             child,
             existing_profraws,
             should_panic: test.should_panic(),
+            scenario: scenario.is_some(),
+            timeout: scenario.and_then(|x| x.timeout),
+            output,
+            samples: None,
+            #[cfg(ptrace_supported)]
+            session: None,
         })
     }
+
+    /// The test binary ran by the process
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The running process, for engines to follow it
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Waits for the process to exit, killing it if it runs past its timeout
+    pub(crate) fn wait(&mut self) -> Result<ExitStatus, RunError> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok(self.child.wait()?),
+        };
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(status);
+            }
+            if start.elapsed() > timeout {
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                return Err(RunError::TestRuntime(format!(
+                    "Error: Timed out after {timeout:?} waiting for {}",
+                    self.path.display()
+                )));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl TestHandle {
+    /// Takes the captured stdout of the test, only processes launched for the llvm engine have it
+    fn take_output(&mut self) -> Option<TestOutput> {
+        match self {
+            TestHandle::Id(_) => None,
+            TestHandle::Process(c) => c.output.take(),
+        }
+    }
 }
 
 impl fmt::Display for TestHandle {
@@ -75,57 +174,374 @@ impl From<RunningProcessHandle> for TestHandle {
     }
 }
 
-pub fn get_test_coverage(
+/// Coverage of a test run. The llvm counters of the run may still be being collected on a worker
+/// thread, which lets the next binary run in the meantime.
+#[derive(Debug, Default)]
+pub struct PendingCoverage<'scope> {
+    ready: TraceMap,
+    collecting: Option<ScopedJoinHandle<'scope, Result<TraceMap, RunError>>>,
+}
+
+impl<'scope> PendingCoverage<'scope> {
+    pub fn ready(traces: TraceMap) -> Self {
+        Self {
+            ready: traces,
+            collecting: None,
+        }
+    }
+
+    /// Coverage still being collected by the worker thread
+    pub fn collecting(handle: ScopedJoinHandle<'scope, Result<TraceMap, RunError>>) -> Self {
+        Self {
+            ready: TraceMap::new(),
+            collecting: Some(handle),
+        }
+    }
+
+    /// Waits for the counters to be collected
+    pub fn wait(self) -> Result<TraceMap, RunError> {
+        let mut traces = self.ready;
+        if let Some(handle) = self.collecting {
+            let collected = handle.join().map_err(|_| {
+                RunError::TestCoverage("Collecting the llvm counters panicked".to_string())
+            })??;
+            traces.merge(&collected);
+        }
+        Ok(traces)
+    }
+
+    /// Adds the coverage of a later run. This waits for the counters of this coverage so only the
+    /// latest run is ever being collected.
+    pub fn followed_by(self, next: PendingCoverage<'scope>) -> Result<Self, RunError> {
+        let mut ready = self.wait()?;
+        ready.merge(&next.ready);
+        Ok(Self {
+            ready,
+            collecting: next.collecting,
+        })
+    }
+}
+
+pub fn get_test_coverage<'scope, 'env>(
     test: &TestBinary,
     other_binaries: &[PathBuf],
-    analysis: &HashMap<PathBuf, LineAnalysis>,
+    analysis: &'env HashMap<PathBuf, LineAnalysis>,
     config: &Config,
     ignored: bool,
     logger: &Option<EventLog>,
-) -> Result<Option<(TraceMap, i32)>, RunError> {
-    let handle = launch_test(test, other_binaries, config, ignored, logger)?;
-    if let Some(handle) = handle {
-        let t = collect_coverage(test.path(), handle, analysis, config, logger)?;
-        Ok(Some(t))
+    scope: &'scope Scope<'scope, 'env>,
+) -> Result<Option<(PendingCoverage<'scope>, i32)>, RunError> {
+    let config = &config.for_binary(test);
+    if let Some(batches) = process_per_test_batches(test, config, ignored) {
+        let mut result = PendingCoverage::default();
+        let mut return_code = 0;
+        let mut errors = vec![];
+        for batch in &batches {
+            let config = with_test_timeouts(test, config, batch);
+            let coverage = run_tests(
+                test,
+                other_binaries,
+                analysis,
+                &config,
+                ignored,
+                logger,
+                batch,
+                scope,
+            );
+            match coverage {
+                Ok(Some((coverage, code))) => {
+                    result = result.followed_by(coverage)?;
+                    return_code |= code;
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.is_empty() {
+            return Ok(Some((result, return_code)));
+        } else if config.no_fail_fast {
+            // Like a failing test the coverage of the other processes is still kept
+            for e in &errors {
+                error!("{}", e);
+            }
+            return Ok(Some((result, 101)));
+        } else if errors.len() == 1 {
+            return Err(errors.remove(0));
+        }
+        let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        return Err(RunError::TestRuntime(format!(
+            "{} of {} test processes failed: {}",
+            errors.len(),
+            batches.len(),
+            errors.join("; ")
+        )));
+    }
+    let config = if config.test_timeouts.is_empty()
+        || !test.is_test_type()
+        || test.run_type() == Some(RunType::Doctests)
+    {
+        Cow::Borrowed(&**config)
+    } else {
+        let mut args = config.varargs.clone();
+        if ignored {
+            args.push("--ignored".to_string());
+        }
+        let tests = list_tests(test, config, &args).unwrap_or_default();
+        with_test_timeouts(test, config, &tests)
+    };
+    run_tests(
+        test,
+        other_binaries,
+        analysis,
+        &config,
+        ignored,
+        logger,
+        &[],
+        scope,
+    )
+}
+
+/// Reruns a test binary which failed in a previous run. When the tests which failed are known, or
+/// the failed run was one process of `--process-per-test`, only those tests are ran again.
+#[allow(clippy::too_many_arguments)]
+pub fn rerun_test_coverage<'scope, 'env>(
+    test: &TestBinary,
+    other_binaries: &[PathBuf],
+    analysis: &'env HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+    ignored: bool,
+    logger: &Option<EventLog>,
+    tests: &[String],
+    scope: &'scope Scope<'scope, 'env>,
+) -> Result<Option<(PendingCoverage<'scope>, i32)>, RunError> {
+    if tests.is_empty() {
+        return get_test_coverage(
+            test,
+            other_binaries,
+            analysis,
+            config,
+            ignored,
+            logger,
+            scope,
+        );
+    }
+    let config = &config.for_binary(test);
+    let config = with_test_timeouts(test, config, tests);
+    run_tests(
+        test,
+        other_binaries,
+        analysis,
+        &config,
+        ignored,
+        logger,
+        tests,
+        scope,
+    )
+}
+
+/// The config with the test timeout raised to the longest `[timeouts]` entry matching one of the
+/// tests, entries only give tests more time so one shorter than the test timeout is ignored
+fn with_test_timeouts<'a>(
+    test: &TestBinary,
+    config: &'a Config,
+    tests: &[String],
+) -> Cow<'a, Config> {
+    match config.test_timeout_for(&test.target_name(), tests) {
+        Some(timeout) if timeout > config.test_timeout => {
+            debug!(
+                "Using a timeout of {:?} for {} from [timeouts]",
+                timeout,
+                test.file_name()
+            );
+            let mut config = config.clone();
+            config.test_timeout = timeout;
+            Cow::Owned(config)
+        }
+        _ => Cow::Borrowed(config),
+    }
+}
+
+/// Runs the tests in the binary, if any test names are given only those tests are ran. The llvm
+/// counters are collected on a thread in the scope so the next binary can start running.
+#[allow(clippy::too_many_arguments)]
+fn run_tests<'scope, 'env>(
+    test: &TestBinary,
+    other_binaries: &[PathBuf],
+    analysis: &'env HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+    ignored: bool,
+    logger: &Option<EventLog>,
+    tests: &[String],
+    scope: &'scope Scope<'scope, 'env>,
+) -> Result<Option<(PendingCoverage<'scope>, i32)>, RunError> {
+    let start = Instant::now();
+    // Kept until the process exits so another process can't be given the same ports
+    let ports = port_block(test, config);
+    let port_base = ports.as_ref().map(PortBlock::base);
+    let handle = launch_test(
+        test,
+        other_binaries,
+        config,
+        ignored,
+        logger,
+        tests,
+        port_base,
+    )?;
+    if let Some(mut handle) = handle {
+        let output = handle.take_output();
+        let coverage = collect_coverage(test.path(), handle, analysis, config, logger);
+        drop(ports);
+        let results = output
+            .map(|x| x.results(config.test_timeout))
+            .unwrap_or_default();
+        log_failed_tests(test, &results);
+        let failed = match &coverage {
+            Ok((_, code, _)) if test.should_panic() => *code == 0,
+            Ok((_, code, _)) => *code != 0,
+            Err(_) => true,
+        };
+        if failed {
+            rerun::record(config, test, ignored, tests, &results);
+        }
+        let (traces, return_code, counters) = match coverage {
+            Ok(coverage) => coverage,
+            // The run is still recorded with the results of its tests, without its coverage
+            Err(e) if config.no_fail_fast => {
+                error!("{}", e);
+                (TraceMap::new(), 101, None)
+            }
+            Err(e) => return Err(e),
+        };
+        let run = TestRun {
+            binary: test.path().to_path_buf(),
+            target: test.target_name(),
+            package: test.pkg_name().clone(),
+            run_type: test.run_type(),
+            duration: start.elapsed().as_secs_f64(),
+            collection: 0.0,
+            size: binary_size(test.path()),
+            return_code,
+            ignored,
+            tests: tests.to_vec(),
+            results,
+        };
+        let coverage = match counters {
+            Some(counters) => {
+                let test = test.clone();
+                let config = config.clone();
+                PendingCoverage::collecting(scope.spawn(move || {
+                    finish_run(traces, Some(counters), &test, analysis, &config, run)
+                }))
+            }
+            None => PendingCoverage::ready(finish_run(traces, None, test, analysis, config, run)?),
+        };
+        Ok(Some((coverage, return_code)))
     } else {
         Ok(None)
     }
 }
 
+/// Collects the counters of a finished test run, if it has any, and records the run
+fn finish_run(
+    mut traces: TraceMap,
+    counters: Option<CounterJob>,
+    test: &TestBinary,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+    mut run: TestRun,
+) -> Result<TraceMap, RunError> {
+    if let Some(counters) = counters {
+        counters.collect(&mut traces, analysis, config)?;
+        run.duration += traces.collection_time().as_secs_f64();
+    }
+    traces.tag_binary(&test.target_name());
+    if let Some(kind) = test.coverage_kind() {
+        traces.tag_run_type(kind);
+    }
+    if config.test_impact {
+        let target = test.target_name();
+        let tests = if run.tests.is_empty() {
+            vec![target]
+        } else {
+            run.tests.iter().map(|x| format!("{target} {x}")).collect()
+        };
+        traces.tag_tests(&tests);
+    }
+    run.collection = traces.collection_time().as_secs_f64();
+    traces.add_run(run);
+    Ok(traces)
+}
+
+fn log_failed_tests(test: &TestBinary, results: &[TestResult]) {
+    let failed = results
+        .iter()
+        .filter(|x| x.outcome == TestOutcome::Failed)
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        error!(
+            "{} failed {} of {} tests: {}",
+            test.file_name(),
+            failed.len(),
+            results.len(),
+            failed.join(", ")
+        );
+    }
+}
+
 fn launch_test(
     test: &TestBinary,
     other_binaries: &[PathBuf],
     config: &Config,
     ignored: bool,
     logger: &Option<EventLog>,
+    tests: &[String],
+    port_base: Option<u16>,
 ) -> Result<Option<TestHandle>, RunError> {
     if let Some(log) = logger.as_ref() {
-        log.push_binary(test.clone());
+        log.push_binary(test.clone(), ignored);
     }
-    match config.engine() {
-        TraceEngine::Ptrace => {
-            cfg_if::cfg_if! {
-                if #[cfg(ptrace_supported)] {
-                    linux::get_test_coverage(test, config, ignored)
-                } else {
-                    error!("Ptrace is not supported on this platform");
-                    Err(RunError::TestCoverage("Unsupported OS".to_string()))
-                }
-            }
-        }
-        TraceEngine::Llvm => {
-            // 1 test thread because https://github.com/rust-lang/rust/issues/91092
-            let res = execute_test(test, other_binaries, ignored, config, Some(1))?;
-            Ok(Some(res))
-        }
-        e => {
-            error!(
-                "Tarpaulin cannot execute tests with {:?} on this platform",
-                e
-            );
-            Err(RunError::TestCoverage("Unsupported OS".to_string()))
+    let serialised = serialisation_reason(test, config);
+    if let Some(reason) = serialised {
+        debug!("Running {} on one thread as {}", test.file_name(), reason);
+        if let Some(log) = logger.as_ref() {
+            log.push_serialised(reason.to_string());
         }
     }
+    let threads = serialised.map(|_| 1);
+    engine_for(config)?.launch(
+        test,
+        other_binaries,
+        config,
+        ignored,
+        tests,
+        threads,
+        port_base,
+    )
+}
+
+/// Block of ports for the test process with `--port-block`. This is allocated before launching as
+/// the ptrace engine forks to launch the test.
+fn port_block(test: &TestBinary, config: &Config) -> Option<PortBlock> {
+    let size = match config.port_block {
+        Some(size) if size > 0 => size,
+        _ => return None,
+    };
+    let block = ports::allocate_block(size);
+    match &block {
+        Some(block) => debug!(
+            "Giving {} ports {}-{}",
+            test.file_name(),
+            block.base(),
+            block.base() + (size - 1)
+        ),
+        None => warn!(
+            "No block of {} free ports left for {}, TARPAULIN_PORT_BASE isn't set",
+            size,
+            test.file_name()
+        ),
+    }
+    block
 }
 
 cfg_if::cfg_if! {
@@ -135,6 +551,8 @@ cfg_if::cfg_if! {
 
         pub mod breakpoint;
         pub mod ptrace_control;
+        mod network;
+        mod pty;
 
         pub type ProcessHandle = nix::unistd::Pid;
     } else {
@@ -142,27 +560,61 @@ cfg_if::cfg_if! {
     }
 }
 
-/// Collects the coverage data from the launched test
+/// Whether the test threads have been set by the user, either in the environment or the test args
+fn user_test_threads(config: &Config) -> bool {
+    env::var("RUST_TEST_THREADS").is_ok()
+        || config.varargs.iter().any(|x| x.contains("--test-threads"))
+}
+
+/// Why tarpaulin has to run the tests in the binary on a single thread, if it does. This is
+/// skipped when the user picks the number of test threads themselves.
+fn serialisation_reason(test: &TestBinary, config: &Config) -> Option<&'static str> {
+    if !test.is_test_type() || config.implicit_test_threads || user_test_threads(config) {
+        return None;
+    }
+    match config.engine() {
+        TraceEngine::Llvm => {
+            Some("llvm coverage may be lost with multiple test threads (rust-lang/rust#91092)")
+        }
+        TraceEngine::Ptrace if config.follow_exec => {
+            Some("following execs with ptrace needs the tests to run on one thread")
+        }
+        _ => None,
+    }
+}
+
+/// Collects the coverage data from the launched test, along with the llvm counters it wrote which
+/// are left for the caller to collect
 pub(crate) fn collect_coverage(
     test_path: &Path,
     test: TestHandle,
     analysis: &HashMap<PathBuf, LineAnalysis>,
     config: &Config,
     logger: &Option<EventLog>,
-) -> Result<(TraceMap, i32), RunError> {
+) -> Result<(TraceMap, i32, Option<CounterJob>), RunError> {
     let mut ret_code = 0;
-    let mut traces = if config.engine() == TraceEngine::Llvm {
-        TraceMap::new()
-    } else {
-        generate_tracemap(test_path, analysis, config)?
-    };
-    {
+    let engine = engine_for(config)?;
+    let start = Instant::now();
+    let mut traces = engine.prepare_traces(test_path, analysis, config)?;
+    traces.add_collection_time(start.elapsed());
+    let counters = {
         let span = trace_span!("Collect coverage", pid=%test);
         let _enter = span.enter();
         let (mut state, mut data) =
-            create_state_machine(test, &mut traces, analysis, config, logger);
+            engine.state_machine(test, &mut traces, analysis, config, logger);
         loop {
-            state = state.step(&mut data, config)?;
+            state = match state.step(&mut data, config) {
+                Ok(state) => state,
+                // With ptrace the hits are recorded as the test runs, so if it crashes or times
+                // out there's still partial coverage worth keeping when not failing fast
+                Err(e) if config.no_fail_fast && engine.keeps_partial_coverage() => {
+                    error!("{}", e);
+                    warn!("Keeping the coverage collected before the failure");
+                    ret_code = 101;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
             if state.is_finished() {
                 if let TestState::End(i) = state {
                     ret_code = i;
@@ -172,11 +624,117 @@ pub(crate) fn collect_coverage(
                 event_logger.push_marker();
             }
         }
+        data.take_counters()
+    };
+    Ok((traces, ret_code, counters))
+}
+
+/// Whether the test binary contains any ignored tests. This is found by listing them, if that
+/// fails it's assumed there are ignored tests so they still get ran.
+pub(crate) fn has_ignored_tests(test: &TestBinary, config: &Config) -> bool {
+    if test.run_type() == Some(RunType::Doctests) {
+        return true;
+    }
+    match list_tests(test, config, &["--ignored".to_string()]) {
+        Some(tests) => {
+            debug!(
+                "{} has {} ignored tests",
+                test.path().display(),
+                tests.len()
+            );
+            !tests.is_empty()
+        }
+        None => true,
+    }
+}
+
+/// Names of the tests the libtest binary runs with the given arguments, `None` if they can't be
+/// listed or none are
+fn list_tests(test: &TestBinary, config: &Config, args: &[String]) -> Option<Vec<String>> {
+    let dir = test.manifest_dir().clone().unwrap_or_else(|| config.root());
+    // The binary is instrumented, don't let the listing leave a profraw in the profraw directory
+    let profile = config.run_dir().join("list-%p.profraw");
+    let output = Command::new(test.path())
+        .arg("--list")
+        .args(args)
+        .envs(get_env_vars(test, config, None))
+        .env("LLVM_PROFILE_FILE", &profile)
+        .current_dir(dir)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    // A binary without the libtest harness may ignore `--list` and list nothing
+    let tests = listed_tests(&String::from_utf8_lossy(&output.stdout));
+    (output.status.success() && !tests.is_empty()).then_some(tests)
+}
+
+/// The tests in the output of a libtest binary ran with `--list`
+fn listed_tests(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|x| x.trim_end().strip_suffix(": test"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits the tests into at most `max` batches of consecutive tests, with one test per batch
+/// when there are few enough tests
+fn batch_tests(tests: Vec<String>, max: usize) -> Vec<Vec<String>> {
+    let size = tests.len().div_ceil(max.max(1)).max(1);
+    tests.chunks(size).map(<[String]>::to_vec).collect()
+}
+
+/// With `--process-per-test` the batches of tests to run in their own process. `None` when the
+/// binary should be ran normally, either as it's not a libtest binary or its tests can't be
+/// listed.
+fn process_per_test_batches(
+    test: &TestBinary,
+    config: &Config,
+    ignored: bool,
+) -> Option<Vec<Vec<String>>> {
+    if !config.process_per_test
+        || config.engine() != TraceEngine::Llvm
+        || !test.is_test_type()
+        || test.scenario().is_some()
+        || test.run_type() == Some(RunType::Doctests)
+    {
+        return None;
     }
-    Ok((traces, ret_code))
+    let mut args = config.varargs.clone();
+    if ignored {
+        args.push("--ignored".to_string());
+    }
+    let tests = list_tests(test, config, &args)?;
+    if tests.len() > MAX_TEST_PROCESSES {
+        info!(
+            "Batching {} tests in {} into {} processes",
+            tests.len(),
+            test.file_name(),
+            MAX_TEST_PROCESSES
+        );
+    }
+    Some(batch_tests(tests, MAX_TEST_PROCESSES))
+}
+
+/// Whether the output of the test is read to find which tests passed and failed, only libtest
+/// binaries report their tests
+fn captures_output(test: &TestBinary) -> bool {
+    test.is_test_type() && test.scenario().is_none() && test.run_type() != Some(RunType::Doctests)
+}
+
+/// Whether the output of a libtest binary is read through a pipe rather than a terminal
+fn pipes_output(test: &TestBinary, config: &Config) -> bool {
+    config.engine() == TraceEngine::Llvm
+        && captures_output(test)
+        && test.uses_libtest()
+        && !(config.allocate_pty && cfg!(ptrace_supported))
 }
 
-fn get_env_vars(test: &TestBinary, config: &Config) -> Vec<(String, String)> {
+fn get_env_vars(
+    test: &TestBinary,
+    config: &Config,
+    port_base: Option<u16>,
+) -> Vec<(String, String)> {
     let mut envars: Vec<(String, String)> = Vec::new();
 
     for (key, value) in env::vars() {
@@ -205,17 +763,23 @@ fn get_env_vars(test: &TestBinary, config: &Config) -> Vec<(String, String)> {
         envars.push((LD_PATH_VAR.to_string(), test.ld_library_path()));
     }
     envars.push(("RUSTFLAGS".to_string(), rust_flags(config)));
+    if let (Some(base), Some(count)) = (port_base, config.port_block) {
+        envars.push(("TARPAULIN_PORT_BASE".to_string(), base.to_string()));
+        envars.push(("TARPAULIN_PORT_COUNT".to_string(), count.to_string()));
+    }
 
     envars
 }
 
 /// Launches the test executable
-fn execute_test(
+pub(crate) fn execute_test(
     test: &TestBinary,
     other_binaries: &[PathBuf],
     ignored: bool,
     config: &Config,
     num_threads: Option<usize>,
+    tests: &[String],
+    port_base: Option<u16>,
 ) -> Result<TestHandle, RunError> {
     info!("running {}", test.path().display());
     let _ = match test.manifest_dir() {
@@ -225,16 +789,37 @@ fn execute_test(
 
     debug!("Current working dir: {:?}", env::current_dir());
 
-    let mut envars = get_env_vars(test, config);
+    let mut envars = get_env_vars(test, config, port_base);
 
     let mut argv = vec![];
-    if ignored {
-        argv.push("--ignored".to_string());
+    if let Some(scenario) = test.scenario() {
+        info!("Scenario: {}", scenario.name);
+        argv.extend_from_slice(&scenario.args);
+        envars.retain(|(key, _)| !scenario.env.contains_key(key));
+        envars.extend(scenario.env.clone());
+    } else {
+        if ignored {
+            argv.push("--ignored".to_string());
+        }
+        argv.extend_from_slice(&config.varargs);
+        if !tests.is_empty() {
+            argv.push("--exact".to_string());
+            argv.extend_from_slice(tests);
+        }
+        if config.color != Color::Auto {
+            argv.push("--color".to_string());
+            argv.push(config.color.to_string().to_ascii_lowercase());
+        } else if pipes_output(test, config) && io::stdout().is_terminal() {
+            // libtest only colours its output for a terminal, and it's echoed to ours
+            argv.push("--color".to_string());
+            argv.push("always".to_string());
+        }
     }
-    argv.extend_from_slice(&config.varargs);
-    if config.color != Color::Auto {
-        argv.push("--color".to_string());
-        argv.push(config.color.to_string().to_ascii_lowercase());
+    if let Some(binary) = config.binary_override(&test.target_name()) {
+        let BinaryOverride { args, env, .. } = binary;
+        argv.extend(args);
+        envars.retain(|(key, _)| !env.contains_key(key));
+        envars.extend(env);
     }
     if let Ok(threads) = env::var("RUST_TEST_THREADS") {
         envars.push(("RUST_TEST_THREADS".to_string(), threads));
@@ -250,11 +835,7 @@ fn execute_test(
     match config.engine() {
         TraceEngine::Llvm => {
             info!("Setting LLVM_PROFILE_FILE");
-            // Used for llvm coverage to avoid report naming clashes TODO could have clashes
-            // between runs
-            let profile_dir = config
-                .profraw_dir()
-                .join(format!("{}_%m-%p.profraw", test.file_name()));
+            let profile_dir = config.profraw_dir().join(profraw_pattern(test.path()));
             envars.push((
                 "LLVM_PROFILE_FILE".to_string(),
                 profile_dir.display().to_string(),
@@ -262,6 +843,23 @@ fn execute_test(
             debug!("Env vars: {:?}", envars);
             debug!("Args: {:?}", argv);
             let mut child = Command::new(test.path());
+            #[cfg(ptrace_supported)]
+            if config.isolate_network {
+                network::isolate(&mut child);
+            }
+            #[cfg(ptrace_supported)]
+            if config.allocate_pty {
+                let terminal = pty::attach(&mut child)?;
+                child.envs(envars).args(&argv);
+                let others = other_binaries.to_vec();
+                let mut hnd = RunningProcessHandle::new(test, others, &mut child, config)?;
+                hnd.output = Some(TestOutput::capture(terminal));
+                hnd.session = Some(pty::forward_interrupts(hnd.child.id()));
+                return Ok(hnd.into());
+            }
+            if captures_output(test) {
+                child.stdout(Stdio::piped());
+            }
             child.envs(envars).args(&argv);
             let others = other_binaries.to_vec();
             let hnd = RunningProcessHandle::new(test, others, &mut child, config)?;
@@ -270,10 +868,52 @@ fn execute_test(
         #[cfg(ptrace_supported)]
         TraceEngine::Ptrace => {
             argv.insert(0, test.path().display().to_string());
+            // Binaries instrumented for the llvm engine would otherwise leave profraws in the
+            // working directory
+            envars.push((
+                "LLVM_PROFILE_FILE".to_string(),
+                config
+                    .run_dir()
+                    .join("ptrace-%p.profraw")
+                    .display()
+                    .to_string(),
+            ));
             debug!("Env vars: {:?}", envars);
             debug!("Args: {:?}", argv);
+            // This runs in the child forked to be traced, so it can isolate itself
+            if config.isolate_network {
+                network::NetworkIsolation::new().enter().map_err(|e| {
+                    RunError::TestRuntime(format!("Failed to isolate the network: {e}"))
+                })?;
+            }
             execute(test.path(), &argv, envars.as_slice())
         }
+        #[cfg(ptrace_supported)]
+        TraceEngine::Perf => {
+            let samples = samples_path(test, config);
+            debug!("Env vars: {:?}", envars);
+            debug!("Args: {:?}", argv);
+            // perf follows any processes the test starts, so prebuilt binaries it runs are
+            // sampled too
+            let mut child = Command::new("perf");
+            child
+                .args(["record", "--quiet", "-F", "max", "-o"])
+                .arg(&samples)
+                .arg("--")
+                .arg(test.path());
+            if config.isolate_network {
+                network::isolate(&mut child);
+            }
+            if captures_output(test) {
+                child.stdout(Stdio::piped());
+            }
+            child.envs(envars).args(&argv);
+            let others = other_binaries.to_vec();
+            let mut hnd = RunningProcessHandle::new(test, others, &mut child, config)
+                .map_err(|e| RunError::Engine(format!("Failed to start perf record: {e}")))?;
+            hnd.samples = Some(samples);
+            Ok(hnd.into())
+        }
         e => Err(RunError::Engine(format!("invalid execution engine {e:?}"))),
     }
 }
@@ -281,14 +921,162 @@ fn execute_test(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+
+    #[test]
+    fn count_ignored_tests() {
+        let output = "tests::slow: test\ntests::slower: test\nbenches::b: benchmark\n\n2 tests, 1 benchmark\n";
+        assert_eq!(listed_tests(output), vec!["tests::slow", "tests::slower"]);
+        assert!(listed_tests("0 tests, 0 benchmarks\n").is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn binary_listing_no_tests_ran_normally() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("tarpaulin-list-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Like a `harness = false` binary which ignores its arguments
+        let binary = dir.join("custom-harness");
+        fs::write(&binary, "#!/bin/sh\necho running my own tests\n").unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        let test = TestBinary::new(binary, Some(RunType::Tests));
+        assert_eq!(list_tests(&test, &Config::default(), &[]), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_timeouts_only_extend_timeout() {
+        use crate::config::TestTimeout;
+
+        let mut config = Config::default();
+        config.test_timeout = Duration::from_secs(60);
+        config.test_timeouts = vec![
+            TestTimeout {
+                test: "sim::physics".to_string(),
+                timeout: Duration::from_secs(600),
+            },
+            TestTimeout {
+                test: "quick".to_string(),
+                timeout: Duration::from_secs(5),
+            },
+        ];
+        let test = TestBinary::new(
+            PathBuf::from("target/debug/deps/sim-0123456789abcdef"),
+            None,
+        );
+        let timeout = |tests: &[&str]| {
+            let tests = tests.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            with_test_timeouts(&test, &config, &tests).test_timeout
+        };
+        assert_eq!(timeout(&["physics::fall"]), Duration::from_secs(600));
+        assert_eq!(timeout(&["quick"]), Duration::from_secs(60));
+        assert_eq!(timeout(&["other"]), Duration::from_secs(60));
+    }
+
+    fn test_run(binary: &str) -> TestRun {
+        TestRun {
+            binary: PathBuf::from(binary),
+            target: String::new(),
+            package: None,
+            run_type: None,
+            duration: 1.0,
+            collection: 0.0,
+            size: None,
+            return_code: 0,
+            ignored: false,
+            tests: vec![],
+            results: vec![],
+        }
+    }
+
+    #[test]
+    fn pending_coverage_follows_run_order() {
+        let traces = |binary: &str| {
+            let mut traces = TraceMap::new();
+            traces.add_run(test_run(binary));
+            traces
+        };
+        let merged = thread::scope(|scope| {
+            let slow = PendingCoverage::collecting(scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                Ok(traces("first"))
+            }));
+            PendingCoverage::ready(traces("second"))
+                .followed_by(slow)
+                .and_then(|x| x.followed_by(PendingCoverage::ready(traces("third"))))
+                .and_then(PendingCoverage::wait)
+        })
+        .unwrap();
+        let binaries = merged
+            .runs()
+            .iter()
+            .map(|x| x.binary.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(binaries, vec!["second", "first", "third"]);
+    }
+
+    #[test]
+    fn pending_coverage_collection_error_returned() {
+        thread::scope(|scope| {
+            let failed = || {
+                PendingCoverage::collecting(
+                    scope.spawn(|| Err(RunError::TestCoverage("no profraws".to_string()))),
+                )
+            };
+            assert!(failed().wait().is_err());
+            assert!(failed()
+                .followed_by(PendingCoverage::ready(TraceMap::new()))
+                .is_err());
+            let pending = PendingCoverage::ready(TraceMap::new())
+                .followed_by(failed())
+                .unwrap();
+            assert!(pending.wait().is_err());
+        });
+    }
+
+    #[test]
+    fn finished_run_recorded() {
+        let test = TestBinary::new(
+            PathBuf::from("target/debug/deps/foo-0123456789abcdef"),
+            None,
+        );
+        let mut config = Config::default();
+        config.test_impact = true;
+        let mut traces = TraceMap::new();
+        let mut trace = Trace::new_stub(1);
+        trace.stats = CoverageStat::Line(1);
+        traces.add_trace(Path::new("src/lib.rs"), trace);
+        let mut run = test_run("target/debug/deps/foo-0123456789abcdef");
+        run.tests = vec!["a".to_string()];
+        let traces = finish_run(traces, None, &test, &HashMap::new(), &config, run).unwrap();
+        assert_eq!(traces.runs().len(), 1);
+        assert_eq!(traces.runs()[0].duration, 1.0);
+        assert_eq!(traces.runs()[0].collection, 0.0);
+        assert!(traces.test_hits().contains_key("foo a"));
+        assert!(traces
+            .get_child_traces(Path::new("src/lib.rs"))
+            .all(|x| x.hit_by.contains("foo")));
+    }
+
+    #[test]
+    fn tests_batched_past_process_limit() {
+        let tests = (0..10).map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(batch_tests(tests.clone(), 20).len(), 10);
+        let batches = batch_tests(tests, 4);
+        assert_eq!(batches.len(), 4);
+        assert_eq!(batches[0], vec!["0", "1", "2"]);
+        assert_eq!(batches[3], vec!["9"]);
+        assert!(batch_tests(vec![], 4).is_empty());
+    }
 
     #[test]
     fn check_ld_library_path_correct() {
         let mut binary = TestBinary::new(PathBuf::from("dummy"), None);
         let default_config = Config::default();
 
-        let vars = get_env_vars(&binary, &default_config);
+        let vars = get_env_vars(&binary, &default_config, None);
 
         if let Some(ld) = vars
             .iter()
@@ -303,7 +1091,7 @@ mod tests {
             .linker_paths
             .push(PathBuf::from("/usr/local/lib/foo"));
 
-        let vars = get_env_vars(&binary, &default_config);
+        let vars = get_env_vars(&binary, &default_config, None);
         let res = vars
             .iter()
             .find(|(key, _)| key == LD_PATH_VAR)
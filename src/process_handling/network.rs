@@ -0,0 +1,123 @@
+//! Running test binaries in their own network namespace with `--isolate-network`, so tests can't
+//! reach other hosts by accident and the ports they bind can't clash with anything else running on
+//! the machine. The namespace only has a loopback interface, which is brought up before the test
+//! starts. Creating a network namespace needs `CAP_SYS_ADMIN`, so without it a user namespace
+//! mapping the user and group onto themselves is created first and the tests still run as them.
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Everything needed to isolate a process, prepared before forking as only async-signal-safe
+/// functions can be called between the fork and the exec
+pub(crate) struct NetworkIsolation {
+    /// Contents of `/proc/self/uid_map` and `/proc/self/gid_map` when a user namespace is needed
+    id_maps: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl NetworkIsolation {
+    pub(crate) fn new() -> Self {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let id_maps = (uid != 0).then(|| {
+            (
+                format!("{uid} {uid} 1").into_bytes(),
+                format!("{gid} {gid} 1").into_bytes(),
+            )
+        });
+        Self { id_maps }
+    }
+
+    /// Moves the calling process into a new network namespace with only loopback
+    pub(crate) fn enter(&self) -> io::Result<()> {
+        match &self.id_maps {
+            Some((uid_map, gid_map)) => {
+                unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET)?;
+                // The groups can only be mapped by an unprivileged user once setgroups is denied
+                write_proc(b"/proc/self/setgroups\0", b"deny")?;
+                write_proc(b"/proc/self/uid_map\0", uid_map)?;
+                write_proc(b"/proc/self/gid_map\0", gid_map)?;
+            }
+            None => unshare(libc::CLONE_NEWNET)?,
+        }
+        loopback_up()
+    }
+}
+
+/// Runs the command in a new network namespace
+pub(crate) fn isolate(cmd: &mut Command) {
+    let isolation = NetworkIsolation::new();
+    unsafe {
+        cmd.pre_exec(move || isolation.enter());
+    }
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn unshare(flags: libc::c_int) -> io::Result<()> {
+    check(unsafe { libc::unshare(flags) }).map(|_| ())
+}
+
+/// Writes to a file in procfs, the path has to be nul terminated
+fn write_proc(path: &[u8], contents: &[u8]) -> io::Result<()> {
+    let flags = libc::O_WRONLY | libc::O_CLOEXEC;
+    let fd = check(unsafe { libc::open(path.as_ptr().cast(), flags) })?;
+    let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+    let res = if written == contents.len() as isize {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    };
+    unsafe { libc::close(fd) };
+    res
+}
+
+/// A new network namespace's loopback interface starts down
+fn loopback_up() -> io::Result<()> {
+    let sock =
+        check(unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) })?;
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    let res = check(unsafe { libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut req as *mut libc::ifreq) })
+        .and_then(|_| {
+            unsafe {
+                req.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            }
+            check(unsafe { libc::ioctl(sock, libc::SIOCSIFFLAGS, &req as *const libc::ifreq) })
+        });
+    unsafe { libc::close(sock) };
+    res.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_in_namespace() {
+        let mut cmd = Command::new("cat");
+        cmd.arg("/proc/self/net/dev");
+        isolate(&mut cmd);
+        // Some containers aren't allowed to create namespaces
+        let output = match cmd.output() {
+            Ok(output) => output,
+            Err(_) => return,
+        };
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // Two header lines and then one line per interface
+        let interfaces = stdout
+            .lines()
+            .skip(2)
+            .filter_map(|x| x.split(':').next())
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        assert_eq!(interfaces, ["lo"]);
+    }
+}
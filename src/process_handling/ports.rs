@@ -0,0 +1,135 @@
+//! Blocks of ports handed to the test processes with `--port-block`. Tests which would bind a fixed
+//! port can read the first port of their block from `TARPAULIN_PORT_BASE` and the number of ports
+//! in it from `TARPAULIN_PORT_COUNT` instead, so they don't collide with another test binary or a
+//! port already in use on the machine. Blocks are handed out in order skipping any with a port in
+//! use, so a binary normally gets the same ports from one run to the next. Once a process exits
+//! its block is handed out again, so a run can start more processes than there are blocks.
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Ports handed out, above the registered ports most services use and below where Linux starts
+/// picking ephemeral ports
+static PORTS: PortRange = PortRange::new(20000, 32767);
+
+#[derive(Debug)]
+struct PortRange {
+    next: AtomicU32,
+    last: u16,
+    /// First port and size of the blocks given back by processes which have exited
+    released: Mutex<Vec<(u16, u16)>>,
+}
+
+/// A block of ports given to a test process, it's given back to be handed out again once dropped
+#[derive(Debug)]
+pub(crate) struct PortBlock {
+    base: u16,
+    size: u16,
+    range: &'static PortRange,
+}
+
+impl PortBlock {
+    /// First port of the block
+    pub(crate) fn base(&self) -> u16 {
+        self.base
+    }
+}
+
+impl Drop for PortBlock {
+    fn drop(&mut self) {
+        self.range.release(self.base, self.size);
+    }
+}
+
+impl PortRange {
+    const fn new(first: u16, last: u16) -> Self {
+        Self {
+            next: AtomicU32::new(first as u32),
+            last,
+            released: Mutex::new(vec![]),
+        }
+    }
+
+    /// Next block of free ports, preferring a released block of the same size. `None` once the
+    /// range is used up and no block is released.
+    fn allocate(&'static self, size: u16) -> Option<PortBlock> {
+        let size = size.max(1);
+        let block = |base| PortBlock {
+            base,
+            size,
+            range: self,
+        };
+        {
+            let mut released = self.released.lock().unwrap_or_else(|e| e.into_inner());
+            let reusable = released
+                .iter()
+                .position(|&(base, x)| x == size && (base..=base + (size - 1)).all(is_free));
+            if let Some(i) = reusable {
+                return Some(block(released.remove(i).0));
+            }
+        }
+        let size = u32::from(size);
+        loop {
+            let base = self.next.fetch_add(size, Ordering::Relaxed);
+            if base + size - 1 > u32::from(self.last) {
+                return None;
+            }
+            if (base..base + size).all(|x| is_free(x as u16)) {
+                return Some(block(base as u16));
+            }
+        }
+    }
+
+    fn release(&self, base: u16, size: u16) {
+        let mut released = self.released.lock().unwrap_or_else(|e| e.into_inner());
+        released.push((base, size));
+    }
+}
+
+fn is_free(port: u16) -> bool {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_ok()
+}
+
+/// Next block of `size` free ports
+pub(crate) fn allocate_block(size: u16) -> Option<PortBlock> {
+    PORTS.allocate(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_in_use_skipped() {
+        let busy = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = busy.local_addr().unwrap().port();
+        let range: &PortRange = Box::leak(Box::new(PortRange::new(
+            port.saturating_sub(2),
+            port.saturating_add(5),
+        )));
+        let mut blocks = vec![];
+        while let Some(block) = range.allocate(2) {
+            blocks.push(block);
+        }
+        assert!(!blocks.is_empty());
+        let bases = blocks.iter().map(PortBlock::base).collect::<Vec<_>>();
+        for base in &bases {
+            assert!(!(*base..*base + 2).contains(&port));
+            assert!(*base < port.saturating_add(5));
+        }
+        assert!(bases.windows(2).all(|x| x[0] + 2 <= x[1]));
+    }
+
+    #[test]
+    fn blocks_reused_once_released() {
+        let range: &PortRange = Box::leak(Box::new(PortRange::new(20000, 20009)));
+        let mut blocks = std::iter::from_fn(|| range.allocate(2)).collect::<Vec<_>>();
+        assert!(range.allocate(2).is_none());
+        // Ports in use on the machine may leave no blocks to release
+        if let Some(block) = blocks.pop() {
+            let base = block.base();
+            drop(block);
+            assert_eq!(range.allocate(2).map(|x| x.base()), Some(base));
+        }
+    }
+}
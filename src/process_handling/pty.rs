@@ -0,0 +1,127 @@
+//! Running test binaries in a pseudo-terminal with `--allocate-pty`, for tests which check their
+//! output is a terminal or drive it interactively. The terminal is the test's stdin, stdout and
+//! stderr and the controlling terminal of its session, everything written to it is read back from
+//! the other side to be captured like piped output.
+//!
+//! The terminal is opened with `openpty`, so this is only built on the Linux targets ptrace is
+//! supported on. Each test is in its own session so Ctrl-C in our terminal doesn't reach it, while
+//! a test runs in a terminal SIGINT is forwarded to its session before we exit.
+use nix::pty::{openpty, OpenptyResult};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use std::fs::File;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Once;
+use tracing::{debug, warn};
+
+/// Sessions of the tests running in a terminal, with 0 for a free slot. These are read in the
+/// SIGINT handler so they're kept in atomics rather than behind a lock.
+static SESSIONS: [AtomicI32; 256] = [const { AtomicI32::new(0) }; 256];
+
+static INSTALL_HANDLER: Once = Once::new();
+
+/// Forwards SIGINT to every test session then exits as SIGINT would have without the handler
+extern "C" fn forward_interrupt(_: libc::c_int) {
+    for session in &SESSIONS {
+        let pid = session.load(Ordering::SeqCst);
+        if pid > 0 {
+            // Only async-signal-safe functions can be called from a signal handler
+            unsafe {
+                libc::kill(-pid, libc::SIGINT);
+            }
+        }
+    }
+    // The handler was reset to the default when it ran, so this ends the process once it returns
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+}
+
+/// A test session SIGINT is forwarded to until this is dropped
+#[derive(Debug)]
+pub(crate) struct Session(Option<usize>);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            SESSIONS[slot].store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Forwards SIGINT to the session of the process started by `attach`. This should be dropped
+/// once the process has exited.
+pub(crate) fn forward_interrupts(pid: u32) -> Session {
+    INSTALL_HANDLER.call_once(|| {
+        let action = SigAction::new(
+            SigHandler::Handler(forward_interrupt),
+            SaFlags::SA_RESETHAND,
+            SigSet::empty(),
+        );
+        if let Err(e) = unsafe { sigaction(Signal::SIGINT, &action) } {
+            warn!("Ctrl-C won't reach tests running in a terminal: {}", e);
+        }
+    });
+    let pid = pid as i32;
+    let slot = SESSIONS.iter().position(|x| {
+        x.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    });
+    if slot.is_none() {
+        debug!("Too many tests in terminals to forward Ctrl-C to {}", pid);
+    }
+    Session(slot)
+}
+
+/// Runs the command in a new pseudo-terminal, returning the side of the terminal its output is
+/// read from. The command has to be dropped once the process is spawned so reading stops when the
+/// process exits.
+pub(crate) fn attach(cmd: &mut Command) -> io::Result<File> {
+    let OpenptyResult { master, slave } = openpty(None, None).map_err(io::Error::from)?;
+    cmd.stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // Only async-signal-safe functions can be called between the fork and the exec
+    unsafe {
+        cmd.pre_exec(|| {
+            // The terminal can only become the controlling terminal of a new session. Stdout is
+            // used as a scenario may replace stdin with a pipe
+            if libc::setsid() == -1 || libc::ioctl(1, libc::TIOCSCTTY, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(File::from(master))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn output_read_from_terminal() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "test -t 0 && test -t 1 && test -t 2 && echo tty"]);
+        let mut terminal = attach(&mut cmd).unwrap();
+        let mut child = cmd.spawn().unwrap();
+        drop(cmd);
+        assert!(child.wait().unwrap().success());
+        // Reading ends in an error once the process has exited and closed the terminal
+        let mut output = vec![];
+        let _ = terminal.read_to_end(&mut output);
+        assert_eq!(String::from_utf8_lossy(&output).trim(), "tty");
+    }
+
+    #[test]
+    fn interrupts_forwarded_until_dropped() {
+        let registered = |pid| SESSIONS.iter().any(|x| x.load(Ordering::SeqCst) == pid);
+        let session = forward_interrupts(4_000_001);
+        assert!(registered(4_000_001));
+        drop(session);
+        assert!(!registered(4_000_001));
+    }
+}
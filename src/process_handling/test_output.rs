@@ -0,0 +1,191 @@
+//! Reading the results of the tests from the output of libtest binaries. Both the default pretty
+//! output and the json events from `-Zunstable-options --format json` are understood, so which
+//! tests passed or failed is known rather than only the exit code of the binary.
+use crate::traces::{TestOutcome, TestResult};
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Stdout of a running test binary, it's echoed to our stdout as it's read
+#[derive(Debug)]
+pub struct TestOutput {
+    results: Receiver<TestResult>,
+}
+
+impl TestOutput {
+    pub fn capture(stdout: impl Read + Send + 'static) -> Self {
+        let (sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            let mut line = vec![];
+            while let Ok(n) = reader.read_until(b'\n', &mut line) {
+                if n == 0 {
+                    break;
+                }
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(&line);
+                let _ = stdout.flush();
+                if let Some(result) = parse_line(&String::from_utf8_lossy(&line)) {
+                    let _ = sender.send(result);
+                }
+                line.clear();
+            }
+        });
+        Self { results }
+    }
+
+    /// Results of the tests the binary reported, to be called once it's exited. The output is read
+    /// until it ends, processes spawned by the tests can keep it open after the test has exited so
+    /// it's given up on after `timeout`.
+    pub fn results(self, timeout: Duration) -> Vec<TestResult> {
+        let deadline = Instant::now() + timeout;
+        let mut results = vec![];
+        loop {
+            match self
+                .results
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(result) => results.push(result),
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    warn!("Test output was still open {:?} after the test exited, processes it started may still be using it", timeout);
+                    break;
+                }
+            }
+        }
+        results
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    exec_time: Option<f64>,
+}
+
+/// The result of a test if the line of output reports one
+pub(crate) fn parse_line(line: &str) -> Option<TestResult> {
+    let line = strip_escapes(line.trim_end());
+    if line.starts_with('{') {
+        parse_json(&line)
+    } else {
+        parse_pretty(&line)
+    }
+}
+
+fn parse_json(line: &str) -> Option<TestResult> {
+    let event: JsonEvent = serde_json::from_str(line).ok()?;
+    if event.kind != "test" {
+        return None;
+    }
+    let outcome = match event.event.as_str() {
+        "ok" => TestOutcome::Passed,
+        "failed" | "timeout" => TestOutcome::Failed,
+        "ignored" => TestOutcome::Ignored,
+        _ => return None,
+    };
+    Some(TestResult {
+        name: event.name?,
+        outcome,
+        exec_time: event.exec_time,
+    })
+}
+
+/// Parses lines like `test tests::it_works ... ok <0.001s>`
+fn parse_pretty(line: &str) -> Option<TestResult> {
+    let (name, status) = line.strip_prefix("test ")?.rsplit_once(" ... ")?;
+    let (outcome, rest) = if let Some(rest) = status.strip_prefix("ok") {
+        (TestOutcome::Passed, rest)
+    } else if let Some(rest) = status.strip_prefix("FAILED") {
+        (TestOutcome::Failed, rest)
+    } else if let Some(rest) = status.strip_prefix("ignored") {
+        (TestOutcome::Ignored, rest)
+    } else {
+        return None;
+    };
+    let exec_time = rest
+        .trim()
+        .strip_prefix('<')
+        .and_then(|x| x.strip_suffix("s>"))
+        .and_then(|x| x.parse().ok());
+    Some(TestResult {
+        name: name.to_string(),
+        outcome,
+        exec_time,
+    })
+}
+
+/// Removes the ANSI escape sequences libtest colours its output with
+fn strip_escapes(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_read_until_output_ends() {
+        let output = "running 2 tests\ntest a ... ok\ntest b ... FAILED\n";
+        let output = TestOutput::capture(io::Cursor::new(output.as_bytes().to_vec()));
+        let start = Instant::now();
+        let results = output.results(Duration::from_secs(60));
+        assert!(start.elapsed() < Duration::from_secs(60));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].outcome, TestOutcome::Failed);
+    }
+
+    #[test]
+    fn pretty_results_parsed() {
+        let result = parse_line("test tests::it_works ... ok\n").unwrap();
+        assert_eq!(result.name, "tests::it_works");
+        assert_eq!(result.outcome, TestOutcome::Passed);
+        assert_eq!(result.exec_time, None);
+
+        let result = parse_line("test a::b ... \u{1b}[31mFAILED\u{1b}[0m <0.250s>").unwrap();
+        assert_eq!(result.name, "a::b");
+        assert_eq!(result.outcome, TestOutcome::Failed);
+        assert_eq!(result.exec_time, Some(0.25));
+
+        let result = parse_line("test src/lib.rs - foo (line 3) ... ignored, slow").unwrap();
+        assert_eq!(result.name, "src/lib.rs - foo (line 3)");
+        assert_eq!(result.outcome, TestOutcome::Ignored);
+
+        assert!(parse_line("running 3 tests").is_none());
+        assert!(parse_line("test result: ok. 3 passed; 0 failed").is_none());
+        assert!(parse_line("test bench ... bench:         10 ns/iter").is_none());
+    }
+
+    #[test]
+    fn json_events_parsed() {
+        let result = parse_line(
+            r#"{ "type": "test", "name": "a::b", "event": "failed", "exec_time": 0.5 }"#,
+        )
+        .unwrap();
+        assert_eq!(result.name, "a::b");
+        assert_eq!(result.outcome, TestOutcome::Failed);
+        assert_eq!(result.exec_time, Some(0.5));
+
+        assert!(parse_line(r#"{ "type": "test", "event": "started", "name": "a::b" }"#).is_none());
+        assert!(parse_line(r#"{ "type": "suite", "event": "ok", "passed": 1 }"#).is_none());
+    }
+}
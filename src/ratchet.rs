@@ -0,0 +1,152 @@
+//! Raising `fail-under` as coverage improves so it can never drop back, using the coverage stored
+//! by the last run.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::report::get_previous_result;
+use std::fs;
+use toml_edit::{value, DocumentMut, Value};
+use tracing::{info, warn};
+
+/// The threshold `fail-under` can be raised to, if coverage exceeds it by at least `margin`
+/// percentage points. Thresholds are rounded down to two decimal places so the run which set them
+/// still passes.
+fn ratcheted_threshold(coverage: f64, threshold: f64, margin: f64) -> Option<f64> {
+    let raised = (coverage * 100.0).floor() / 100.0;
+    (coverage - threshold >= margin && raised > threshold).then_some(raised)
+}
+
+/// Sets `fail-under` in the table of the config file, keeping the rest of the file as it was
+fn rewrite_threshold(config_file: &str, table: &str, threshold: f64) -> Result<String, RunError> {
+    let mut doc = config_file
+        .parse::<DocumentMut>()
+        .map_err(|e| RunError::Manifest(format!("Failed to parse config file: {e}")))?;
+    match doc.get_mut(table).and_then(|x| x.as_table_like_mut()) {
+        Some(table) => {
+            // Replace just the number so comments next to it are kept
+            match table.get_mut("fail-under").and_then(|x| x.as_value_mut()) {
+                Some(existing) => {
+                    let decor = existing.decor().clone();
+                    *existing = Value::from(threshold);
+                    *existing.decor_mut() = decor;
+                }
+                None => {
+                    table.insert("fail-under", value(threshold));
+                }
+            }
+            Ok(doc.to_string())
+        }
+        None => Err(RunError::Manifest(format!(
+            "No [{table}] table in the config file"
+        ))),
+    }
+}
+
+/// The config whose `fail-under` is checked against the coverage stored by a run. The stored
+/// coverage is merged from every config, so with several configs only the `report` table's
+/// threshold applies to it, the thresholds of the others are checked against their own coverage
+/// which isn't stored.
+fn top_level_config(configs: &[Config]) -> Option<&Config> {
+    match configs {
+        [config] => Some(config),
+        _ => configs.iter().find(|c| c.name == "report"),
+    }
+}
+
+/// Raises the top-level `fail-under` using the coverage stored by the last run, when it's above
+/// the threshold by at least `margin` percentage points. With `write` the threshold is written to
+/// the config file, otherwise the new threshold is printed.
+pub fn ratchet(configs: &[Config], margin: f64, write: bool) -> Result<(), RunError> {
+    let config = match top_level_config(configs) {
+        Some(config) => config,
+        None => {
+            warn!("Only the fail-under of a [report] table can be ratcheted with several configs");
+            return Ok(());
+        }
+    };
+    if configs
+        .iter()
+        .any(|c| c.name != config.name && c.fail_under.is_some())
+    {
+        info!("fail-under of the other configs is checked against their own coverage, which isn't stored, so it's left as it is");
+    }
+    let threshold = match config.fail_under {
+        Some(threshold) => threshold,
+        None => return Ok(()),
+    };
+    let coverage = match get_previous_result(config) {
+        Some(result) => result.coverage_percentage() * 100.0,
+        None => {
+            warn!("No stored coverage, run tarpaulin first");
+            return Ok(());
+        }
+    };
+    let raised = match ratcheted_threshold(coverage, threshold, margin) {
+        Some(raised) => raised,
+        None => {
+            info!(
+                "Coverage {:.2}% isn't {}% above fail-under {}, leaving it as it is",
+                coverage, margin, threshold
+            );
+            return Ok(());
+        }
+    };
+    match (&config.config, write) {
+        (Some(path), true) => {
+            let contents = fs::read_to_string(path)?;
+            fs::write(path, rewrite_threshold(&contents, &config.name, raised)?)?;
+            info!(
+                "Raised fail-under from {} to {} in {}",
+                threshold,
+                raised,
+                path.display()
+            );
+        }
+        (None, true) => {
+            warn!("fail-under isn't set in a config file so can't be rewritten");
+            println!("{raised}");
+        }
+        (_, false) => println!("{raised}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_raised_past_margin() {
+        assert_eq!(ratcheted_threshold(85.678, 80.0, 1.0), Some(85.67));
+        assert_eq!(ratcheted_threshold(80.5, 80.0, 1.0), None);
+        assert_eq!(ratcheted_threshold(80.004, 80.0, 0.0), None);
+        assert_eq!(ratcheted_threshold(70.0, 80.0, 0.0), None);
+    }
+
+    #[test]
+    fn threshold_rewritten_in_place() {
+        let config = "# Coverage settings\n[default]\nfail-under = 80 # don't drop\nout = [\"Xml\"]\n\n[other]\nfail-under = 50\n";
+        let rewritten = rewrite_threshold(config, "default", 85.5).unwrap();
+        assert_eq!(
+            rewritten,
+            "# Coverage settings\n[default]\nfail-under = 85.5 # don't drop\nout = [\"Xml\"]\n\n[other]\nfail-under = 50\n"
+        );
+        assert!(rewrite_threshold(config, "missing", 85.5).is_err());
+    }
+
+    #[test]
+    fn only_top_level_threshold_ratcheted() {
+        let named = |name: &str| {
+            let mut config = Config::default();
+            config.name = name.to_string();
+            config
+        };
+        let single = [named("default")];
+        assert_eq!(top_level_config(&single).unwrap().name, "default");
+
+        let several = [named("unit"), named("integration")];
+        assert!(top_level_config(&several).is_none());
+
+        let with_report = [named("unit"), named("report"), named("integration")];
+        assert_eq!(top_level_config(&with_report).unwrap().name, "report");
+    }
+}
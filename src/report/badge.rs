@@ -0,0 +1,119 @@
+//! A coverage badge for READMEs which doesn't need a third party service. Both an SVG in the
+//! flat style of shields.io and a shields.io endpoint json file, for projects which would rather
+//! render it through shields.io, are written to the output directory.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::report::report_file;
+use crate::traces::TraceMap;
+use std::fs;
+
+const LABEL: &str = "coverage";
+
+/// Badge colours from shields.io, each used from the coverage percentage given upwards
+const COLOURS: &[(f64, &str, &str)] = &[
+    (90.0, "brightgreen", "#4c1"),
+    (80.0, "green", "#97ca00"),
+    (70.0, "yellowgreen", "#a4a61d"),
+    (60.0, "yellow", "#dfb317"),
+    (50.0, "orange", "#fe7d37"),
+    (0.0, "red", "#e05d44"),
+];
+
+/// Name and hex code of the colour for the coverage percentage
+fn colour(percentage: f64) -> (&'static str, &'static str) {
+    COLOURS
+        .iter()
+        .find(|(threshold, _, _)| percentage >= *threshold)
+        .map(|(_, name, hex)| (*name, *hex))
+        .unwrap_or(("red", "#e05d44"))
+}
+
+/// Rough width of text in 11px Verdana, badges don't need to be exact
+fn text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+fn svg(message: &str, colour: &str) -> String {
+    let label_width = text_width(LABEL);
+    let message_width = text_width(message);
+    let width = label_width + message_width;
+    let label_x = label_width as f64 / 2.0;
+    let message_x = label_width as f64 + message_width as f64 / 2.0;
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{LABEL}: {message}">
+  <title>{LABEL}: {message}</title>
+  <linearGradient id="s" x2="0" y2="100%">
+    <stop offset="0" stop-color="#bbb" stop-opacity=".1"/>
+    <stop offset="1" stop-opacity=".1"/>
+  </linearGradient>
+  <clipPath id="r">
+    <rect width="{width}" height="20" rx="3" fill="#fff"/>
+  </clipPath>
+  <g clip-path="url(#r)">
+    <rect width="{label_width}" height="20" fill="#555"/>
+    <rect x="{label_width}" width="{message_width}" height="20" fill="{colour}"/>
+    <rect width="{width}" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="{label_x}" y="14">{LABEL}</text>
+    <text x="{message_x}" y="14">{message}</text>
+  </g>
+</svg>
+"##
+    )
+}
+
+/// The json read by shields.io's endpoint badge
+fn endpoint(message: &str, colour: &str) -> serde_json::Value {
+    serde_json::json!({
+        "schemaVersion": 1,
+        "label": LABEL,
+        "message": message,
+        "color": colour,
+    })
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let percentage = 100.0 * coverage_data.coverage_percentage();
+    let percentage = if percentage.is_nan() { 0.0 } else { percentage };
+    let message = format!("{percentage:.2}%");
+    let (name, hex) = colour(percentage);
+    fs::write(
+        report_file(config, "coverage-badge.svg", "-badge.svg"),
+        svg(&message, hex),
+    )?;
+    let json = serde_json::to_string_pretty(&endpoint(&message, name))?;
+    fs::write(
+        report_file(config, "coverage-badge.json", "-badge.json"),
+        json,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_coloured_by_coverage() {
+        assert_eq!(colour(100.0), ("brightgreen", "#4c1"));
+        assert_eq!(colour(85.5), ("green", "#97ca00"));
+        assert_eq!(colour(50.0), ("orange", "#fe7d37"));
+        assert_eq!(colour(12.0), ("red", "#e05d44"));
+
+        let badge = svg("85.50%", "#97ca00");
+        assert!(badge.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="118""#));
+        assert!(badge.contains(r##"<rect x="66" width="52" height="20" fill="#97ca00"/>"##));
+        assert!(badge.contains(r#"<text x="92" y="14">85.50%</text>"#));
+
+        assert_eq!(
+            endpoint("85.50%", "green"),
+            serde_json::json!({
+                "schemaVersion": 1,
+                "label": "coverage",
+                "message": "85.50%",
+                "color": "green",
+            })
+        );
+    }
+}
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 /// The XML structure for a cobatura report is roughly as follows:
 /// ```xml
+/// <!-- tarpaulin-toolchain {"tarpaulin_version":"...",...} -->
 /// <coverage lines-valid="5" lines-covered="0" line-rate="0.0" branches-valid="0"
 /// branches-covered="0" branch-rate="0.0" version="1.9" timestamp="...">
 ///   <sources>
@@ -38,11 +39,11 @@
 ///   </packages>
 /// </coverage>
 /// ```
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::error;
 use std::fmt;
 use std::fs::File;
-use std::io::{Cursor, Write};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -54,11 +55,13 @@ use quick_xml::{
 use chrono::offset::Utc;
 
 use crate::config::Config;
+use crate::report::{log_progress, report_file};
+use crate::toolchain::Toolchain;
 use crate::traces::{CoverageStat, Trace, TraceMap};
 
 pub fn report(traces: &TraceMap, config: &Config) -> Result<(), Error> {
     let result = Report::render(config, traces)?;
-    result.export(config)
+    result.export(config, traces)
 }
 
 #[derive(Debug)]
@@ -67,7 +70,14 @@ pub enum Error {
     ExportError(std::io::Error),
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::ExportError(e) => Some(e),
+            Error::Unknown => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     #[inline]
@@ -118,14 +128,22 @@ impl Report {
         })
     }
 
-    pub fn export(&self, config: &Config) -> Result<(), Error> {
-        let file_path = config.output_dir().join("cobertura.xml");
-        let mut file = File::create(file_path).map_err(|e| Error::ExportError(e))?;
+    /// Writes the report to `cobertura.xml` or `<report-name>.xml`. The classes and lines for each file are rendered
+    /// from the traces as they're written so the whole document is never held in memory.
+    pub fn export(&self, config: &Config, traces: &TraceMap) -> Result<(), Error> {
+        let file_path = report_file(config, "cobertura.xml", ".xml");
+        let file = File::create(file_path).map_err(Error::ExportError)?;
 
-        let mut writer = Writer::new(Cursor::new(vec![]));
+        let mut writer = Writer::new(BufWriter::new(file));
         writer
             .write_event(Event::Decl(BytesDecl::new("1.0", None, None)))
             .map_err(Error::ExportError)?;
+        let toolchain = Toolchain::probe(config)
+            .to_xml_comment()
+            .map_err(|_| Error::Unknown)?;
+        writer
+            .write_event(Event::Comment(BytesText::from_escaped(toolchain)))
+            .map_err(Error::ExportError)?;
 
         let cov_tag = "coverage";
         let mut cov = BytesStart::new(cov_tag);
@@ -154,15 +172,14 @@ impl Report {
         self.export_header(&mut writer)
             .map_err(Error::ExportError)?;
 
-        self.export_packages(&mut writer)
+        self.export_packages(config, traces, &mut writer)
             .map_err(Error::ExportError)?;
 
         writer
             .write_event(Event::End(BytesEnd::new(cov_tag)))
             .map_err(Error::ExportError)?;
 
-        let result = writer.into_inner().into_inner();
-        file.write_all(&result).map_err(|e| Error::ExportError(e))
+        writer.into_inner().flush().map_err(Error::ExportError)
     }
 
     fn export_header<T: Write>(&self, writer: &mut Writer<T>) -> Result<(), std::io::Error> {
@@ -181,9 +198,16 @@ impl Report {
             .map(|_| ())
     }
 
-    fn export_packages<T: Write>(&self, writer: &mut Writer<T>) -> Result<(), std::io::Error> {
+    fn export_packages<T: Write>(
+        &self,
+        config: &Config,
+        traces: &TraceMap,
+        writer: &mut Writer<T>,
+    ) -> Result<(), std::io::Error> {
         let packages_tag = "packages";
         let pack_tag = "package";
+        let total = traces.files().len();
+        let mut written = 0;
 
         writer.write_event(Event::Start(BytesStart::new(packages_tag)))?;
         // Export the package
@@ -195,7 +219,8 @@ impl Report {
             pack.push_attribute(("complexity", package.complexity.to_string().as_ref()));
 
             writer.write_event(Event::Start(pack))?;
-            self.export_classes(&package.classes, writer)?;
+            written += self.export_classes(config, traces, &package.path, writer)?;
+            log_progress("Cobertura", written, total);
             writer.write_event(Event::End(BytesEnd::new(pack_tag)))?;
         }
 
@@ -204,17 +229,22 @@ impl Report {
             .map(|_| ())
     }
 
+    /// Renders and writes the classes for each file in the package one at a time, returning the
+    /// number of files in the package
     fn export_classes<T: Write>(
         &self,
-        classes: &[Class],
+        config: &Config,
+        traces: &TraceMap,
+        pkg: &Path,
         writer: &mut Writer<T>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<usize, std::io::Error> {
         let classes_tag = "classes";
         let class_tag = "class";
         let methods_tag = "methods";
 
+        let files = package_files(traces, pkg);
         writer.write_event(Event::Start(BytesStart::new(classes_tag)))?;
-        for class in classes {
+        for class in files.iter().filter_map(|x| render_class(config, traces, x)) {
             let mut c = BytesStart::new(class_tag);
             c.push_attribute(("name", class.name.as_ref()));
             c.push_attribute(("filename", class.file_name.as_ref()));
@@ -227,9 +257,8 @@ impl Report {
             self.export_lines(&class.lines, writer)?;
             writer.write_event(Event::End(BytesEnd::new(class_tag)))?;
         }
-        writer
-            .write_event(Event::End(BytesEnd::new(classes_tag)))
-            .map(|_| ())
+        writer.write_event(Event::End(BytesEnd::new(classes_tag)))?;
+        Ok(files.len())
     }
 
     fn export_lines<T: Write>(
@@ -268,14 +297,14 @@ fn render_sources(config: &Config) -> Vec<PathBuf> {
 #[derive(Debug)]
 struct Package {
     name: String,
+    path: PathBuf,
     line_rate: f64,
     branch_rate: f64,
     complexity: f64,
-    classes: Vec<Class>,
 }
 
 fn render_packages(config: &Config, traces: &TraceMap) -> Vec<Package> {
-    let dirs: HashSet<&Path> = traces
+    let dirs: BTreeSet<&Path> = traces
         .files()
         .into_iter()
         .filter_map(|x| x.parent())
@@ -299,10 +328,10 @@ fn render_package(config: &Config, traces: &TraceMap, pkg: &Path) -> Package {
 
     Package {
         name,
+        path: pkg.to_path_buf(),
         line_rate,
         branch_rate: 0.0,
         complexity: 0.0,
-        classes: render_classes(config, traces, pkg),
     }
 }
 
@@ -317,12 +346,11 @@ struct Class {
     methods: Vec<Method>,
 }
 
-fn render_classes(config: &Config, traces: &TraceMap, pkg: &Path) -> Vec<Class> {
+fn package_files<'a>(traces: &'a TraceMap, pkg: &Path) -> Vec<&'a PathBuf> {
     traces
         .files()
-        .iter()
+        .into_iter()
         .filter(|x| x.parent() == Some(pkg))
-        .filter_map(|x| render_class(config, traces, x))
         .collect()
 }
 
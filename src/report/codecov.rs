@@ -0,0 +1,404 @@
+//! Uploading coverage to Codecov with `--codecov`, so CI doesn't need Codecov's uploader
+//! installed. The coverage is sent in Codecov's json format where each line maps to its hits, or
+//! to `covered/total` for lines with branches. The commit, branch and pull request are detected
+//! from the environment of the common CI services.
+use crate::config::Config;
+use crate::errors::RunError;
+#[cfg(feature = "codecov")]
+use crate::http;
+use crate::path_utils::portable_path;
+#[cfg(feature = "codecov")]
+use crate::report::upload::with_retries;
+use crate::traces::{amount_covered, CoverageStat, TraceMap};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+#[cfg(feature = "codecov")]
+use std::env;
+use std::path::Path;
+use std::process::Command;
+#[cfg(feature = "codecov")]
+use tracing::info;
+#[cfg(not(feature = "codecov"))]
+use tracing::warn;
+
+#[cfg(feature = "codecov")]
+const DEFAULT_URL: &str = "https://codecov.io";
+
+/// What the CI service says about the build being uploaded, sent with the upload so Codecov can
+/// attach the coverage to the right commit and pull request
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(not(feature = "codecov"), allow(dead_code))]
+pub(crate) struct CiInfo {
+    pub service: Option<&'static str>,
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+    pub pr: Option<String>,
+    pub build: Option<String>,
+    pub build_url: Option<String>,
+    pub job: Option<String>,
+    pub slug: Option<String>,
+}
+
+#[cfg_attr(not(feature = "codecov"), allow(dead_code))]
+impl CiInfo {
+    /// Reads the build from the environment of the CI service it's running on, `var` looks up an
+    /// environment variable
+    pub fn detect(var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| var(name).filter(|x| !x.is_empty());
+        if var("GITHUB_ACTIONS").is_some() {
+            let slug = var("GITHUB_REPOSITORY");
+            let build = var("GITHUB_RUN_ID");
+            let build_url = match (var("GITHUB_SERVER_URL"), &slug, &build) {
+                (Some(server), Some(slug), Some(build)) => {
+                    Some(format!("{server}/{slug}/actions/runs/{build}"))
+                }
+                _ => None,
+            };
+            let pr = var("GITHUB_REF")
+                .and_then(|x| Some(x.strip_prefix("refs/pull/")?.split('/').next()?.to_string()));
+            Self {
+                service: Some("github-actions"),
+                commit: var("GITHUB_SHA"),
+                branch: var("GITHUB_HEAD_REF").or_else(|| var("GITHUB_REF_NAME")),
+                pr,
+                build,
+                build_url,
+                job: var("GITHUB_JOB"),
+                slug,
+            }
+        } else if var("GITLAB_CI").is_some() {
+            Self {
+                service: Some("gitlab"),
+                commit: var("CI_MERGE_REQUEST_SOURCE_BRANCH_SHA").or_else(|| var("CI_COMMIT_SHA")),
+                branch: var("CI_MERGE_REQUEST_SOURCE_BRANCH_NAME")
+                    .or_else(|| var("CI_COMMIT_REF_NAME")),
+                pr: var("CI_MERGE_REQUEST_IID"),
+                build: var("CI_JOB_ID"),
+                build_url: var("CI_JOB_URL"),
+                job: var("CI_JOB_NAME"),
+                slug: var("CI_PROJECT_PATH"),
+            }
+        } else if var("CIRCLECI").is_some() {
+            let slug = match (
+                var("CIRCLE_PROJECT_USERNAME"),
+                var("CIRCLE_PROJECT_REPONAME"),
+            ) {
+                (Some(user), Some(repo)) => Some(format!("{user}/{repo}")),
+                _ => None,
+            };
+            Self {
+                service: Some("circleci"),
+                commit: var("CIRCLE_SHA1"),
+                branch: var("CIRCLE_BRANCH"),
+                pr: var("CIRCLE_PR_NUMBER"),
+                build: var("CIRCLE_BUILD_NUM"),
+                build_url: var("CIRCLE_BUILD_URL"),
+                job: var("CIRCLE_JOB"),
+                slug,
+            }
+        } else if var("TRAVIS").is_some() {
+            Self {
+                service: Some("travis"),
+                commit: var("TRAVIS_PULL_REQUEST_SHA").or_else(|| var("TRAVIS_COMMIT")),
+                branch: var("TRAVIS_PULL_REQUEST_BRANCH").or_else(|| var("TRAVIS_BRANCH")),
+                pr: var("TRAVIS_PULL_REQUEST").filter(|x| x != "false"),
+                build: var("TRAVIS_JOB_NUMBER"),
+                build_url: var("TRAVIS_JOB_WEB_URL"),
+                job: var("TRAVIS_JOB_ID"),
+                slug: var("TRAVIS_REPO_SLUG"),
+            }
+        } else if var("TF_BUILD").is_some() {
+            Self {
+                service: Some("azure_pipelines"),
+                commit: var("BUILD_SOURCEVERSION"),
+                branch: var("SYSTEM_PULLREQUEST_SOURCEBRANCH")
+                    .or_else(|| var("BUILD_SOURCEBRANCHNAME")),
+                pr: var("SYSTEM_PULLREQUEST_PULLREQUESTNUMBER")
+                    .or_else(|| var("SYSTEM_PULLREQUEST_PULLREQUESTID")),
+                build: var("BUILD_BUILDNUMBER"),
+                build_url: None,
+                job: var("BUILD_BUILDID"),
+                slug: var("BUILD_REPOSITORY_NAME"),
+            }
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Fills in the commit and branch from git when the CI service didn't give them
+    fn fill_from_git(&mut self, root: &Path) {
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(root)
+                .output()
+                .ok()?;
+            let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+            (output.status.success() && !value.is_empty() && value != "HEAD").then_some(value)
+        };
+        if self.commit.is_none() {
+            self.commit = git(&["rev-parse", "HEAD"]);
+        }
+        if self.branch.is_none() {
+            self.branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]);
+        }
+    }
+
+    /// Query parameters describing the build for the upload
+    fn query(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("service", self.service),
+            ("commit", self.commit.as_deref()),
+            ("branch", self.branch.as_deref()),
+            ("pr", self.pr.as_deref()),
+            ("build", self.build.as_deref()),
+            ("build_url", self.build_url.as_deref()),
+            ("job", self.job.as_deref()),
+            ("slug", self.slug.as_deref()),
+        ]
+        .iter()
+        .filter_map(|(key, value)| Some((*key, (*value)?)))
+        .collect()
+    }
+}
+
+/// Coverage in Codecov's json format, paths are relative to the root
+#[cfg_attr(not(feature = "codecov"), allow(dead_code))]
+pub(crate) fn codecov_json(traces: &TraceMap, root: &Path) -> Value {
+    let mut branches = BTreeMap::<(&Path, u64), (usize, usize)>::new();
+    for (path, file_branches) in traces.branches() {
+        for branch in file_branches {
+            let (covered, total) = branches.entry((path.as_path(), branch.line)).or_default();
+            *covered += usize::from(branch.stats.been_true) + usize::from(branch.stats.been_false);
+            *total += 2;
+        }
+    }
+    let mut files = Map::new();
+    for (path, file_traces) in traces.iter() {
+        let mut hits = BTreeMap::<u64, u64>::new();
+        for trace in file_traces {
+            *hits.entry(trace.line).or_default() += match trace.stats {
+                CoverageStat::Line(hits) => hits,
+                _ => amount_covered(std::iter::once(trace)) as u64,
+            };
+        }
+        let lines = hits
+            .into_iter()
+            .map(|(line, hits)| {
+                let value = match branches.get(&(path.as_path(), line)) {
+                    Some((covered, total)) => Value::from(format!("{covered}/{total}")),
+                    None => Value::from(hits),
+                };
+                (line.to_string(), value)
+            })
+            .collect::<Map<_, _>>();
+        files.insert(
+            portable_path(path, root).to_string_lossy().to_string(),
+            Value::Object(lines),
+        );
+    }
+    serde_json::json!({ "coverage": files })
+}
+
+#[cfg(feature = "codecov")]
+fn codecov_error(e: impl std::fmt::Display) -> RunError {
+    RunError::CovReport(format!("Codecov upload failed. {e}"))
+}
+
+/// Request asking Codecov where to upload the report. The token is sent in a header so it isn't
+/// in the url errors include.
+#[cfg(feature = "codecov")]
+fn upload_request(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    query: &[(&str, &str)],
+    token: Option<&str>,
+) -> reqwest::blocking::RequestBuilder {
+    let request = client
+        .post(format!("{}/upload/v4", url.trim_end_matches('/')))
+        .query(query)
+        .header(reqwest::header::ACCEPT, "text/plain")
+        .header("X-Reduced-Redundancy", "false")
+        .header("X-Content-Type", "text/plain");
+    match token {
+        Some(token) => request.header(reqwest::header::AUTHORIZATION, format!("token {token}")),
+        None => request,
+    }
+}
+
+/// Uploads the coverage to Codecov, first asking Codecov where to put the report and then putting
+/// it there
+#[cfg(feature = "codecov")]
+pub(crate) fn upload(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    if !config.codecov {
+        return Ok(());
+    }
+    let token_var = config
+        .codecov_token_env
+        .as_deref()
+        .unwrap_or("CODECOV_TOKEN");
+    // Public repositories on some CI services can upload without a token
+    let token = env::var(token_var).ok().filter(|x| !x.is_empty());
+    let mut ci = CiInfo::detect(|name| env::var(name).ok());
+    ci.fill_from_git(&config.root());
+    if ci.commit.is_none() {
+        return Err(RunError::CovReport(
+            "Codecov upload needs the commit, which couldn't be found from the CI or git"
+                .to_string(),
+        ));
+    }
+    let client = http::client(config)?;
+    let url = env::var("CODECOV_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+    let mut query = ci.query();
+    query.push((
+        "package",
+        concat!("cargo-tarpaulin-", env!("CARGO_PKG_VERSION")),
+    ));
+    let report = format!(
+        "# path=codecov.json\n{}\n<<<<<< EOF\n",
+        codecov_json(result, &config.root())
+    );
+
+    // Creating the upload isn't idempotent so it's only sent once
+    let locations = upload_request(&client, &url, &query, token.as_deref())
+        .send()
+        .and_then(|x| x.error_for_status())
+        .and_then(|x| x.text())
+        .map_err(codecov_error)?;
+    let mut locations = locations.lines();
+    let (report_url, storage_url) = match (locations.next(), locations.next()) {
+        (Some(report_url), Some(storage_url)) => (report_url, storage_url),
+        _ => {
+            return Err(codecov_error(
+                "Codecov didn't say where to upload the report",
+            ))
+        }
+    };
+    // The storage url is signed so it's left out of the errors
+    with_retries("Codecov", || {
+        client
+            .put(storage_url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+            .body(report.clone())
+            .send()
+            .and_then(|x| x.error_for_status())
+            .map(|_| ())
+            .map_err(reqwest::Error::without_url)
+    })
+    .map_err(codecov_error)?;
+    info!("Coverage sent to Codecov: {}", report_url);
+    Ok(())
+}
+
+#[cfg(not(feature = "codecov"))]
+pub(crate) fn upload(config: &Config, _result: &TraceMap) -> Result<(), RunError> {
+    if config.codecov {
+        warn!("Tarpaulin was built without the codecov feature, not uploading to Codecov");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{BranchTrace, LogicState, Trace};
+    use std::collections::HashMap;
+
+    #[test]
+    fn lines_and_branches_serialized() {
+        let root = Path::new("/project");
+        let file = root.join("src").join("lib.rs");
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 3), (2, 0), (3, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(&file, trace);
+        }
+        traces.add_branch(
+            &file,
+            BranchTrace {
+                line: 3,
+                column: 4,
+                stats: LogicState {
+                    been_true: true,
+                    been_false: false,
+                },
+            },
+        );
+        let expected = serde_json::json!({
+            "coverage": {
+                "src/lib.rs": { "1": 3, "2": 0, "3": "1/2" }
+            }
+        });
+        assert_eq!(codecov_json(&traces, root), expected);
+    }
+
+    #[test]
+    fn ci_detected_from_environment() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>();
+            CiInfo::detect(move |name| vars.get(name).cloned())
+        };
+        let github = env(&[
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_SHA", "abc123"),
+            ("GITHUB_REF", "refs/pull/42/merge"),
+            ("GITHUB_HEAD_REF", "feature"),
+            ("GITHUB_REPOSITORY", "owner/repo"),
+            ("GITHUB_RUN_ID", "7"),
+            ("GITHUB_SERVER_URL", "https://github.com"),
+        ]);
+        assert_eq!(github.service, Some("github-actions"));
+        assert_eq!(github.commit.as_deref(), Some("abc123"));
+        assert_eq!(github.pr.as_deref(), Some("42"));
+        assert_eq!(github.branch.as_deref(), Some("feature"));
+        assert_eq!(
+            github.build_url.as_deref(),
+            Some("https://github.com/owner/repo/actions/runs/7")
+        );
+
+        let travis = env(&[
+            ("TRAVIS", "true"),
+            ("TRAVIS_COMMIT", "def456"),
+            ("TRAVIS_BRANCH", "main"),
+            ("TRAVIS_PULL_REQUEST", "false"),
+        ]);
+        assert_eq!(travis.service, Some("travis"));
+        assert_eq!(travis.pr, None);
+        assert_eq!(
+            travis.query(),
+            [
+                ("service", "travis"),
+                ("commit", "def456"),
+                ("branch", "main")
+            ]
+        );
+
+        assert_eq!(env(&[]), CiInfo::default());
+    }
+
+    #[test]
+    #[cfg(feature = "codecov")]
+    fn token_sent_in_header() {
+        let client = reqwest::blocking::Client::new();
+        let request = upload_request(
+            &client,
+            "https://codecov.example/",
+            &[("commit", "abc")],
+            Some("secret"),
+        )
+        .build()
+        .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://codecov.example/upload/v4?commit=abc"
+        );
+        assert_eq!(
+            request.headers()[reqwest::header::AUTHORIZATION],
+            "token secret"
+        );
+    }
+}
@@ -1,12 +1,20 @@
 use crate::config::Config;
 use crate::errors::RunError;
+use crate::http;
+use crate::report::upload::with_retries;
 use crate::traces::{CoverageStat, TraceMap};
 use coveralls_api::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::blocking::multipart::{Form, Part};
+use reqwest::blocking::Client;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{info, trace, warn};
 
+const COVERALLS_URL: &str = "https://coveralls.io/api/v1/jobs";
+
 fn get_git_info(manifest_path: &Path) -> Result<GitInfo, String> {
     let dir_path = manifest_path
         .parent()
@@ -53,6 +61,20 @@ fn get_git_info(manifest_path: &Path) -> Result<GitInfo, String> {
     })
 }
 
+/// Sends the gzipped report as `coveralls-api` does, but with the client from the config so the
+/// proxy and CA certificates are used
+fn send_report(client: &Client, url: &str, report: &[u8]) -> Result<(), reqwest::Error> {
+    let part = Part::bytes(report.to_vec())
+        .mime_str("gzip/json")?
+        .file_name("report");
+    client
+        .post(url)
+        .multipart(Form::new().part("json_file", part))
+        .send()?
+        .error_for_status()
+        .map(|_| ())
+}
+
 fn get_identity(ci_tool: &Option<CiService>, key: &str) -> Identity {
     match ci_tool {
         Some(ref service) => {
@@ -113,12 +135,18 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
             Err(err) => warn!("Failed to collect git info: {}", err),
         }
 
+        let client = http::client(config)?;
+        let mut body = GzEncoder::new(vec![], Compression::default());
+        serde_json::to_writer(&mut body, &report)?;
+        let body = body.finish()?;
         let res = if let Some(uri) = &config.report_uri {
             info!("Sending report to endpoint: {}", uri);
-            report.send_to_endpoint(uri)
+            with_retries(uri, || send_report(&client, uri, &body))
         } else {
             info!("Sending coverage data to coveralls.io");
-            report.send_to_coveralls()
+            with_retries("coveralls.io", || {
+                send_report(&client, COVERALLS_URL, &body)
+            })
         };
         if config.debug {
             if let Ok(text) = serde_json::to_string(&report) {
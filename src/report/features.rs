@@ -0,0 +1,186 @@
+//! Coverage grouped by the crate features gating the code. Features which are enabled but have
+//! none of their code exercised usually mean the tests don't cover that configuration at all.
+use crate::config::Config;
+use crate::traces::{FeatureCoverage, TraceMap};
+use cargo_metadata::{CargoOpt, MetadataCommand};
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::debug;
+
+/// Features enabled for a package with the given feature table, following features enabled by
+/// other features. Features of dependencies and optional dependencies are left out. This is only
+/// used when cargo can't resolve the features.
+fn resolve_features(
+    package: &str,
+    table: &BTreeMap<String, Vec<String>>,
+    requested: &[&str],
+    all_features: bool,
+    no_default_features: bool,
+) -> BTreeSet<String> {
+    let mut pending = if all_features {
+        table.keys().map(String::as_str).collect::<Vec<_>>()
+    } else {
+        requested
+            .iter()
+            .filter_map(|x| match x.split_once('/') {
+                Some((pkg, feature)) if pkg == package => Some(feature),
+                Some(_) => None,
+                None => Some(*x),
+            })
+            .collect()
+    };
+    if !no_default_features {
+        pending.push("default");
+    }
+    let mut enabled = BTreeSet::new();
+    while let Some(feature) = pending.pop() {
+        if let Some(implied) = table.get(feature) {
+            if enabled.insert(feature.to_string()) {
+                pending.extend(
+                    implied
+                        .iter()
+                        .filter(|x| !x.starts_with("dep:") && !x.contains('/'))
+                        .map(String::as_str),
+                );
+            }
+        }
+    }
+    enabled.remove("default");
+    enabled
+}
+
+/// Features cargo resolves for the packages of the workspace with the features requested in the
+/// config, this includes features enabled by other packages in the workspace depending on them
+fn resolved_features(config: &Config, requested: &[&str]) -> Option<BTreeSet<String>> {
+    let mut cmd = MetadataCommand::new();
+    cmd.manifest_path(config.manifest())
+        .other_options(config.cargo_network_flags())
+        .features(CargoOpt::SomeFeatures(
+            requested.iter().map(|x| x.to_string()).collect(),
+        ));
+    if config.all_features {
+        cmd.features(CargoOpt::AllFeatures);
+    }
+    if config.no_default_features {
+        cmd.features(CargoOpt::NoDefaultFeatures);
+    }
+    let meta = match cmd.exec() {
+        Ok(meta) => meta,
+        Err(e) => {
+            debug!("Unable to resolve the enabled features: {}", e);
+            return None;
+        }
+    };
+    let members = meta.workspace_members;
+    let features = meta
+        .resolve?
+        .nodes
+        .into_iter()
+        .filter(|x| members.contains(&x.id))
+        .flat_map(|x| x.features)
+        .filter(|x| x != "default")
+        .collect();
+    Some(features)
+}
+
+/// Features enabled in any package of the workspace
+fn enabled_features(config: &Config) -> BTreeSet<String> {
+    let requested = config.features.as_deref().unwrap_or_default();
+    let requested = requested
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    if let Some(features) = resolved_features(config, &requested) {
+        return features;
+    }
+    config
+        .get_metadata()
+        .as_ref()
+        .map(|meta| {
+            meta.workspace_packages()
+                .into_iter()
+                .flat_map(|p| {
+                    resolve_features(
+                        &p.name,
+                        &p.features,
+                        &requested,
+                        config.all_features,
+                        config.no_default_features,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Coverage of each feature with gated code that was compiled, and the enabled features where
+/// none of it was exercised
+pub(crate) fn feature_summary(
+    config: &Config,
+    result: &TraceMap,
+) -> (Vec<FeatureCoverage>, Vec<String>) {
+    let features = result
+        .feature_coverage()
+        .into_iter()
+        .filter(|x| x.coverable > 0)
+        .collect::<Vec<_>>();
+    if features.is_empty() {
+        return (features, vec![]);
+    }
+    let enabled = enabled_features(config);
+    let unexercised = features
+        .iter()
+        .filter(|x| x.covered == 0 && enabled.contains(&x.feature))
+        .map(|x| x.feature.clone())
+        .collect();
+    (features, unexercised)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn table() -> BTreeMap<String, Vec<String>> {
+        [
+            ("default", vec!["std"]),
+            ("std", vec!["alloc", "serde?/std"]),
+            ("alloc", vec![]),
+            ("serde", vec!["dep:serde"]),
+            ("cli", vec![]),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.iter().map(|f| f.to_string()).collect()))
+        .collect()
+    }
+
+    #[test]
+    fn implied_features_enabled() {
+        let features = |requested: &[&str], all, no_default| {
+            resolve_features("foo", &table(), requested, all, no_default)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(features(&[], false, false), vec!["alloc", "std"]);
+        assert_eq!(features(&["foo/cli"], false, true), vec!["cli"]);
+        assert_eq!(features(&["bar/cli", "serde"], false, true), vec!["serde"]);
+        assert_eq!(
+            features(&[], true, true),
+            vec!["alloc", "cli", "serde", "std"]
+        );
+    }
+    #[test]
+    fn features_resolved_by_cargo() {
+        let mut config = Config::default();
+        config.set_manifest(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+        config.offline = true;
+        let features = resolved_features(&config, &["github"]).unwrap();
+        assert!(features.contains("coveralls"));
+        assert!(features.contains("github"));
+        assert!(!features.contains("codecov"));
+        assert!(!features.contains("default"));
+
+        config.no_default_features = true;
+        let features = resolved_features(&config, &[]).unwrap();
+        assert!(!features.contains("coveralls"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Annotated copies of the sources in the format of `gcov`, each line prefixed with the number of
+//! times it was hit. Lines which aren't coverable are marked with `-` and uncovered lines with
+//! `#####`:
+//! ```text
+//!         -:    0:Source:src/lib.rs
+//!         -:    1:pub fn add(a: u32, b: u32) -> u32 {
+//!         5:    2:    a + b
+//!         -:    3:}
+//!     #####:    4:fn unused() {}
+//! ```
+//! One file is written for each source into the `gcov` directory in the output directory, named
+//! like `gcov --preserve-paths` names them with the separators in the path replaced by `#`.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::{fit_file_name, portable_path};
+use crate::report::report_file;
+use crate::traces::{amount_covered, CoverageStat, Trace, TraceMap};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Hits of each coverable line. Lines with several traces use the most hit one, and branch or
+/// condition traces count as a single hit when any part of them was covered.
+fn line_hits(traces: &[Trace]) -> BTreeMap<u64, u64> {
+    let mut lines = BTreeMap::new();
+    for trace in traces {
+        let hits = match trace.stats {
+            CoverageStat::Line(hits) => hits,
+            _ => (amount_covered(std::iter::once(trace)) > 0).into(),
+        };
+        let line = lines.entry(trace.line).or_insert(0);
+        *line = hits.max(*line);
+    }
+    lines
+}
+
+/// Name of the annotated file for the source, the relative path with the separators replaced by
+/// `#`. The `:` of a windows drive is replaced too as it can't be in a file name.
+fn gcov_name(source: &Path) -> String {
+    let path = source.to_string_lossy();
+    let name = path
+        .trim_start_matches(['/', '\\'])
+        .replace(['/', '\\', ':'], "#");
+    fit_file_name(&format!("{name}.gcov"))
+}
+
+fn write_gcov(
+    mut file: impl Write,
+    source: &Path,
+    contents: &str,
+    lines: &BTreeMap<u64, u64>,
+    runs: usize,
+) -> Result<(), RunError> {
+    writeln!(file, "{:>9}:{:>5}:Source:{}", "-", 0, source.display())?;
+    if runs > 0 {
+        writeln!(file, "{:>9}:{:>5}:Runs:{}", "-", 0, runs)?;
+    }
+    for (number, text) in (1..).zip(contents.lines()) {
+        let count = match lines.get(&number) {
+            Some(0) => "#####".to_string(),
+            Some(hits) => hits.to_string(),
+            None => "-".to_string(),
+        };
+        writeln!(file, "{count:>9}:{number:>5}:{text}")?;
+    }
+    Ok(())
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let dir = report_file(config, "gcov", "-gcov");
+    fs::create_dir_all(&dir)?;
+    let root = config.root();
+    for (path, traces) in coverage_data.iter() {
+        if traces.is_empty() {
+            continue;
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Couldn't read {} to annotate: {}", path.display(), e);
+                continue;
+            }
+        };
+        let source = portable_path(path, &root);
+        let mut file = BufWriter::new(File::create(dir.join(gcov_name(&source)))?);
+        write_gcov(
+            &mut file,
+            &source,
+            &contents,
+            &line_hits(traces),
+            coverage_data.runs().len(),
+        )?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::LogicState;
+
+    #[test]
+    fn sources_annotated_with_hits() {
+        let mut traces = vec![];
+        for (line, hits) in [(2, 5), (2, 1), (4, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.push(trace);
+        }
+        let mut branch = Trace::new_stub(5);
+        branch.stats = CoverageStat::Branch(LogicState {
+            been_true: true,
+            been_false: false,
+        });
+        traces.push(branch);
+        let contents =
+            "pub fn add(a: u32, b: u32) -> u32 {\n    a + b\n}\nfn unused() {}\nlet x = y;\n";
+
+        let mut data = vec![];
+        let source = Path::new("src/lib.rs");
+        write_gcov(&mut data, source, contents, &line_hits(&traces), 2).unwrap();
+        let expected = "        -:    0:Source:src/lib.rs
+        -:    0:Runs:2
+        -:    1:pub fn add(a: u32, b: u32) -> u32 {
+        5:    2:    a + b
+        -:    3:}
+    #####:    4:fn unused() {}
+        1:    5:let x = y;
+";
+        assert_eq!(String::from_utf8(data).unwrap(), expected);
+        assert_eq!(gcov_name(source), "src#lib.rs.gcov");
+        assert_eq!(
+            gcov_name(Path::new("/usr/src/lib.rs")),
+            "usr#src#lib.rs.gcov"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Commenting the coverage of a pull request's changes on it with `--github-pr`. The diff is
+//! fetched from the GitHub API so nothing but the token needs setting up in CI, and the comment
+//! is found again by a marker so later runs update it instead of adding another.
+use crate::config::Config;
+use crate::errors::RunError;
+#[cfg(feature = "github")]
+use crate::http;
+#[cfg(feature = "github")]
+use crate::patch::coverage_for_patch;
+use crate::patch::PatchCoverage;
+use crate::report::markdown::write_uncovered_lines;
+#[cfg(feature = "github")]
+use crate::report::upload::with_retries;
+use crate::traces::TraceMap;
+#[cfg(feature = "github")]
+use serde::Deserialize;
+#[cfg(feature = "github")]
+use std::env;
+use std::fmt::Write;
+#[cfg(feature = "github")]
+use tracing::info;
+#[cfg(not(feature = "github"))]
+use tracing::warn;
+
+/// Start of the comment tarpaulin posts, used to find it to update on later runs
+#[cfg_attr(not(feature = "github"), allow(dead_code))]
+const MARKER: &str = "<!-- cargo-tarpaulin -->";
+
+#[cfg(feature = "github")]
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+#[cfg(feature = "github")]
+const PAGE_SIZE: usize = 100;
+
+#[cfg(feature = "github")]
+const JSON: &str = "application/vnd.github+json";
+
+/// Author of comments posted with the `GITHUB_TOKEN` of GitHub Actions
+#[cfg(feature = "github")]
+const ACTIONS_BOT: &str = "github-actions[bot]";
+
+/// Markdown comment describing the coverage of the lines the pull request changes
+#[cfg_attr(not(feature = "github"), allow(dead_code))]
+pub(crate) fn comment_body(traces: &TraceMap, patch: &PatchCoverage) -> String {
+    // Writing to a string can't fail
+    let mut body = format!("{MARKER}\n### Coverage report\n\n");
+    if patch.coverable == 0 {
+        body.push_str("This pull request doesn't change any coverable lines");
+    } else {
+        let _ = write!(
+            body,
+            "**{:.2}%** of the {} coverable lines changed by this pull request are covered",
+            100.0 * patch.coverage_percentage(),
+            patch.coverable
+        );
+    }
+    let _ = writeln!(
+        body,
+        ", overall coverage is **{:.2}%** ({}/{} lines).",
+        100.0 * traces.coverage_percentage(),
+        traces.total_covered(),
+        traces.total_coverable()
+    );
+    let files = patch
+        .files
+        .iter()
+        .map(|file| {
+            let mut uncovered = file
+                .hunks
+                .iter()
+                .flat_map(|x| x.uncovered.iter().copied())
+                .collect::<Vec<_>>();
+            uncovered.sort_unstable();
+            let covered = file.hunks.iter().map(|x| x.covered.len()).sum::<usize>();
+            (file, covered, uncovered)
+        })
+        .filter(|(_, covered, uncovered)| covered + uncovered.len() > 0)
+        .collect::<Vec<_>>();
+    if !files.is_empty() {
+        body.push_str("\n| File | Covered | Coverable |\n");
+        body.push_str("|---|---:|---:|\n");
+        for (file, covered, uncovered) in &files {
+            let _ = writeln!(
+                body,
+                "| `{}` | {} | {} |",
+                file.path.display(),
+                covered,
+                covered + uncovered.len()
+            );
+        }
+        for (file, _, uncovered) in files.iter().filter(|(_, _, x)| !x.is_empty()) {
+            write_uncovered_lines(&mut body, &file.path, uncovered);
+        }
+    }
+    body
+}
+
+#[cfg(feature = "github")]
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+#[cfg(feature = "github")]
+#[derive(Deserialize)]
+struct Comment {
+    id: u64,
+    body: Option<String>,
+    /// `None` if the author's account was deleted
+    user: Option<User>,
+}
+
+/// The comment a previous run posted as the user, other users' comments are left alone even if
+/// they quote the marker
+#[cfg(feature = "github")]
+fn own_comment(comments: &[Comment], login: &str) -> Option<u64> {
+    comments
+        .iter()
+        .find(|x| {
+            x.user.as_ref().is_some_and(|user| user.login == login)
+                && x.body.as_deref().is_some_and(|x| x.starts_with(MARKER))
+        })
+        .map(|x| x.id)
+}
+
+#[cfg(feature = "github")]
+struct PullRequest<'a> {
+    client: reqwest::blocking::Client,
+    api: String,
+    repo: String,
+    number: u64,
+    token: &'a str,
+}
+
+#[cfg(feature = "github")]
+impl PullRequest<'_> {
+    fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        accept: &str,
+    ) -> reqwest::blocking::RequestBuilder {
+        self.client
+            .request(method, format!("{}/repos/{}/{}", self.api, self.repo, path))
+            .bearer_auth(self.token)
+            .header(reqwest::header::ACCEPT, accept)
+    }
+
+    fn diff(&self) -> Result<String, reqwest::Error> {
+        self.request(
+            reqwest::Method::GET,
+            &format!("pulls/{}", self.number),
+            "application/vnd.github.v3.diff",
+        )
+        .send()?
+        .error_for_status()?
+        .text()
+    }
+
+    /// Login of the user the token authenticates as. Installation tokens like the `GITHUB_TOKEN`
+    /// of Actions can't read the user, their comments are posted by the Actions bot.
+    fn login(&self) -> Result<String, reqwest::Error> {
+        let response = self
+            .client
+            .get(format!("{}/user", self.api))
+            .bearer_auth(self.token)
+            .header(reqwest::header::ACCEPT, JSON)
+            .send()?;
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            return Ok(ACTIONS_BOT.to_string());
+        }
+        Ok(response.error_for_status()?.json::<User>()?.login)
+    }
+
+    /// The comment posted by a previous run as the user, if there is one
+    fn find_comment(&self, login: &str) -> Result<Option<u64>, reqwest::Error> {
+        for page in 1.. {
+            let comments: Vec<Comment> = self
+                .request(
+                    reqwest::Method::GET,
+                    &format!(
+                        "issues/{}/comments?per_page={PAGE_SIZE}&page={page}",
+                        self.number
+                    ),
+                    JSON,
+                )
+                .send()?
+                .error_for_status()?
+                .json()?;
+            if let Some(id) = own_comment(&comments, login) {
+                return Ok(Some(id));
+            }
+            if comments.len() < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    fn post_comment(&self, existing: Option<u64>, body: &str) -> Result<(), reqwest::Error> {
+        let request = match existing {
+            Some(id) => self.request(
+                reqwest::Method::PATCH,
+                &format!("issues/comments/{id}"),
+                JSON,
+            ),
+            None => self.request(
+                reqwest::Method::POST,
+                &format!("issues/{}/comments", self.number),
+                JSON,
+            ),
+        };
+        request
+            .json(&serde_json::json!({ "body": body }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "github")]
+fn github_error(e: reqwest::Error) -> RunError {
+    RunError::CovReport(format!("GitHub request failed. {e}"))
+}
+
+/// Posts the coverage of the pull request's changes as a comment, or updates the comment left by
+/// an earlier run
+#[cfg(feature = "github")]
+pub(crate) fn comment_on_pr(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    let number = match config.github_pr {
+        Some(number) => number,
+        None => return Ok(()),
+    };
+    let token_var = config.github_token_env.as_deref().unwrap_or("GITHUB_TOKEN");
+    let token = env::var(token_var).map_err(|_| {
+        RunError::CovReport(format!("--github-pr needs a GitHub token in ${token_var}"))
+    })?;
+    let repo = env::var("GITHUB_REPOSITORY").map_err(|_| {
+        RunError::CovReport("--github-pr needs the owner/repo in $GITHUB_REPOSITORY".to_string())
+    })?;
+    let client = http::client(config)?;
+    let pr = PullRequest {
+        client,
+        api: env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string()),
+        repo,
+        number,
+        token: &token,
+    };
+    let diff = with_retries("GitHub", || pr.diff()).map_err(github_error)?;
+    let body = comment_body(result, &coverage_for_patch(result, &diff));
+    let login = with_retries("GitHub", || pr.login()).map_err(github_error)?;
+    let existing = with_retries("GitHub", || pr.find_comment(&login)).map_err(github_error)?;
+    with_retries("GitHub", || pr.post_comment(existing, &body)).map_err(github_error)?;
+    if existing.is_some() {
+        info!("Updated the coverage comment on pull request #{}", number);
+    } else {
+        info!("Commented the coverage on pull request #{}", number);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "github"))]
+pub(crate) fn comment_on_pr(config: &Config, _result: &TraceMap) -> Result<(), RunError> {
+    if config.github_pr.is_some() {
+        warn!("Tarpaulin was built without the github feature, not commenting on the pull request");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::{FilePatchCoverage, HunkCoverage};
+    use crate::traces::{CoverageStat, Trace};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn comment_lists_changed_files() {
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 1), (2, 0), (3, 1), (4, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(Path::new("/project/src/lib.rs"), trace);
+        }
+        let patch = PatchCoverage {
+            files: vec![
+                FilePatchCoverage {
+                    path: PathBuf::from("src/lib.rs"),
+                    source: Some(PathBuf::from("/project/src/lib.rs")),
+                    hunks: vec![HunkCoverage {
+                        start: 1,
+                        len: 3,
+                        covered: vec![1],
+                        uncovered: vec![2],
+                    }],
+                },
+                FilePatchCoverage {
+                    path: PathBuf::from("README.md"),
+                    source: None,
+                    hunks: vec![],
+                },
+            ],
+            coverable: 2,
+            covered: 1,
+        };
+        let body = comment_body(&traces, &patch);
+        assert!(body.starts_with(MARKER));
+        assert!(body.contains("**50.00%** of the 2 coverable lines"));
+        assert!(body.contains("overall coverage is **75.00%** (3/4 lines)"));
+        assert!(body.contains("| `src/lib.rs` | 1 | 2 |"));
+        assert!(
+            body.contains("<summary><code>src/lib.rs</code>: 1 uncovered lines</summary>\n\n2\n")
+        );
+        assert!(!body.contains("README.md"));
+
+        let body = comment_body(&traces, &PatchCoverage::default());
+        assert!(body.contains("doesn't change any coverable lines, overall coverage"));
+        assert!(!body.contains("| File |"));
+    }
+
+    #[test]
+    #[cfg(feature = "github")]
+    fn only_own_comment_updated() {
+        let comments: Vec<Comment> = serde_json::from_str(&format!(
+            r#"[
+                {{ "id": 1, "body": "{MARKER} quoted", "user": {{ "login": "someone" }} }},
+                {{ "id": 2, "body": "looks good", "user": {{ "login": "ci-bot" }} }},
+                {{ "id": 3, "body": "{MARKER} old", "user": null }},
+                {{ "id": 4, "body": "{MARKER} report", "user": {{ "login": "ci-bot" }} }}
+            ]"#
+        ))
+        .unwrap();
+        assert_eq!(own_comment(&comments, "ci-bot"), Some(4));
+        assert_eq!(own_comment(&comments, "other"), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! The total coverage of the last runs, stored in `target/tarpaulin` next to the run report and
+//! drawn as a trend on the landing page of the HTML report. Only the totals of each run are kept
+//! so the history stays small however big the project is.
+use crate::config::{Config, RunReportFormat};
+use crate::errors::RunError;
+use crate::report::run_report_path;
+use crate::traces::TraceMap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Runs kept in the history, the oldest are dropped past this
+const MAX_RUNS: usize = 30;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub(crate) struct HistoryEntry {
+    /// Seconds since the unix epoch when the run was reported
+    pub timestamp: u64,
+    pub covered: usize,
+    pub coverable: usize,
+}
+
+fn history_path(config: &Config) -> PathBuf {
+    run_report_path(config, RunReportFormat::Json).with_extension("history.json")
+}
+
+/// The runs recorded in the history, oldest first
+pub(crate) fn read_history(config: &Config) -> Vec<HistoryEntry> {
+    let path = history_path(config);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(history) => history,
+        Err(e) => {
+            warn!(
+                "Ignoring unreadable coverage history {}: {}",
+                path.display(),
+                e
+            );
+            vec![]
+        }
+    }
+}
+
+fn push_entry(history: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    history.push(entry);
+    let excess = history.len().saturating_sub(MAX_RUNS);
+    history.drain(..excess);
+}
+
+/// Adds the coverage of this run to the history
+pub(crate) fn record(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    let mut history = read_history(config);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+    push_entry(
+        &mut history,
+        HistoryEntry {
+            timestamp,
+            covered: result.total_covered(),
+            coverable: result.total_coverable(),
+        },
+    );
+    let path = history_path(config);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string(&history)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_runs_dropped() {
+        let entry = |timestamp| HistoryEntry {
+            timestamp,
+            covered: 1,
+            coverable: 2,
+        };
+        let mut history = (0..MAX_RUNS as u64).map(entry).collect::<Vec<_>>();
+        push_entry(&mut history, entry(100));
+        assert_eq!(history.len(), MAX_RUNS);
+        assert_eq!(history[0].timestamp, 1);
+        assert_eq!(history.last(), Some(&entry(100)));
+    }
+}
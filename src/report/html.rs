@@ -1,10 +1,20 @@
-use crate::config::Config;
+use crate::config::{Config, HtmlTheme, OutputFile};
 use crate::errors::*;
-use crate::report::{get_previous_result, safe_json};
-use crate::traces::{Trace, TraceMap};
+use crate::path_utils::fit_file_name;
+use crate::report::history::{read_history, HistoryEntry};
+use crate::report::stats::{owning_package, workspace_packages};
+use crate::report::{get_previous_result, report_file, safe_json};
+use crate::source_analysis::BranchArm;
+use crate::toolchain::Toolchain;
+use crate::traces::{amount_coverable, amount_covered, ConfigSummary, Trace, TraceMap};
 use serde::Serialize;
-use std::fs::{read_to_string, File};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs::{self, create_dir_all, read_to_string, File};
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
 
 #[derive(Serialize)]
 struct SourceFile {
@@ -13,11 +23,58 @@ struct SourceFile {
     pub traces: Vec<Trace>,
     pub covered: usize,
     pub coverable: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub uncovered_arms: Vec<BranchArm>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<FunctionSummary>,
+}
+
+/// Coverage of the lines of a function found by source analysis
+#[derive(Serialize)]
+struct FunctionSummary {
+    pub name: String,
+    pub start: u64,
+    pub end: u64,
+    pub covered: usize,
+    pub coverable: usize,
+}
+
+fn function_summaries(
+    coverage_data: &TraceMap,
+    path: &Path,
+    traces: &[Trace],
+) -> Vec<FunctionSummary> {
+    let mut functions = coverage_data
+        .get_functions(path)
+        .map(|function| {
+            let body = || {
+                traces
+                    .iter()
+                    .filter(|x| (function.start..=function.end).contains(&x.line))
+            };
+            FunctionSummary {
+                name: function.name.clone(),
+                start: function.start,
+                end: function.end,
+                covered: amount_covered(body()),
+                coverable: amount_coverable(body()),
+            }
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|x| x.start);
+    functions
 }
 
 #[derive(Serialize)]
 struct CoverageReport {
     pub files: Vec<SourceFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<Toolchain>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sampled: bool,
+    /// Total coverage of the last runs, for the trend on the landing page
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryEntry>,
 }
 
 #[derive(PartialEq)]
@@ -26,8 +83,18 @@ enum Context {
     PreviousResults,
 }
 
-fn get_json(coverage_data: &TraceMap, context: Context) -> Result<String, RunError> {
-    let mut report = CoverageReport { files: Vec::new() };
+fn get_json(
+    coverage_data: &TraceMap,
+    context: Context,
+    toolchain: Option<Toolchain>,
+    history: Vec<HistoryEntry>,
+) -> Result<String, RunError> {
+    let mut report = CoverageReport {
+        files: Vec::new(),
+        toolchain,
+        sampled: coverage_data.is_sampled(),
+        history,
+    };
 
     for (path, traces) in coverage_data.iter() {
         let content = match read_to_string(path) {
@@ -53,6 +120,12 @@ fn get_json(coverage_data: &TraceMap, context: Context) -> Result<String, RunErr
             traces: traces.clone(),
             covered: coverage_data.covered_in_path(path),
             coverable: coverage_data.coverable_in_path(path),
+            uncovered_arms: coverage_data
+                .uncovered_branch_arms(path)
+                .into_iter()
+                .cloned()
+                .collect(),
+            functions: function_summaries(coverage_data, path, traces),
         });
     }
 
@@ -60,26 +133,307 @@ fn get_json(coverage_data: &TraceMap, context: Context) -> Result<String, RunErr
         .map_err(|e| RunError::Html(format!("Report isn't serializable: {e}")))
 }
 
-pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
-    let file_path = config.output_dir().join("tarpaulin-report.html");
-    let mut file = match File::create(file_path) {
+/// A report linked from the index page
+struct IndexEntry {
+    name: String,
+    link: String,
+    covered: usize,
+    coverable: usize,
+}
+
+impl IndexEntry {
+    fn new(name: &str, link: String, coverage_data: &TraceMap) -> Self {
+        Self {
+            name: name.to_string(),
+            link,
+            covered: coverage_data.total_covered(),
+            coverable: coverage_data.total_coverable(),
+        }
+    }
+}
+
+/// A config of the run listed on the index page, with a link to its report if one was written
+struct ConfigEntry<'a> {
+    summary: &'a ConfigSummary,
+    link: Option<String>,
+}
+
+/// Theme and custom stylesheet of the report pages
+#[derive(Default)]
+struct PageStyle {
+    theme: HtmlTheme,
+    css: String,
+}
+
+impl PageStyle {
+    fn new(config: &Config) -> Result<Self, RunError> {
+        let css = match &config.html_css {
+            Some(path) => read_to_string(path)
+                .map_err(|e| RunError::Html(format!("Couldn't read {}: {e}", path.display())))?,
+            None => String::new(),
+        };
+        Ok(Self {
+            theme: config.html_theme(),
+            css,
+        })
+    }
+
+    /// Value of the `data-theme` attribute the stylesheets pick the colours with
+    fn theme_name(&self) -> &'static str {
+        match self.theme {
+            HtmlTheme::Auto => "auto",
+            HtmlTheme::Light => "light",
+            HtmlTheme::Dark => "dark",
+        }
+    }
+
+    /// The custom stylesheet, after the page's own so it overrides it
+    fn custom_style(&self) -> String {
+        if self.css.is_empty() {
+            String::new()
+        } else {
+            format!("<style>{}</style>", self.css)
+        }
+    }
+}
+
+fn write_report(
+    path: &Path,
+    coverage_data: &TraceMap,
+    toolchain: Option<Toolchain>,
+    previous: Option<&TraceMap>,
+    history: Vec<HistoryEntry>,
+    style: &PageStyle,
+) -> Result<(), RunError> {
+    let mut file = match File::create(path) {
         Ok(k) => k,
         Err(e) => return Err(RunError::Html(format!("File is not writeable: {e}"))),
     };
 
-    let report_json = get_json(coverage_data, Context::CurrentResults)?;
-    let previous_report_json = match get_previous_result(config) {
-        Some(result) => get_json(&result, Context::PreviousResults)?,
+    let report_json = get_json(coverage_data, Context::CurrentResults, toolchain, history)?;
+    let previous_report_json = match previous {
+        Some(result) => get_json(result, Context::PreviousResults, None, vec![])?,
         None => String::from("null"),
     };
 
-    match write!(
-        file,
+    match file.write_all(page(&report_json, &previous_report_json, style).as_bytes()) {
+        Ok(_) => (),
+        Err(e) => return Err(RunError::Html(e.to_string())),
+    };
+
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Directory of the reports linked from the index, named so it doesn't overwrite a project's own
+/// files in the output directory
+fn reports_dir(config: &Config) -> PathBuf {
+    report_file(config, "tarpaulin-html", "-html")
+}
+
+/// File name for the report of a package or config
+fn report_name(name: &str) -> String {
+    let stem = name.replace(
+        |c: char| !(c.is_alphanumeric() || c == '-' || c == '_'),
+        "_",
+    );
+    fit_file_name(&format!("{stem}.html"))
+}
+
+fn config_report_path(config: &Config, name: &str) -> PathBuf {
+    reports_dir(config).join("configs").join(report_name(name))
+}
+
+/// Writes the report of one config of a run with several, for the index to link to. The config is
+/// the one the merged coverage is reported with, `name` is the config the coverage is from.
+pub(crate) fn export_config(
+    coverage_data: &TraceMap,
+    name: &str,
+    config: &Config,
+) -> Result<(), RunError> {
+    let path = config_report_path(config, name);
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)
+            .map_err(|e| RunError::Html(format!("Couldn't create {}: {e}", dir.display())))?;
+    }
+    write_report(
+        &path,
+        coverage_data,
+        Some(Toolchain::probe(config)),
+        None,
+        vec![],
+        &PageStyle::new(config)?,
+    )
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file_path = report_file(config, "tarpaulin-report.html", ".html");
+    let toolchain = Toolchain::probe(config);
+    let previous = get_previous_result(config);
+    let style = PageStyle::new(config)?;
+    write_report(
+        &file_path,
+        coverage_data,
+        Some(toolchain.clone()),
+        previous.as_ref(),
+        read_history(config),
+        &style,
+    )?;
+
+    // A workspace gets a report for each package and an index linking them all
+    let packages = workspace_packages(config);
+    let names = coverage_data
+        .files()
+        .into_iter()
+        .map(|x| owning_package(&packages, x))
+        .collect::<BTreeSet<_>>();
+    let configs = coverage_data.config_summaries();
+    if names.len() < 2 && configs.len() < 2 {
+        return Ok(());
+    }
+    let mut entries = vec![IndexEntry::new(
+        "All packages",
+        file_name(&file_path),
+        coverage_data,
+    )];
+    let dir = reports_dir(config);
+    if names.len() > 1 {
+        create_dir_all(&dir)
+            .map_err(|e| RunError::Html(format!("Couldn't create {}: {e}", dir.display())))?;
+        for name in names {
+            let in_package = |x: &Path| owning_package(&packages, x) == name;
+            let package_data = coverage_data.subset(in_package);
+            let path = dir.join(report_name(name));
+            let previous = previous.as_ref().map(|x| x.subset(in_package));
+            write_report(
+                &path,
+                &package_data,
+                Some(toolchain.clone()),
+                previous.as_ref(),
+                // The history is only of the whole project's coverage
+                vec![],
+                &style,
+            )?;
+            let link = format!("{}/{}", file_name(&dir), file_name(&path));
+            entries.push(IndexEntry::new(name, link, &package_data));
+        }
+    }
+    // Config reports are written as each config finishes, only link the ones which exist
+    let configs = configs
+        .iter()
+        .map(|summary| ConfigEntry {
+            summary,
+            link: Some(config_report_path(config, &summary.name))
+                .filter(|x| x.is_file())
+                .map(|x| format!("{}/configs/{}", file_name(&dir), file_name(&x))),
+        })
+        .collect::<Vec<_>>();
+    fs::write(
+        report_file(config, "tarpaulin-index.html", "-index.html"),
+        index_page(&entries, &configs, &style),
+    )
+    .map_err(|e| RunError::Html(e.to_string()))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn percentage(covered: usize, coverable: usize) -> String {
+    if coverable == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}%", 100.0 * covered as f64 / coverable as f64)
+    }
+}
+
+/// Entry point linking the report of each package with its coverage, and the report and coverage
+/// of each config when there's more than one
+fn index_page(entries: &[IndexEntry], configs: &[ConfigEntry], style: &PageStyle) -> String {
+    let mut rows = String::new();
+    for entry in entries {
+        let _ = writeln!(
+            rows,
+            r#"        <tr><td><a href="{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            escape(&entry.link),
+            escape(&entry.name),
+            entry.covered,
+            entry.coverable,
+            percentage(entry.covered, entry.coverable),
+        );
+    }
+    let mut config_table = String::new();
+    if configs.len() > 1 {
+        config_table.push_str(
+            "    <h2>Configs</h2>\n    <table>\n        <tr><th>Config</th><th>Covered</th><th>Coverable</th><th>Coverage</th></tr>\n",
+        );
+        for entry in configs {
+            let summary = entry.summary;
+            let name = match &entry.link {
+                Some(link) => format!(
+                    r#"<a href="{}">{}</a>"#,
+                    escape(link),
+                    escape(&summary.name)
+                ),
+                None => escape(&summary.name),
+            };
+            let _ = writeln!(
+                config_table,
+                "        <tr><td>{}</td><td>{}</td><td>{}</td><td>{:.2}%</td></tr>",
+                name, summary.covered, summary.coverable, summary.coverage,
+            );
+        }
+        config_table.push_str("    </table>\n");
+    }
+    let theme = style.theme_name();
+    let custom_style = style.custom_style();
+    format!(
+        r#"<!doctype html>
+<html data-theme="{theme}">
+<head>
+    <meta charset="utf-8">
+    <title>Coverage reports</title>
+    <style>
+        :root {{ color-scheme: light; }}
+        :root[data-theme="dark"] {{ color-scheme: dark; }}
+        :root[data-theme="auto"] {{ color-scheme: light dark; }}
+        body {{ font-family: sans-serif; margin: 2em; }}
+        table {{ border-collapse: collapse; }}
+        th, td {{ padding: 0.3em 1em; border-bottom: 1px solid #8888; text-align: right; }}
+        th:first-child, td:first-child {{ text-align: left; }}
+    </style>
+    {custom_style}
+</head>
+<body>
+    <h1>Coverage reports</h1>
+    <table>
+        <tr><th>Report</th><th>Covered</th><th>Coverable</th><th>Coverage</th></tr>
+{rows}    </table>
+{config_table}</body>
+</html>
+"#
+    )
+}
+
+/// The report page, every script and style is inlined so it works offline and can be moved or
+/// archived as a single file
+fn page(report_json: &str, previous_report_json: &str, style: &PageStyle) -> String {
+    format!(
         r##"<!doctype html>
-<html>
+<html data-theme="{}">
 <head>
     <meta charset="utf-8">
     <style>{}</style>
+    {}
 </head>
 <body>
     <div id="root"></div>
@@ -87,21 +441,161 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
         var data = {};
         var previousData = {};
     </script>
-    <script crossorigin>{}</script>
-    <script crossorigin>{}</script>
+    <script>{}</script>
+    <script>{}</script>
     <script>{}</script>
 </body>
 </html>"##,
+        style.theme_name(),
         include_str!("report_viewer.css"),
+        style.custom_style(),
         report_json,
         previous_report_json,
         include_str!("react.production.min.js"),
         include_str!("react-dom.production.min.js"),
         include_str!("report_viewer.js"),
-    ) {
-        Ok(_) => (),
-        Err(e) => return Err(RunError::Html(e.to_string())),
-    };
+    )
+}
 
-    Ok(())
+/// Opens the HTML report in the default browser
+pub(crate) fn open(config: &Config) {
+    if !config.generate.contains(&OutputFile::Html) {
+        warn!("--open has no effect without `--out Html`");
+        return;
+    }
+    let path = report_file(config, "tarpaulin-report.html", ".html");
+    info!("Opening {}", path.display());
+    if let Err(e) = browser_command(&path).spawn() {
+        warn!("Couldn't open {} in a browser: {}", path.display(), e);
+    }
+}
+
+fn browser_command(path: &Path) -> Command {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "macos")] {
+            let mut cmd = Command::new("open");
+        } else if #[cfg(windows)] {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", "start", ""]);
+        } else {
+            let mut cmd = Command::new("xdg-open");
+        }
+    }
+    cmd.arg(path);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_analysis::Function;
+    use crate::traces::CoverageStat;
+    use std::collections::HashMap;
+
+    #[test]
+    fn page_has_no_external_assets() {
+        let page = page("{\"files\":[]}", "null", &PageStyle::default());
+        assert!(!page.contains("<link"));
+        assert!(!page.contains(" src="));
+        assert!(!page.contains("@import"));
+    }
+
+    #[test]
+    fn functions_summarised() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (6, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let function = |name: &str, start, end| Function {
+            name: name.to_string(),
+            start,
+            end,
+            public: false,
+            ffi: false,
+        };
+        map.set_functions(HashMap::from([(
+            file.to_path_buf(),
+            vec![function("second", 5, 7), function("first", 1, 4)],
+        )]));
+        let traces = map.get_child_traces(file).cloned().collect::<Vec<_>>();
+        let functions = function_summaries(&map, file, &traces)
+            .into_iter()
+            .map(|x| (x.name, x.covered, x.coverable))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            functions,
+            vec![("first".to_string(), 1, 2), ("second".to_string(), 1, 1)]
+        );
+    }
+
+    #[test]
+    fn index_links_package_reports() {
+        let entries = [
+            IndexEntry {
+                name: "All packages".to_string(),
+                link: "tarpaulin-report.html".to_string(),
+                covered: 3,
+                coverable: 4,
+            },
+            IndexEntry {
+                name: "<unknown>".to_string(),
+                link: "tarpaulin-html/_unknown_.html".to_string(),
+                covered: 0,
+                coverable: 0,
+            },
+        ];
+        let page = index_page(&entries, &[], &PageStyle::default());
+        assert!(page.contains(
+            r#"<tr><td><a href="tarpaulin-report.html">All packages</a></td><td>3</td><td>4</td><td>75.00%</td></tr>"#
+        ));
+        assert!(page.contains(
+            r#"<tr><td><a href="tarpaulin-html/_unknown_.html">&lt;unknown&gt;</a></td><td>0</td><td>0</td><td>-</td></tr>"#
+        ));
+        assert!(!page.contains("<h2>Configs</h2>"));
+
+        let summary =
+            |name: &str| ConfigSummary::new(name.to_string(), Default::default(), &TraceMap::new());
+        let (unit, docs) = (summary("unit"), summary("docs"));
+        let configs = [
+            ConfigEntry {
+                summary: &unit,
+                link: Some("tarpaulin-html/configs/unit.html".to_string()),
+            },
+            ConfigEntry {
+                summary: &docs,
+                link: None,
+            },
+        ];
+        let page = index_page(&entries, &configs, &PageStyle::default());
+        assert!(page.contains(
+            r#"<tr><td><a href="tarpaulin-html/configs/unit.html">unit</a></td><td>0</td><td>0</td><td>0.00%</td></tr>"#
+        ));
+        assert!(page.contains("<tr><td>docs</td><td>0</td><td>0</td><td>0.00%</td></tr>"));
+    }
+
+    #[test]
+    fn theme_and_custom_css_applied() {
+        let style = PageStyle {
+            theme: HtmlTheme::Dark,
+            css: ".app { font-size: 20px; }".to_string(),
+        };
+        let report = page("{\"files\":[]}", "null", &style);
+        assert!(report.contains(r#"<html data-theme="dark">"#));
+        // The custom stylesheet comes after the report's own so it takes precedence
+        let builtin = report.find(include_str!("report_viewer.css")).unwrap();
+        let custom = report
+            .find("<style>.app { font-size: 20px; }</style>")
+            .unwrap();
+        assert!(builtin < custom);
+
+        let index = index_page(&[], &[], &style);
+        assert!(index.contains(r#"<html data-theme="dark">"#));
+        assert!(index.contains("<style>.app { font-size: 20px; }</style>"));
+
+        let default = page("{\"files\":[]}", "null", &PageStyle::default());
+        assert!(default.contains(r#"<html data-theme="auto">"#));
+    }
 }
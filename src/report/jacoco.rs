@@ -0,0 +1,413 @@
+//! JaCoCo's XML report, for tools such as the Jenkins coverage plugins which only read JaCoCo.
+//! Each directory of the project is a package and each file both a class and a sourcefile, the
+//! functions in the file are the methods of the class. The structure is roughly:
+//! ```xml
+//! <report name="project">
+//!   <sessioninfo id="tarpaulin" start="..." dump="..."/>
+//!   <package name="src">
+//!     <class name="src/lib" sourcefilename="lib.rs">
+//!       <method name="add" desc="()" line="3">
+//!         <counter type="LINE" missed="0" covered="2"/>
+//!         ...
+//!       </method>
+//!       <counter type="LINE" missed="1" covered="2"/>
+//!       ...
+//!     </class>
+//!     <sourcefile name="lib.rs">
+//!       <line nr="3" mi="0" ci="1" mb="0" cb="0"/>
+//!       ...
+//!     </sourcefile>
+//!   </package>
+//!   <counter type="LINE" missed="1" covered="2"/>
+//! </report>
+//! ```
+//! JaCoCo counts bytecode instructions, here every trace on a line is counted as an instruction.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::portable_path;
+use crate::report::report_file;
+use crate::traces::{amount_covered, Trace, TraceMap};
+use chrono::offset::Utc;
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Writer,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+
+const DOCTYPE: &str = r#"report PUBLIC "-//JACOCO//DTD Report 1.1//EN" "report.dtd""#;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct Counter {
+    missed: usize,
+    covered: usize,
+}
+
+impl Counter {
+    fn from_covered(covered: bool) -> Self {
+        Self {
+            missed: usize::from(!covered),
+            covered: usize::from(covered),
+        }
+    }
+
+    fn is_covered(&self) -> bool {
+        self.covered > 0
+    }
+}
+
+impl AddAssign for Counter {
+    fn add_assign(&mut self, other: Self) {
+        self.missed += other.missed;
+        self.covered += other.covered;
+    }
+}
+
+/// The counters JaCoCo writes for every element, in the order it writes them
+#[derive(Debug, Default, Copy, Clone)]
+struct Counters {
+    instruction: Counter,
+    branch: Counter,
+    line: Counter,
+    method: Counter,
+    class: Counter,
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other: Self) {
+        self.instruction += other.instruction;
+        self.branch += other.branch;
+        self.line += other.line;
+        self.method += other.method;
+        self.class += other.class;
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Line {
+    instructions: Counter,
+    branches: Counter,
+}
+
+impl Line {
+    fn counters(&self) -> Counters {
+        Counters {
+            instruction: self.instructions,
+            branch: self.branches,
+            line: Counter::from_covered(self.instructions.is_covered()),
+            ..Default::default()
+        }
+    }
+}
+
+struct Method<'a> {
+    name: &'a str,
+    line: u64,
+    counters: Counters,
+}
+
+/// A source file, which is both a class and a sourcefile in the report
+struct Class<'a> {
+    path: &'a Path,
+    lines: BTreeMap<u64, Line>,
+    methods: Vec<Method<'a>>,
+    counters: Counters,
+}
+
+fn file_lines(coverage_data: &TraceMap, path: &Path, traces: &[Trace]) -> BTreeMap<u64, Line> {
+    let mut lines = BTreeMap::<u64, Line>::new();
+    for trace in traces {
+        let covered = amount_covered(std::iter::once(trace)) > 0;
+        lines.entry(trace.line).or_default().instructions += Counter::from_covered(covered);
+    }
+    let branches = coverage_data
+        .branches()
+        .find(|(file, _)| file.as_path() == path)
+        .map(|(_, branches)| branches.as_slice())
+        .unwrap_or_default();
+    for branch in branches {
+        let line = lines.entry(branch.line).or_default();
+        line.branches += Counter::from_covered(branch.stats.been_true);
+        line.branches += Counter::from_covered(branch.stats.been_false);
+    }
+    lines
+}
+
+fn class<'a>(coverage_data: &'a TraceMap, path: &'a Path, traces: &[Trace]) -> Class<'a> {
+    let lines = file_lines(coverage_data, path, traces);
+    let mut counters = Counters::default();
+    for line in lines.values() {
+        counters += line.counters();
+    }
+    let methods = coverage_data
+        .get_functions(path)
+        .map(|function| {
+            let mut counters = Counters::default();
+            for line in lines.range(function.start..=function.end).map(|(_, x)| x) {
+                counters += line.counters();
+            }
+            counters.method = Counter::from_covered(counters.line.is_covered());
+            Method {
+                name: &function.name,
+                line: function.start,
+                counters,
+            }
+        })
+        .filter(|x| x.counters.line != Counter::default())
+        .collect::<Vec<_>>();
+    for method in &methods {
+        counters.method += method.counters.method;
+    }
+    counters.class = Counter::from_covered(counters.line.is_covered());
+    Class {
+        path,
+        lines,
+        methods,
+        counters,
+    }
+}
+
+fn write_counters<W: Write>(writer: &mut Writer<W>, counters: &Counters) -> Result<(), RunError> {
+    let counters = [
+        ("INSTRUCTION", counters.instruction),
+        ("BRANCH", counters.branch),
+        ("LINE", counters.line),
+        ("METHOD", counters.method),
+        ("CLASS", counters.class),
+    ];
+    for (kind, counter) in counters {
+        if counter == Counter::default() {
+            continue;
+        }
+        let mut element = BytesStart::new("counter");
+        element.push_attribute(("type", kind));
+        element.push_attribute(("missed", counter.missed.to_string().as_ref()));
+        element.push_attribute(("covered", counter.covered.to_string().as_ref()));
+        writer.write_event(Event::Empty(element))?;
+    }
+    Ok(())
+}
+
+fn write_class<W: Write>(
+    writer: &mut Writer<W>,
+    class: &Class,
+    root: &Path,
+    file_name: &str,
+) -> Result<(), RunError> {
+    let name = portable_path(&class.path.with_extension(""), root);
+    let mut element = BytesStart::new("class");
+    element.push_attribute(("name", name.to_string_lossy().as_ref()));
+    element.push_attribute(("sourcefilename", file_name));
+    writer.write_event(Event::Start(element))?;
+    for method in &class.methods {
+        let mut element = BytesStart::new("method");
+        element.push_attribute(("name", method.name));
+        element.push_attribute(("desc", "()"));
+        element.push_attribute(("line", method.line.to_string().as_ref()));
+        writer.write_event(Event::Start(element))?;
+        write_counters(writer, &method.counters)?;
+        writer.write_event(Event::End(BytesEnd::new("method")))?;
+    }
+    write_counters(writer, &class.counters)?;
+    writer.write_event(Event::End(BytesEnd::new("class")))?;
+    Ok(())
+}
+
+fn write_sourcefile<W: Write>(
+    writer: &mut Writer<W>,
+    class: &Class,
+    file_name: &str,
+) -> Result<(), RunError> {
+    let mut element = BytesStart::new("sourcefile");
+    element.push_attribute(("name", file_name));
+    writer.write_event(Event::Start(element))?;
+    for (number, line) in &class.lines {
+        let mut element = BytesStart::new("line");
+        element.push_attribute(("nr", number.to_string().as_ref()));
+        element.push_attribute(("mi", line.instructions.missed.to_string().as_ref()));
+        element.push_attribute(("ci", line.instructions.covered.to_string().as_ref()));
+        element.push_attribute(("mb", line.branches.missed.to_string().as_ref()));
+        element.push_attribute(("cb", line.branches.covered.to_string().as_ref()));
+        writer.write_event(Event::Empty(element))?;
+    }
+    let counters = Counters {
+        method: Counter::default(),
+        class: Counter::default(),
+        ..class.counters
+    };
+    write_counters(writer, &counters)?;
+    writer.write_event(Event::End(BytesEnd::new("sourcefile")))?;
+    Ok(())
+}
+
+fn write_jacoco(
+    file: impl Write,
+    coverage_data: &TraceMap,
+    root: &Path,
+    timestamp: i64,
+) -> Result<(), RunError> {
+    let mut packages = BTreeMap::<PathBuf, Vec<Class>>::new();
+    for (path, traces) in coverage_data.iter() {
+        if traces.is_empty() {
+            continue;
+        }
+        let package = path.parent().unwrap_or(root).to_path_buf();
+        packages
+            .entry(package)
+            .or_default()
+            .push(class(coverage_data, path, traces));
+    }
+
+    let mut writer = Writer::new_with_indent(file, b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new(
+        "1.0",
+        Some("UTF-8"),
+        Some("yes"),
+    )))?;
+    writer.write_event(Event::DocType(BytesText::from_escaped(DOCTYPE)))?;
+    let name = root
+        .file_name()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default();
+    let mut report = BytesStart::new("report");
+    report.push_attribute(("name", name.as_ref()));
+    writer.write_event(Event::Start(report))?;
+    let mut session = BytesStart::new("sessioninfo");
+    session.push_attribute(("id", "tarpaulin"));
+    session.push_attribute(("start", timestamp.to_string().as_ref()));
+    session.push_attribute(("dump", timestamp.to_string().as_ref()));
+    writer.write_event(Event::Empty(session))?;
+
+    let mut total = Counters::default();
+    for (package, classes) in &packages {
+        let mut element = BytesStart::new("package");
+        element.push_attribute((
+            "name",
+            portable_path(package, root).to_string_lossy().as_ref(),
+        ));
+        writer.write_event(Event::Start(element))?;
+        let mut counters = Counters::default();
+        let file_names = classes
+            .iter()
+            .map(|x| x.path.file_name().unwrap_or_default().to_string_lossy())
+            .collect::<Vec<_>>();
+        for (class, file_name) in classes.iter().zip(&file_names) {
+            write_class(&mut writer, class, root, file_name)?;
+            counters += class.counters;
+        }
+        for (class, file_name) in classes.iter().zip(&file_names) {
+            write_sourcefile(&mut writer, class, file_name)?;
+        }
+        write_counters(&mut writer, &counters)?;
+        writer.write_event(Event::End(BytesEnd::new("package")))?;
+        total += counters;
+    }
+    write_counters(&mut writer, &total)?;
+    writer.write_event(Event::End(BytesEnd::new("report")))?;
+    Ok(())
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file = File::create(report_file(config, "jacoco.xml", "-jacoco.xml"))?;
+    let mut file = BufWriter::new(file);
+    write_jacoco(
+        &mut file,
+        coverage_data,
+        &config.root(),
+        Utc::now().timestamp_millis(),
+    )?;
+    file.flush().map_err(RunError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_analysis::Function;
+    use crate::traces::{BranchTrace, CoverageStat, LogicState};
+    use std::collections::HashMap;
+
+    #[test]
+    fn counters_for_each_level() {
+        let root = Path::new("/project");
+        let file = root.join("src").join("lib.rs");
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (3, 1), (6, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(&file, trace);
+        }
+        traces.add_branch(
+            &file,
+            BranchTrace {
+                line: 3,
+                column: 4,
+                stats: LogicState {
+                    been_true: true,
+                    been_false: false,
+                },
+            },
+        );
+        let function = |name: &str, start, end| Function {
+            name: name.to_string(),
+            start,
+            end,
+            public: true,
+            ffi: false,
+        };
+        traces.set_functions(HashMap::from([(
+            file.clone(),
+            vec![function("add", 1, 4), function("unused", 5, 7)],
+        )]));
+
+        let mut data = vec![];
+        write_jacoco(&mut data, &traces, root, 0).unwrap();
+        let xml = String::from_utf8(data).unwrap();
+        assert!(xml
+            .contains(r#"<!DOCTYPE report PUBLIC "-//JACOCO//DTD Report 1.1//EN" "report.dtd">"#));
+        let expected = r#"<report name="project">
+  <sessioninfo id="tarpaulin" start="0" dump="0"/>
+  <package name="src">
+    <class name="src/lib" sourcefilename="lib.rs">
+      <method name="add" desc="()" line="1">
+        <counter type="INSTRUCTION" missed="1" covered="2"/>
+        <counter type="BRANCH" missed="1" covered="1"/>
+        <counter type="LINE" missed="0" covered="2"/>
+        <counter type="METHOD" missed="0" covered="1"/>
+      </method>
+      <method name="unused" desc="()" line="5">
+        <counter type="INSTRUCTION" missed="1" covered="0"/>
+        <counter type="LINE" missed="1" covered="0"/>
+        <counter type="METHOD" missed="1" covered="0"/>
+      </method>
+      <counter type="INSTRUCTION" missed="2" covered="2"/>
+      <counter type="BRANCH" missed="1" covered="1"/>
+      <counter type="LINE" missed="1" covered="2"/>
+      <counter type="METHOD" missed="1" covered="1"/>
+      <counter type="CLASS" missed="0" covered="1"/>
+    </class>
+    <sourcefile name="lib.rs">
+      <line nr="2" mi="0" ci="1" mb="0" cb="0"/>
+      <line nr="3" mi="1" ci="1" mb="1" cb="1"/>
+      <line nr="6" mi="1" ci="0" mb="0" cb="0"/>
+      <counter type="INSTRUCTION" missed="2" covered="2"/>
+      <counter type="BRANCH" missed="1" covered="1"/>
+      <counter type="LINE" missed="1" covered="2"/>
+    </sourcefile>
+    <counter type="INSTRUCTION" missed="2" covered="2"/>
+    <counter type="BRANCH" missed="1" covered="1"/>
+    <counter type="LINE" missed="1" covered="2"/>
+    <counter type="METHOD" missed="1" covered="1"/>
+    <counter type="CLASS" missed="0" covered="1"/>
+  </package>
+  <counter type="INSTRUCTION" missed="2" covered="2"/>
+  <counter type="BRANCH" missed="1" covered="1"/>
+  <counter type="LINE" missed="1" covered="2"/>
+  <counter type="METHOD" missed="1" covered="1"/>
+  <counter type="CLASS" missed="0" covered="1"/>
+</report>"#;
+        assert!(xml.ends_with(expected), "{}", xml);
+    }
+}
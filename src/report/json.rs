@@ -1,71 +1,147 @@
 use crate::config::Config;
 use crate::errors::*;
-use crate::traces::{Trace, TraceMap};
-use serde::Serialize;
-use std::{fs, io::Write};
+use crate::report::{log_progress, report_file};
+use crate::toolchain::Toolchain;
+use crate::traces::{
+    ConfigSummary, DocExampleStats, ErrorPathCoverage, FeatureCoverage, FfiFunction, LineClass,
+    RunTypeCoverage, Trace, TraceMap, UnsafeCoverage,
+};
+use serde::{Serialize, Serializer};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
 #[derive(Serialize)]
-struct SourceFile {
+struct SourceFile<'a> {
     path: Vec<String>,
     content: String,
-    traces: Vec<Trace>,
+    traces: &'a [Trace],
     covered: usize,
     coverable: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    line_classes: Vec<LineClass>,
+}
+
+impl<'a> SourceFile<'a> {
+    fn new(coverage_data: &TraceMap, path: &Path, traces: &'a [Trace]) -> Result<Self, RunError> {
+        let content = fs::read_to_string(path).map_err(RunError::from)?;
+        Ok(SourceFile {
+            path: path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect(),
+            content,
+            traces,
+            covered: coverage_data.covered_in_path(path),
+            coverable: coverage_data.coverable_in_path(path),
+            line_classes: coverage_data.line_classes(path),
+        })
+    }
+}
+
+/// The source files in the report, these are read and serialised one at a time so only a single
+/// file's contents are in memory while the report is written.
+struct SourceFiles<'a>(&'a TraceMap);
+
+impl Serialize for SourceFiles<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let total = self.0.files().len();
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .enumerate()
+                .inspect(|(i, _)| log_progress("JSON", i + 1, total))
+                .filter_map(|(_, (path, traces))| SourceFile::new(self.0, path, traces).ok()),
+        )
+    }
 }
 
 #[derive(Serialize)]
-pub struct CoverageReport {
-    files: Vec<SourceFile>,
+pub struct CoverageReport<'a> {
+    files: SourceFiles<'a>,
     coverage: f64,
     covered: usize,
     coverable: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    run_types: Vec<RunTypeCoverage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doc_examples: Option<DocExampleStats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    features: Vec<FeatureCoverage>,
+    #[serde(rename = "unsafe", skip_serializing_if = "Option::is_none")]
+    unsafe_code: Option<UnsafeCoverage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ffi_functions: Vec<FfiFunction>,
+    /// Only with `--error-path-coverage`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_paths: Option<ErrorPathCoverage>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    configs: &'a [ConfigSummary],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    toolchain: Option<Toolchain>,
+    /// Whether the coverage was sampled by the perf engine and is only approximate
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    sampled: bool,
 }
 
-impl From<&TraceMap> for Vec<SourceFile> {
-    fn from(coverage_data: &TraceMap) -> Self {
-        coverage_data
-            .iter()
-            .map(|(path, traces)| -> Result<SourceFile, RunError> {
-                let content = fs::read_to_string(path).map_err(RunError::from)?;
-                Ok(SourceFile {
-                    path: path
-                        .components()
-                        .map(|c| c.as_os_str().to_string_lossy().to_string())
-                        .collect(),
-                    content,
-                    traces: traces.clone(),
-                    covered: coverage_data.covered_in_path(path),
-                    coverable: coverage_data.coverable_in_path(path),
-                })
-            })
-            .filter_map(Result::ok)
-            .collect()
-    }
-}
-
-impl From<&TraceMap> for CoverageReport {
-    fn from(coverage_data: &TraceMap) -> Self {
+impl<'a> From<&'a TraceMap> for CoverageReport<'a> {
+    fn from(coverage_data: &'a TraceMap) -> Self {
         CoverageReport {
-            files: Vec::<SourceFile>::from(coverage_data),
+            files: SourceFiles(coverage_data),
             coverage: 100.0 * coverage_data.coverage_percentage(),
             covered: coverage_data.total_covered(),
             coverable: coverage_data.total_coverable(),
+            run_types: coverage_data.run_type_coverage(),
+            doc_examples: coverage_data.doc_example_stats(),
+            features: coverage_data.feature_coverage(),
+            unsafe_code: coverage_data.unsafe_coverage(),
+            ffi_functions: coverage_data.ffi_functions(),
+            error_paths: None,
+            configs: coverage_data.config_summaries(),
+            toolchain: None,
+            sampled: coverage_data.is_sampled(),
         }
     }
 }
 
-type JsonStringResult = Result<String, serde_json::error::Error>;
-
-impl From<&TraceMap> for JsonStringResult {
-    fn from(val: &TraceMap) -> Self {
-        serde_json::to_string(&CoverageReport::from(val))
-    }
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file_path = report_file(config, "tarpaulin-report.json", ".json");
+    let mut writer = BufWriter::new(fs::File::create(file_path)?);
+    let report = CoverageReport {
+        toolchain: Some(Toolchain::probe(config)),
+        error_paths: config
+            .error_path_coverage
+            .then(|| coverage_data.error_path_coverage())
+            .flatten(),
+        ..CoverageReport::from(coverage_data)
+    };
+    serde_json::to_writer(&mut writer, &report)?;
+    writer.flush().map_err(RunError::from)
 }
 
-pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
-    let file_path = config.output_dir().join("tarpaulin-report.json");
-    let report: JsonStringResult = coverage_data.into();
-    fs::File::create(file_path)?
-        .write_all(report?.as_bytes())
-        .map_err(RunError::from)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::CoverageStat;
+
+    #[test]
+    fn files_streamed_into_report() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/report/json.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(1, 1), (2, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(&source, trace);
+        }
+        // Unreadable files are left out of the report
+        map.add_trace(Path::new("missing.rs"), Trace::new_stub(1));
+
+        let report = serde_json::to_value(CoverageReport::from(&map)).unwrap();
+        let files = report["files"].as_array().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0]["traces"].as_array().unwrap().len(), 2);
+        assert_eq!(files[0]["covered"], 1);
+        assert_eq!(files[0]["coverable"], 2);
+        assert_eq!(report["coverable"], 3);
+    }
 }
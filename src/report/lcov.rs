@@ -1,18 +1,20 @@
 use crate::config::Config;
 use crate::errors::RunError;
-use crate::traces::{CoverageStat, TraceMap};
-use std::collections::BTreeMap;
+use crate::report::report_file;
+use crate::traces::{amount_covered, CoverageStat, TraceMap};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 
 pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
-    let file_path = config.output_dir().join("lcov.info");
+    let file_path = report_file(config, "lcov.info", ".info");
     let file = match File::create(file_path) {
         Ok(k) => k,
         Err(e) => return Err(RunError::Lcov(format!("File is not writeable: {e}"))),
     };
 
-    write_lcov(file, coverage_data)
+    let mut file = BufWriter::new(file);
+    write_lcov(&mut file, coverage_data)?;
+    file.flush().map_err(RunError::from)
 }
 
 fn write_lcov(mut file: impl Write, coverage_data: &TraceMap) -> Result<(), RunError> {
@@ -23,51 +25,45 @@ fn write_lcov(mut file: impl Write, coverage_data: &TraceMap) -> Result<(), RunE
         writeln!(file, "TN:")?;
         writeln!(file, "SF:{}", path.to_str().unwrap())?;
 
-        let mut fns: Vec<String> = vec![];
-        let mut fnda: Vec<String> = vec![];
-        let mut da: Vec<(u64, u64)> = vec![];
-
-        let mut fn_locs = coverage_data
-            .get_functions(&path)
-            .map(|x| ((x.start, x.end), &x.name))
-            .collect::<BTreeMap<_, _>>();
-
-        let mut first_fn = fn_locs.pop_first();
-
-        for trace in traces {
-            match &first_fn {
-                Some(((start, end), name)) if (*start..=*end).contains(&trace.line) => {
-                    let fn_hits = match trace.stats {
-                        CoverageStat::Line(hits) => hits,
-                        _ => {
-                            return Err(RunError::Lcov(
-                                "Function doesn't have hits number".to_string(),
-                            ))
-                        }
-                    };
-
-                    fns.push(format!("FN:{},{}", trace.line, name));
-                    fnda.push(format!("FNDA:{fn_hits},{name}"));
-
-                    first_fn = fn_locs.pop_first();
-                }
-                _ => {}
-            }
+        let mut functions = coverage_data.get_functions(path).collect::<Vec<_>>();
+        functions.sort_by_key(|x| (x.start, x.end));
+        // A function's hits are the hits of its first coverable line, functions without any
+        // coverable lines are left out
+        let functions = functions
+            .into_iter()
+            .filter_map(|function| {
+                let entry = traces
+                    .iter()
+                    .filter(|x| (function.start..=function.end).contains(&x.line))
+                    .min_by_key(|x| x.line)?;
+                let hits = match entry.stats {
+                    CoverageStat::Line(hits) => hits,
+                    _ => amount_covered(std::iter::once(entry)) as u64,
+                };
+                Some((function, hits))
+            })
+            .collect::<Vec<_>>();
 
-            if let CoverageStat::Line(hits) = trace.stats {
-                da.push((trace.line, hits));
-            }
+        for (function, _) in &functions {
+            writeln!(file, "FN:{},{}", function.start, function.name)?;
         }
-
-        for fn_line in &fns {
-            writeln!(file, "{fn_line}",)?;
+        for (function, hits) in &functions {
+            writeln!(file, "FNDA:{hits},{}", function.name)?;
         }
+        writeln!(file, "FNF:{}", functions.len())?;
+        writeln!(
+            file,
+            "FNH:{}",
+            functions.iter().filter(|(_, hits)| *hits != 0).count()
+        )?;
 
-        writeln!(file, "FNF:{}", fns.len())?;
-
-        for fnda_line in fnda {
-            writeln!(file, "{fnda_line}")?;
-        }
+        let da = traces
+            .iter()
+            .filter_map(|trace| match trace.stats {
+                CoverageStat::Line(hits) => Some((trace.line, hits)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
         for (line, hits) in &da {
             writeln!(file, "DA:{line},{hits}")?;
@@ -80,11 +76,31 @@ fn write_lcov(mut file: impl Write, coverage_data: &TraceMap) -> Result<(), RunE
             da.iter().filter(|(_, hits)| *hits != 0).count()
         )?;
 
-        // TODO: add support for branching
-        // BRDA (BRDA:<line number>,<block number>,<branch number>,<hits>)
-        // BRF (branches found)
-        // BRH (branches hit)
-        // More at http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+        // BRDA:<line number>,<block number>,<branch number>,<taken> with `-` when the branch was
+        // never reached. More at http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+        let branches = coverage_data
+            .branches()
+            .find(|(file, _)| file.as_path() == path)
+            .map(|(_, branches)| branches.as_slice())
+            .unwrap_or_default();
+        let mut branches_hit = 0;
+        for (block, branch) in branches.iter().enumerate() {
+            let outcomes = [branch.stats.been_true, branch.stats.been_false];
+            let reached = outcomes.contains(&true);
+            for (number, taken) in outcomes.iter().enumerate() {
+                let taken = match (reached, *taken) {
+                    (false, _) => "-",
+                    (true, true) => "1",
+                    (true, false) => "0",
+                };
+                writeln!(file, "BRDA:{},{block},{number},{taken}", branch.line)?;
+            }
+            branches_hit += outcomes.iter().filter(|x| **x).count();
+        }
+        if !branches.is_empty() {
+            writeln!(file, "BRF:{}", branches.len() * 2)?;
+            writeln!(file, "BRH:{branches_hit}")?;
+        }
 
         writeln!(file, "end_of_record")?;
     }
@@ -97,7 +113,7 @@ mod tests {
     use crate::source_analysis::Function;
     use crate::traces::*;
     use lcov::{record::Record, Reader};
-    use std::collections::HashMap;
+    use std::collections::{BTreeSet, HashMap};
     use std::io::Cursor;
     use std::path::{Path, PathBuf};
 
@@ -109,6 +125,7 @@ mod tests {
             Trace {
                 line: 4,
                 stats: CoverageStat::Line(1),
+                hit_by: BTreeSet::new(),
                 address: Default::default(),
                 length: 0,
             },
@@ -118,6 +135,7 @@ mod tests {
             Trace {
                 line: 5,
                 stats: CoverageStat::Line(0),
+                hit_by: BTreeSet::new(),
                 address: Default::default(),
                 length: 0,
             },
@@ -128,6 +146,7 @@ mod tests {
             Trace {
                 line: 14,
                 stats: CoverageStat::Line(9),
+                hit_by: BTreeSet::new(),
                 address: Default::default(),
                 length: 0,
             },
@@ -140,6 +159,8 @@ mod tests {
                 name: "baz".to_string(),
                 start: 14,
                 end: 20,
+                public: false,
+                ffi: false,
             }],
         );
         traces.set_functions(functions);
@@ -202,9 +223,9 @@ mod tests {
                     }
                 }
                 Record::LinesHit { hit } => {
-                    if current_source == Path::new("bar.rs") {
-                        assert_eq!(hit, 1);
-                    } else if current_source == Path::new("foo.rs") {
+                    if current_source == Path::new("bar.rs")
+                        || current_source == Path::new("foo.rs")
+                    {
                         assert_eq!(hit, 1);
                     } else {
                         panic!("Lines found not attached to file");
@@ -217,4 +238,111 @@ mod tests {
         }
         assert!(items > 0);
     }
+
+    #[test]
+    fn branches_written() {
+        let mut traces = TraceMap::new();
+        let file = Path::new("foo.rs");
+        traces.add_trace(file, Trace::new_stub(3));
+        let branches = [(3, true, false), (4, true, true), (5, false, false)];
+        for (line, been_true, been_false) in branches {
+            traces.add_branch(
+                file,
+                BranchTrace {
+                    line,
+                    column: 10,
+                    stats: LogicState {
+                        been_true,
+                        been_false,
+                    },
+                },
+            );
+        }
+
+        let mut data = vec![];
+        write_lcov(&mut data, &traces).unwrap();
+
+        let mut branch_data = vec![];
+        let mut found = None;
+        let mut hit = None;
+        for record in Reader::new(data.as_slice()) {
+            match record.unwrap() {
+                Record::BranchData {
+                    line,
+                    block,
+                    branch,
+                    taken,
+                } => branch_data.push((line, block, branch, taken)),
+                Record::BranchesFound { found: x } => found = Some(x),
+                Record::BranchesHit { hit: x } => hit = Some(x),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            branch_data,
+            [
+                (3, 0, 0, Some(1)),
+                (3, 0, 1, Some(0)),
+                (4, 1, 0, Some(1)),
+                (4, 1, 1, Some(1)),
+                (5, 2, 0, None),
+                (5, 2, 1, None),
+            ]
+        );
+        assert_eq!(found, Some(6));
+        assert_eq!(hit, Some(3));
+    }
+
+    #[test]
+    fn function_records_written() {
+        let mut traces = TraceMap::new();
+        let file = Path::new("foo.rs");
+        for (line, hits) in [(6, 2), (7, 2), (11, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(file, trace);
+        }
+        let function = |name: &str, start, end| Function {
+            name: name.to_string(),
+            start,
+            end,
+            public: false,
+            ffi: false,
+        };
+        traces.set_functions(HashMap::from([(
+            file.to_path_buf(),
+            vec![
+                function("uncoverable", 1, 3),
+                function("called", 5, 8),
+                function("uncalled", 10, 12),
+            ],
+        )]));
+
+        let mut data = vec![];
+        write_lcov(&mut data, &traces).unwrap();
+
+        let mut names = vec![];
+        let mut hits = vec![];
+        let mut found = None;
+        let mut hit = None;
+        for record in Reader::new(data.as_slice()) {
+            match record.unwrap() {
+                Record::FunctionName { name, start_line } => names.push((name, start_line)),
+                Record::FunctionData { name, count } => hits.push((name, count)),
+                Record::FunctionsFound { found: x } => found = Some(x),
+                Record::FunctionsHit { hit: x } => hit = Some(x),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            names,
+            [("called".to_string(), 5), ("uncalled".to_string(), 10)]
+        );
+        assert_eq!(
+            hits,
+            [("called".to_string(), 2), ("uncalled".to_string(), 0)]
+        );
+        assert_eq!(found, Some(2));
+        assert_eq!(hit, Some(1));
+    }
 }
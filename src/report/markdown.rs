@@ -0,0 +1,225 @@
+//! A markdown summary of the coverage written to `tarpaulin-report.md`. The default summary style
+//! is the total coverage and a table of the coverage of each file. With
+//! `--markdown-style pr-comment` it's laid out for posting on a pull request instead, with the
+//! change in coverage since the previous run stored in `target/tarpaulin`, the files sorted by how
+//! much their coverage changed and the uncovered lines of each file in collapsible sections.
+use crate::config::{Config, MarkdownStyle};
+use crate::errors::RunError;
+use crate::report::quickfix::uncovered_lines;
+use crate::report::{format_line_ranges, get_previous_result, report_file};
+use crate::traces::{coverage_percentage, TraceMap};
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct FileRow {
+    path: PathBuf,
+    covered: usize,
+    coverable: usize,
+    /// Change in the coverage percentage since the previous run, `None` if it didn't cover the file
+    change: Option<f64>,
+    uncovered: Vec<u64>,
+}
+
+impl FileRow {
+    fn percentage(&self) -> f64 {
+        100.0 * self.covered as f64 / self.coverable as f64
+    }
+}
+
+fn file_rows(result: &TraceMap, previous: Option<&TraceMap>, config: &Config) -> Vec<FileRow> {
+    let mut uncovered = uncovered_lines(result);
+    result
+        .files()
+        .into_iter()
+        .filter(|file| result.coverable_in_path(file) > 0)
+        .map(|file| {
+            let change = previous
+                .filter(|last| last.contains_file(file) && last.coverable_in_path(file) > 0)
+                .map(|last| {
+                    let current = coverage_percentage(result.get_child_traces(file));
+                    100.0 * (current - coverage_percentage(last.get_child_traces(file)))
+                });
+            FileRow {
+                path: config.strip_base_dir(file),
+                covered: result.covered_in_path(file),
+                coverable: result.coverable_in_path(file),
+                change,
+                uncovered: uncovered.remove(file).unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+fn format_change(change: Option<f64>) -> String {
+    match change {
+        Some(change) => format!("{change:+.2}%"),
+        None => "new".to_string(),
+    }
+}
+
+/// Writes a collapsible section listing a file's uncovered lines, shared with the comment posted
+/// by `--github-pr`
+pub(crate) fn write_uncovered_lines(body: &mut String, path: &Path, uncovered: &[u64]) {
+    // Writing to a string can't fail
+    let _ = writeln!(
+        body,
+        "\n<details>\n<summary><code>{}</code>: {} uncovered lines</summary>\n\n{}\n\n</details>",
+        path.display(),
+        uncovered.len(),
+        format_line_ranges(uncovered.iter().copied())
+    );
+}
+
+fn write_markdown(
+    result: &TraceMap,
+    previous: Option<&TraceMap>,
+    style: MarkdownStyle,
+    config: &Config,
+) -> String {
+    // Writing to a string can't fail
+    let mut body = String::from("## Coverage report\n\n");
+    let percentage = 100.0 * result.coverage_percentage();
+    let _ = write!(body, "**{percentage:.2}%** coverage");
+    let previous = previous.filter(|x| !x.is_empty());
+    if let (MarkdownStyle::PrComment, Some(last)) = (style, previous) {
+        let change = percentage - 100.0 * last.coverage_percentage();
+        let _ = write!(body, " ({change:+.2}%)");
+    }
+    let _ = writeln!(
+        body,
+        ", {}/{} lines covered",
+        result.total_covered(),
+        result.total_coverable()
+    );
+    let mut rows = file_rows(result, previous, config);
+    if rows.is_empty() {
+        return body;
+    }
+    match style {
+        MarkdownStyle::Summary => {
+            body.push_str("\n| File | Covered | Coverable | Coverage |\n");
+            body.push_str("|---|---:|---:|---:|\n");
+            for row in &rows {
+                let _ = writeln!(
+                    body,
+                    "| `{}` | {} | {} | {:.2}% |",
+                    row.path.display(),
+                    row.covered,
+                    row.coverable,
+                    row.percentage()
+                );
+            }
+        }
+        MarkdownStyle::PrComment => {
+            // New files first then the biggest changes in either direction
+            rows.sort_by(|a, b| match (a.change, b.change) {
+                (Some(a), Some(b)) => b.abs().partial_cmp(&a.abs()).unwrap_or(Ordering::Equal),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            });
+            body.push_str("\n| File | Coverage | Change | Covered |\n");
+            body.push_str("|---|---:|---:|---:|\n");
+            for row in &rows {
+                let _ = writeln!(
+                    body,
+                    "| `{}` | {:.2}% | {} | {}/{} |",
+                    row.path.display(),
+                    row.percentage(),
+                    format_change(row.change),
+                    row.covered,
+                    row.coverable
+                );
+            }
+            for row in rows.iter().filter(|x| !x.uncovered.is_empty()) {
+                write_uncovered_lines(&mut body, &row.path, &row.uncovered);
+            }
+        }
+    }
+    body
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let previous = match config.markdown_style() {
+        MarkdownStyle::PrComment => get_previous_result(config),
+        MarkdownStyle::Summary => None,
+    };
+    let body = write_markdown(
+        coverage_data,
+        previous.as_ref(),
+        config.markdown_style(),
+        config,
+    );
+    fs::write(report_file(config, "tarpaulin-report.md", ".md"), body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    fn traces(files: &[(&str, &[(u64, u64)])]) -> TraceMap {
+        let mut traces = TraceMap::new();
+        for (file, lines) in files {
+            for (line, hits) in lines.iter() {
+                let mut trace = Trace::new_stub(*line);
+                trace.stats = CoverageStat::Line(*hits);
+                traces.add_trace(Path::new(file), trace);
+            }
+        }
+        traces
+    }
+
+    #[test]
+    fn pr_comment_shows_changes() {
+        let mut config = Config::default();
+        config.workspace_root = Some(PathBuf::from("/project"));
+        let previous = traces(&[
+            ("/project/a.rs", &[(1, 1), (2, 0)]),
+            ("/project/b.rs", &[(1, 1), (2, 1)]),
+        ]);
+        let current = traces(&[
+            ("/project/a.rs", &[(1, 1), (2, 1)]),
+            ("/project/b.rs", &[(1, 1), (2, 1)]),
+            ("/project/c.rs", &[(1, 0), (2, 0), (3, 0), (5, 1)]),
+        ]);
+
+        let summary = write_markdown(&current, Some(&previous), MarkdownStyle::Summary, &config);
+        assert_eq!(
+            summary,
+            "## Coverage report
+
+**62.50%** coverage, 5/8 lines covered
+
+| File | Covered | Coverable | Coverage |
+|---|---:|---:|---:|
+| `a.rs` | 2 | 2 | 100.00% |
+| `b.rs` | 2 | 2 | 100.00% |
+| `c.rs` | 1 | 4 | 25.00% |
+"
+        );
+
+        let comment = write_markdown(&current, Some(&previous), MarkdownStyle::PrComment, &config);
+        assert_eq!(
+            comment,
+            "## Coverage report
+
+**62.50%** coverage (-12.50%), 5/8 lines covered
+
+| File | Coverage | Change | Covered |
+|---|---:|---:|---:|
+| `c.rs` | 25.00% | new | 1/4 |
+| `a.rs` | 100.00% | +50.00% | 2/2 |
+| `b.rs` | 100.00% | +0.00% | 2/2 |
+
+<details>
+<summary><code>c.rs</code>: 3 uncovered lines</summary>
+
+1-3
+
+</details>
+"
+        );
+    }
+}
@@ -1,21 +1,42 @@
 #![allow(unreachable_patterns)] // We may want to add more warnings and keep error logs stable
 use crate::config::*;
 use crate::errors::*;
+use crate::patch::{coverage_for_patch, read_diff};
+use crate::path_utils::{canonical_path, fit_file_name, long_path, path_hash};
+use crate::run_report;
+use crate::source_analysis::BranchArmKind;
+use crate::test_impact;
 use crate::test_loader::TracerData;
 use crate::traces::*;
 use cargo_metadata::Metadata;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs::{create_dir_all, File};
 use std::io::{self, BufReader, Write};
-use tracing::{error, info};
+use std::path::PathBuf;
+use tracing::{error, info, warn};
 
+pub mod badge;
 pub mod cobertura;
+mod codecov;
 #[cfg(feature = "coveralls")]
 pub mod coveralls;
+mod features;
+pub mod gcov;
+mod github;
+mod history;
 pub mod html;
+pub mod jacoco;
 pub mod json;
 pub mod lcov;
+pub mod markdown;
+pub mod quickfix;
 mod safe_json;
+pub mod sarif;
+pub mod sonar;
+pub mod stats;
+pub mod template;
+pub(crate) mod upload;
 /// Trait for report formats to implement.
 /// Currently reports must be serializable using serde
 pub trait Report<Out: Serialize> {
@@ -23,31 +44,148 @@ pub trait Report<Out: Serialize> {
     fn export(coverage_data: &[TracerData], config: &Config);
 }
 
-fn coverage_report_name(config: &Config) -> String {
-    config
-        .get_metadata()
-        .as_ref()
-        .and_then(Metadata::root_package)
-        .map(|x| format!("{}-coverage.json", x.name))
-        .unwrap_or_else(|| "coverage.json".to_string())
+/// Number of files written between progress messages for reports on large projects
+const PROGRESS_INTERVAL: usize = 500;
+
+/// Logs how far through writing a report we are, only reports covering more than
+/// `PROGRESS_INTERVAL` files log anything
+pub(crate) fn log_progress(report: &str, written: usize, total: usize) {
+    if total > PROGRESS_INTERVAL && (written.is_multiple_of(PROGRESS_INTERVAL) || written == total)
+    {
+        info!("Writing {} report: {}/{} files", report, written, total);
+    }
+}
+
+/// Manifest listing the artifacts written by the latest run, it has a fixed name so CI can always
+/// find the reports whatever they're called
+const MANIFEST: &str = "tarpaulin-latest.json";
+
+/// Name of the run report stored in the target directory. It includes a hash of the workspace root,
+/// so workspaces sharing a target directory don't overwrite each other's reports.
+fn coverage_report_name(config: &Config, format: RunReportFormat) -> String {
+    let extension = run_report::extension(format);
+    let workspace = path_hash(&canonical_path(&config.root()));
+    let name = match config.report_name.as_ref() {
+        Some(name) => name.clone(),
+        None => match config
+            .get_metadata()
+            .as_ref()
+            .and_then(Metadata::root_package)
+        {
+            Some(package) => package.name.to_string(),
+            // A virtual workspace has no root package to name the report after
+            None => "workspace".to_string(),
+        },
+    };
+    fit_file_name(&format!("{name}-{workspace}-coverage.{extension}"))
+}
+
+/// Path of the run report stored in the target directory
+fn run_report_path(config: &Config, format: RunReportFormat) -> PathBuf {
+    long_path(
+        config
+            .target_dir()
+            .join("tarpaulin")
+            .join(coverage_report_name(config, format)),
+    )
+}
+
+/// Path of a report file in the output directory. With `--report-name` the suffix is appended to
+/// the name given, otherwise the default name is used.
+pub(crate) fn report_file(config: &Config, default: &str, suffix: &str) -> PathBuf {
+    let name = match config.report_name.as_ref() {
+        Some(name) => format!("{name}{suffix}"),
+        None => default.to_string(),
+    };
+    long_path(config.output_dir().join(fit_file_name(&name)))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    artifacts: Vec<PathBuf>,
+    /// Root of the workspace the reports were written for
+    #[serde(default)]
+    workspace: Option<PathBuf>,
+}
+
+/// Warns when requested reports would overwrite each other, or would overwrite the reports
+/// another workspace wrote to the same output directory
+fn check_report_clashes(config: &Config) {
+    if config.generate.iter().all(|x| *x == OutputFile::Stdout) {
+        return;
+    }
+    let mut written = HashSet::new();
+    for path in report_paths(config) {
+        if !written.insert(path.clone()) {
+            warn!(
+                "More than one report is written to {}, only the last one will be kept",
+                path.display()
+            );
+        }
+    }
+    let previous = File::open(config.output_dir().join(MANIFEST))
+        .ok()
+        .and_then(|x| serde_json::from_reader::<_, Manifest>(BufReader::new(x)).ok())
+        .and_then(|x| x.workspace);
+    let workspace = canonical_path(&config.root());
+    if let Some(previous) = previous.filter(|x| *x != workspace) {
+        warn!(
+            "Overwriting reports in {} written for the workspace at {}, use --report-name or --output-dir to keep them apart",
+            config.output_dir().display(),
+            previous.display()
+        );
+    }
+}
+
+/// Writes the manifest of the reports which exist after the run, nothing is written unless a
+/// report file was requested
+fn write_manifest(config: &Config) -> Result<(), RunError> {
+    if config.generate.iter().all(|x| *x == OutputFile::Stdout) {
+        return Ok(());
+    }
+    let manifest = Manifest {
+        artifacts: report_paths(config)
+            .into_iter()
+            .filter(|x| x.exists())
+            .collect(),
+        workspace: Some(canonical_path(&config.root())),
+    };
+    let file = File::create(config.output_dir().join(MANIFEST))?;
+    serde_json::to_writer_pretty(file, &manifest)?;
+    Ok(())
 }
 
 /// Reports the test coverage using the users preferred method. See config.rs
 /// or help text for details.
+/// Adds the coverage of a test run to the history the HTML report draws its trend from. This
+/// isn't part of `report_coverage` so merging or importing coverage doesn't add a run to it.
+pub(crate) fn record_history(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    if result.is_empty() {
+        Ok(())
+    } else {
+        history::record(config, result)
+    }
+}
+
 pub fn report_coverage(config: &Config, result: &TraceMap) -> Result<(), RunError> {
     if !result.is_empty() {
+        check_report_clashes(config);
         generate_requested_reports(config, result)?;
-        let mut report_dir = config.target_dir();
-        report_dir.push("tarpaulin");
+        let report_dir = config.target_dir().join("tarpaulin");
         if !report_dir.exists() {
             let _ = create_dir_all(&report_dir);
         }
-        report_dir.push(coverage_report_name(config));
-        let file = File::create(&report_dir)
-            .map_err(|_| RunError::CovReport("Failed to create run report".to_string()))?;
-        serde_json::to_writer(&file, &result)
-            .map_err(|_| RunError::CovReport("Failed to save run report".to_string()))?;
-        Ok(())
+        let report = run_report_path(config, config.run_report_format());
+        // Stored with portable paths so reports from different OSes can be merged
+        let mut portable = TraceMap::new();
+        portable.merge(result);
+        portable.make_portable(&config.root());
+        run_report::write_run_report(&report, &portable, config.run_report_format())?;
+        if config.test_impact {
+            test_impact::record(config, result)?;
+        }
+        write_manifest(config)?;
+        upload::upload_coverage(config, result)
     } else if !config.no_run {
         Err(RunError::CovReport(
             "No coverage results collected.".to_string(),
@@ -57,12 +195,48 @@ pub fn report_coverage(config: &Config, result: &TraceMap) -> Result<(), RunErro
     }
 }
 
-fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(), RunError> {
-    #[cfg(feature = "coveralls")]
-    if config.is_coveralls() {
-        coveralls::export(result, config)?;
-        info!("Coverage data sent");
+/// The files written when reporting coverage with the given config
+pub(crate) fn report_paths(config: &Config) -> Vec<PathBuf> {
+    let mut paths = config
+        .generate
+        .iter()
+        .flat_map(|x| match x {
+            OutputFile::Xml => vec![("cobertura.xml", ".xml")],
+            OutputFile::Html => vec![
+                ("tarpaulin-report.html", ".html"),
+                ("tarpaulin-index.html", "-index.html"),
+                ("tarpaulin-html", "-html"),
+            ],
+            OutputFile::Lcov => vec![("lcov.info", ".info")],
+            OutputFile::Json => vec![("tarpaulin-report.json", ".json")],
+            OutputFile::Stats => vec![
+                ("tarpaulin-stats.json", "-stats.json"),
+                ("tarpaulin-stats.csv", "-stats.csv"),
+            ],
+            OutputFile::Sarif => vec![("tarpaulin-report.sarif", ".sarif")],
+            OutputFile::Sonar => vec![("sonar-coverage.xml", "-sonar.xml")],
+            OutputFile::Jacoco => vec![("jacoco.xml", "-jacoco.xml")],
+            OutputFile::Gcov => vec![("gcov", "-gcov")],
+            OutputFile::Quickfix => vec![("tarpaulin-quickfix.txt", "-quickfix.txt")],
+            OutputFile::Badge => vec![
+                ("coverage-badge.svg", "-badge.svg"),
+                ("coverage-badge.json", "-badge.json"),
+            ],
+            OutputFile::Markdown => vec![("tarpaulin-report.md", ".md")],
+            OutputFile::Stdout | OutputFile::Template => vec![],
+        })
+        .map(|(default, suffix)| report_file(config, default, suffix))
+        .collect::<Vec<_>>();
+    if config.generate.contains(&OutputFile::Template) {
+        if let Some(template) = &config.template {
+            paths.push(template::output_path(config, template));
+        }
     }
+    paths.push(run_report_path(config, config.run_report_format()));
+    paths
+}
+
+fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(), RunError> {
     info!("Coverage Results:");
 
     if !config.is_default_output_dir() && create_dir_all(config.output_dir()).is_err() {
@@ -89,6 +263,33 @@ fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(),
             OutputFile::Json => {
                 json::export(result, config)?;
             }
+            OutputFile::Stats => {
+                stats::export(result, config)?;
+            }
+            OutputFile::Sarif => {
+                sarif::export(result, config)?;
+            }
+            OutputFile::Sonar => {
+                sonar::export(result, config)?;
+            }
+            OutputFile::Jacoco => {
+                jacoco::export(result, config)?;
+            }
+            OutputFile::Gcov => {
+                gcov::export(result, config)?;
+            }
+            OutputFile::Quickfix => {
+                quickfix::export(result, config)?;
+            }
+            OutputFile::Badge => {
+                badge::export(result, config)?;
+            }
+            OutputFile::Markdown => {
+                markdown::export(result, config)?;
+            }
+            OutputFile::Template => {
+                template::export(result, config)?;
+            }
             OutputFile::Stdout => {
                 // Already reported the missing lines
                 if !config.verbose {
@@ -102,8 +303,14 @@ fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(),
             }
         }
     }
+    if config.fail_on_uncovered_public.is_some() {
+        print_uncovered_public_functions(config, result);
+    }
     // We always want to report the short summary
     print_summary(config, result);
+    if config.open {
+        html::open(config);
+    }
     Ok(())
 }
 
@@ -113,8 +320,30 @@ fn print_missing_lines(config: &Config, result: &TraceMap) {
     } else {
         Box::new(io::stdout().lock())
     };
+    let changed_files = if config.uncovered_diff_only {
+        match read_diff(config) {
+            Some(diff) => Some(
+                coverage_for_patch(result, &diff)
+                    .files
+                    .into_iter()
+                    .filter_map(|x| x.source)
+                    .collect::<HashSet<_>>(),
+            ),
+            None => {
+                warn!("--uncovered-diff-only needs a diff from --diff-file, printing every file");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut remaining = config.max_uncovered_lines_printed;
+    let (mut hidden_lines, mut hidden_files) = (0, 0);
     writeln!(w, "|| Uncovered Lines:").unwrap();
     for (key, value) in result.iter() {
+        if changed_files.as_ref().is_some_and(|x| !x.contains(key)) {
+            continue;
+        }
         let path = config.strip_base_dir(key);
         let mut uncovered_lines = vec![];
         for v in value.iter() {
@@ -123,26 +352,119 @@ fn print_missing_lines(config: &Config, result: &TraceMap) {
             }
         }
         uncovered_lines.sort_unstable();
-        let (groups, last_group) = uncovered_lines
+        let hidden = truncate_uncovered(&mut uncovered_lines, &mut remaining);
+        if uncovered_lines.is_empty() {
+            if hidden > 0 {
+                hidden_lines += hidden;
+                hidden_files += 1;
+            }
+            continue;
+        }
+        let groups = format_line_ranges(uncovered_lines);
+        if hidden > 0 {
+            writeln!(
+                w,
+                "|| {}: {} ... and {} more",
+                path.display(),
+                groups,
+                hidden
+            )
+            .unwrap();
+        } else {
+            writeln!(w, "|| {}: {}", path.display(), groups).unwrap();
+        }
+    }
+    if hidden_files > 0 {
+        writeln!(
+            w,
+            "|| {} uncovered lines in {} more files not printed, see --max-uncovered-lines-printed",
+            hidden_lines, hidden_files
+        )
+        .unwrap();
+    }
+    if config.branch_requested {
+        print_uncovered_branches(config, result, &mut w);
+        print_uncovered_arms(config, result, &mut w);
+    }
+}
+
+fn print_uncovered_arms(config: &Config, result: &TraceMap, w: &mut impl Write) {
+    let mut header = false;
+    for file in result.files() {
+        let uncovered = result
+            .uncovered_branch_arms(file)
             .into_iter()
-            .fold((vec![], vec![]), accumulate_lines);
-        let (groups, _) = accumulate_lines((groups, last_group), u64::max_value());
-        if !groups.is_empty() {
-            writeln!(w, "|| {}: {}", path.display(), groups.join(", ")).unwrap();
+            .map(|x| match x.kind {
+                BranchArmKind::MatchArm => format!("{} (match arm)", x.line),
+                BranchArmKind::Else => format!("{} (else)", x.line),
+            })
+            .collect::<Vec<_>>();
+        if !uncovered.is_empty() {
+            if !header {
+                writeln!(w, "|| Uncovered Match Arms and Else Branches:").unwrap();
+                header = true;
+            }
+            let path = config.strip_base_dir(file);
+            writeln!(w, "|| {}: {}", path.display(), uncovered.join(", ")).unwrap();
+        }
+    }
+}
+
+fn print_uncovered_branches(config: &Config, result: &TraceMap, w: &mut impl Write) {
+    let mut header = false;
+    for (file, branches) in result.branches() {
+        let uncovered = branches
+            .iter()
+            .filter(|x| !x.stats.been_false)
+            .map(|x| format!("{}:{}", x.line, x.column))
+            .collect::<Vec<_>>();
+        if !uncovered.is_empty() {
+            if !header {
+                writeln!(w, "|| Uncovered `?` Error Paths:").unwrap();
+                header = true;
+            }
+            let path = config.strip_base_dir(file);
+            writeln!(w, "|| {}: {}", path.display(), uncovered.join(", ")).unwrap();
         }
     }
 }
 
-fn get_previous_result(config: &Config) -> Option<TraceMap> {
+fn print_uncovered_public_functions(config: &Config, result: &TraceMap) {
+    let mut w: Box<dyn Write> = if config.stderr {
+        Box::new(io::stderr().lock())
+    } else {
+        Box::new(io::stdout().lock())
+    };
+    let uncovered = result.uncovered_public_functions();
+    writeln!(w, "|| Uncovered Public Functions: {}", uncovered.len()).unwrap();
+    for (file, function) in uncovered {
+        let path = config.strip_base_dir(file);
+        writeln!(
+            w,
+            "|| {}:{}: {}",
+            path.display(),
+            function.start,
+            function.name
+        )
+        .unwrap();
+    }
+}
+
+pub(crate) fn get_previous_result(config: &Config) -> Option<TraceMap> {
     // Check for previous report
     let mut report_dir = config.target_dir();
     report_dir.push("tarpaulin");
     if report_dir.exists() {
-        // is report there?
-        report_dir.push(coverage_report_name(config));
-        let file = File::open(&report_dir).ok()?;
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader).ok()
+        // The format may have changed since the last run so the newest report in either is used
+        let report = [RunReportFormat::Json, RunReportFormat::Compact]
+            .iter()
+            .map(|format| run_report_path(config, *format))
+            .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+            .max()?
+            .1;
+        let mut result = run_report::read_run_report(&report).ok()?;
+        result.make_native(&config.root());
+        Some(result)
     } else {
         // make directory
         create_dir_all(&report_dir)
@@ -216,30 +538,312 @@ fn print_summary(config: &Config, result: &TraceMap) {
         )
         .unwrap();
     }
-}
-
-fn accumulate_lines(
-    (mut acc, mut group): (Vec<String>, Vec<u64>),
-    next: u64,
-) -> (Vec<String>, Vec<u64>) {
-    if let Some(last) = group.last().cloned() {
-        if next == last + 1 {
-            group.push(next);
-            (acc, group)
-        } else {
-            match (group.first(), group.last()) {
-                (Some(first), Some(last)) if first == last => {
-                    acc.push(format!("{first}"));
-                }
-                (Some(first), Some(last)) => {
-                    acc.push(format!("{first}-{last}"));
-                }
-                (Some(_), None) | (None, _) => (),
+    if result.is_sampled() {
+        writeln!(
+            w,
+            "|| Coverage was sampled with perf, lines which ran may be reported as uncovered"
+        )
+        .unwrap();
+    }
+    if let Some(examples) = result.doc_example_stats() {
+        writeln!(
+            w,
+            "|| Doc examples: {}/{} ran, {} no_run or compile_fail, {} ignored and not counted",
+            examples.run,
+            examples.run + examples.no_run,
+            examples.no_run,
+            examples.ignored
+        )
+        .unwrap();
+    }
+    if let Some(stats) = result.unsafe_coverage() {
+        writeln!(
+            w,
+            "|| Unsafe code: {}/{} lines covered ({:.2}%)",
+            stats.covered, stats.coverable, stats.coverage
+        )
+        .unwrap();
+    }
+    let ffi = result.ffi_functions();
+    if !ffi.is_empty() {
+        let uncovered = ffi.iter().filter(|x| !x.covered).collect::<Vec<_>>();
+        writeln!(
+            w,
+            "|| FFI functions: {}/{} covered",
+            ffi.len() - uncovered.len(),
+            ffi.len()
+        )
+        .unwrap();
+        for function in uncovered {
+            let path = config.strip_base_dir(&function.path);
+            writeln!(
+                w,
+                "|| Uncovered {}:{}: {}",
+                path.display(),
+                function.line,
+                function.name
+            )
+            .unwrap();
+        }
+    }
+    let errors = config
+        .error_path_coverage
+        .then(|| result.error_path_coverage())
+        .flatten();
+    if let Some(errors) = errors {
+        writeln!(
+            w,
+            "|| Error paths: {}/{} Err paths and {}/{} panics ran ({:.2}%)",
+            errors.error_paths_hit,
+            errors.error_paths,
+            errors.panics_hit,
+            errors.panics,
+            errors.coverage
+        )
+        .unwrap();
+    }
+    let run_types = result.run_type_coverage();
+    if run_types.len() > 1 {
+        writeln!(w, "|| Lines covered by kind of test:").unwrap();
+        for stats in &run_types {
+            writeln!(
+                w,
+                "|| {}: {} lines ({:.2}%), {} not covered by other tests",
+                run_type_label(stats.run_type),
+                stats.covered,
+                stats.coverage,
+                stats.unique
+            )
+            .unwrap();
+        }
+    }
+    let (features, unexercised) = features::feature_summary(config, result);
+    if !features.is_empty() {
+        writeln!(w, "|| Lines covered by feature:").unwrap();
+        for stats in &features {
+            writeln!(
+                w,
+                "|| {}: {}/{} ({:.2}%)",
+                stats.feature, stats.covered, stats.coverable, stats.coverage
+            )
+            .unwrap();
+        }
+    }
+    if !unexercised.is_empty() {
+        writeln!(
+            w,
+            "|| Enabled features with no exercised code: {}",
+            unexercised.join(", ")
+        )
+        .unwrap();
+    }
+    let mut aliases = result.aliases().peekable();
+    if aliases.peek().is_some() {
+        let packages = stats::workspace_packages(config);
+        writeln!(w, "|| Files shared between packages, counted once:").unwrap();
+        for (file, paths) in aliases {
+            let paths = paths
+                .iter()
+                .map(|x| config.strip_base_dir(x).display().to_string())
+                .collect::<Vec<_>>();
+            writeln!(
+                w,
+                "|| {}: attributed to {}, also reached as {}",
+                config.strip_base_dir(file).display(),
+                stats::owning_package(&packages, file),
+                paths.join(", ")
+            )
+            .unwrap();
+        }
+    }
+    let overheads = result.binary_overheads();
+    if config.show_overhead && !overheads.is_empty() {
+        writeln!(w, "|| Coverage overhead by test binary:").unwrap();
+        for overhead in &overheads {
+            let size = match overhead.size {
+                Some(size) => format!(
+                    "{} instrumentation and {} debug info of {}",
+                    format_size(size.instrumentation),
+                    format_size(size.debug_info),
+                    format_size(size.total),
+                ),
+                None => "size unknown".to_string(),
             };
-            (acc, vec![next])
+            // The slowdown of the tests themselves from instrumentation or breakpoints can't be
+            // told apart from the test runtime, so only the collection after the run is timed
+            writeln!(
+                w,
+                "|| {}: {}, {:.2}s of {:.2}s wall time ({:.1}%) collecting coverage",
+                config.strip_base_dir(&overhead.binary).display(),
+                size,
+                overhead.collection,
+                overhead.duration,
+                overhead.collection_percentage()
+            )
+            .unwrap();
         }
+    }
+    let configs = result.config_summaries();
+    if configs.len() > 1 {
+        writeln!(w, "|| Time by config:").unwrap();
+        writeln!(
+            w,
+            "|| {:<20} {:>9} {:>9} {:>11} {:>9} {:>9}",
+            "Config", "Build", "Test", "Collection", "Total", "Coverage"
+        )
+        .unwrap();
+        for summary in configs {
+            writeln!(
+                w,
+                "|| {:<20} {:>8.2}s {:>8.2}s {:>10.2}s {:>8.2}s {:>8.2}%",
+                summary.name,
+                summary.build,
+                summary.test,
+                summary.collection,
+                summary.total(),
+                summary.coverage
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Formats a number of bytes using the largest unit it's at least one of
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
     } else {
-        group.push(next);
-        (acc, group)
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+fn run_type_label(run_type: RunType) -> &'static str {
+    match run_type {
+        RunType::Lib => "library unit tests",
+        RunType::Bins => "binary unit tests",
+        RunType::Tests | RunType::AllTargets => "integration tests",
+        RunType::Doctests => "doctests",
+        RunType::Examples => "examples",
+        RunType::Benchmarks => "benchmarks",
+    }
+}
+
+/// Keeps as many of the uncovered lines as there are `remaining` to print, taking them from the
+/// remaining count. Returns how many lines were dropped.
+fn truncate_uncovered(lines: &mut Vec<u64>, remaining: &mut Option<usize>) -> usize {
+    let total = lines.len();
+    if let Some(remaining) = remaining.as_mut() {
+        lines.truncate(*remaining);
+        *remaining -= lines.len();
+    }
+    total - lines.len()
+}
+
+/// Groups sorted line numbers into inclusive ranges of consecutive lines
+pub(crate) fn line_ranges(lines: impl IntoIterator<Item = u64>) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+}
+
+/// Sorted line numbers as ranges of consecutive lines, e.g. `"1-3, 5"`
+pub(crate) fn format_line_ranges(lines: impl IntoIterator<Item = u64>) -> String {
+    line_ranges(lines)
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_formatted() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(1536), "1.5KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0MiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024 * 1024), "5120.0GiB");
+    }
+
+    #[test]
+    fn uncovered_lines_limited() {
+        let mut remaining = Some(5);
+        let mut lines = vec![1, 2, 3];
+        assert_eq!(truncate_uncovered(&mut lines, &mut remaining), 0);
+        let mut lines = vec![4, 5, 6, 7];
+        assert_eq!(truncate_uncovered(&mut lines, &mut remaining), 2);
+        assert_eq!(lines, vec![4, 5]);
+        let mut lines = vec![8];
+        assert_eq!(truncate_uncovered(&mut lines, &mut remaining), 1);
+        assert!(lines.is_empty());
+
+        let mut lines = vec![1, 2];
+        assert_eq!(truncate_uncovered(&mut lines, &mut None), 0);
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn consecutive_lines_grouped() {
+        assert_eq!(line_ranges(vec![]), vec![]);
+        assert_eq!(
+            line_ranges(vec![1, 2, 3, 5, 7, 8]),
+            vec![(1, 3), (5, 5), (7, 8)]
+        );
+        assert_eq!(format_line_ranges(vec![1, 2, 3, 5, 7, 8]), "1-3, 5, 7-8");
+    }
+
+    #[test]
+    fn report_name_used_for_every_file() {
+        let mut config = Config::default();
+        config.output_directory = Some(std::env::temp_dir());
+        config.generate = vec![OutputFile::Xml, OutputFile::Lcov, OutputFile::Stats];
+        let names = |config: &Config| {
+            report_paths(config)
+                .iter()
+                .map(|x| x.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            &names(&config)[..4],
+            [
+                "cobertura.xml",
+                "lcov.info",
+                "tarpaulin-stats.json",
+                "tarpaulin-stats.csv"
+            ]
+        );
+
+        config.report_name = Some("nightly".to_string());
+        let workspace = path_hash(&canonical_path(&config.root()));
+        assert_eq!(
+            names(&config),
+            [
+                "nightly.xml".to_string(),
+                "nightly.info".to_string(),
+                "nightly-stats.json".to_string(),
+                "nightly-stats.csv".to_string(),
+                format!("nightly-{workspace}-coverage.json")
+            ]
+        );
     }
 }
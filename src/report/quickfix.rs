@@ -0,0 +1,125 @@
+//! Uncovered lines as a quickfix list, one `file:line: uncovered` entry per line, which Vim reads
+//! with `:cfile` and Emacs with `compilation-mode` so the uncovered lines can be jumped through in
+//! the editor. Paths are relative to the project root. With `--uncovered-diff-only` and a diff
+//! from `--diff-file` only the uncovered lines the diff adds are listed.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::patch::{coverage_for_patch, read_diff};
+use crate::report::report_file;
+use crate::traces::{amount_covered, TraceMap};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Uncovered lines of each file, a line is uncovered if none of its traces were hit
+pub(crate) fn uncovered_lines(coverage_data: &TraceMap) -> BTreeMap<PathBuf, Vec<u64>> {
+    coverage_data
+        .iter()
+        .map(|(path, traces)| {
+            let mut lines = BTreeMap::<u64, bool>::new();
+            for trace in traces {
+                *lines.entry(trace.line).or_default() |= amount_covered(std::iter::once(trace)) > 0;
+            }
+            let uncovered = lines
+                .into_iter()
+                .filter(|(_, covered)| !covered)
+                .map(|(line, _)| line)
+                .collect();
+            (path.clone(), uncovered)
+        })
+        .collect()
+}
+
+/// Uncovered lines added by the diff in each file
+fn uncovered_diff_lines(coverage_data: &TraceMap, diff: &str) -> BTreeMap<PathBuf, Vec<u64>> {
+    let mut result = BTreeMap::<PathBuf, Vec<u64>>::new();
+    for file in coverage_for_patch(coverage_data, diff).files {
+        if let Some(source) = file.source {
+            let lines = result.entry(source).or_default();
+            lines.extend(file.hunks.into_iter().flat_map(|x| x.uncovered));
+            lines.sort_unstable();
+            lines.dedup();
+        }
+    }
+    result
+}
+
+fn write_quickfix(
+    mut file: impl Write,
+    uncovered: &BTreeMap<PathBuf, Vec<u64>>,
+    config: &Config,
+) -> Result<(), RunError> {
+    for (path, lines) in uncovered {
+        let path = config.strip_base_dir(path);
+        for line in lines {
+            writeln!(file, "{}:{}: uncovered", path.display(), line)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let diff = if config.uncovered_diff_only {
+        let diff = read_diff(config);
+        if diff.is_none() {
+            warn!("--uncovered-diff-only needs a diff from --diff-file, listing every file");
+        }
+        diff
+    } else {
+        None
+    };
+    let uncovered = match diff {
+        Some(diff) => uncovered_diff_lines(coverage_data, &diff),
+        None => uncovered_lines(coverage_data),
+    };
+    let file = File::create(report_file(
+        config,
+        "tarpaulin-quickfix.txt",
+        "-quickfix.txt",
+    ))?;
+    let mut file = BufWriter::new(file);
+    write_quickfix(&mut file, &uncovered, config)?;
+    file.flush().map_err(RunError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+    use std::path::Path;
+
+    #[test]
+    fn uncovered_lines_listed() {
+        let mut config = Config::default();
+        config.workspace_root = Some(PathBuf::from("/project"));
+        let file = Path::new("/project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 1), (2, 0), (3, 0), (3, 1), (4, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(file, trace);
+        }
+
+        let mut data = vec![];
+        write_quickfix(&mut data, &uncovered_lines(&traces), &config).unwrap();
+        let expected = format!(
+            "{0}:2: uncovered\n{0}:4: uncovered\n",
+            Path::new("src").join("lib.rs").display()
+        );
+        assert_eq!(String::from_utf8(data).unwrap(), expected);
+
+        let diff = "--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,2 +1,3 @@
+ fn foo() {
++    bar();
+ }
+";
+        assert_eq!(
+            uncovered_diff_lines(&traces, diff),
+            BTreeMap::from([(file.to_path_buf(), vec![2])])
+        );
+    }
+}
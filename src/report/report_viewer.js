@@ -129,12 +129,14 @@ class App extends React.Component {
       });
     }
 
-    return e('div', {className: 'app'}, w);
+    const trend = path.length === 1 ? e(TrendChart, {history: this.props.history}) : null;
+
+    return e('div', {className: 'app'}, trend, w, e(ToolchainInfo, {toolchain: this.props.toolchain, sampled: this.props.sampled}));
   }
 
-  selectFile(file) {
+  selectFile(path) {
     this.setState(({current}) => {
-      return {current: [...current, file.path[0]]};
+      return {current: [...current, ...path]};
     }, () => this.updateHash());
   }
 
@@ -153,25 +155,127 @@ class App extends React.Component {
   }
 }
 
-function FilesList({folder, onSelectFile, onBack}) {
-  let files = folder.children;
-  return e('div', {className: 'display-folder'},
-    e(FileHeader, {file: folder, onBack}),
-    e('table', {className: 'files-list'},
-      e('thead', {className: 'files-list__head'},
-        e('tr', null,
-          e('th', null, "Path"),
-          e('th', null, "Coverage")
-        )
-      ),
-      e('tbody', {className: 'files-list__body'},
-        files.map(file => e(File, {file, onClick: onSelectFile}))
-      )
+function TrendChart({history}) {
+  if (!history || history.length < 2) {
+    return null;
+  }
+  const width = 600;
+  const height = 120;
+  const padding = 5;
+  const percentages = history.map(run => run.coverable ? 100 * run.covered / run.coverable : 0);
+  const min = Math.floor(Math.min(...percentages));
+  const max = Math.ceil(Math.max(...percentages));
+  const x = i => padding + i * (width - 2 * padding) / (history.length - 1);
+  const y = p => height - padding - (p - min) * (height - 2 * padding) / Math.max(max - min, 1);
+  const points = percentages.map((p, i) => `${x(i)},${y(p)}`).join(' ');
+
+  return e('div', {className: 'trend'},
+    e('div', {className: 'trend__title'},
+      `Coverage of the last ${history.length} runs, between ${min}% and ${max}%`),
+    e('svg', {className: 'trend__chart', viewBox: `0 0 ${width} ${height}`},
+      e('polyline', {className: 'trend__line', points}),
+      percentages.map((p, i) => e('circle', {key: i, className: 'trend__point', cx: x(i), cy: y(p), r: 3},
+        e('title', null, `${new Date(history[i].timestamp * 1000).toLocaleString()}: ${p.toFixed(2)}%`)
+      ))
     )
   );
 }
 
-function File({file, onClick}) {
+function ToolchainInfo({toolchain, sampled}) {
+  if (!toolchain) {
+    return null;
+  }
+  const versions = [
+    `tarpaulin ${toolchain.tarpaulin_version}`,
+    toolchain.rustc_version,
+    toolchain.llvm_version && `LLVM ${toolchain.llvm_version}`,
+    `${toolchain.engine} engine`,
+    sampled && 'sampled coverage, lines which ran may be missed',
+  ].filter(Boolean).join(', ');
+  return e('div', {className: 'toolchain', title: `RUSTFLAGS="${toolchain.rustflags}"`}, versions);
+}
+
+// Whether the characters of the query appear in the text in order, ignoring case
+function fuzzyMatch(text, query) {
+  text = text.toLowerCase();
+  let i = 0;
+  for (const c of query.toLowerCase()) {
+    i = text.indexOf(c, i);
+    if (i < 0) {
+      return false;
+    }
+    i += 1;
+  }
+  return true;
+}
+
+class FilesList extends React.Component {
+  constructor(...args) {
+    super(...args);
+
+    this.state = {
+      query: '',
+      expanded: new Set(),
+    };
+  }
+
+  toggle(key) {
+    this.setState(({expanded}) => {
+      const next = new Set(expanded);
+      next.has(key) ? next.delete(key) : next.add(key);
+      return {expanded: next};
+    });
+  }
+
+  // Rows of the tree under the folders, while searching only the matching files and the
+  // folders containing them are shown
+  rows(nodes, parents, depth) {
+    const {query, expanded} = this.state;
+    return nodes.flatMap(node => {
+      const path = [...parents, ...node.path];
+      const key = path.join('/');
+      if (!node.is_folder) {
+        if (query && !fuzzyMatch(key, query)) {
+          return [];
+        }
+        return [e(File, {key, file: node, depth, onClick: () => this.props.onSelectFile(path)})];
+      }
+      const open = Boolean(query) || expanded.has(key);
+      const children = open ? this.rows(node.children, path, depth + 1) : [];
+      if (query && !children.length) {
+        return [];
+      }
+      return [e(File, {key, file: node, depth, open, onClick: () => this.toggle(key)}), ...children];
+    });
+  }
+
+  render() {
+    const {folder, onBack} = this.props;
+    return e('div', {className: 'display-folder'},
+      e(FileHeader, {file: folder, onBack}),
+      e('input', {
+        className: 'files-search',
+        type: 'search',
+        placeholder: 'Search files',
+        value: this.state.query,
+        onChange: event => this.setState({query: event.target.value}),
+      }),
+      e('table', {className: 'files-list'},
+        e('thead', {className: 'files-list__head'},
+          e('tr', null,
+            e('th', null, "Path"),
+            e('th', null, "Coverage")
+          )
+        ),
+        e('tbody', {className: 'files-list__body'},
+          this.rows(folder.children, [], 0)
+        )
+      )
+    );
+  }
+}
+
+function File({file, depth, open, onClick}) {
   const coverage = file.coverable ? file.covered / file.coverable * 100 : -1;
   const coverageDelta = file.prevRun &&
     (file.covered / file.coverable * 100 - file.prevRun.covered / file.prevRun.coverable * 100);
@@ -181,10 +285,11 @@ function File({file, onClick}) {
         + (coverage >= 0 && coverage < 50 ? ' files-list__file_low': '')
         + (coverage >= 50 && coverage < 80 ? ' files-list__file_medium': '')
         + (coverage >= 80 ? ' files-list__file_high': '')
-        + (file.is_folder ? ' files-list__file_folder': ''),
-      onClick: () => onClick(file),
+        + (file.is_folder ? ' files-list__file_folder': '')
+        + (open ? ' files-list__file_open': ''),
+      onClick,
     },
-    e('td', null, e('a', null, pathToString(file.path))),
+    e('td', {style: {paddingLeft: (10 + 20 * depth) + 'px'}}, e('a', null, pathToString(file.path))),
     e('td', null,
       file.covered + ' / ' + file.coverable +
       (coverage >= 0 ? ' (' + coverage.toFixed(2) + '%)' : ''),
@@ -194,10 +299,92 @@ function File({file, onClick}) {
   );
 }
 
-function DisplayFile({file, onBack}) {
-  return e('div', {className: 'display-file'},
-    e(FileHeader, {file, onBack}),
-    e(FileContent, {file})
+class DisplayFile extends React.Component {
+  constructor(...args) {
+    super(...args);
+
+    this.state = {
+      tab: 'source',
+      line: null,
+    };
+  }
+
+  componentDidUpdate() {
+    if (this.state.tab === 'source' && this.state.line) {
+      const line = document.getElementById('line-' + this.state.line);
+      line && line.scrollIntoView({block: 'center'});
+      this.setState({line: null});
+    }
+  }
+
+  render() {
+    const {file, onBack} = this.props;
+    const functions = file.functions || [];
+    const showFunctions = this.state.tab === 'functions' && functions.length;
+
+    return e('div', {className: 'display-file'},
+      e(FileHeader, {file, onBack}),
+      functions.length ? e(FileTabs, {tab: this.state.tab, onSelect: tab => this.setState({tab})}) : null,
+      showFunctions ? e(FunctionsList, {functions, onJump: line => this.setState({tab: 'source', line})}) : null,
+      showFunctions ? null : e(UncoveredArms, {file}),
+      showFunctions ? null : e(FileContent, {file})
+    );
+  }
+}
+
+function FileTabs({tab, onSelect}) {
+  const item = (name, label) => e('a', {
+      className: 'file-tabs__tab' + (tab === name ? ' file-tabs__tab_active' : ''),
+      onClick: () => onSelect(name),
+    }, label);
+  return e('div', {className: 'file-tabs'}, item('source', 'Source'), item('functions', 'Functions'));
+}
+
+function FunctionsList({functions, onJump}) {
+  return e('table', {className: 'files-list'},
+    e('thead', {className: 'files-list__head'},
+      e('tr', null,
+        e('th', null, "Function"),
+        e('th', null, "Lines"),
+        e('th', null, "Coverage")
+      )
+    ),
+    e('tbody', {className: 'files-list__body'},
+      functions.map((func, i) => {
+        const coverage = func.coverable ? func.covered / func.coverable * 100 : -1;
+        return e('tr', {
+            key: i,
+            className: 'functions-list__function'
+              + (coverage >= 0 && coverage < 50 ? ' files-list__file_low': '')
+              + (coverage >= 50 && coverage < 80 ? ' files-list__file_medium': '')
+              + (coverage >= 80 ? ' files-list__file_high': ''),
+            title: 'Jump to line ' + func.start,
+            onClick: () => onJump(func.start),
+          },
+          e('td', null, e('a', null, func.name)),
+          e('td', null, func.start + '-' + func.end),
+          e('td', null,
+            func.covered + ' / ' + func.coverable +
+            (coverage >= 0 ? ' (' + coverage.toFixed(2) + '%)' : ''))
+        );
+      })
+    )
+  );
+}
+
+function armLabel(arm) {
+  return arm.kind === 'Else' ? 'else' : 'match arm';
+}
+
+function UncoveredArms({file}) {
+  if (!file.uncovered_arms || !file.uncovered_arms.length) {
+    return null;
+  }
+  return e('div', {className: 'uncovered-arms'},
+    'Uncovered branches: ',
+    file.uncovered_arms.map((arm, i) => e('span', {key: i},
+      (i ? ', ' : '') + `line ${arm.line} (${armLabel(arm)})`
+    ))
   );
 }
 
@@ -217,17 +404,32 @@ function FileHeader({file, onBack}) {
   );
 }
 
+function traceTitle(trace) {
+  const stats = JSON.stringify(trace.stats, null, 2);
+  return trace.hit_by && trace.hit_by.length
+    ? `${stats}\nHit by: ${trace.hit_by.join(', ')}`
+    : stats;
+}
+
 function FileContent({file}) {
+  const arms = file.uncovered_arms || [];
   return e('pre', {className: 'file-content'},
     file.content.split(/\r?\n/).map((line, index) => {
       const trace = file.traces.find(trace => trace.line === index + 1);
+      const arm = arms.find(arm => arm.line === index + 1);
       const covered = trace && trace.stats.Line;
       const uncovered = trace && !trace.stats.Line;
+      let title = trace ? traceTitle(trace) : null;
+      if (arm) {
+        title = `Uncovered ${armLabel(arm)}` + (title ? `\n${title}` : '');
+      }
       return e('code', {
+          id: 'line-' + (index + 1),
           className: 'code-line'
             + (covered ? ' code-line_covered' : '')
-            + (uncovered ? ' code-line_uncovered' : ''),
-          title: trace ? JSON.stringify(trace.stats, null, 2) : null,
+            + (uncovered ? ' code-line_uncovered' : '')
+            + (arm ? ' code-line_uncovered-arm' : ''),
+          title,
         }, line);
     })
   );
@@ -268,5 +470,5 @@ function FileContent({file}) {
     }
   };
 
-  ReactDOM.render(e(App, {root, prevFilesMap}), document.getElementById('root'));
+  ReactDOM.render(e(App, {root, prevFilesMap, toolchain: data.toolchain, sampled: data.sampled, history: data.history}), document.getElementById('root'));
 }());
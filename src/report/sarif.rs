@@ -0,0 +1,269 @@
+//! SARIF report of uncovered lines, each run of consecutive uncovered lines is a note level result
+//! so GitHub code scanning or reviewdog can annotate them on a pull request. With `--diff-file`
+//! only the uncovered lines the diff changes are included.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::patch::{coverage_for_patch, read_diff};
+use crate::path_utils::portable_path;
+use crate::report::{line_ranges, report_file};
+use crate::traces::{amount_covered, TraceMap};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const RULE_ID: &str = "uncovered-line";
+const MESSAGE: &str = "Line isn't covered by tests";
+
+#[derive(Serialize)]
+struct Sarif {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    information_uri: &'static str,
+    version: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    short_description: Message,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation {
+    uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri_base_id: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u64,
+    end_line: u64,
+}
+
+/// Every uncovered line in each file
+fn uncovered_lines(coverage_data: &TraceMap) -> BTreeMap<PathBuf, Vec<u64>> {
+    coverage_data
+        .iter()
+        .map(|(path, traces)| {
+            let mut lines = BTreeMap::<u64, bool>::new();
+            for trace in traces {
+                *lines.entry(trace.line).or_default() |= amount_covered(std::iter::once(trace)) > 0;
+            }
+            let uncovered = lines
+                .into_iter()
+                .filter(|(_, covered)| !covered)
+                .map(|(line, _)| line)
+                .collect();
+            (path.clone(), uncovered)
+        })
+        .collect()
+}
+
+/// The uncovered lines changed by the diff in each file
+fn uncovered_changed_lines(coverage_data: &TraceMap, diff: &str) -> BTreeMap<PathBuf, Vec<u64>> {
+    let mut lines = BTreeMap::<PathBuf, Vec<u64>>::new();
+    for file in coverage_for_patch(coverage_data, diff).files {
+        if let Some(source) = file.source {
+            let uncovered = lines.entry(source).or_default();
+            uncovered.extend(file.hunks.into_iter().flat_map(|x| x.uncovered));
+        }
+    }
+    lines
+}
+
+/// Percent encodes a path with `/` separators for use in a URI
+fn encode_uri_path(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~:@".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+/// The artifact URI of a source file, relative to `%SRCROOT%` when it's in the root and an
+/// absolute `file:///` URI otherwise
+fn artifact_uri(path: &Path, root: &Path) -> (String, Option<&'static str>) {
+    let portable = portable_path(path, root);
+    if portable.is_relative() {
+        let uri = encode_uri_path(&portable.to_string_lossy());
+        return (uri, Some("%SRCROOT%"));
+    }
+    let mut absolute = portable.to_string_lossy().to_string();
+    if cfg!(windows) {
+        absolute = absolute.replace('\\', "/");
+    }
+    if !absolute.starts_with('/') {
+        // Windows drive letters, `C:/src` is `file:///C:/src`
+        absolute.insert(0, '/');
+    }
+    (format!("file://{}", encode_uri_path(&absolute)), None)
+}
+
+fn result(path: &Path, (start_line, end_line): (u64, u64), root: &Path) -> SarifResult {
+    let (uri, uri_base_id) = artifact_uri(path, root);
+    SarifResult {
+        rule_id: RULE_ID,
+        level: "note",
+        message: Message { text: MESSAGE },
+        locations: vec![Location {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation { uri, uri_base_id },
+                region: Region {
+                    start_line,
+                    end_line,
+                },
+            },
+        }],
+    }
+}
+
+fn sarif(lines: &BTreeMap<PathBuf, Vec<u64>>, root: &Path) -> Sarif {
+    let results = lines
+        .iter()
+        .flat_map(|(path, lines)| {
+            line_ranges(lines.iter().copied())
+                .into_iter()
+                .map(move |range| result(path, range, root))
+        })
+        .collect();
+    Sarif {
+        schema: SCHEMA,
+        version: "2.1.0",
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "cargo-tarpaulin",
+                    information_uri: "https://github.com/xd009642/tarpaulin",
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: vec![Rule {
+                        id: RULE_ID,
+                        short_description: Message { text: MESSAGE },
+                    }],
+                },
+            },
+            results,
+        }],
+    }
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let lines = match read_diff(config) {
+        Some(diff) => uncovered_changed_lines(coverage_data, &diff),
+        None => uncovered_lines(coverage_data),
+    };
+    let file = File::create(report_file(config, "tarpaulin-report.sarif", ".sarif"))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &sarif(&lines, &config.root()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    #[test]
+    fn uncovered_changed_lines_are_notes() {
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 1), (2, 0), (3, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(Path::new("/project/src/lib.rs"), trace);
+        }
+        assert_eq!(
+            uncovered_lines(&traces)[Path::new("/project/src/lib.rs")],
+            vec![2, 3]
+        );
+
+        let diff = "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,3 @@\n a\n+b\n c\n";
+        let lines = uncovered_changed_lines(&traces, diff);
+        assert_eq!(lines[Path::new("/project/src/lib.rs")], vec![2]);
+
+        let report = serde_json::to_value(sarif(&lines, Path::new("/project"))).unwrap();
+        let result = &report["runs"][0]["results"][0];
+        assert_eq!(result["level"], "note");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/lib.rs");
+        assert_eq!(location["region"]["startLine"], 2);
+        assert_eq!(location["region"]["endLine"], 2);
+
+        let lines = uncovered_lines(&traces);
+        let report = serde_json::to_value(sarif(&lines, Path::new("/project"))).unwrap();
+        let results = report["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        let region = &results[0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 2);
+        assert_eq!(region["endLine"], 3);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn artifact_uris() {
+        let root = Path::new("/project");
+        assert_eq!(
+            artifact_uri(Path::new("/project/src/my mod.rs"), root),
+            ("src/my%20mod.rs".to_string(), Some("%SRCROOT%"))
+        );
+        assert_eq!(
+            artifact_uri(Path::new("/registry/dep-1.0/src/lib.rs"), root),
+            ("file:///registry/dep-1.0/src/lib.rs".to_string(), None)
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! SonarQube's generic test coverage format, so the report can be passed straight to
+//! sonar-scanner with `sonar.coverageReportPaths`. The structure is:
+//! ```xml
+//! <coverage version="1">
+//!   <file path="src/lib.rs">
+//!     <lineToCover lineNumber="6" covered="true"/>
+//!     <lineToCover lineNumber="7" covered="false" branchesToCover="2" coveredBranches="1"/>
+//!   </file>
+//! </coverage>
+//! ```
+//! Paths are relative to the project root, which sonar-scanner resolves them against.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::portable_path;
+use crate::report::report_file;
+use crate::traces::{amount_covered, Trace, TraceMap};
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, Event},
+    Writer,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Default)]
+struct LineToCover {
+    covered: bool,
+    branches: usize,
+    covered_branches: usize,
+}
+
+/// Coverage of each coverable line in the file, a line is covered if any of its traces are
+fn file_lines(
+    coverage_data: &TraceMap,
+    path: &Path,
+    traces: &[Trace],
+) -> BTreeMap<u64, LineToCover> {
+    let mut lines = BTreeMap::<u64, LineToCover>::new();
+    for trace in traces {
+        lines.entry(trace.line).or_default().covered |= amount_covered(std::iter::once(trace)) > 0;
+    }
+    let branches = coverage_data
+        .branches()
+        .find(|(file, _)| file.as_path() == path)
+        .map(|(_, branches)| branches.as_slice())
+        .unwrap_or_default();
+    for branch in branches {
+        let line = lines.entry(branch.line).or_default();
+        line.branches += 2;
+        line.covered_branches +=
+            usize::from(branch.stats.been_true) + usize::from(branch.stats.been_false);
+    }
+    lines
+}
+
+fn write_sonar(file: impl Write, coverage_data: &TraceMap, root: &Path) -> Result<(), RunError> {
+    let mut writer = Writer::new_with_indent(file, b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    let mut coverage = BytesStart::new("coverage");
+    coverage.push_attribute(("version", "1"));
+    writer.write_event(Event::Start(coverage))?;
+    for (path, traces) in coverage_data.iter() {
+        if traces.is_empty() {
+            continue;
+        }
+        let mut file = BytesStart::new("file");
+        file.push_attribute(("path", portable_path(path, root).to_string_lossy().as_ref()));
+        writer.write_event(Event::Start(file))?;
+        for (number, line) in file_lines(coverage_data, path, traces) {
+            let mut element = BytesStart::new("lineToCover");
+            element.push_attribute(("lineNumber", number.to_string().as_ref()));
+            element.push_attribute(("covered", line.covered.to_string().as_ref()));
+            if line.branches > 0 {
+                element.push_attribute(("branchesToCover", line.branches.to_string().as_ref()));
+                element.push_attribute((
+                    "coveredBranches",
+                    line.covered_branches.to_string().as_ref(),
+                ));
+            }
+            writer.write_event(Event::Empty(element))?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("file")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("coverage")))?;
+    Ok(())
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file = File::create(report_file(config, "sonar-coverage.xml", "-sonar.xml"))?;
+    let mut file = BufWriter::new(file);
+    write_sonar(&mut file, coverage_data, &config.root())?;
+    file.flush().map_err(RunError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{BranchTrace, CoverageStat, LogicState};
+
+    #[test]
+    fn lines_and_branches_written() {
+        let root = Path::new("/project");
+        let file = root.join("src").join("lib.rs");
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 2), (2, 0), (2, 1), (3, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(&file, trace);
+        }
+        traces.add_branch(
+            &file,
+            BranchTrace {
+                line: 3,
+                column: 10,
+                stats: LogicState {
+                    been_true: true,
+                    been_false: false,
+                },
+            },
+        );
+
+        let mut data = vec![];
+        write_sonar(&mut data, &traces, root).unwrap();
+        let xml = String::from_utf8(data).unwrap();
+        let expected = r#"<coverage version="1">
+  <file path="src/lib.rs">
+    <lineToCover lineNumber="1" covered="true"/>
+    <lineToCover lineNumber="2" covered="true"/>
+    <lineToCover lineNumber="3" covered="false" branchesToCover="2" coveredBranches="1"/>
+  </file>
+</coverage>"#;
+        assert!(xml.ends_with(expected), "{}", xml);
+    }
+}
@@ -0,0 +1,224 @@
+//! Aggregate statistics for each crate in the workspace. For large workspaces this gives a single
+//! artifact to track coverage, test counts and durations across every crate. With `--test-impact`
+//! the tests which passed without running any code outside of tests are listed under their crate,
+//! these can't fail whatever the code they're meant to test does.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::report::report_file;
+use crate::traces::{amount_coverable, amount_covered, TraceMap};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name used for files which don't belong to any workspace package
+const UNKNOWN_CRATE: &str = "<unknown>";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CrateStats {
+    pub name: String,
+    pub files: usize,
+    pub coverable: usize,
+    pub covered: usize,
+    /// Percentage coverage ranging from 0-100
+    pub coverage: f64,
+    pub test_binaries: usize,
+    /// Time spent running the crates tests in seconds
+    pub duration: f64,
+    /// Tests which passed without running a line outside of test code
+    pub tautological_tests: Vec<String>,
+}
+
+/// Names and directories of the workspace packages, nested packages come before their parents
+pub(crate) fn workspace_packages(config: &Config) -> Vec<(String, PathBuf)> {
+    let mut packages = config
+        .get_metadata()
+        .as_ref()
+        .map(|meta| {
+            meta.workspace_packages()
+                .into_iter()
+                .filter_map(|p| {
+                    p.manifest_path
+                        .parent()
+                        .map(|dir| (p.name.clone(), dir.as_std_path().to_path_buf()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    // Nested packages need to be checked before their parents
+    packages.sort_by_key(|(_, dir)| std::cmp::Reverse(dir.components().count()));
+    packages
+}
+
+fn crate_entry<'a>(stats: &'a mut BTreeMap<String, CrateStats>, name: &str) -> &'a mut CrateStats {
+    stats.entry(name.to_string()).or_insert_with(|| CrateStats {
+        name: name.to_string(),
+        ..Default::default()
+    })
+}
+
+/// The package a source file is attributed to, the innermost package directory containing it
+pub(crate) fn owning_package<'a>(packages: &'a [(String, PathBuf)], path: &Path) -> &'a str {
+    packages
+        .iter()
+        .find(|(_, dir)| path.starts_with(dir))
+        .map(|(name, _)| name.as_str())
+        .unwrap_or(UNKNOWN_CRATE)
+}
+
+fn collect_stats(packages: &[(String, PathBuf)], coverage_data: &TraceMap) -> Vec<CrateStats> {
+    let mut stats = BTreeMap::<String, CrateStats>::new();
+    for (path, traces) in coverage_data.iter() {
+        let name = owning_package(packages, path);
+        let crate_stats = crate_entry(&mut stats, name);
+        crate_stats.files += 1;
+        crate_stats.coverable += amount_coverable(traces.iter());
+        crate_stats.covered += amount_covered(traces.iter());
+    }
+    for run in coverage_data.runs() {
+        let name = run.package.as_deref().unwrap_or(UNKNOWN_CRATE);
+        let crate_stats = crate_entry(&mut stats, name);
+        crate_stats.test_binaries += 1;
+        crate_stats.duration += run.duration;
+    }
+    for test in coverage_data.tautological_tests() {
+        // The only lines these tests ran are in their own crate's tests
+        let name = coverage_data
+            .test_hits()
+            .get(test)
+            .and_then(|hits| hits.keys().next())
+            .map(|file| owning_package(packages, file))
+            .unwrap_or(UNKNOWN_CRATE);
+        let crate_stats = crate_entry(&mut stats, name);
+        crate_stats.tautological_tests.push(test.to_string());
+    }
+    stats
+        .into_values()
+        .map(|mut s| {
+            if s.coverable > 0 {
+                s.coverage = 100.0 * s.covered as f64 / s.coverable as f64;
+            }
+            s
+        })
+        .collect()
+}
+
+fn write_csv(stats: &[CrateStats], mut writer: impl Write) -> Result<(), RunError> {
+    writeln!(
+        writer,
+        "crate,files,coverable,covered,coverage,test_binaries,duration,tautological_tests"
+    )?;
+    for s in stats {
+        writeln!(
+            writer,
+            "{},{},{},{},{:.2},{},{:.3},{}",
+            s.name,
+            s.files,
+            s.coverable,
+            s.covered,
+            s.coverage,
+            s.test_binaries,
+            s.duration,
+            s.tautological_tests.len()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let stats = collect_stats(&workspace_packages(config), coverage_data);
+
+    let json = File::create(report_file(config, "tarpaulin-stats.json", "-stats.json"))?;
+    serde_json::to_writer_pretty(json, &stats)?;
+
+    let csv = File::create(report_file(config, "tarpaulin-stats.csv", "-stats.csv"))?;
+    write_csv(&stats, csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, TestRun, Trace};
+    use std::collections::{BTreeSet, HashSet};
+    use std::path::Path;
+
+    fn add_line(map: &mut TraceMap, file: &str, line: u64, hits: u64) {
+        map.add_trace(
+            Path::new(file),
+            Trace {
+                line,
+                address: HashSet::new(),
+                length: 0,
+                stats: CoverageStat::Line(hits),
+                hit_by: BTreeSet::new(),
+            },
+        );
+    }
+
+    #[test]
+    fn stats_grouped_by_crate() {
+        let packages = vec![
+            ("inner".to_string(), PathBuf::from("/ws/crates/inner")),
+            ("ws".to_string(), PathBuf::from("/ws")),
+        ];
+        let mut map = TraceMap::new();
+        add_line(&mut map, "/ws/src/lib.rs", 1, 1);
+        add_line(&mut map, "/ws/src/lib.rs", 2, 0);
+        add_line(&mut map, "/ws/crates/inner/src/lib.rs", 1, 3);
+        add_line(&mut map, "/elsewhere/lib.rs", 1, 0);
+        map.add_run(TestRun {
+            binary: PathBuf::from("/ws/target/debug/deps/inner-1234"),
+            target: "inner".to_string(),
+            package: Some("inner".to_string()),
+            run_type: None,
+            duration: 1.5,
+            collection: 0.0,
+            size: None,
+            return_code: 0,
+            ignored: false,
+            tests: vec![],
+            results: vec![],
+        });
+
+        let stats = collect_stats(&packages, &map);
+        assert_eq!(stats.len(), 3);
+
+        let ws = stats.iter().find(|x| x.name == "ws").unwrap();
+        assert_eq!(ws.files, 1);
+        assert_eq!(ws.coverable, 2);
+        assert_eq!(ws.covered, 1);
+        assert_eq!(ws.coverage, 50.0);
+        assert_eq!(ws.test_binaries, 0);
+
+        let inner = stats.iter().find(|x| x.name == "inner").unwrap();
+        assert_eq!(inner.files, 1);
+        assert_eq!(inner.covered, 1);
+        assert_eq!(inner.test_binaries, 1);
+        assert_eq!(inner.duration, 1.5);
+
+        let unknown = stats.iter().find(|x| x.name == UNKNOWN_CRATE).unwrap();
+        assert_eq!(unknown.files, 1);
+        assert_eq!(unknown.covered, 0);
+    }
+
+    #[test]
+    fn csv_output() {
+        let stats = vec![CrateStats {
+            name: "foo".to_string(),
+            files: 2,
+            coverable: 4,
+            covered: 3,
+            coverage: 75.0,
+            test_binaries: 1,
+            duration: 0.5,
+            tautological_tests: vec!["foo tests::always".to_string()],
+        }];
+        let mut out = vec![];
+        write_csv(&stats, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "crate,files,coverable,covered,coverage,test_binaries,duration,tautological_tests\nfoo,2,4,3,75.00,1,0.500,1\n"
+        );
+    }
+}
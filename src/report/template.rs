@@ -0,0 +1,273 @@
+//! Reports rendered through a user provided [Tera](https://keats.github.io/tera/) template, for
+//! formats tarpaulin doesn't support such as wiki markup or internal dashboards. The template is
+//! given the following context:
+//!
+//! * `tarpaulin_version`
+//! * `covered`, `coverable` and `coverage` (a percentage) for the whole project, along with
+//!   `branches_covered` and `branches_total` counting each way a branch can go
+//! * `files`, each with `path`, `relative_path`, `covered`, `coverable`, `coverage`,
+//!   `branches_covered`, `branches_total` and `lines`. Every coverable line has `line`, `hits`,
+//!   `branches_covered`, `branches_total` and `covered`, `hits` only counts line coverage
+//! * `config` with the `name`, `root`, `engine`, `features` and `branch_coverage` of the config
+//!
+//! The template is checked when the config is loaded so mistakes in it are found before the tests
+//! run. The report is written to the output directory named after the template with the `.tera`
+//! extension removed, so `summary.md.tera` is written to `summary.md`.
+use crate::config::{Config, TraceEngine};
+use crate::errors::RunError;
+use crate::path_utils::{fit_file_name, long_path};
+use crate::traces::{amount_coverable, amount_covered, CoverageStat, Trace, TraceMap};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+
+#[derive(Default, Serialize)]
+struct LineContext {
+    line: u64,
+    hits: u64,
+    branches_covered: usize,
+    branches_total: usize,
+    covered: bool,
+}
+
+#[derive(Serialize)]
+struct FileContext {
+    path: String,
+    relative_path: String,
+    covered: usize,
+    coverable: usize,
+    coverage: f64,
+    branches_covered: usize,
+    branches_total: usize,
+    lines: Vec<LineContext>,
+}
+
+#[derive(Serialize)]
+struct ConfigContext<'a> {
+    name: &'a str,
+    root: PathBuf,
+    engine: TraceEngine,
+    features: Option<&'a str>,
+    branch_coverage: bool,
+}
+
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    tarpaulin_version: &'static str,
+    covered: usize,
+    coverable: usize,
+    coverage: f64,
+    branches_covered: usize,
+    branches_total: usize,
+    files: Vec<FileContext>,
+    config: ConfigContext<'a>,
+}
+
+fn percentage(covered: usize, coverable: usize) -> f64 {
+    if coverable == 0 {
+        0.0
+    } else {
+        100.0 * covered as f64 / coverable as f64
+    }
+}
+
+/// Hits and branches of each coverable line, lines with several traces add them up
+fn lines(traces: &[Trace]) -> Vec<LineContext> {
+    let mut lines = BTreeMap::<u64, LineContext>::new();
+    for trace in traces {
+        let line = lines.entry(trace.line).or_insert_with(|| LineContext {
+            line: trace.line,
+            ..Default::default()
+        });
+        if let CoverageStat::Line(hits) = trace.stats {
+            line.hits += hits;
+        } else {
+            line.branches_covered += amount_covered(std::iter::once(trace));
+            line.branches_total += amount_coverable(std::iter::once(trace));
+        }
+        line.covered = line.hits > 0 || line.branches_covered > 0;
+    }
+    lines.into_values().collect()
+}
+
+fn context<'a>(coverage_data: &TraceMap, config: &'a Config) -> TemplateContext<'a> {
+    let files = coverage_data
+        .iter()
+        .map(|(path, traces)| {
+            let covered = coverage_data.covered_in_path(path);
+            let coverable = coverage_data.coverable_in_path(path);
+            let lines = lines(traces);
+            FileContext {
+                path: path.display().to_string(),
+                relative_path: config.strip_base_dir(path).display().to_string(),
+                covered,
+                coverable,
+                coverage: percentage(covered, coverable),
+                branches_covered: lines.iter().map(|x| x.branches_covered).sum(),
+                branches_total: lines.iter().map(|x| x.branches_total).sum(),
+                lines,
+            }
+        })
+        .collect::<Vec<_>>();
+    let covered = coverage_data.total_covered();
+    let coverable = coverage_data.total_coverable();
+    TemplateContext {
+        tarpaulin_version: env!("CARGO_PKG_VERSION"),
+        covered,
+        coverable,
+        coverage: percentage(covered, coverable),
+        branches_covered: files.iter().map(|x| x.branches_covered).sum(),
+        branches_total: files.iter().map(|x| x.branches_total).sum(),
+        files,
+        config: ConfigContext {
+            name: &config.name,
+            root: config.root(),
+            engine: config.engine(),
+            features: config.features.as_deref(),
+            branch_coverage: config.branch_coverage,
+        },
+    }
+}
+
+/// Tera puts the reason rendering failed in the error sources
+fn describe(e: &tera::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+fn render(template: &str, context: &TemplateContext) -> Result<String, RunError> {
+    let context = Context::from_serialize(context)
+        .map_err(|e| RunError::OutFormat(format!("Invalid template context: {}", describe(&e))))?;
+    Tera::one_off(template, &context, false)
+        .map_err(|e| RunError::OutFormat(format!("Failed to render template: {}", describe(&e))))
+}
+
+/// Reads the template and checks it parses, so a broken template fails when the config is loaded
+/// instead of after the tests have run
+pub(crate) fn load_template(template: &Path) -> Result<String, RunError> {
+    let source = fs::read_to_string(template).map_err(|e| {
+        RunError::Config(format!(
+            "Couldn't read template {}: {}",
+            template.display(),
+            e
+        ))
+    })?;
+    Tera::default()
+        .add_raw_template(&template.display().to_string(), &source)
+        .map_err(|e| {
+            RunError::Config(format!(
+                "Invalid template {}: {}",
+                template.display(),
+                describe(&e)
+            ))
+        })?;
+    Ok(source)
+}
+
+/// Where the report rendered from the template is written
+pub(crate) fn output_path(config: &Config, template: &Path) -> PathBuf {
+    let name = template
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.strip_suffix(".tera").unwrap_or(&name);
+    let name = match (&config.report_name, Path::new(name).extension()) {
+        (Some(report_name), Some(ext)) => format!("{}.{}", report_name, ext.to_string_lossy()),
+        (Some(report_name), None) => report_name.clone(),
+        (None, _) => name.to_string(),
+    };
+    long_path(config.output_dir().join(fit_file_name(&name)))
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let template = config.template.as_ref().ok_or_else(|| {
+        RunError::OutFormat("`--out Template` needs a template given with --template".to_string())
+    })?;
+    let source = load_template(template)?;
+    let report = render(&source, &context(coverage_data, config))?;
+    fs::write(output_path(config, template), report)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::LogicState;
+
+    #[test]
+    fn template_rendered_with_coverage() {
+        let mut traces = TraceMap::new();
+        for (line, hits) in [(1, 2), (2, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            traces.add_trace(Path::new("/project/src/lib.rs"), trace);
+        }
+        let config = Config::default();
+        let template = "{{ coverage }}%\n{% for file in files %}{{ file.path }} \
+            {% for line in file.lines %}{{ line.line }}:{{ line.hits }} {% endfor %}{% endfor %}";
+        let report = render(template, &context(&traces, &config)).unwrap();
+        assert_eq!(report, "50%\n/project/src/lib.rs 1:2 2:0 ");
+
+        assert!(render("{{ missing.field }}", &context(&traces, &config)).is_err());
+    }
+
+    #[test]
+    fn branches_counted_separately() {
+        let mut traces = TraceMap::new();
+        let mut line = Trace::new_stub(1);
+        line.stats = CoverageStat::Line(3);
+        traces.add_trace(Path::new("/project/src/lib.rs"), line);
+        let mut branch = Trace::new_stub(1);
+        branch.stats = CoverageStat::Branch(LogicState {
+            been_true: true,
+            been_false: false,
+        });
+        traces.add_trace(Path::new("/project/src/lib.rs"), branch);
+        let template = "{{ branches_covered }}/{{ branches_total }} \
+            {% for line in files[0].lines %}{{ line.hits }} {{ line.branches_covered }}/{{ line.branches_total }}{% endfor %}";
+        let report = render(template, &context(&traces, &Config::default())).unwrap();
+        assert_eq!(report, "1/2 3 1/2");
+    }
+
+    #[test]
+    fn template_checked_when_loaded() {
+        let dir = std::env::temp_dir().join(format!("tarpaulin-template-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let valid = dir.join("valid.tera");
+        fs::write(&valid, "{{ coverage }}").unwrap();
+        let invalid = dir.join("invalid.tera");
+        fs::write(&invalid, "{% for file in files %}").unwrap();
+
+        assert_eq!(load_template(&valid).unwrap(), "{{ coverage }}");
+        assert!(matches!(load_template(&invalid), Err(RunError::Config(_))));
+        assert!(matches!(
+            load_template(&dir.join("missing.tera")),
+            Err(RunError::Config(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_named_after_template() {
+        let mut config = Config::default();
+        let template = Path::new("templates/summary.md.tera");
+        assert_eq!(
+            output_path(&config, template),
+            config.output_dir().join("summary.md")
+        );
+        config.report_name = Some("coverage".to_string());
+        assert_eq!(
+            output_path(&config, template),
+            config.output_dir().join("coverage.md")
+        );
+    }
+}
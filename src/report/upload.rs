@@ -0,0 +1,139 @@
+//! Sending coverage to online services. Uploads happen after the local reports are written, and
+//! the run report stored in `target/tarpaulin` can be uploaded again with `--upload-only` so a
+//! network failure doesn't mean running the tests again.
+use crate::config::Config;
+use crate::errors::RunError;
+#[cfg(feature = "coveralls")]
+use crate::report::coveralls;
+use crate::report::get_previous_result;
+use crate::report::{codecov, github};
+use crate::traces::TraceMap;
+use std::fmt::Display;
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "coveralls")]
+use tracing::info;
+use tracing::warn;
+
+/// Times an upload is attempted before giving up
+const UPLOAD_ATTEMPTS: usize = 4;
+/// Wait before the first retry, this doubles for every retry after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Calls `send` until it succeeds or fails with an error `is_transient` says won't go away by
+/// itself, backing off exponentially between attempts
+#[cfg_attr(
+    not(any(feature = "coveralls", feature = "github", feature = "codecov")),
+    allow(dead_code)
+)]
+fn retry<T, E: Display>(
+    service: &str,
+    attempts: usize,
+    initial_backoff: Duration,
+    is_transient: impl Fn(&E) -> bool,
+    mut send: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match send() {
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                warn!(
+                    "Upload to {} failed ({}), retrying in {}s",
+                    service,
+                    e,
+                    backoff.as_secs()
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Whether a failed request may succeed if it's sent again, only failing to connect, timing out
+/// and server errors are. Client errors such as a bad token fail the same way every time.
+#[cfg(any(feature = "coveralls", feature = "github", feature = "codecov"))]
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.status().is_some_and(|x| x.is_server_error())
+}
+
+/// Sends a request until it succeeds backing off exponentially between attempts, returning the
+/// error once it fails with an error that isn't transient or every attempt fails
+#[cfg(any(feature = "coveralls", feature = "github", feature = "codecov"))]
+pub(crate) fn with_retries<T>(
+    service: &str,
+    send: impl FnMut() -> Result<T, reqwest::Error>,
+) -> Result<T, reqwest::Error> {
+    retry(
+        service,
+        UPLOAD_ATTEMPTS,
+        INITIAL_BACKOFF,
+        is_transient,
+        send,
+    )
+}
+
+/// Sends the coverage to every service the config has set up
+pub(crate) fn upload_coverage(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    #[cfg(feature = "coveralls")]
+    if config.is_coveralls() {
+        coveralls::export(result, config)?;
+        info!("Coverage data sent");
+    }
+    codecov::upload(config, result)?;
+    github::comment_on_pr(config, result)
+}
+
+/// Uploads the run report stored by the last run instead of running the tests
+pub(crate) fn upload_stored_report(config: &Config) -> Result<(), RunError> {
+    if !config.is_coveralls() && !config.codecov && config.github_pr.is_none() {
+        warn!("--upload-only has nothing to upload without --coveralls, --codecov or --github-pr");
+        return Ok(());
+    }
+    match get_previous_result(config) {
+        Some(result) => upload_coverage(config, &result),
+        None => Err(RunError::CovReport(
+            "No stored report to upload, run tarpaulin without --upload-only first".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_retried_until_success() {
+        let transient = |e: &&str| *e == "unavailable";
+        let mut calls = 0;
+        let res = retry("test", 4, Duration::ZERO, transient, || {
+            calls += 1;
+            if calls < 3 {
+                Err("unavailable")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(res, Ok(3));
+
+        let mut calls = 0;
+        let res: Result<(), _> = retry("test", 4, Duration::ZERO, transient, || {
+            calls += 1;
+            Err("unavailable")
+        });
+        assert_eq!(res, Err("unavailable"));
+        assert_eq!(calls, 4);
+
+        // Errors which aren't transient aren't retried
+        let mut calls = 0;
+        let res: Result<(), _> = retry("test", 4, Duration::ZERO, transient, || {
+            calls += 1;
+            Err("unauthorized")
+        });
+        assert_eq!(res, Err("unauthorized"));
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Scripts reproducing the cargo commands tarpaulin builds the tests with, so build failures such
+//! as linker errors can be debugged without tarpaulin. A shell script and a PowerShell script are
+//! written to `target/tarpaulin` before building, setting the same environment and running the
+//! same commands from the same directory.
+use crate::config::Config;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Variables from tarpaulin's environment which change how cargo builds the project
+const BUILD_VARS: &[&str] = &[
+    "RUSTUP_TOOLCHAIN",
+    "CARGO_HOME",
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET",
+    "RUSTC",
+    "RUSTC_WRAPPER",
+    "CC",
+    "CXX",
+    "AR",
+    "CFLAGS",
+    "CXXFLAGS",
+    "LDFLAGS",
+];
+
+/// Per target linker and runner settings
+const BUILD_VAR_PREFIX: &str = "CARGO_TARGET_";
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn ps_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn inherited_vars() -> Vec<(String, String)> {
+    let mut vars = env::vars()
+        .filter(|(k, _)| BUILD_VARS.contains(&k.as_str()) || k.starts_with(BUILD_VAR_PREFIX))
+        .collect::<Vec<_>>();
+    vars.sort();
+    vars
+}
+
+fn command_args(cmd: &Command) -> Vec<String> {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|x| x.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Variables the command sets, `None` for ones it removes
+fn command_vars(cmd: &Command) -> Vec<(String, Option<String>)> {
+    cmd.get_envs()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().into_owned(),
+                v.map(|v| v.to_string_lossy().into_owned()),
+            )
+        })
+        .collect()
+}
+
+fn sh_script(dir: &str, inherited: &[(String, String)], cmds: &[Command]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# Cargo commands ran by cargo-tarpaulin {}\nset -e\ncd {}\n",
+        env!("CARGO_PKG_VERSION"),
+        sh_quote(dir)
+    );
+    for (k, v) in inherited {
+        script.push_str(&format!("export {}={}\n", k, sh_quote(v)));
+    }
+    for cmd in cmds {
+        // Each command runs in a subshell so its environment doesn't leak into the next one
+        script.push_str("(\n");
+        for (k, v) in command_vars(cmd) {
+            match v {
+                Some(v) => script.push_str(&format!("  export {}={}\n", k, sh_quote(&v))),
+                None => script.push_str(&format!("  unset {k}\n")),
+            }
+        }
+        let args = command_args(cmd)
+            .iter()
+            .map(|x| sh_quote(x))
+            .collect::<Vec<_>>();
+        script.push_str(&format!("  {}\n)\n", args.join(" ")));
+    }
+    script
+}
+
+fn ps1_script(dir: &str, inherited: &[(String, String)], cmds: &[Command]) -> String {
+    let mut script = format!(
+        "# Cargo commands ran by cargo-tarpaulin {}\n$ErrorActionPreference = 'Stop'\nSet-Location {}\n",
+        env!("CARGO_PKG_VERSION"),
+        ps_quote(dir)
+    );
+    for (k, v) in inherited {
+        script.push_str(&format!("$env:{} = {}\n", k, ps_quote(v)));
+    }
+    for cmd in cmds {
+        for (k, v) in command_vars(cmd) {
+            match v {
+                Some(v) => script.push_str(&format!("$env:{} = {}\n", k, ps_quote(&v))),
+                None => script.push_str(&format!("Remove-Item Env:{k} -ErrorAction Ignore\n")),
+            }
+        }
+        let args = command_args(cmd)
+            .iter()
+            .map(|x| ps_quote(x))
+            .collect::<Vec<_>>();
+        script.push_str(&format!(
+            "& {}\nif ($LASTEXITCODE -ne 0) {{ exit $LASTEXITCODE }}\n",
+            args.join(" ")
+        ));
+    }
+    script
+}
+
+/// Writes `reproduce.sh` and `reproduce.ps1` for the commands, named after the config when it
+/// has a name. Returns the path of the shell script.
+pub(crate) fn write_scripts(config: &Config, cmds: &[Command]) -> io::Result<PathBuf> {
+    let dir = config.target_dir().join("tarpaulin");
+    fs::create_dir_all(&dir)?;
+    let name = if config.name.is_empty() {
+        "reproduce".to_string()
+    } else {
+        format!("reproduce-{}", config.name)
+    };
+    let cwd = env::current_dir()?;
+    let cwd = cwd.to_string_lossy();
+    let inherited = inherited_vars();
+    let sh = dir.join(format!("{name}.sh"));
+    fs::write(&sh, sh_script(&cwd, &inherited, cmds))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&sh, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::write(
+        dir.join(format!("{name}.ps1")),
+        ps1_script(&cwd, &inherited, cmds),
+    )?;
+    Ok(sh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands() -> Vec<Command> {
+        let mut cmd = Command::new("cargo");
+        cmd.args(["test", "--no-run", "--features", "it's"])
+            .env("RUSTFLAGS", "-Cinstrument-coverage --cfg=tarpaulin")
+            .env_remove("RUSTC_BOOTSTRAP");
+        vec![cmd]
+    }
+
+    #[test]
+    fn shell_script_quotes_arguments() {
+        let inherited = vec![("CC".to_string(), "clang".to_string())];
+        let script = sh_script("/project", &inherited, &commands());
+        assert!(script.contains("cd '/project'\nexport CC='clang'\n"));
+        assert!(script.contains("  export RUSTFLAGS='-Cinstrument-coverage --cfg=tarpaulin'\n"));
+        assert!(script.contains("  unset RUSTC_BOOTSTRAP\n"));
+        assert!(script.contains("  'cargo' 'test' '--no-run' '--features' 'it'\\''s'\n)\n"));
+    }
+
+    #[test]
+    fn powershell_script_quotes_arguments() {
+        let script = ps1_script("C:\\project", &[], &commands());
+        assert!(script.contains("Set-Location 'C:\\project'\n"));
+        assert!(script.contains("$env:RUSTFLAGS = '-Cinstrument-coverage --cfg=tarpaulin'\n"));
+        assert!(script.contains("Remove-Item Env:RUSTC_BOOTSTRAP -ErrorAction Ignore\n"));
+        assert!(script.contains("& 'cargo' 'test' '--no-run' '--features' 'it''s'\n"));
+    }
+}
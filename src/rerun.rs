@@ -0,0 +1,164 @@
+//! The test runs which failed, recorded in `target/tarpaulin` as each one fails for
+//! `--rerun-failed`. They're recorded separately from the run report as a failing run may not
+//! store a report, such as when it stops at the first failure.
+use crate::cargo::TestBinary;
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::fit_file_name;
+use crate::traces::{TestOutcome, TestResult};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// A run of a test binary which failed
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct FailedRun {
+    pub binary: PathBuf,
+    /// Whether this was the run of the ignored tests in the binary
+    pub ignored: bool,
+    /// Tests to run again, empty to run every test in the binary
+    pub tests: Vec<String>,
+}
+
+impl FailedRun {
+    /// The failed run of the binary. Only the tests the output reports as failed are ran again, or
+    /// the tests the run was given if the output couldn't be read.
+    fn new(test: &Path, ignored: bool, tests: &[String], results: &[TestResult]) -> Self {
+        let failed = results
+            .iter()
+            .filter(|x| x.outcome == TestOutcome::Failed)
+            .map(|x| x.name.clone())
+            .collect::<Vec<_>>();
+        Self {
+            binary: test.to_path_buf(),
+            ignored,
+            tests: if failed.is_empty() {
+                tests.to_vec()
+            } else {
+                failed
+            },
+        }
+    }
+}
+
+fn record_path(config: &Config) -> PathBuf {
+    let name = if config.name.is_empty() {
+        "failed-runs.jsonl".to_string()
+    } else {
+        format!("{}-failed-runs.jsonl", config.name)
+    };
+    config
+        .target_dir()
+        .join("tarpaulin")
+        .join(fit_file_name(&name))
+}
+
+/// Starts an empty record of failed runs for the tests about to run
+pub(crate) fn clear(config: &Config) {
+    let path = record_path(config);
+    let res = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, ""));
+    if let Err(e) = res {
+        warn!("Failed to clear {}: {}", path.display(), e);
+    }
+}
+
+/// Records a failed run of the test binary
+pub(crate) fn record(
+    config: &Config,
+    test: &TestBinary,
+    ignored: bool,
+    tests: &[String],
+    results: &[TestResult],
+) {
+    let path = record_path(config);
+    let run = FailedRun::new(test.path(), ignored, tests, results);
+    let res = serde_json::to_string(&run)
+        .map_err(io::Error::from)
+        .and_then(|line| {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(file, "{line}")
+        });
+    if let Err(e) = res {
+        warn!(
+            "Failed to record the failed run in {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn parse_record(contents: &str) -> Result<Vec<FailedRun>, serde_json::Error> {
+    contents
+        .lines()
+        .filter(|x| !x.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// The failed runs recorded by the last run. This should be called before the tests are built,
+/// it's an error if there's no record or the tests it lists were built again after it was written
+/// as then the record isn't from the last build.
+pub(crate) fn recorded(config: &Config) -> Result<Vec<FailedRun>, RunError> {
+    let path = record_path(config);
+    let (contents, written) = match (fs::read_to_string(&path), modified(&path)) {
+        (Ok(contents), Some(written)) => (contents, written),
+        _ => {
+            return Err(RunError::Config(
+                "No failed tests were recorded, run tarpaulin without --rerun-failed first"
+                    .to_string(),
+            ))
+        }
+    };
+    let runs = parse_record(&contents)
+        .map_err(|e| RunError::Json(format!("Failed to read {}: {}", path.display(), e)))?;
+    if let Some(run) = runs
+        .iter()
+        .find(|x| modified(&x.binary).is_some_and(|built| built > written))
+    {
+        return Err(RunError::Config(format!(
+            "{} was built after the failed tests were recorded, run tarpaulin without --rerun-failed first",
+            run.binary.display()
+        )));
+    }
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_tests_rerun() {
+        let result = |name: &str, outcome| TestResult {
+            name: name.to_string(),
+            outcome,
+            exec_time: None,
+        };
+        let results = [
+            result("tests::a", TestOutcome::Passed),
+            result("tests::b", TestOutcome::Failed),
+        ];
+        let run = FailedRun::new(Path::new("target/debug/deps/a"), false, &[], &results);
+        assert_eq!(run.tests, vec!["tests::b"]);
+
+        let given = ["tests::c".to_string()];
+        let run = FailedRun::new(Path::new("target/debug/deps/a"), true, &given, &[]);
+        assert_eq!(run.tests, given);
+
+        let line = serde_json::to_string(&run).unwrap();
+        assert_eq!(
+            parse_record(&format!("{line}\n\n{line}\n")).unwrap(),
+            vec![run.clone(), run]
+        );
+    }
+}
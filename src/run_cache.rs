@@ -0,0 +1,119 @@
+//! When several configs build identical test binaries there's no need to run them more than
+//! once. The coverage collected for a binary is stored against a hash of its contents and the
+//! contents of the other binaries it may launch, along with every setting which changes how it
+//! runs or how its coverage is mapped to the source, so a later config with the same binaries and
+//! settings reuses it.
+use crate::cargo::TestBinary;
+use crate::config::Config;
+use crate::traces::TraceMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RunKey {
+    contents: u64,
+    ignored: bool,
+    settings: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RunCache {
+    runs: HashMap<RunKey, (TraceMap, i32)>,
+}
+
+impl RunCache {
+    /// Key for running the binary with the config, `None` if the binary or one of the other
+    /// binaries it may launch can't be read or it's a scenario as they're specific to the config
+    /// file
+    pub(crate) fn key(
+        test: &TestBinary,
+        other_binaries: &[PathBuf],
+        config: &Config,
+        ignored: bool,
+    ) -> Option<RunKey> {
+        if test.scenario().is_some() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        for binary in
+            std::iter::once(test.path()).chain(other_binaries.iter().map(PathBuf::as_path))
+        {
+            hash_file(binary, &mut hasher).ok()?;
+        }
+        let config = config.for_binary(test);
+        Some(RunKey {
+            contents: hasher.finish(),
+            ignored,
+            settings: config.run_settings(&test.target_name()),
+        })
+    }
+
+    /// The coverage collected by an earlier run with the same key
+    pub(crate) fn get(&self, key: &RunKey) -> Option<(&TraceMap, i32)> {
+        self.runs.get(key).map(|(traces, code)| (traces, *code))
+    }
+
+    pub(crate) fn insert(&mut self, key: RunKey, traces: TraceMap, code: i32) {
+        debug!("Caching coverage for binary with hash {:x}", key.contents);
+        self.runs.insert(key, (traces, code));
+    }
+}
+
+/// Hashes the contents of the file a buffer at a time, test binaries can be large
+fn hash_file(path: &Path, hasher: &mut DefaultHasher) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        hasher.write(buf);
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+
+    #[test]
+    fn reused_for_same_binary_and_settings() {
+        let exe = TestBinary::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"),
+            None,
+        );
+        let config = Config::default();
+        let key = RunCache::key(&exe, &[], &config, false).unwrap();
+        assert_ne!(Some(key.clone()), RunCache::key(&exe, &[], &config, true));
+
+        let mut other = Config::default();
+        other.varargs.push("--nocapture".to_string());
+        assert_ne!(Some(key.clone()), RunCache::key(&exe, &[], &other, false));
+
+        // The other binaries the test may launch are part of the key
+        let launched = [PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("README.md")];
+        assert_ne!(
+            Some(key.clone()),
+            RunCache::key(&exe, &launched, &config, false)
+        );
+        let missing = [PathBuf::from("not-a-binary")];
+        assert!(RunCache::key(&exe, &missing, &config, false).is_none());
+
+        let mut cache = RunCache::default();
+        let mut traces = TraceMap::new();
+        traces.add_trace(Path::new("src/lib.rs"), Trace::new_stub(1));
+        cache.insert(key, traces, 0);
+        let (cached, code) = cache
+            .get(&RunCache::key(&exe, &[], &config, false).unwrap())
+            .unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(cached.total_coverable(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+//! Every tarpaulin run gets its own directory in `target/tarpaulin/runs` for the profraws it
+//! generates, so two runs never pick up or remove each others files. Runs sharing a
+//! target directory are still serialised by a lock file as they'd race on the stored reports,
+//! unless `--allow-concurrent` is passed.
+use crate::config::{Config, ProfrawCleanup};
+use crate::errors::RunError;
+use std::fs::{self, create_dir_all, read_dir, remove_dir_all, remove_file, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use tracing::{debug, info, warn};
+
+const LOCK_FILE: &str = "tarpaulin.lock";
+
+/// Holds the lock on the target directory and the run directory for the duration of the run.
+/// When dropped the lock is released and the run directory removed if the profraw cleanup policy
+/// requires it.
+pub struct RunGuard {
+    lock: Option<PathBuf>,
+    run_dir: PathBuf,
+    remove_run_dir: bool,
+}
+
+impl RunGuard {
+    pub fn acquire(config: &Config) -> Result<Self, RunError> {
+        let tarpaulin_dir = config.target_dir().join("tarpaulin");
+        create_dir_all(&tarpaulin_dir)?;
+        let lock = if config.allow_concurrent {
+            None
+        } else {
+            let lock = tarpaulin_dir.join(LOCK_FILE);
+            take_lock(&lock)?;
+            Some(lock)
+        };
+        if config.profraw_cleanup() != ProfrawCleanup::Never {
+            remove_stale_runs(config);
+        }
+        let run_dir = config.run_dir();
+        debug!("Run directory: {}", run_dir.display());
+        create_dir_all(&run_dir)?;
+        Ok(Self {
+            lock,
+            run_dir,
+            remove_run_dir: config.profraw_cleanup() == ProfrawCleanup::Always,
+        })
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        if self.remove_run_dir {
+            if let Err(e) = remove_dir_all(&self.run_dir) {
+                warn!("Unable to remove {}: {}", self.run_dir.display(), e);
+            }
+        }
+        if let Some(lock) = self.lock.as_ref() {
+            let _ = remove_file(lock);
+        }
+    }
+}
+
+fn take_lock(lock: &Path) -> Result<(), RunError> {
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(lock) {
+            Ok(mut file) => {
+                write!(file, "{}", process::id())?;
+                return Ok(());
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let owner = fs::read_to_string(lock)
+                    .ok()
+                    .and_then(|x| x.trim().parse::<u32>().ok());
+                match owner {
+                    Some(pid) if pid != process::id() && is_running(pid) => {
+                        return Err(RunError::ConcurrentRun(format!(
+                            "tarpaulin (pid {}) is already running with this target directory. Wait for it to finish, pass --allow-concurrent or remove {} if it's stale",
+                            pid,
+                            lock.display()
+                        )));
+                    }
+                    _ => {
+                        info!("Removing stale lock file {}", lock.display());
+                        remove_file(lock)?;
+                    }
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Removes the directories of previous runs which are no longer running
+pub(crate) fn remove_stale_runs(config: &Config) {
+    let entries = match read_dir(config.target_dir().join("tarpaulin").join("runs")) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let stale = run_pid(&path).is_none_or(|pid| !is_running(pid));
+        if stale && path != config.run_dir() {
+            debug!("Removing run directory {}", path.display());
+            if let Err(e) = remove_dir_all(&path) {
+                warn!("Unable to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Run directories are named `<timestamp>-<pid>`
+fn run_pid(run_dir: &Path) -> Option<u32> {
+    run_dir
+        .file_name()?
+        .to_str()?
+        .rsplit_once('-')
+        .and_then(|(_, pid)| pid.parse().ok())
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn is_running(pid: u32) -> bool {
+    process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(process::Stdio::null())
+        .status()
+        .map(|x| x.success())
+        .unwrap_or(true)
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            // Processes of other users can't be opened but are still running
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0;
+        let res = GetExitCodeProcess(handle, &mut code);
+        CloseHandle(handle);
+        res == 0 || code == STILL_ACTIVE as u32
+    }
+}
+
+/// Without a cheap way to check we assume the process is running, the lock file can be removed
+/// manually if it's stale
+#[cfg(not(any(unix, windows)))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_dir_pid() {
+        assert_eq!(run_pid(Path::new("runs/20240101123000-4321")), Some(4321));
+        assert_eq!(run_pid(Path::new("runs/profraws")), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn lock_excludes_running_processes() {
+        let dir = std::env::temp_dir().join(format!("tarpaulin-lock-{}", process::id()));
+        create_dir_all(&dir).unwrap();
+        let lock = dir.join(LOCK_FILE);
+
+        // A lock left behind by a process which isn't running is taken over
+        fs::write(&lock, u32::MAX.to_string()).unwrap();
+        take_lock(&lock).unwrap();
+        assert_eq!(
+            fs::read_to_string(&lock).unwrap(),
+            process::id().to_string()
+        );
+
+        // Our parent is definitely still running
+        fs::write(&lock, std::os::unix::process::parent_id().to_string()).unwrap();
+        assert!(matches!(take_lock(&lock), Err(RunError::ConcurrentRun(_))));
+        remove_dir_all(&dir).unwrap();
+    }
+}
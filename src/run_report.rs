@@ -0,0 +1,121 @@
+//! Reading and writing the run report stored in `target/tarpaulin`. As well as plain json it can be
+//! stored compactly as gzipped json behind a header giving the format version, which gets large
+//! workspaces' reports down to a fraction of the size. Reports are read in either format so the
+//! format only matters when writing.
+use crate::config::RunReportFormat;
+use crate::errors::RunError;
+use crate::traces::TraceMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Start of a compact run report, followed by the version byte
+const MAGIC: &[u8] = b"TARPAULIN";
+/// Version of the compact format, bumped when what follows the header changes
+const VERSION: u8 = 1;
+
+/// Extension of the stored run report in the format
+pub(crate) fn extension(format: RunReportFormat) -> &'static str {
+    match format {
+        RunReportFormat::Json => "json",
+        RunReportFormat::Compact => "bin",
+    }
+}
+
+fn report_error(path: &Path, e: impl std::fmt::Display) -> RunError {
+    RunError::CovReport(format!("Failed to read {}: {}", path.display(), e))
+}
+
+pub fn write_run_report(
+    path: &Path,
+    traces: &TraceMap,
+    format: RunReportFormat,
+) -> Result<(), RunError> {
+    let save_error = |_| RunError::CovReport("Failed to save run report".to_string());
+    let file = File::create(path)
+        .map_err(|_| RunError::CovReport("Failed to create run report".to_string()))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        RunReportFormat::Json => serde_json::to_writer(&mut writer, traces).map_err(save_error)?,
+        RunReportFormat::Compact => {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+            let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+            serde_json::to_writer(&mut encoder, traces).map_err(save_error)?;
+            encoder.finish()?;
+        }
+    }
+    writer.flush().map_err(RunError::from)
+}
+
+/// Reads a run report in either format
+pub fn read_run_report(path: &Path) -> Result<TraceMap, RunError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(MAGIC) {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header)?;
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            return Err(report_error(
+                path,
+                format!("compact report version {version} isn't supported, expected {VERSION}"),
+            ));
+        }
+        serde_json::from_reader(GzDecoder::new(reader)).map_err(|e| report_error(path, e))
+    } else {
+        serde_json::from_reader(reader).map_err(|e| report_error(path, e))
+    }
+}
+
+/// Writes a run report as json, by default next to it with a json extension
+pub fn convert_run_report(report: &Path, output: Option<&Path>) -> Result<(), RunError> {
+    let traces = read_run_report(report)?;
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| report.with_extension(extension(RunReportFormat::Json)));
+    write_run_report(&output, &traces, RunReportFormat::Json)?;
+    info!("Wrote {}", output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn reports_read_in_either_format() {
+        let mut traces = TraceMap::new();
+        for line in 1..100 {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(line % 3);
+            traces.add_trace(Path::new("/project/src/lib.rs"), trace);
+        }
+        let dir = env::temp_dir().join("tarpaulin_run_report");
+        let _ = fs::create_dir_all(&dir);
+        let json = dir.join("coverage.json");
+        let compact = dir.join("coverage.bin");
+        write_run_report(&json, &traces, RunReportFormat::Json).unwrap();
+        write_run_report(&compact, &traces, RunReportFormat::Compact).unwrap();
+        assert!(fs::metadata(&compact).unwrap().len() < fs::metadata(&json).unwrap().len());
+        let expected = serde_json::to_value(&traces).unwrap();
+        let read = |path: &Path| serde_json::to_value(read_run_report(path).unwrap()).unwrap();
+        assert_eq!(read(&json), expected);
+        assert_eq!(read(&compact), expected);
+
+        let converted = dir.join("converted.json");
+        convert_run_report(&compact, Some(&converted)).unwrap();
+        assert_eq!(read(&converted), expected);
+
+        let mut future = fs::read(&compact).unwrap();
+        future[MAGIC.len()] = VERSION + 1;
+        fs::write(&compact, future).unwrap();
+        assert!(read_run_report(&compact).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Source analysis lives in the `tarpaulin-analysis` crate so other tools can reuse it, this
+//! re-exports it and handles finding the source files from the tarpaulin config.
+use crate::config::Config;
+use crate::path_utils::get_source_walker;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info, trace, warn};
+
+/// Number of `cfg(tarpaulin)` locations listed when warning they have no effect
+const LISTED_CFGS: usize = 5;
+/// Number of the slowest files to analyse which are logged
+const LISTED_SLOW_FILES: usize = 5;
+
+pub use tarpaulin_analysis::*;
+
+/// Runs source analysis on every source file of the project not excluded by the config
+pub fn get_analysis(config: &Config) -> SourceAnalysis {
+    let files = get_source_walker(config).map(|e| e.path().to_path_buf());
+    let result = SourceAnalysis::get_analysis(files, &config.root(), &config.analysis_config());
+    debug_printout(&result, config);
+    if config.avoid_cfg_tarpaulin {
+        warn_inert_cfgs(&result, config);
+    }
+    report_exclusions(&result, config);
+    report_analysis_limits(&result, config);
+    result
+}
+
+/// Logs the files which took longest to analyse and warns about the files skipped because of
+/// the analysis limits
+fn report_analysis_limits(result: &SourceAnalysis, config: &Config) {
+    let mut times = result.analysis_times.iter().collect::<Vec<_>>();
+    times.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+    for (path, time) in times.iter().take(LISTED_SLOW_FILES) {
+        debug!(
+            "Source analysis of {} took {:.2}s",
+            config.strip_base_dir(path).display(),
+            time.as_secs_f64()
+        );
+    }
+    let outcome = if config.include_skipped_files {
+        "every line with debug info is coverable"
+    } else {
+        "it's left out of coverage"
+    };
+    for (path, reason) in &result.skipped_files {
+        warn!(
+            "Skipped source analysis of {} as {}, {}",
+            config.strip_base_dir(path).display(),
+            reason,
+            outcome
+        );
+    }
+}
+
+/// Lists how many lines each of the `ignore-line-patterns` excluded from coverage and which
+/// physical lines were merged into one logical line
+fn report_exclusions(result: &SourceAnalysis, config: &Config) {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    let mut merged = BTreeMap::new();
+    for (path, analysis) in result
+        .lines
+        .iter()
+        .filter(|(_, x)| !x.ignore.contains(&Lines::All))
+    {
+        for (_, pattern) in &analysis.pattern_ignored {
+            *counts.entry(pattern.as_str()).or_default() += 1;
+        }
+        if !analysis.logical_lines.is_empty() {
+            merged.insert(config.strip_base_dir(path), &analysis.logical_lines);
+        }
+    }
+    for pattern in &config.ignore_line_patterns {
+        info!(
+            "ignore-line-pattern '{}' excluded {} lines",
+            pattern,
+            counts.get(pattern.as_str()).copied().unwrap_or_default()
+        );
+    }
+    if !merged.is_empty() {
+        info!(
+            "{} physical lines were merged into logical lines",
+            merged.values().map(|x| x.len()).sum::<usize>()
+        );
+    }
+    for (path, lines) in &merged {
+        debug!(
+            "Logical lines in {} (physical -> logical): {}",
+            path.display(),
+            logical_line_mapping(lines)
+        );
+    }
+}
+
+/// The physical lines merged into each logical line, such as `3,4 -> 2; 9 -> 8`
+fn logical_line_mapping(lines: &HashMap<usize, usize>) -> String {
+    let mut grouped = BTreeMap::<usize, Vec<usize>>::new();
+    for (physical, logical) in lines {
+        grouped.entry(*logical).or_default().push(*physical);
+    }
+    grouped
+        .into_iter()
+        .map(|(logical, mut physical)| {
+            physical.sort_unstable();
+            let physical = physical.iter().map(ToString::to_string).collect::<Vec<_>>();
+            format!("{} -> {}", physical.join(","), logical)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Warns about code gated on `cfg(tarpaulin)` when it's never set because of
+/// `--avoid-cfg-tarpaulin`
+fn warn_inert_cfgs(result: &SourceAnalysis, config: &Config) {
+    let mut locations = result
+        .lines
+        .iter()
+        .flat_map(|(path, analysis)| {
+            let path = config.strip_base_dir(path);
+            analysis
+                .tarpaulin_cfgs
+                .iter()
+                .map(move |line| format!("{}:{}", path.display(), line))
+        })
+        .collect::<Vec<_>>();
+    if !locations.is_empty() {
+        locations.sort();
+        warn!(
+            "cfg(tarpaulin) is used in {} places but is never set with --avoid-cfg-tarpaulin, consider gating on your own cfg passed with --cfg instead: {}",
+            locations.len(),
+            locations
+                .iter()
+                .take(LISTED_CFGS)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+/// Printout a debug summary of the results of source analysis if debug logging
+/// is enabled
+#[cfg(not(tarpaulin_include))]
+fn debug_printout(result: &SourceAnalysis, config: &Config) {
+    if config.debug {
+        for (path, analysis) in &result.lines {
+            trace!(
+                "Source analysis for {}",
+                config.strip_base_dir(path).display()
+            );
+            let mut lines = Vec::new();
+            for l in &analysis.ignore {
+                match l {
+                    Lines::All => {
+                        lines.clear();
+                        trace!("All lines are ignorable");
+                        break;
+                    }
+                    Lines::Line(i) => {
+                        lines.push(i);
+                    }
+                }
+            }
+            if !lines.is_empty() {
+                lines.sort();
+                trace!("Ignorable lines: {:?}", lines);
+                lines.clear();
+            }
+            for c in &analysis.cover {
+                lines.push(c);
+            }
+
+            if !lines.is_empty() {
+                lines.sort();
+                trace!("Coverable lines: {:?}", lines);
+            }
+            if !analysis.pattern_ignored.is_empty() {
+                trace!(
+                    "Lines ignored by ignore-line-patterns: {:?}",
+                    analysis.pattern_ignored
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logical_lines_grouped() {
+        let lines = vec![(4, 2), (3, 2), (9, 8)].into_iter().collect();
+        assert_eq!(logical_line_mapping(&lines), "3,4 -> 2; 9 -> 8");
+        assert_eq!(logical_line_mapping(&HashMap::new()), "");
+    }
+}
@@ -0,0 +1,79 @@
+//! Source files edited after the tests were built no longer match the line information in the
+//! binaries, so their coverage ends up on the wrong lines. This is easy to hit with `--skip-clean`
+//! when editing while tarpaulin runs.
+use crate::cargo::TestBinary;
+use crate::config::Config;
+use crate::traces::TraceMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tracing::warn;
+
+/// When the newest of the binaries was built
+fn build_time(binaries: &[TestBinary]) -> Option<SystemTime> {
+    binaries
+        .iter()
+        .filter_map(|x| fs::metadata(x.path()).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+/// The files modified after the build
+fn modified_after<'a>(
+    files: impl IntoIterator<Item = &'a PathBuf>,
+    built: SystemTime,
+) -> Vec<PathBuf> {
+    files
+        .into_iter()
+        .filter(|x| {
+            fs::metadata(x)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified > built)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Warns about each traced source file modified after the test binaries were built, with
+/// `--exclude-stale-files` they're also removed from the results so they aren't reported.
+pub(crate) fn check_stale_sources(result: &mut TraceMap, binaries: &[TestBinary], config: &Config) {
+    let built = match build_time(binaries) {
+        Some(built) => built,
+        None => return,
+    };
+    for file in modified_after(result.files(), built) {
+        let path = config.strip_base_dir(&file);
+        if config.exclude_stale_files {
+            warn!(
+                "{} was modified after the tests were built, leaving it out of the reports",
+                path.display()
+            );
+            result.remove_file(&file);
+        } else {
+            warn!(
+                "{} was modified after the tests were built, its coverage may be on the wrong lines",
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::Duration;
+
+    #[test]
+    fn files_edited_after_build_found() {
+        let source = env::temp_dir().join("tarpaulin_stale_source.rs");
+        fs::write(&source, "fn main() {}").unwrap();
+        let modified = fs::metadata(&source).unwrap().modified().unwrap();
+        let files = [source.clone(), PathBuf::from("missing.rs")];
+
+        let before = modified - Duration::from_secs(60);
+        assert_eq!(modified_after(&files, before), vec![source.clone()]);
+        let after = modified + Duration::from_secs(60);
+        assert!(modified_after(&files, after).is_empty());
+        let _ = fs::remove_file(source);
+    }
+}
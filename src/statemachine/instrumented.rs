@@ -1,15 +1,19 @@
 #![allow(dead_code)]
-use crate::path_utils::{get_profile_walker, get_source_walker};
+use crate::cargo::rust_flags;
+use crate::objects::coverage_objects;
+use crate::path_utils::{
+    get_profile_walker, get_source_walker, is_profraw_for, remapped_prefixes, unmap_path,
+};
 use crate::process_handling::RunningProcessHandle;
+use crate::source_analysis::RegionKind;
 use crate::statemachine::*;
+use crate::traces::{Region, RegionHits, SiteTrace};
 use llvm_profparser::*;
 use std::thread::sleep;
 use tracing::{info, warn};
 
 pub fn create_state_machine<'a>(
     test: impl Into<TestHandle>,
-    traces: &'a mut TraceMap,
-    analysis: &'a HashMap<PathBuf, LineAnalysis>,
     config: &'a Config,
     event_log: &'a Option<EventLog>,
 ) -> (TestState, LlvmInstrumentedData<'a>) {
@@ -19,8 +23,7 @@ pub fn create_state_machine<'a>(
             process: Some(process),
             event_log,
             config,
-            traces,
-            analysis,
+            counters: None,
         };
         (TestState::start_state(), llvm)
     } else {
@@ -29,13 +32,208 @@ pub fn create_state_machine<'a>(
             process: None,
             config,
             event_log,
-            traces,
-            analysis,
+            counters: None,
         };
         (TestState::End(1), invalid)
     }
 }
 
+/// The profraws written by a finished test binary, mapping their counters onto the source is left
+/// until after the test so it can be done on another thread while the next binary runs
+#[derive(Debug)]
+pub struct CounterJob {
+    /// Test binary the profraws are from
+    binary: PathBuf,
+    /// Other objects the counters could be from
+    extra_binaries: Vec<PathBuf>,
+    profraws: Vec<PathBuf>,
+}
+
+impl CounterJob {
+    /// Merges the profraws and adds the coverage of their counters to the traces
+    pub fn collect(
+        self,
+        traces: &mut TraceMap,
+        analysis: &HashMap<PathBuf, LineAnalysis>,
+        config: &Config,
+    ) -> Result<(), RunError> {
+        let collection_start = Instant::now();
+        info!("Merging coverage reports");
+        let instrumentation = merge_profiles(&self.profraws)?;
+        if instrumentation.is_empty() {
+            warn!("profraw file has no records after merging. If this is unexpected it may be caused by a panic or signal used in a test that prevented the LLVM instrumentation runtime from serialising results");
+            return Ok(());
+        }
+
+        let mut binaries = self
+            .extra_binaries
+            .iter()
+            .filter(|path| {
+                // extra binaries might not exist yet and be created
+                // later by the test suite
+                if path.exists() {
+                    true
+                } else {
+                    info!(
+                        "Skipping additional object '{}' since the file does not exist",
+                        path.display()
+                    );
+                    false
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        binaries.push(self.binary);
+        info!("Mapping coverage data to source");
+        let objects = coverage_objects(&binaries, config);
+        let mapping = CoverageMapping::new(&objects, &instrumentation, true).map_err(|e| {
+            error!("Failed to get coverage: {}", e);
+            RunError::TestCoverage(e.to_string())
+        })?;
+        let root = config.root();
+        let remaps = remapped_prefixes(&rust_flags(config));
+        let report = mapping.generate_subreport(|paths| {
+            paths.iter().any(|path| {
+                let path = unmap_path(path, &remaps);
+                path.starts_with(&root) && config.focus_path(&path)
+            })
+        });
+
+        let regions = report
+            .files
+            .iter()
+            .map(|(file, result)| {
+                let regions = result
+                    .hits
+                    .iter()
+                    .map(|(loc, hits)| {
+                        let region = Region {
+                            line_start: loc.line_start,
+                            column_start: loc.column_start,
+                            line_end: loc.line_end,
+                            column_end: loc.column_end,
+                        };
+                        (region, *hits as u64)
+                    })
+                    .collect::<RegionHits>();
+                (unmap_path(file, &remaps), regions)
+            })
+            .collect::<HashMap<PathBuf, RegionHits>>();
+
+        if traces.is_empty() {
+            for source_file in get_source_walker(config) {
+                let file = source_file.path();
+                let analysis = analysis.get(file);
+                if let Some(result) = regions.get(file) {
+                    for (line, hits) in result.line_hits() {
+                        let include = match analysis.as_ref() {
+                            Some(analysis) => !analysis.should_ignore(line),
+                            None => true,
+                        };
+                        if include {
+                            let mut trace = Trace::new_stub(line as u64);
+                            trace.stats = CoverageStat::Line(hits);
+                            traces.add_trace(file, trace);
+                        }
+                    }
+                }
+                if let Some(analysis) = analysis {
+                    for line in analysis.cover.iter() {
+                        if !traces.contains_location(file, *line as u64) {
+                            let mut trace = Trace::new_stub(*line as u64);
+                            trace.stats = CoverageStat::Line(0);
+                            traces.add_trace(file, trace);
+                        }
+                    }
+                }
+            }
+        } else {
+            traces.dedup();
+
+            for (file, result) in regions.iter() {
+                if let Some(traces) = traces.file_traces_mut(file) {
+                    for trace in traces.iter_mut() {
+                        if let Some(hits) = result.hits_for_line(trace.line as usize) {
+                            if let CoverageStat::Line(ref mut x) = trace.stats {
+                                *x = hits;
+                            }
+                        }
+                    }
+                } else {
+                    warn!("Couldn't find {} in {:?}", file.display(), traces.files());
+                }
+            }
+        }
+
+        if config.error_path_coverage {
+            // An error path or panic ran if the code at its start did, the hits of its lines
+            // would include any other code sharing them
+            for (file, result) in regions.iter() {
+                let analysis = match analysis.get(file) {
+                    Some(analysis) => analysis,
+                    None => continue,
+                };
+                let sites = analysis
+                    .regions
+                    .iter()
+                    .filter(|x| matches!(x.kind, RegionKind::ErrorPath | RegionKind::Panic))
+                    .filter(|x| x.column > 0 && !analysis.should_ignore(x.start));
+                for site in sites {
+                    if let Some(hits) = result.hits_at(site.start, site.column) {
+                        let site = SiteTrace {
+                            line: site.start as u64,
+                            column: site.column as u64,
+                            hit: hits > 0,
+                        };
+                        traces.add_site(file, site);
+                    }
+                }
+            }
+        }
+
+        if config.branch_requested {
+            // The early return from a `?` gets its own coverage region starting at
+            // the `?` token, so the hits on that region are the times the error path
+            // was taken. Any other hits on the line mean the expression continued.
+            for (file, result) in report.files.iter() {
+                let analysis = match analysis.get(file) {
+                    Some(analysis) => analysis,
+                    None => continue,
+                };
+                for &(line, column) in &analysis.try_branches {
+                    if analysis.should_ignore(line) {
+                        continue;
+                    }
+                    let evaluated = regions
+                        .get(file)
+                        .and_then(|x| x.hits_for_line(line))
+                        .unwrap_or_default() as usize;
+                    let early_return = result
+                        .hits
+                        .iter()
+                        .filter(|(loc, _)| loc.line_start == line && loc.column_start == column)
+                        .map(|(_, hits)| *hits)
+                        .max()
+                        .unwrap_or_default();
+                    let branch = BranchTrace {
+                        line: line as u64,
+                        column: column as u64,
+                        stats: LogicState {
+                            been_true: evaluated > early_return,
+                            been_false: early_return > 0,
+                        },
+                    };
+                    traces.add_branch(file, branch);
+                }
+            }
+        }
+
+        traces.add_collection_time(collection_start.elapsed());
+        Ok(())
+    }
+}
+
 /// Handle to the process for an instrumented binary. This will simply
 pub struct LlvmInstrumentedData<'a> {
     /// Parent pid of the test
@@ -44,10 +242,8 @@ pub struct LlvmInstrumentedData<'a> {
     config: &'a Config,
     /// Optional event log to update as the test progresses
     event_log: &'a Option<EventLog>,
-    /// Instrumentation points in code with associated coverage data
-    traces: &'a mut TraceMap,
-    /// Source analysis, needed in case we need to follow any executables
-    analysis: &'a HashMap<PathBuf, LineAnalysis>,
+    /// Profraws of the finished test, the coverage is collected from them after the state machine
+    counters: Option<CounterJob>,
 }
 
 impl<'a> LlvmInstrumentedData<'a> {
@@ -77,17 +273,20 @@ impl<'a> StateData for LlvmInstrumentedData<'a> {
     fn wait(&mut self) -> Result<Option<TestState>, RunError> {
         let should_panic = self.should_panic();
         if let Some(parent) = self.process.as_mut() {
-            match parent.child.wait() {
+            match parent.wait() {
                 Ok(exit) => {
-                    if !exit.success() && !should_panic {
+                    if !exit.success() && !should_panic && !parent.scenario {
                         return Err(RunError::TestFailed);
                     }
                     if let Some(delay) = self.config.post_test_delay {
                         sleep(delay);
                     }
+                    // Processes outliving the test may write their profraws later, only taking
+                    // the ones named after the test stops them being attributed to the next one
                     let profraws = get_profile_walker(self.config)
                         .map(|x| x.path().to_path_buf())
                         .filter(|x| !parent.existing_profraws.contains(x))
+                        .filter(|x| is_profraw_for(x, &parent.path))
                         .collect::<Vec<_>>();
 
                     info!(
@@ -99,103 +298,16 @@ impl<'a> StateData for LlvmInstrumentedData<'a> {
                         info!("Generated: {}", profraw_name.display());
                     }
 
-                    let binary_path = parent.path.clone();
-                    info!("Merging coverage reports");
-                    let instrumentation = merge_profiles(&profraws)?;
-                    if instrumentation.is_empty() {
-                        warn!("profraw file has no records after merging. If this is unexpected it may be caused by a panic or signal used in a test that prevented the LLVM instrumentation runtime from serialising results");
-                        self.process = None;
-                        let code = exit.code().unwrap_or(1);
-                        return Ok(Some(TestState::End(code)));
-                    }
-
-                    let mut binaries = parent
-                        .extra_binaries
-                        .iter()
-                        .filter(|path| {
-                            // extra binaries might not exist yet and be created
-                            // later by the test suite
-                            if path.exists() {
-                                true
-                            } else {
-                                info!(
-                                    "Skipping additional object '{}' since the file does not exist",
-                                    path.display()
-                                );
-                                false
-                            }
-                        })
-                        .cloned()
-                        .collect::<Vec<_>>();
-
-                    binaries.push(binary_path);
-                    info!("Mapping coverage data to source");
-                    let mapping =
-                        CoverageMapping::new(&binaries, &instrumentation, true).map_err(|e| {
-                            error!("Failed to get coverage: {}", e);
-                            RunError::TestCoverage(e.to_string())
-                        })?;
-                    let root = self.config.root();
-                    let report = mapping.generate_subreport(|paths| {
-                        paths.iter().any(|path| path.starts_with(&root))
+                    self.counters = Some(CounterJob {
+                        binary: parent.path.clone(),
+                        extra_binaries: parent.extra_binaries.clone(),
+                        profraws,
                     });
-
-                    if self.traces.is_empty() {
-                        for source_file in get_source_walker(self.config) {
-                            let file = source_file.path();
-                            let analysis = self.analysis.get(file);
-                            if let Some(result) = report.files.get(file) {
-                                for (loc, hits) in result.hits.iter() {
-                                    for line in loc.line_start..(loc.line_end + 1) {
-                                        let include = match analysis.as_ref() {
-                                            Some(analysis) => !analysis.should_ignore(line),
-                                            None => true,
-                                        };
-                                        if include {
-                                            let mut trace = Trace::new_stub(line as u64);
-                                            trace.stats = CoverageStat::Line(*hits as u64);
-                                            self.traces.add_trace(file, trace);
-                                        }
-                                    }
-                                }
-                            }
-                            if let Some(analysis) = analysis {
-                                for line in analysis.cover.iter() {
-                                    if !self.traces.contains_location(file, *line as u64) {
-                                        let mut trace = Trace::new_stub(*line as u64);
-                                        trace.stats = CoverageStat::Line(0);
-                                        self.traces.add_trace(file, trace);
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        self.traces.dedup();
-
-                        for (file, result) in report.files.iter() {
-                            if let Some(traces) = self.traces.file_traces_mut(file) {
-                                for trace in traces.iter_mut() {
-                                    if let Some(hits) = result.hits_for_line(trace.line as usize) {
-                                        if let CoverageStat::Line(ref mut x) = trace.stats {
-                                            *x = hits as _;
-                                        }
-                                    }
-                                }
-                            } else {
-                                warn!(
-                                    "Couldn't find {} in {:?}",
-                                    file.display(),
-                                    self.traces.files()
-                                );
-                            }
-                        }
-                    }
-
                     self.process = None;
                     let code = exit.code().unwrap_or(1);
                     Ok(Some(TestState::End(code)))
                 }
-                Err(e) => Err(e.into()),
+                Err(e) => Err(e),
             }
         } else {
             Err(RunError::TestCoverage("Test was not launched".to_string()))
@@ -205,4 +317,8 @@ impl<'a> StateData for LlvmInstrumentedData<'a> {
     fn stop(&mut self) -> Result<TestState, RunError> {
         unreachable!();
     }
+
+    fn take_counters(&mut self) -> Option<CounterJob> {
+        self.counters.take()
+    }
 }
@@ -167,12 +167,7 @@ impl<'a> StateData for LinuxData<'a> {
 
     fn last_wait_attempt(&mut self) -> Result<Option<TestState>, RunError> {
         if let Some(ec) = self.exit_code {
-            let parent = self.parent;
-            for (_, process) in self.processes.iter().filter(|(k, _)| **k != parent) {
-                if let Some(tm) = process.traces.as_ref() {
-                    self.traces.merge(tm);
-                }
-            }
+            self.merge_process_traces();
             Ok(Some(TestState::End(ec)))
         } else {
             Ok(None)
@@ -410,6 +405,14 @@ impl<'a> StateData for LinuxData<'a> {
     }
 }
 
+impl Drop for LinuxData<'_> {
+    /// If tracing stops early because the test crashed or timed out the hits from any processes
+    /// it spawned are still kept
+    fn drop(&mut self) {
+        self.merge_process_traces();
+    }
+}
+
 impl<'a> LinuxData<'a> {
     pub fn new(
         traces: &'a mut TraceMap,
@@ -432,6 +435,16 @@ impl<'a> LinuxData<'a> {
         }
     }
 
+    /// Moves the hits from processes still being traced into the root trace map. The processes
+    /// are removed so their hits can't be counted twice.
+    fn merge_process_traces(&mut self) {
+        for (_, process) in self.processes.drain() {
+            if let Some(tm) = process.traces.as_ref() {
+                self.traces.merge(tm);
+            }
+        }
+    }
+
     fn get_parent(&self, pid: Pid) -> Option<Pid> {
         self.pid_map.get(&pid).copied().or_else(|| {
             let mut parent_pid = None;
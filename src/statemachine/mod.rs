@@ -1,4 +1,4 @@
-use crate::config::{Config, TraceEngine};
+use crate::config::Config;
 use crate::errors::RunError;
 use crate::event_log::*;
 use crate::traces::*;
@@ -10,44 +10,14 @@ use std::time::Instant;
 use tracing::error;
 
 pub mod instrumented;
+pub use instrumented::CounterJob;
 cfg_if::cfg_if! {
     if #[cfg(ptrace_supported)] {
         pub mod linux;
+        pub mod perf;
         pub use linux::ProcessInfo;
     }
 }
-pub fn create_state_machine<'a>(
-    test: impl Into<TestHandle>,
-    traces: &'a mut TraceMap,
-    source_analysis: &'a HashMap<PathBuf, LineAnalysis>,
-    config: &'a Config,
-    event_log: &'a Option<EventLog>,
-) -> (TestState, Box<dyn StateData + 'a>) {
-    match config.engine() {
-        TraceEngine::Ptrace => {
-            cfg_if::cfg_if! {
-                if #[cfg(ptrace_supported)] {
-                    let (state, machine) = linux::create_state_machine(test, traces, source_analysis, config, event_log);
-                    (state, Box::new(machine))
-                } else {
-                    error!("The ptrace backend is not supported on this system");
-                    panic!()
-                }
-            }
-        }
-        // Should never be auto so ignore our normal rules
-        TraceEngine::Llvm | TraceEngine::Auto => {
-            let (state, machine) = instrumented::create_state_machine(
-                test,
-                traces,
-                source_analysis,
-                config,
-                event_log,
-            );
-            (state, Box::new(machine))
-        }
-    }
-}
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum TestState {
@@ -110,6 +80,10 @@ pub trait StateData {
     /// Handle a stop in the test executable. Coverage data will
     /// be collected here as well as other OS specific functions
     fn stop(&mut self) -> Result<TestState, RunError>;
+    /// Counters written by the test which still have to be collected once it's finished
+    fn take_counters(&mut self) -> Option<CounterJob> {
+        None
+    }
 }
 
 impl<'a> StateData for Box<dyn StateData + 'a> {
@@ -132,6 +106,10 @@ impl<'a> StateData for Box<dyn StateData + 'a> {
     fn stop(&mut self) -> Result<TestState, RunError> {
         self.as_mut().stop()
     }
+
+    fn take_counters(&mut self) -> Option<CounterJob> {
+        self.as_mut().take_counters()
+    }
 }
 
 impl TestState {
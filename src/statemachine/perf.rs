@@ -0,0 +1,267 @@
+//! The perf engine runs the test under `perf record`, which samples the instructions it runs
+//! without modifying or tracing it. Once the test exits `perf script` maps each sampled address to
+//! the source line it came from using the debug info. Only sampled lines count as hit, so code
+//! which runs briefly is easily missed and the hits are numbers of samples rather than executions.
+use crate::cargo::TestBinary;
+use crate::process_handling::RunningProcessHandle;
+use crate::source_analysis::LineAnalysis;
+use crate::statemachine::*;
+use crate::test_loader::generate_tracemap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{debug, info};
+
+/// Recordings started so far, keeping the sample files of tests ran at the same time apart
+static RECORDINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// File for `perf record` to write the samples of a test run to
+pub(crate) fn samples_path(test: &TestBinary, config: &Config) -> PathBuf {
+    let recording = RECORDINGS.fetch_add(1, Ordering::Relaxed);
+    config
+        .run_dir()
+        .join(format!("perf-{}-{}.data", test.file_name(), recording))
+}
+
+pub fn create_state_machine<'a>(
+    test: impl Into<TestHandle>,
+    traces: &'a mut TraceMap,
+    analysis: &'a HashMap<PathBuf, LineAnalysis>,
+    config: &'a Config,
+) -> (TestState, PerfData<'a>) {
+    let process = match test.into() {
+        TestHandle::Process(process) => Some(process),
+        TestHandle::Id(_) => {
+            error!("The perf statemachine requires a process::Child");
+            None
+        }
+    };
+    let state = if process.is_some() {
+        TestState::start_state()
+    } else {
+        TestState::End(1)
+    };
+    (
+        state,
+        PerfData {
+            process,
+            traces,
+            analysis,
+            config,
+        },
+    )
+}
+
+/// Handle to a test running under `perf record`
+pub struct PerfData<'a> {
+    /// The perf process recording the test
+    process: Option<RunningProcessHandle>,
+    /// Traces the samples are added to once the test finishes
+    traces: &'a mut TraceMap,
+    /// Source analysis used to trace the other binaries the test ran
+    analysis: &'a HashMap<PathBuf, LineAnalysis>,
+    /// Program config
+    config: &'a Config,
+}
+
+/// Runs `perf script` to list the source line of every sample in the recording
+fn read_samples(samples: &Path) -> Result<String, RunError> {
+    let output = Command::new("perf")
+        .args(["script", "--full-source-path", "-F", "ip,dso,srcline", "-i"])
+        .arg(samples)
+        .output()
+        .map_err(|e| RunError::Engine(format!("Failed to run perf script: {e}")))?;
+    if !output.status.success() {
+        return Err(RunError::Engine(format!(
+            "perf script failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The binary or library a sample line of `perf script -F ip,dso,srcline` is from, the line is the
+/// address followed by the path in brackets.
+fn sample_dso(line: &str) -> Option<&str> {
+    let (ip, dso) = line.trim().split_once(' ')?;
+    if ip.is_empty() || !ip.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    dso.trim().strip_prefix('(')?.strip_suffix(')')
+}
+
+/// Files of the binaries and libraries with samples in the output of `perf script`. Samples
+/// perf couldn't map to a file, such as `[unknown]` or `[kernel.kallsyms]`, are left out.
+fn sampled_dsos(script: &str) -> BTreeSet<PathBuf> {
+    script
+        .lines()
+        .filter_map(sample_dso)
+        .filter(|x| !x.starts_with('['))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Number of samples on each source line from the output of `perf script -F ip,dso,srcline`.
+/// Each sample is the address and binary on one line followed by `file:line` on the next, with
+/// `??:0` for addresses without debug info.
+fn line_samples(script: &str) -> HashMap<PathBuf, BTreeMap<u64, u64>> {
+    let mut result = HashMap::<PathBuf, BTreeMap<u64, u64>>::new();
+    for line in script.lines() {
+        if sample_dso(line).is_some() {
+            continue;
+        }
+        let location = line
+            .trim()
+            .split(" (discriminator")
+            .next()
+            .unwrap_or_default();
+        let (path, number) = match location.rsplit_once(':') {
+            Some(location) => location,
+            None => continue,
+        };
+        match number.parse::<u64>() {
+            Ok(number) if number > 0 && path != "??" => {
+                *result
+                    .entry(PathBuf::from(path))
+                    .or_default()
+                    .entry(number)
+                    .or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Adds the samples to the hits of the traced lines. Samples from lines which aren't traced are
+/// from code outside of the project or lines the source analysis ignored so they're dropped.
+fn add_samples(traces: &mut TraceMap, samples: &HashMap<PathBuf, BTreeMap<u64, u64>>) {
+    for (file, lines) in samples {
+        if let Some(file_traces) = traces.file_traces_mut(file) {
+            for trace in file_traces.iter_mut() {
+                if let (Some(hits), CoverageStat::Line(x)) =
+                    (lines.get(&trace.line), &mut trace.stats)
+                {
+                    *x += hits;
+                }
+            }
+        }
+    }
+    traces.set_sampled();
+}
+
+impl<'a> StateData for PerfData<'a> {
+    fn start(&mut self) -> Result<Option<TestState>, RunError> {
+        // perf starts the test itself so there's nothing to attach to
+        Ok(Some(TestState::wait_state()))
+    }
+
+    fn init(&mut self) -> Result<TestState, RunError> {
+        unreachable!();
+    }
+
+    fn last_wait_attempt(&mut self) -> Result<Option<TestState>, RunError> {
+        unreachable!();
+    }
+
+    fn wait(&mut self) -> Result<Option<TestState>, RunError> {
+        let parent = match self.process.as_mut() {
+            Some(parent) => parent,
+            None => return Err(RunError::TestCoverage("Test was not launched".to_string())),
+        };
+        let exit = parent.wait()?;
+        let samples = parent.samples.take().unwrap_or_default();
+        if !samples.is_file() {
+            return Err(RunError::Engine(
+                "perf record didn't write any samples, check perf_event_paranoid".to_string(),
+            ));
+        }
+        if !exit.success() && !parent.should_panic && !parent.scenario {
+            return Err(RunError::TestFailed);
+        }
+        info!(
+            "For binary: {}",
+            self.config.strip_base_dir(&parent.path).display()
+        );
+        let collection_start = Instant::now();
+        let script = read_samples(&samples)?;
+        if let Err(e) = fs::remove_file(&samples) {
+            debug!("Couldn't remove {}: {}", samples.display(), e);
+        }
+        // The test may run prebuilt binaries with their own debug info, their lines need tracing
+        // before their samples can be added
+        for dso in sampled_dsos(&script) {
+            if dso == parent.path || !dso.is_file() {
+                continue;
+            }
+            match generate_tracemap(&dso, self.analysis, self.config) {
+                Ok(traces) => self.traces.merge(&traces),
+                Err(e) => debug!("No traces for {}: {}", dso.display(), e),
+            }
+        }
+        add_samples(self.traces, &line_samples(&script));
+        self.traces.add_collection_time(collection_start.elapsed());
+        self.process = None;
+        Ok(Some(TestState::End(exit.code().unwrap_or(1))))
+    }
+
+    fn stop(&mut self) -> Result<TestState, RunError> {
+        unreachable!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_counted_on_traced_lines() {
+        let script = "
+            55d4c3a1b2c3 (/project/target/debug/deps/a-1234)
+  /project/src/lib.rs:2
+            55d4c3a1b2c8 (/project/target/debug/deps/a-1234)
+  /project/src/lib.rs:2 (discriminator 1)
+            55d4c3a1b2d0 (/project/vendor/bin/tool)
+  /project/src/lib.rs:4
+            7f1e2a3b4c5d ([unknown])
+  ??:0
+            55d4c3a1b2e0 (/project/target/debug/deps/a-1234)
+  /rustc/library/core/src/ops.rs:10
+";
+        assert_eq!(
+            sampled_dsos(script),
+            BTreeSet::from([
+                PathBuf::from("/project/target/debug/deps/a-1234"),
+                PathBuf::from("/project/vendor/bin/tool"),
+            ])
+        );
+        let samples = line_samples(script);
+        assert_eq!(
+            samples[Path::new("/project/src/lib.rs")],
+            BTreeMap::from([(2, 2), (4, 1)])
+        );
+        assert_eq!(samples.len(), 2);
+
+        let file = Path::new("/project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        for line in 1..=3 {
+            traces.add_trace(file, Trace::new_stub(line));
+        }
+        add_samples(&mut traces, &samples);
+        let hits = traces
+            .get_child_traces(file)
+            .map(|x| (x.line, x.stats.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hits,
+            vec![
+                (1, CoverageStat::Line(0)),
+                (2, CoverageStat::Line(2)),
+                (3, CoverageStat::Line(0)),
+            ]
+        );
+        assert!(traces.is_sampled());
+    }
+}
@@ -0,0 +1,225 @@
+//! The test impact database records which lines each test hit with `--test-impact`, keyed by the
+//! commit the tests ran on so the mapping of each branch is kept as they're switched between.
+//! `cargo tarpaulin which-tests src/lib.rs:120` queries it for the tests covering a line, for
+//! tooling which only runs the tests affected by a change. Tests run in their own process with
+//! `--process-per-test` are recorded individually, otherwise the whole binary counts as one test.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::path_utils::portable_path;
+use crate::traces::TraceMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+const DATABASE: &str = "test-impact.json";
+
+/// Commits kept in the database, the oldest are dropped past this
+const MAX_COMMITS: usize = 20;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ImpactDatabase {
+    /// Oldest commit first
+    commits: Vec<CommitImpact>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct CommitImpact {
+    commit: String,
+    /// Tests recorded on the commit, referred to by their index in `files`
+    tests: Vec<String>,
+    /// Tests hitting each line of each source file, relative to the project root
+    files: BTreeMap<String, BTreeMap<u64, Vec<usize>>>,
+}
+
+impl CommitImpact {
+    fn new(commit: String, traces: &TraceMap, root: &Path) -> Self {
+        let mut files = BTreeMap::<String, BTreeMap<u64, Vec<usize>>>::new();
+        let tests = traces.test_hits().keys().cloned().collect();
+        for (index, hits) in traces.test_hits().values().enumerate() {
+            for (file, lines) in hits {
+                let file = portable_path(file, root).to_string_lossy().to_string();
+                let file = files.entry(file).or_default();
+                for line in lines {
+                    file.entry(*line).or_default().push(index);
+                }
+            }
+        }
+        Self {
+            commit,
+            tests,
+            files,
+        }
+    }
+
+    /// Tests hitting the line of the file, or any line of it when no line is given
+    fn tests_covering(&self, file: &str, line: Option<u64>) -> Vec<&str> {
+        let lines = match self.files.get(file) {
+            Some(lines) => lines,
+            None => return vec![],
+        };
+        let indices = match line {
+            Some(line) => lines
+                .get(&line)
+                .into_iter()
+                .flatten()
+                .collect::<BTreeSet<_>>(),
+            None => lines.values().flatten().collect(),
+        };
+        indices
+            .into_iter()
+            .filter_map(|x| self.tests.get(*x))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+fn database_path(config: &Config) -> PathBuf {
+    config.target_dir().join("tarpaulin").join(DATABASE)
+}
+
+fn read_database(path: &Path) -> Result<ImpactDatabase, RunError> {
+    match File::open(path) {
+        Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ImpactDatabase::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Full hash of a revision in the project's git repository
+fn resolve_commit(root: &Path, rev: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{rev}^{{commit}}"))
+        .current_dir(root)
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !commit.is_empty()).then_some(commit)
+}
+
+/// Records the lines each test hit against the commit checked out, replacing anything recorded
+/// for it by an earlier run
+pub(crate) fn record(config: &Config, traces: &TraceMap) -> Result<(), RunError> {
+    if traces.test_hits().is_empty() {
+        return Ok(());
+    }
+    let root = config.root();
+    let commit = match resolve_commit(&root, "HEAD") {
+        Some(commit) => commit,
+        None => {
+            warn!("Not recording test impact as the project isn't in a git repository");
+            return Ok(());
+        }
+    };
+    let path = database_path(config);
+    let mut database = read_database(&path)?;
+    database.commits.retain(|x| x.commit != commit);
+    database
+        .commits
+        .push(CommitImpact::new(commit, traces, &root));
+    let excess = database.commits.len().saturating_sub(MAX_COMMITS);
+    database.commits.drain(..excess);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string(&database)?)?;
+    info!("Recorded test impact in {}", path.display());
+    Ok(())
+}
+
+/// Splits `FILE:LINE` into the file and line, a location without a line is the whole file
+fn parse_location(location: &str) -> (&str, Option<u64>) {
+    match location.rsplit_once(':') {
+        Some((file, line)) => match line.parse() {
+            Ok(line) => (file, Some(line)),
+            Err(_) => (location, None),
+        },
+        None => (location, None),
+    }
+}
+
+/// Prints the tests recorded as hitting the source location, one per line. The coverage
+/// recorded for `commit` is used if given, otherwise HEAD's or the latest commit's recorded.
+pub fn which_tests(
+    configs: &[Config],
+    location: &str,
+    commit: Option<&str>,
+) -> Result<(), RunError> {
+    let config = match configs.first() {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let root = config.root();
+    let database = read_database(&database_path(config))?;
+    let recorded = match commit {
+        Some(rev) => {
+            let commit = resolve_commit(&root, rev).unwrap_or_else(|| rev.to_string());
+            database.commits.iter().find(|x| x.commit == commit)
+        }
+        None => resolve_commit(&root, "HEAD")
+            .and_then(|head| database.commits.iter().find(|x| x.commit == head))
+            .or_else(|| database.commits.last()),
+    };
+    let recorded = recorded.ok_or_else(|| {
+        RunError::TestCoverage("No test impact recorded, run with --test-impact first".to_string())
+    })?;
+    let (file, line) = parse_location(location);
+    let file = env::current_dir().unwrap_or_default().join(file);
+    let file = portable_path(&file, &root).to_string_lossy().to_string();
+    info!("Using the test impact recorded on {}", recorded.commit);
+    for test in recorded.tests_covering(&file, line) {
+        println!("{test}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    #[test]
+    fn tests_found_by_line() {
+        let root = Path::new("/project");
+        let file = root.join("src").join("lib.rs");
+        let mut traces = TraceMap::new();
+        let run = |hits: &[u64], tests: &[&str]| {
+            let mut run = TraceMap::new();
+            for line in 1..=4 {
+                let mut trace = Trace::new_stub(line);
+                trace.stats = CoverageStat::Line(hits.contains(&line) as u64);
+                run.add_trace(&file, trace);
+            }
+            run.tag_tests(&tests.iter().map(|x| x.to_string()).collect::<Vec<_>>());
+            run
+        };
+        traces.merge(&run(&[1, 2], &["lib tests::add"]));
+        traces.merge(&run(&[1, 3], &["lib tests::sub", "lib tests::mul"]));
+        traces.merge(&run(&[4], &["integration"]));
+
+        let impact = CommitImpact::new("abc".to_string(), &traces, root);
+        assert_eq!(
+            impact.tests_covering("src/lib.rs", Some(1)),
+            ["lib tests::add", "lib tests::mul", "lib tests::sub"]
+        );
+        assert_eq!(
+            impact.tests_covering("src/lib.rs", Some(4)),
+            ["integration"]
+        );
+        assert!(impact.tests_covering("src/lib.rs", Some(5)).is_empty());
+        assert!(impact.tests_covering("src/main.rs", None).is_empty());
+        assert_eq!(impact.tests_covering("src/lib.rs", None).len(), 4);
+
+        assert_eq!(parse_location("src/lib.rs:120"), ("src/lib.rs", Some(120)));
+        assert_eq!(parse_location("src/lib.rs"), ("src/lib.rs", None));
+        assert_eq!(
+            parse_location(r"C:\project\lib.rs"),
+            (r"C:\project\lib.rs", None)
+        );
+    }
+}
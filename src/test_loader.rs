@@ -1,5 +1,6 @@
+use crate::cargo::rust_flags;
 use crate::config::{types::TraceEngine, Config};
-use crate::path_utils::{fix_unc_path, is_coverable_file_path};
+use crate::path_utils::{fix_unc_path, is_coverable_file_path, remapped_prefixes, unmap_path};
 use crate::source_analysis::*;
 use crate::traces::*;
 use gimli::*;
@@ -150,6 +151,7 @@ fn get_addresses_from_program<R, Offset>(
     debug_strs: &DebugStr<R>,
     entries: &[(u64, LineType, &Option<String>)],
     config: &Config,
+    remaps: &[(PathBuf, PathBuf)],
     result: &mut HashMap<SourceLocation, Vec<TracerData>>,
 ) -> Result<()>
 where
@@ -183,11 +185,16 @@ where
                 let line = ln_row.line().unwrap();
                 if let Some(file) = file.string_value(debug_strs).and_then(get_string) {
                     path.push(file);
+                    if !remaps.is_empty() {
+                        path = unmap_path(&path, remaps);
+                    }
                     if !path.is_file() {
                         // Not really a source file!
                         continue;
                     }
-                    if is_coverable_file_path(&path, &project, &config.target_dir()) {
+                    if is_coverable_file_path(&path, &project, config.target_dir())
+                        && config.focus_path(&path)
+                    {
                         let address = ln_row.address();
                         let (desc, fn_name) = entries
                             .iter()
@@ -230,6 +237,7 @@ fn get_line_addresses<'data>(
     };
     trace!("Reading object sections");
     let mut result = TraceMap::new();
+    let remaps = remapped_prefixes(&rust_flags(config));
     trace!("Reading .debug_info");
     let debug_info = obj.section_by_name(".debug_info").ok_or(Error::Io)?;
     let debug_info = DebugInfo::new(debug_info.data().map_err(io_err)?, endian);
@@ -272,9 +280,14 @@ fn get_line_addresses<'data>(
             let prog = debug_line.program(offset, addr_size, None, None)?; // Here?
             let mut temp_map: HashMap<SourceLocation, Vec<TracerData>> = HashMap::new();
 
-            if let Err(e) =
-                get_addresses_from_program(prog, &debug_strings, &entries, config, &mut temp_map)
-            {
+            if let Err(e) = get_addresses_from_program(
+                prog,
+                &debug_strings,
+                &entries,
+                config,
+                &remaps,
+                &mut temp_map,
+            ) {
                 debug!("Potential issue reading test addresses {}", e);
             } else {
                 // Deduplicate addresses
@@ -284,7 +297,7 @@ fn get_line_addresses<'data>(
                 let temp_map = temp_map
                     .into_iter()
                     .filter(|(ref k, _)| {
-                        config.include_tests() || !k.path.starts_with(project.join("tests"))
+                        config.include_test_helpers() || !k.path.starts_with(project.join("tests"))
                     })
                     .filter(|(ref k, _)| !(config.exclude_path(&k.path)))
                     .filter(|(ref k, _)| config.include_path(&k.path))
@@ -415,3 +428,64 @@ pub fn generate_tracemap(
             io::Error::new(io::ErrorKind::InvalidData, "Error while parsing binary or DWARF info.")
         })
 }
+
+/// Whether a section holds the llvm counters, profiling data or coverage map added by
+/// `-Cinstrument-coverage`
+fn is_instrumentation_section(name: &str) -> bool {
+    let name = name.trim_start_matches('.').trim_start_matches("__");
+    ["llvm_prf_", "llvm_cov", "lprf", "lcov"]
+        .iter()
+        .any(|x| name.starts_with(x))
+}
+
+fn is_debug_section(name: &str) -> bool {
+    [".debug_", ".zdebug_", "__debug_"]
+        .iter()
+        .any(|x| name.starts_with(x))
+}
+
+/// How much of the test binary is instrumentation and debug info, `None` if it can't be read
+pub fn binary_size(test: &Path) -> Option<BinarySize> {
+    let file = File::open(test).ok()?;
+    let total = file.metadata().ok()?.len();
+    let file = object::read::ReadCache::new(file);
+    let obj = object::File::parse(&file).ok()?;
+    let mut size = BinarySize {
+        total,
+        ..Default::default()
+    };
+    for section in obj.sections() {
+        let len = section.file_range().map(|(_, len)| len).unwrap_or_default();
+        match section.name() {
+            Ok(name) if is_instrumentation_section(name) => size.instrumentation += len,
+            Ok(name) if is_debug_section(name) => size.debug_info += len,
+            _ => {}
+        }
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_classified() {
+        for name in [
+            "__llvm_prf_cnts",
+            "__llvm_covmap",
+            "__llvm_covfun",
+            ".lprfc$M",
+            ".lcovmap",
+        ] {
+            assert!(is_instrumentation_section(name), "{}", name);
+            assert!(!is_debug_section(name), "{}", name);
+        }
+        for name in [".debug_info", ".zdebug_line", "__debug_str"] {
+            assert!(is_debug_section(name), "{}", name);
+            assert!(!is_instrumentation_section(name), "{}", name);
+        }
+        assert!(!is_instrumentation_section(".text"));
+        assert!(!is_debug_section(".text"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Helpers for testing plugins, engines and reports against real tarpaulin runs. These are the
+//! same checks tarpaulin's own integration tests use, along with scaffolding to create throwaway
+//! projects, and are only available with the `test-utils` feature.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::event_log::EventLog;
+use crate::launch_tarpaulin;
+use crate::traces::TraceMap;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Timeout used for the tests in projects run by these helpers
+const TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+static PROJECT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A library crate in a new directory under the temporary directory, removed when dropped
+pub struct TestProject {
+    root: PathBuf,
+}
+
+impl TestProject {
+    /// Creates a library crate with the name and an empty `src/lib.rs`
+    pub fn new(name: &str) -> io::Result<Self> {
+        let count = PROJECT_COUNT.fetch_add(1, Ordering::SeqCst);
+        let root = env::temp_dir().join(format!("tarpaulin-{}-{}-{}", name, process::id(), count));
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+        let project = Self { root };
+        project
+            .file(
+                "Cargo.toml",
+                &format!(
+                    "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n"
+                ),
+            )?
+            .file("src/lib.rs", "")
+    }
+
+    /// Writes a file in the project, creating any directories it's in
+    pub fn file(self, path: impl AsRef<Path>, contents: &str) -> io::Result<Self> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        Ok(self)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest(&self) -> PathBuf {
+        self.root.join("Cargo.toml")
+    }
+
+    /// A default config for running the project's tests
+    pub fn config(&self) -> Config {
+        let mut config = Config::default();
+        config.set_manifest(self.manifest());
+        config.set_include_tests(true);
+        config.set_clean(false);
+        config.test_timeout = TEST_TIMEOUT;
+        config
+    }
+}
+
+impl Drop for TestProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Runs the tests of the project with the manifest in `project` and returns the coverage along
+/// with the return code of the tests. The working directory is restored afterwards, and an event
+/// log is written if `dump_traces` is set.
+pub fn run_project(project: &Path, mut config: Config) -> Result<(TraceMap, i32), RunError> {
+    let restore_dir = env::current_dir()?;
+    env::set_current_dir(project)?;
+    config.set_manifest(project.join("Cargo.toml"));
+    config.set_clean(false);
+    let event_log = if config.dump_traces {
+        let mut paths = HashSet::new();
+        paths.insert(config.manifest());
+        Some(EventLog::new(paths, &config))
+    } else {
+        None
+    };
+    let result = launch_tarpaulin(&config, &event_log);
+    env::set_current_dir(restore_dir)?;
+    result
+}
+
+/// Runs the tests of the project and asserts they passed with at least `minimum_coverage`
+/// (0.0-1.0). If the project shouldn't have any coverable lines `has_lines` is false.
+pub fn check_percentage_with_config(
+    project: &Path,
+    minimum_coverage: f64,
+    has_lines: bool,
+    mut config: Config,
+) -> TraceMap {
+    config.test_timeout = TEST_TIMEOUT;
+    let (res, ret) = run_project(project, config).unwrap();
+    assert_eq!(ret, 0);
+    if has_lines {
+        assert!(res.total_coverable() > 0);
+        assert!(
+            res.coverage_percentage() >= minimum_coverage,
+            "Assertion failed {} >= {}",
+            res.coverage_percentage(),
+            minimum_coverage
+        );
+    } else {
+        assert_eq!(res.total_coverable(), 0);
+    }
+    res
+}
+
+/// `check_percentage_with_config` with the default config including tests
+pub fn check_percentage(project: &Path, minimum_coverage: f64, has_lines: bool) -> TraceMap {
+    let mut config = Config::default();
+    config.set_include_tests(true);
+    check_percentage_with_config(project, minimum_coverage, has_lines, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_scaffolded_and_removed() {
+        let project = TestProject::new("scaffold")
+            .and_then(|x| x.file("tests/it.rs", "#[test]\nfn it() {}\n"))
+            .unwrap();
+        let root = project.root().to_path_buf();
+        let manifest = fs::read_to_string(project.manifest()).unwrap();
+        assert!(manifest.contains("name = \"scaffold\""));
+        assert!(root.join("src/lib.rs").exists());
+        assert!(root.join("tests/it.rs").exists());
+        assert_eq!(project.config().manifest(), project.manifest());
+        drop(project);
+        assert!(!root.exists());
+    }
+}
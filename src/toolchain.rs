@@ -0,0 +1,180 @@
+//! The toolchain and settings coverage was collected with. This is embedded in the JSON, HTML and
+//! Cobertura reports so surprising numbers can be traced back to the environment which produced
+//! them, and can be read back out of a stored report with `read_toolchain`.
+use crate::cargo::{rust_flags, rustdoc_flags};
+use crate::config::{Config, TraceEngine};
+use crate::errors::RunError;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Start of the comment holding the toolchain in a Cobertura report
+const XML_COMMENT_PREFIX: &str = " tarpaulin-toolchain ";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+    pub tarpaulin_version: String,
+    /// First line of `rustc -vV`
+    pub rustc_version: Option<String>,
+    pub cargo_version: Option<String>,
+    /// LLVM version rustc was built with
+    pub llvm_version: Option<String>,
+    pub engine: TraceEngine,
+    pub rustflags: String,
+    pub rustdocflags: String,
+}
+
+/// Rustc and LLVM versions from the output of `rustc -vV`
+fn parse_rustc_verbose(output: &str) -> (Option<String>, Option<String>) {
+    let rustc = output
+        .lines()
+        .next()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty());
+    let llvm = output
+        .lines()
+        .find_map(|x| x.strip_prefix("LLVM version:"))
+        .map(|x| x.trim().to_string());
+    (rustc, llvm)
+}
+
+fn command_output(program: &str, arg: &str) -> Option<String> {
+    Command::new(program)
+        .arg(arg)
+        .output()
+        .ok()
+        .filter(|x| x.status.success())
+        .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+}
+
+lazy_static! {
+    static ref RUSTC_VERSIONS: (Option<String>, Option<String>) =
+        parse_rustc_verbose(&command_output("rustc", "-vV").unwrap_or_default());
+    static ref CARGO_VERSION: Option<String> = command_output("cargo", "--version");
+}
+
+impl Toolchain {
+    /// The toolchain and flags tarpaulin builds the tests with for the config
+    pub fn probe(config: &Config) -> Self {
+        let (rustc_version, llvm_version) = RUSTC_VERSIONS.clone();
+        Self {
+            tarpaulin_version: env!("CARGO_PKG_VERSION").to_string(),
+            rustc_version,
+            cargo_version: CARGO_VERSION.clone(),
+            llvm_version,
+            engine: config.engine(),
+            rustflags: rust_flags(config),
+            rustdocflags: rustdoc_flags(config),
+        }
+    }
+
+    /// The toolchain as the contents of an XML comment. Comments can't contain `--` so any `-`
+    /// following another is written as a json unicode escape.
+    pub(crate) fn to_xml_comment(&self) -> serde_json::Result<String> {
+        let json = serde_json::to_string(self)?;
+        Ok(format!(
+            "{}{} ",
+            XML_COMMENT_PREFIX,
+            json.replace("--", "-\\u002d")
+        ))
+    }
+}
+
+fn from_json_report(report: &str) -> Result<Option<Toolchain>, RunError> {
+    let mut report: serde_json::Value = serde_json::from_str(report)?;
+    match report.get_mut("toolchain").map(serde_json::Value::take) {
+        Some(toolchain) => Ok(Some(serde_json::from_value(toolchain)?)),
+        None => Ok(None),
+    }
+}
+
+fn from_html_report(report: &str) -> Result<Option<Toolchain>, RunError> {
+    match report
+        .lines()
+        .find_map(|x| x.trim().strip_prefix("var data = "))
+        .and_then(|x| x.strip_suffix(';'))
+    {
+        Some(data) => from_json_report(data),
+        None => Ok(None),
+    }
+}
+
+fn from_xml_report(report: &str) -> Result<Option<Toolchain>, RunError> {
+    let start = format!("<!--{XML_COMMENT_PREFIX}");
+    match report
+        .split_once(&start)
+        .and_then(|(_, x)| x.split_once("-->"))
+    {
+        Some((json, _)) => Ok(Some(serde_json::from_str(json)?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the toolchain from a JSON, HTML or Cobertura report written by tarpaulin, the format is
+/// chosen from the file extension. Reports from before the toolchain was recorded give `None`.
+pub fn read_toolchain(report: &Path) -> Result<Option<Toolchain>, RunError> {
+    let contents = fs::read_to_string(report)?;
+    match report.extension().and_then(|x| x.to_str()) {
+        Some("json") => from_json_report(&contents),
+        Some("html") => from_html_report(&contents),
+        Some("xml") => from_xml_report(&contents),
+        _ => Err(RunError::OutFormat(format!(
+            "Can't read the toolchain from {}, expected a json, html or xml report",
+            report.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toolchain() -> Toolchain {
+        Toolchain {
+            tarpaulin_version: "0.31.4".to_string(),
+            rustc_version: Some("rustc 1.80.0 (051478957 2024-07-21)".to_string()),
+            cargo_version: Some("cargo 1.80.0 (376290515 2024-07-16)".to_string()),
+            llvm_version: Some("18.1.7".to_string()),
+            engine: TraceEngine::Llvm,
+            rustflags: "-Cdebuginfo=2 --cfg=tarpaulin -Cstrip=none".to_string(),
+            rustdocflags: "--cfg=tarpaulin ---weird".to_string(),
+        }
+    }
+
+    #[test]
+    fn rustc_verbose_version_parsed() {
+        let output = "rustc 1.80.0 (051478957 2024-07-21)
+binary: rustc
+commit-hash: 051478957371ee0084a7c0913941d2a8c4757bb9
+host: x86_64-unknown-linux-gnu
+release: 1.80.0
+LLVM version: 18.1.7
+";
+        let (rustc, llvm) = parse_rustc_verbose(output);
+        assert_eq!(
+            rustc.as_deref(),
+            Some("rustc 1.80.0 (051478957 2024-07-21)")
+        );
+        assert_eq!(llvm.as_deref(), Some("18.1.7"));
+        assert_eq!(parse_rustc_verbose(""), (None, None));
+    }
+
+    #[test]
+    fn toolchain_read_from_reports() {
+        let json = serde_json::json!({ "files": [], "toolchain": toolchain() }).to_string();
+        assert_eq!(from_json_report(&json).unwrap(), Some(toolchain()));
+        assert_eq!(from_json_report("{\"files\":[]}").unwrap(), None);
+
+        let html =
+            format!("<script>\n        var data = {json};\n        var previousData = null;");
+        assert_eq!(from_html_report(&html).unwrap(), Some(toolchain()));
+
+        let comment = toolchain().to_xml_comment().unwrap();
+        assert!(!comment.contains("--"));
+        let xml = format!("<?xml version=\"1.0\"?><!--{comment}--><coverage></coverage>");
+        assert_eq!(from_xml_report(&xml).unwrap(), Some(toolchain()));
+        assert_eq!(from_xml_report("<coverage></coverage>").unwrap(), None);
+    }
+}
@@ -1,10 +1,16 @@
-use crate::source_analysis::Function;
+use crate::config::RunType;
+use crate::path_utils::{native_path, portable_path};
+use crate::source_analysis::{
+    BranchArm, CodeRegion, DocExample, DocExampleKind, FeatureGate, Function, RegionKind,
+};
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering};
 use std::collections::btree_map::Iter;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::iter::FromIterator;
 use std::ops::Add;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 use tracing::trace;
 
 /// Used to track the state of logical conditions
@@ -62,6 +68,9 @@ pub struct Trace {
     pub length: usize,
     /// Coverage stats
     pub stats: CoverageStat,
+    /// Names of the test binaries which hit the trace
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hit_by: BTreeSet<String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -79,6 +88,7 @@ impl Trace {
             address,
             length,
             stats: CoverageStat::Line(0),
+            hit_by: BTreeSet::new(),
         }
     }
 
@@ -88,14 +98,14 @@ impl Trace {
             address: HashSet::new(),
             length: 0,
             stats: CoverageStat::Line(0),
+            hit_by: BTreeSet::new(),
         }
     }
 }
 
 impl PartialOrd for Trace {
     fn partial_cmp(&self, other: &Trace) -> Option<Ordering> {
-        // Not sure if I care about the others
-        self.line.partial_cmp(&other.line)
+        Some(self.cmp(other))
     }
 }
 
@@ -153,6 +163,333 @@ pub fn coverage_percentage<'a>(traces: impl Iterator<Item = &'a Trace>) -> f64 {
     (amount_covered(t.iter().copied()) as f64) / (amount_coverable(t.iter().copied()) as f64)
 }
 
+/// Coverage of an implicit branch in the source such as the early return introduced by the `?`
+/// operator. These are kept separate from the line traces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BranchTrace {
+    pub line: u64,
+    pub column: u64,
+    /// `been_true` is the expression continuing and `been_false` the early return
+    pub stats: LogicState,
+}
+
+/// Whether the error path or panic starting at the line and column ran. This is judged from the
+/// llvm coverage regions so other code on the same line, such as the `Ok` side of a one line
+/// `if`, doesn't count towards it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SiteTrace {
+    pub line: u64,
+    pub column: u64,
+    pub hit: bool,
+}
+
+/// A region of source an LLVM coverage counter is attached to, lines and columns are 1-indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Region {
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+impl Region {
+    fn contains_line(&self, line: usize) -> bool {
+        self.line_start <= line && line <= self.line_end
+    }
+}
+
+/// Counters for the regions in a single source file. A generic function gets a copy of its
+/// counters for every monomorphisation (and each object it's linked into), all of which point at
+/// the same region of source. Hits for the same region are summed so every instantiation
+/// contributes, and a line takes its hits from the regions on it instead of adding every region
+/// that spans it - otherwise nested regions and instantiations get counted multiple times.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionHits {
+    regions: BTreeMap<Region, u64>,
+}
+
+impl RegionHits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, region: Region, hits: u64) {
+        *self.regions.entry(region).or_default() += hits;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Hits for a line, as llvm-cov counts them. This is the most hit of the regions starting on
+    /// the line and the innermost region wrapping it from an earlier line, so `if x {` still counts
+    /// the condition when the body never ran. Returns `None` if no region covers the line.
+    pub fn hits_for_line(&self, line: usize) -> Option<u64> {
+        let starting = self
+            .regions
+            .iter()
+            .filter(|(region, _)| region.line_start == line)
+            .map(|(_, hits)| *hits)
+            .max();
+        let wrapping = self
+            .regions
+            .iter()
+            .filter(|(region, _)| region.line_start < line && region.contains_line(line))
+            .max_by_key(|(region, _)| {
+                (
+                    region.line_start,
+                    region.column_start,
+                    std::cmp::Reverse((region.line_end, region.column_end)),
+                )
+            })
+            .map(|(_, hits)| *hits);
+        starting.max(wrapping)
+    }
+
+    /// Hits of the innermost region containing the position, which are the hits of code starting
+    /// there. Returns `None` if no region contains the position.
+    pub fn hits_at(&self, line: usize, column: usize) -> Option<u64> {
+        self.regions
+            .iter()
+            .filter(|(region, _)| {
+                (region.line_start, region.column_start) <= (line, column)
+                    && (line, column) < (region.line_end, region.column_end)
+            })
+            .max_by_key(|(region, _)| {
+                (
+                    region.line_start,
+                    region.column_start,
+                    std::cmp::Reverse((region.line_end, region.column_end)),
+                )
+            })
+            .map(|(_, hits)| *hits)
+    }
+
+    /// Every line covered by a region with the hits for that line
+    pub fn line_hits(&self) -> BTreeMap<usize, u64> {
+        let lines = self
+            .regions
+            .keys()
+            .flat_map(|region| region.line_start..=region.line_end)
+            .collect::<BTreeSet<_>>();
+        lines
+            .into_iter()
+            .filter_map(|line| self.hits_for_line(line).map(|hits| (line, hits)))
+            .collect()
+    }
+}
+
+impl FromIterator<(Region, u64)> for RegionHits {
+    fn from_iter<I: IntoIterator<Item = (Region, u64)>>(iter: I) -> Self {
+        let mut result = Self::new();
+        for (region, hits) in iter {
+            result.add(region, hits);
+        }
+        result
+    }
+}
+
+/// A test executable that was ran to collect the coverage in a `TraceMap`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TestRun {
+    pub binary: PathBuf,
+    /// Target the binary was built for, tests recorded with `TraceMap::tag_tests` are named
+    /// after it
+    #[serde(default)]
+    pub target: String,
+    pub package: Option<String>,
+    pub run_type: Option<RunType>,
+    /// Wall clock time the test took to run and have its coverage collected in seconds
+    pub duration: f64,
+    /// Part of the duration spent loading debug info or mapping profraws to source in seconds
+    #[serde(default)]
+    pub collection: f64,
+    /// Size of the test binary, `None` if it couldn't be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<BinarySize>,
+    pub return_code: i32,
+    /// Whether this was the run of the ignored tests in the binary
+    #[serde(default)]
+    pub ignored: bool,
+    /// Tests ran in this process with `--process-per-test`, empty if it ran every test
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<String>,
+    /// Results of the tests read from the output of the binary, empty if it couldn't be read
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<TestResult>,
+}
+
+/// How a test finished according to libtest
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+/// Result of a single test reported by a test binary
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: TestOutcome,
+    /// Seconds the test took, only reported with `--report-time`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec_time: Option<f64>,
+}
+
+/// Sizes of the sections of a test binary in bytes
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BinarySize {
+    pub total: u64,
+    /// Counters, profiling data and coverage map added by `-Cinstrument-coverage`
+    pub instrumentation: u64,
+    pub debug_info: u64,
+}
+
+/// The cost of collecting coverage from one test binary over all of its runs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BinaryOverhead {
+    pub binary: PathBuf,
+    pub size: Option<BinarySize>,
+    /// Seconds spent running the binary and collecting its coverage
+    pub duration: f64,
+    /// Seconds of the duration spent collecting coverage
+    pub collection: f64,
+}
+
+impl BinaryOverhead {
+    /// Percentage of the time spent collecting coverage rather than running tests
+    pub fn collection_percentage(&self) -> f64 {
+        if self.duration > 0.0 {
+            100.0 * self.collection / self.duration
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Where the time went for one config of a run, and the coverage it collected
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConfigSummary {
+    pub name: String,
+    /// Seconds spent building the tests
+    pub build: f64,
+    /// Seconds spent running the tests, not counting collecting their coverage
+    pub test: f64,
+    /// Seconds spent collecting coverage
+    pub collection: f64,
+    pub covered: usize,
+    pub coverable: usize,
+    /// Percentage coverage ranging from 0-100
+    pub coverage: f64,
+}
+
+impl ConfigSummary {
+    /// Summarises the config from the time spent building it and the traces it collected
+    pub fn new(name: String, build: Duration, traces: &TraceMap) -> Self {
+        let collection = traces.runs.iter().map(|x| x.collection).sum::<f64>();
+        let duration = traces.runs.iter().map(|x| x.duration).sum::<f64>();
+        let coverable = traces.total_coverable();
+        Self {
+            name,
+            build: build.as_secs_f64(),
+            test: (duration - collection).max(0.0),
+            collection,
+            covered: traces.total_covered(),
+            coverable,
+            coverage: if coverable == 0 {
+                0.0
+            } else {
+                100.0 * traces.coverage_percentage()
+            },
+        }
+    }
+
+    /// Seconds spent on the config in total
+    pub fn total(&self) -> f64 {
+        self.build + self.test + self.collection
+    }
+}
+
+/// How much of the coverage came from the tests of one kind of target
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunTypeCoverage {
+    /// `Lib` and `Bins` are the unit tests of those targets, `Tests` the integration tests
+    pub run_type: RunType,
+    /// Lines hit by tests of this kind
+    pub covered: usize,
+    /// Percentage of the coverable lines hit by tests of this kind, ranging from 0-100
+    pub coverage: f64,
+    /// Lines no other kind of test hit
+    pub unique: usize,
+}
+
+/// Coverage of the code only compiled with a crate feature enabled
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureCoverage {
+    pub feature: String,
+    pub coverable: usize,
+    pub covered: usize,
+    /// Percentage coverage of the gated code ranging from 0-100
+    pub coverage: f64,
+}
+
+/// Coverage of the lines in `unsafe` blocks and functions outside of tests
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct UnsafeCoverage {
+    pub coverable: usize,
+    pub covered: usize,
+    /// Percentage coverage of the unsafe code ranging from 0-100
+    pub coverage: f64,
+}
+
+/// A function other languages can call through FFI and whether the tests ran it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FfiFunction {
+    pub path: PathBuf,
+    pub name: String,
+    pub line: u64,
+    pub covered: bool,
+}
+
+/// What kind of code a coverable line is in, for tools such as mutation testers which only want
+/// to work on covered production code. Lines which aren't test code are production code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LineClass {
+    pub line: u64,
+    pub covered: bool,
+    pub test: bool,
+    #[serde(rename = "unsafe")]
+    pub unsafe_code: bool,
+    pub branch: bool,
+}
+
+/// How many of the error handling paths and panics outside of tests ran. Sites without any
+/// coverable lines can't be judged so aren't counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ErrorPathCoverage {
+    /// `Err` match arms, `if let Err` bodies, expressions constructing an `Err` and the early
+    /// returns of `?` when branch coverage was collected
+    pub error_paths: usize,
+    pub error_paths_hit: usize,
+    /// `panic!` calls
+    pub panics: usize,
+    pub panics_hit: usize,
+    /// Percentage of the error paths and panics which ran ranging from 0-100
+    pub coverage: f64,
+}
+
+/// Number of rust examples in doc comments by how rustdoc treats them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DocExampleStats {
+    pub run: usize,
+    /// Examples which are compiled but don't run, these can't contribute any coverage
+    pub no_run: usize,
+    /// Examples which aren't compiled, these aren't counted as doc examples
+    pub ignored: usize,
+}
+
 /// Stores all the program traces mapped to files and provides an interface to
 /// add, query and change traces.
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -160,6 +497,77 @@ pub struct TraceMap {
     ///rTraces in the program mapped to the given file
     traces: BTreeMap<PathBuf, Vec<Trace>>,
     functions: HashMap<PathBuf, Vec<Function>>,
+    /// Test executables ran to produce these traces
+    #[serde(default)]
+    runs: Vec<TestRun>,
+    /// Summaries of each config ran to produce these traces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    configs: Vec<ConfigSummary>,
+    /// Implicit branches found in the source, only collected with branch coverage
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    branches: BTreeMap<PathBuf, Vec<BranchTrace>>,
+    /// Error paths and panics judged from the coverage regions, only collected with the llvm
+    /// engine and `--error-path-coverage`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sites: BTreeMap<PathBuf, Vec<SiteTrace>>,
+    /// Rust code examples in the doc comments of each file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    doc_examples: BTreeMap<PathBuf, Vec<DocExample>>,
+    /// Code gated on crate features in each file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    feature_gates: BTreeMap<PathBuf, Vec<FeatureGate>>,
+    /// Match arms and else branches in each file, only collected with branch coverage
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    branch_arms: BTreeMap<PathBuf, Vec<BranchArm>>,
+    /// Test code, unsafe code and branches in each file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    code_regions: BTreeMap<PathBuf, Vec<CodeRegion>>,
+    /// Other paths the traces of a file were found under and merged from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    /// Lines hit by the tests of each kind of target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    run_type_hits: BTreeMap<RunType, BTreeMap<PathBuf, BTreeSet<u64>>>,
+    /// Lines hit by each test, only collected with `--test-impact`. Tests are named by their
+    /// target and test name separated by a space, or just the target when the whole binary ran
+    /// in one process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    test_hits: BTreeMap<String, BTreeMap<PathBuf, BTreeSet<u64>>>,
+    /// Whether the source paths are relative to the project root with `/` separators, from
+    /// `TraceMap::make_portable`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    portable: bool,
+    /// Whether any of the coverage was sampled by the perf engine, sampled hit counts are only
+    /// approximate and lines which ran can be missed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    sampled: bool,
+    /// Time spent collecting the coverage of the test binary being ran, moved to its `TestRun`
+    #[serde(skip)]
+    collection_time: Duration,
+}
+
+/// Applies `f` to the keys of a map of files
+fn map_keys<M, V>(map: &mut M, f: &impl Fn(&Path) -> PathBuf)
+where
+    M: Default + IntoIterator<Item = (PathBuf, V)> + FromIterator<(PathBuf, V)>,
+{
+    *map = std::mem::take(map)
+        .into_iter()
+        .map(|(k, v)| (f(&k), v))
+        .collect();
+}
+
+/// Copies the entries of a map of files for the files `include` accepts
+fn filter_keys<'a, M, V>(map: &'a M, include: &impl Fn(&Path) -> bool) -> M
+where
+    &'a M: IntoIterator<Item = (&'a PathBuf, &'a V)>,
+    M: FromIterator<(PathBuf, V)>,
+    V: Clone + 'a,
+{
+    map.into_iter()
+        .filter(|(k, _)| include(k))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
 }
 
 impl TraceMap {
@@ -168,17 +576,599 @@ impl TraceMap {
         Self::default()
     }
 
+    fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        map_keys(&mut self.traces, &f);
+        map_keys(&mut self.functions, &f);
+        map_keys(&mut self.branches, &f);
+        map_keys(&mut self.sites, &f);
+        map_keys(&mut self.doc_examples, &f);
+        map_keys(&mut self.feature_gates, &f);
+        map_keys(&mut self.branch_arms, &f);
+        map_keys(&mut self.code_regions, &f);
+        map_keys(&mut self.aliases, &f);
+        for hits in self.run_type_hits.values_mut() {
+            map_keys(hits, &f);
+        }
+        for hits in self.test_hits.values_mut() {
+            map_keys(hits, &f);
+        }
+    }
+
+    /// Makes the source paths relative to the project root with `/` as the separator, so traces
+    /// collected on one OS can be merged with traces from another
+    pub fn make_portable(&mut self, root: &Path) {
+        if !self.portable {
+            self.map_paths(|x| portable_path(x, root));
+            self.portable = true;
+        }
+    }
+
+    /// Converts the paths of traces made portable with `make_portable` back to native paths
+    /// under the root, traces which aren't portable are left as they are
+    pub fn make_native(&mut self, root: &Path) {
+        if self.portable {
+            self.map_paths(|x| native_path(x, root));
+            self.portable = false;
+        }
+    }
+
+    /// Copy of the traces of the files `include` accepts, the test runs are kept as they are
+    pub fn subset(&self, include: impl Fn(&Path) -> bool) -> TraceMap {
+        TraceMap {
+            traces: filter_keys(&self.traces, &include),
+            functions: filter_keys(&self.functions, &include),
+            runs: self.runs.clone(),
+            configs: vec![],
+            branches: filter_keys(&self.branches, &include),
+            sites: filter_keys(&self.sites, &include),
+            doc_examples: filter_keys(&self.doc_examples, &include),
+            feature_gates: filter_keys(&self.feature_gates, &include),
+            branch_arms: filter_keys(&self.branch_arms, &include),
+            code_regions: filter_keys(&self.code_regions, &include),
+            aliases: filter_keys(&self.aliases, &include),
+            run_type_hits: self
+                .run_type_hits
+                .iter()
+                .map(|(run_type, hits)| (*run_type, filter_keys(hits, &include)))
+                .collect(),
+            test_hits: self
+                .test_hits
+                .iter()
+                .map(|(test, hits)| (test.clone(), filter_keys(hits, &include)))
+                .collect(),
+            portable: self.portable,
+            sampled: self.sampled,
+            collection_time: Duration::ZERO,
+        }
+    }
+
+    /// Removes a source file and everything recorded about it
+    pub fn remove_file(&mut self, path: &Path) {
+        self.take_file(path);
+    }
+
+    /// Removes a source file returning everything recorded about it
+    fn take_file(&mut self, path: &Path) -> TraceMap {
+        let mut file = TraceMap::new();
+        if let Some(traces) = self.traces.remove(path) {
+            file.traces.insert(path.to_path_buf(), traces);
+        }
+        if let Some(functions) = self.functions.remove(path) {
+            file.functions.insert(path.to_path_buf(), functions);
+        }
+        if let Some(branches) = self.branches.remove(path) {
+            file.branches.insert(path.to_path_buf(), branches);
+        }
+        if let Some(sites) = self.sites.remove(path) {
+            file.sites.insert(path.to_path_buf(), sites);
+        }
+        if let Some(examples) = self.doc_examples.remove(path) {
+            file.doc_examples.insert(path.to_path_buf(), examples);
+        }
+        if let Some(gates) = self.feature_gates.remove(path) {
+            file.feature_gates.insert(path.to_path_buf(), gates);
+        }
+        if let Some(arms) = self.branch_arms.remove(path) {
+            file.branch_arms.insert(path.to_path_buf(), arms);
+        }
+        if let Some(regions) = self.code_regions.remove(path) {
+            file.code_regions.insert(path.to_path_buf(), regions);
+        }
+        if let Some(aliases) = self.aliases.remove(path) {
+            file.aliases.insert(path.to_path_buf(), aliases);
+        }
+        for (run_type, hits) in self.run_type_hits.iter_mut() {
+            if let Some(lines) = hits.remove(path) {
+                file.run_type_hits
+                    .entry(*run_type)
+                    .or_default()
+                    .insert(path.to_path_buf(), lines);
+            }
+        }
+        for (test, hits) in self.test_hits.iter_mut() {
+            if let Some(lines) = hits.remove(path) {
+                file.test_hits
+                    .entry(test.clone())
+                    .or_default()
+                    .insert(path.to_path_buf(), lines);
+            }
+        }
+        file
+    }
+
+    /// Merges the traces of files found under more than one path, such as a shared crate
+    /// reached through a symlink or `..` from several packages, when `canonical` resolves the
+    /// paths to the same file. The traces are kept under one of the paths found, preferring one
+    /// inside `root` without `..`, so files are still reported where the project reaches them.
+    /// Returns how many files were found under more than one path.
+    pub fn merge_aliases(&mut self, root: &Path, canonical: impl Fn(&Path) -> PathBuf) -> usize {
+        let mut paths = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+        for path in self.traces.keys() {
+            paths.entry(canonical(path)).or_default().push(path.clone());
+        }
+        let mut duplicated = 0;
+        for mut found in paths.into_values().filter(|x| x.len() > 1) {
+            duplicated += 1;
+            found.sort_by_key(|x| {
+                let parent_dir = x.components().any(|c| c == Component::ParentDir);
+                (!x.starts_with(root), parent_dir, x.components().count())
+            });
+            let path = found.remove(0);
+            self.aliases
+                .entry(path.clone())
+                .or_default()
+                .extend(found.iter().cloned());
+            for alias in found {
+                let mut file = self.take_file(&alias);
+                file.map_paths(|_| path.clone());
+                self.merge(&file);
+            }
+        }
+        duplicated
+    }
+
+    /// Files whose traces were merged from other paths by `merge_aliases`, with those paths
+    pub fn aliases(&self) -> impl Iterator<Item = (&PathBuf, &BTreeSet<PathBuf>)> {
+        self.aliases.iter()
+    }
+
     pub fn set_functions(&mut self, functions: HashMap<PathBuf, Vec<Function>>) {
         self.functions = functions;
     }
 
+    pub fn set_doc_examples(&mut self, examples: HashMap<PathBuf, Vec<DocExample>>) {
+        self.doc_examples = examples.into_iter().collect();
+    }
+
+    pub fn set_branch_arms(&mut self, arms: HashMap<PathBuf, Vec<BranchArm>>) {
+        self.branch_arms = arms.into_iter().collect();
+    }
+
+    pub fn set_code_regions(&mut self, regions: HashMap<PathBuf, Vec<CodeRegion>>) {
+        self.code_regions = regions.into_iter().collect();
+    }
+
+    /// Classifies each coverable line of the file as test or production code, unsafe and whether
+    /// it's a branch. Empty if the file wasn't classified by source analysis.
+    pub fn line_classes(&self, file: &Path) -> Vec<LineClass> {
+        let (regions, traces) = match (self.code_regions.get(file), self.traces.get(file)) {
+            (Some(regions), Some(traces)) => (regions, traces),
+            _ => return vec![],
+        };
+        let mut lines = BTreeMap::<u64, bool>::new();
+        for trace in traces {
+            *lines.entry(trace.line).or_default() |= amount_covered(std::iter::once(trace)) > 0;
+        }
+        lines
+            .into_iter()
+            .map(|(line, covered)| {
+                let is = |kind| {
+                    regions
+                        .iter()
+                        .any(|x| x.kind == kind && x.contains(line as usize))
+                };
+                LineClass {
+                    line,
+                    covered,
+                    test: is(RegionKind::Test),
+                    unsafe_code: is(RegionKind::Unsafe),
+                    branch: is(RegionKind::Branch),
+                }
+            })
+            .collect()
+    }
+
+    /// Coverage of the lines in unsafe code outside of tests, `None` if there aren't any
+    pub fn unsafe_coverage(&self) -> Option<UnsafeCoverage> {
+        let (mut coverable, mut covered) = (0, 0);
+        for file in self.code_regions.keys() {
+            for class in self.line_classes(file) {
+                if class.unsafe_code && !class.test {
+                    coverable += 1;
+                    covered += usize::from(class.covered);
+                }
+            }
+        }
+        if coverable == 0 {
+            return None;
+        }
+        Some(UnsafeCoverage {
+            coverable,
+            covered,
+            coverage: 100.0 * covered as f64 / coverable as f64,
+        })
+    }
+
+    /// Counts the error paths and panics outside of tests by whether they ran, `None` if there
+    /// aren't any. Where the coverage regions were collected a site ran if the code at its start
+    /// did, otherwise it ran if any of its lines were hit.
+    pub fn error_path_coverage(&self) -> Option<ErrorPathCoverage> {
+        let mut stats = ErrorPathCoverage::default();
+        let in_test = |file: &Path, line: u64| {
+            self.code_regions.get(file).is_some_and(|regions| {
+                regions
+                    .iter()
+                    .any(|x| x.kind == RegionKind::Test && x.contains(line as usize))
+            })
+        };
+        // The early returns of `?`
+        for (file, branches) in &self.branches {
+            for branch in branches.iter().filter(|x| !in_test(file, x.line)) {
+                stats.error_paths += 1;
+                stats.error_paths_hit += usize::from(branch.stats.been_false);
+            }
+        }
+        for (file, regions) in &self.code_regions {
+            let traces = match self.traces.get(file) {
+                Some(traces) => traces,
+                None => continue,
+            };
+            let file_sites = self.sites.get(file).map(Vec::as_slice).unwrap_or_default();
+            let within = |inner: &CodeRegion, outer: &CodeRegion| {
+                inner != outer && outer.start <= inner.start && inner.end <= outer.end
+            };
+            for region in regions {
+                let (total, hit) = match region.kind {
+                    RegionKind::ErrorPath => (&mut stats.error_paths, &mut stats.error_paths_hit),
+                    RegionKind::Panic => (&mut stats.panics, &mut stats.panics_hit),
+                    _ => continue,
+                };
+                // Sites in tests aren't error handling, and an `Err` returned from an `Err` arm
+                // is the same path so is only counted once
+                if regions.iter().any(|x| {
+                    (x.kind == RegionKind::Test || x.kind == region.kind) && within(region, x)
+                }) {
+                    continue;
+                }
+                let site = file_sites
+                    .iter()
+                    .find(|x| x.line == region.start as u64 && x.column == region.column as u64);
+                if let Some(site) = site {
+                    *total += 1;
+                    *hit += usize::from(site.hit);
+                    continue;
+                }
+                let mut body = traces
+                    .iter()
+                    .filter(|x| region.contains(x.line as usize))
+                    .peekable();
+                if body.peek().is_some() {
+                    *total += 1;
+                    if amount_covered(body) > 0 {
+                        *hit += 1;
+                    }
+                }
+            }
+        }
+        let sites = stats.error_paths + stats.panics;
+        if sites == 0 {
+            return None;
+        }
+        stats.coverage = 100.0 * (stats.error_paths_hit + stats.panics_hit) as f64 / sites as f64;
+        Some(stats)
+    }
+
+    /// Match arms and else branches in the file where none of the coverable lines in their body
+    /// were hit. Arms without any coverable lines can't be judged so are left out.
+    pub fn uncovered_branch_arms(&self, file: &Path) -> Vec<&BranchArm> {
+        let (arms, traces) = match (self.branch_arms.get(file), self.traces.get(file)) {
+            (Some(arms), Some(traces)) => (arms, traces),
+            _ => return vec![],
+        };
+        arms.iter()
+            .filter(|arm| {
+                let mut body = traces
+                    .iter()
+                    .filter(|x| (arm.start as u64..=arm.end as u64).contains(&x.line))
+                    .peekable();
+                body.peek().is_some() && amount_covered(body) == 0
+            })
+            .collect()
+    }
+
+    pub fn set_feature_gates(&mut self, gates: HashMap<PathBuf, Vec<FeatureGate>>) {
+        self.feature_gates = gates.into_iter().collect();
+    }
+
+    /// Coverage of the code gated on each feature, sorted by feature name. A line gated on the
+    /// same feature more than once is only counted once.
+    pub fn feature_coverage(&self) -> Vec<FeatureCoverage> {
+        let mut lines = BTreeMap::<&str, BTreeMap<u64, bool>>::new();
+        for (file, gates) in &self.feature_gates {
+            let traces = match self.traces.get(file) {
+                Some(traces) => traces,
+                None => continue,
+            };
+            for gate in gates {
+                let feature_lines = lines.entry(gate.feature.as_str()).or_default();
+                for trace in traces
+                    .iter()
+                    .filter(|x| (gate.start as u64..=gate.end as u64).contains(&x.line))
+                {
+                    let covered = amount_covered(std::iter::once(trace)) > 0;
+                    *feature_lines.entry(trace.line).or_default() |= covered;
+                }
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(feature, lines)| {
+                let coverable = lines.len();
+                let covered = lines.values().filter(|x| **x).count();
+                FeatureCoverage {
+                    feature: feature.to_string(),
+                    coverable,
+                    covered,
+                    coverage: 100.0 * covered as f64 / coverable.max(1) as f64,
+                }
+            })
+            .collect()
+    }
+
+    /// Counts the doc examples by whether they're ran, `None` if there aren't any
+    pub fn doc_example_stats(&self) -> Option<DocExampleStats> {
+        let mut stats = DocExampleStats::default();
+        for example in self.doc_examples.values().flatten() {
+            match example.kind {
+                DocExampleKind::Run => stats.run += 1,
+                DocExampleKind::NoRun => stats.no_run += 1,
+                DocExampleKind::Ignore => stats.ignored += 1,
+            }
+        }
+        (stats != DocExampleStats::default()).then_some(stats)
+    }
+
+    /// Records a test executable ran to collect these traces
+    pub fn add_run(&mut self, run: TestRun) {
+        self.runs.push(run);
+    }
+
+    /// Test executables ran to collect these traces
+    pub fn runs(&self) -> &[TestRun] {
+        &self.runs
+    }
+
+    /// Records the summary of a config ran to collect these traces
+    pub fn add_config_summary(&mut self, summary: ConfigSummary) {
+        self.configs.push(summary);
+    }
+
+    /// Summaries of the configs ran to collect these traces
+    pub fn config_summaries(&self) -> &[ConfigSummary] {
+        &self.configs
+    }
+
+    /// Merges the coverage of tests rerun after failing over these traces. The failed runs of the
+    /// rerun binaries are dropped and the config summaries are the rerun's.
+    pub fn merge_rerun(&mut self, rerun: &TraceMap) {
+        self.runs.retain(|run| {
+            run.return_code == 0
+                || !rerun
+                    .runs
+                    .iter()
+                    .any(|x| x.binary == run.binary && x.ignored == run.ignored)
+        });
+        self.configs.clear();
+        self.merge(rerun);
+        self.dedup();
+    }
+
+    /// Marks the coverage as sampled rather than traced
+    pub fn set_sampled(&mut self) {
+        self.sampled = true;
+    }
+
+    /// Whether any of the coverage was sampled, making it approximate
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Records time spent collecting coverage rather than running the test
+    pub fn add_collection_time(&mut self, time: Duration) {
+        self.collection_time += time;
+    }
+
+    pub fn collection_time(&self) -> Duration {
+        self.collection_time
+    }
+
+    /// Records the test binary as having hit every trace hit so far, this should be called on the
+    /// traces collected from a single test binary
+    pub fn tag_binary(&mut self, binary: &str) {
+        for trace in self
+            .all_traces_mut()
+            .filter(|x| amount_covered(std::iter::once(&**x)) > 0)
+        {
+            trace.hit_by.insert(binary.to_string());
+        }
+    }
+
+    /// Records the lines hit so far as being hit by tests of the given kind of target, this
+    /// should be called on the traces collected from a single test binary
+    pub fn tag_run_type(&mut self, run_type: RunType) {
+        let hits = self.run_type_hits.entry(run_type).or_default();
+        for (file, traces) in &self.traces {
+            let lines = traces
+                .iter()
+                .filter(|x| amount_covered(std::iter::once(*x)) > 0)
+                .map(|x| x.line);
+            hits.entry(file.clone()).or_default().extend(lines);
+        }
+    }
+
+    /// Records the lines hit so far as being hit by each of the tests, this should be called on
+    /// the traces collected from a single test process
+    pub fn tag_tests(&mut self, tests: &[String]) {
+        let mut hits = BTreeMap::<PathBuf, BTreeSet<u64>>::new();
+        for (file, traces) in &self.traces {
+            let lines = traces
+                .iter()
+                .filter(|x| amount_covered(std::iter::once(*x)) > 0)
+                .map(|x| x.line)
+                .collect::<BTreeSet<_>>();
+            if !lines.is_empty() {
+                hits.insert(file.clone(), lines);
+            }
+        }
+        for test in tests {
+            let existing = self.test_hits.entry(test.clone()).or_default();
+            for (file, lines) in &hits {
+                existing.entry(file.clone()).or_default().extend(lines);
+            }
+        }
+    }
+
+    /// Lines hit by each test recorded with `tag_tests`
+    pub fn test_hits(&self) -> &BTreeMap<String, BTreeMap<PathBuf, BTreeSet<u64>>> {
+        &self.test_hits
+    }
+
+    /// Tests recorded with `tag_tests` which passed without running a line outside of test code,
+    /// so they pass whatever the code they're meant to test does. Only tests ran in their own
+    /// process are judged, one passed if libtest reported a test of its name in its target
+    /// passing.
+    pub fn tautological_tests(&self) -> Vec<&str> {
+        let passed = self
+            .runs
+            .iter()
+            .flat_map(|run| {
+                run.results
+                    .iter()
+                    .filter(|x| x.outcome == TestOutcome::Passed)
+                    .map(move |x| (run.target.as_str(), x.name.as_str()))
+            })
+            .collect::<HashSet<_>>();
+        let is_test_code = |file: &PathBuf, line: u64| {
+            self.code_regions.get(file).is_some_and(|regions| {
+                regions
+                    .iter()
+                    .any(|x| x.kind == RegionKind::Test && x.contains(line as usize))
+            })
+        };
+        self.test_hits
+            .iter()
+            .filter(|(test, _)| test.split_once(' ').is_some_and(|x| passed.contains(&x)))
+            .filter(|(_, hits)| {
+                hits.iter()
+                    .all(|(file, lines)| lines.iter().all(|line| is_test_code(file, *line)))
+            })
+            .map(|(test, _)| test.as_str())
+            .collect()
+    }
+
+    /// Size and time overhead of each test binary ran, a binary ran more than once such as for
+    /// its ignored tests or with `--process-per-test` has its runs added up
+    pub fn binary_overheads(&self) -> Vec<BinaryOverhead> {
+        let mut overheads = BTreeMap::<&Path, BinaryOverhead>::new();
+        for run in &self.runs {
+            let overhead = overheads
+                .entry(&run.binary)
+                .or_insert_with(|| BinaryOverhead {
+                    binary: run.binary.clone(),
+                    size: None,
+                    duration: 0.0,
+                    collection: 0.0,
+                });
+            overhead.size = overhead.size.or(run.size);
+            overhead.duration += run.duration;
+            overhead.collection += run.collection;
+        }
+        overheads.into_values().collect()
+    }
+
+    /// The coverage contributed by each kind of test, empty if the traces weren't tagged
+    pub fn run_type_coverage(&self) -> Vec<RunTypeCoverage> {
+        let coverable = self
+            .traces
+            .values()
+            .map(|x| x.iter().map(|x| x.line).collect::<BTreeSet<_>>().len())
+            .sum::<usize>()
+            .max(1);
+        let hit_by_others = |run_type: &RunType, file: &PathBuf, line: &u64| {
+            self.run_type_hits
+                .iter()
+                .filter(|(rt, _)| *rt != run_type)
+                .any(|(_, hits)| hits.get(file).is_some_and(|x| x.contains(line)))
+        };
+        self.run_type_hits
+            .iter()
+            .map(|(run_type, hits)| {
+                let covered = hits.values().map(BTreeSet::len).sum::<usize>();
+                let unique = hits
+                    .iter()
+                    .flat_map(|(file, lines)| lines.iter().map(move |l| (file, l)))
+                    .filter(|(file, line)| !hit_by_others(run_type, file, line))
+                    .count();
+                RunTypeCoverage {
+                    run_type: *run_type,
+                    covered,
+                    coverage: 100.0 * covered as f64 / coverable as f64,
+                    unique,
+                }
+            })
+            .collect()
+    }
+
+    /// Adds a branch, if the branch is already present the results are combined
+    pub fn add_branch(&mut self, file: &Path, branch: BranchTrace) {
+        let branches = self.branches.entry(file.to_path_buf()).or_default();
+        if let Some(existing) = branches
+            .iter_mut()
+            .find(|x| x.line == branch.line && x.column == branch.column)
+        {
+            existing.stats = &existing.stats + &branch.stats;
+        } else {
+            branches.push(branch);
+            branches.sort_unstable_by_key(|x| (x.line, x.column));
+        }
+    }
+
+    /// Adds an error path or panic, if it's already present it's hit if either was hit
+    pub fn add_site(&mut self, file: &Path, site: SiteTrace) {
+        let sites = self.sites.entry(file.to_path_buf()).or_default();
+        if let Some(existing) = sites
+            .iter_mut()
+            .find(|x| x.line == site.line && x.column == site.column)
+        {
+            existing.hit |= site.hit;
+        } else {
+            sites.push(site);
+            sites.sort_unstable_by_key(|x| (x.line, x.column));
+        }
+    }
+
+    /// Provides an iterator over the files and their implicit branches
+    pub fn branches(&self) -> Iter<'_, PathBuf, Vec<BranchTrace>> {
+        self.branches.iter()
+    }
+
     /// Returns true if there are no traces
     pub fn is_empty(&self) -> bool {
         self.traces.is_empty()
     }
 
     /// Provides an iterator to the underlying map of PathBufs to Vec<Trace>
-    pub fn iter(&self) -> Iter<PathBuf, Vec<Trace>> {
+    pub fn iter(&self) -> Iter<'_, PathBuf, Vec<Trace>> {
         self.traces.iter()
     }
 
@@ -188,6 +1178,61 @@ impl TraceMap {
     pub fn merge(&mut self, other: &TraceMap) {
         self.functions
             .extend(other.functions.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.runs.extend(other.runs.iter().cloned());
+        self.configs.extend(other.configs.iter().cloned());
+        self.sampled |= other.sampled;
+        self.doc_examples.extend(
+            other
+                .doc_examples
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        self.feature_gates.extend(
+            other
+                .feature_gates
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        self.branch_arms.extend(
+            other
+                .branch_arms
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        self.code_regions.extend(
+            other
+                .code_regions
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        for (file, aliases) in &other.aliases {
+            self.aliases
+                .entry(file.clone())
+                .or_default()
+                .extend(aliases.iter().cloned());
+        }
+        for (run_type, hits) in &other.run_type_hits {
+            let existing = self.run_type_hits.entry(*run_type).or_default();
+            for (file, lines) in hits {
+                existing.entry(file.clone()).or_default().extend(lines);
+            }
+        }
+        for (test, hits) in &other.test_hits {
+            let existing = self.test_hits.entry(test.clone()).or_default();
+            for (file, lines) in hits {
+                existing.entry(file.clone()).or_default().extend(lines);
+            }
+        }
+        for (file, branches) in &other.branches {
+            for branch in branches {
+                self.add_branch(file, *branch);
+            }
+        }
+        for (file, sites) in &other.sites {
+            for site in sites {
+                self.add_site(file, *site);
+            }
+        }
         for (k, values) in other.iter() {
             if !self.traces.contains_key(k) {
                 self.traces.insert(k.clone(), values.clone());
@@ -200,6 +1245,7 @@ impl TraceMap {
                         .find(|x| x.line == v.line && x.address == v.address)
                     {
                         t.stats = t.stats.clone() + v.stats.clone();
+                        t.hit_by.extend(v.hit_by.iter().cloned());
                         added = true;
                     }
                     if !added {
@@ -218,36 +1264,33 @@ impl TraceMap {
     pub fn dedup(&mut self) {
         for values in self.traces.values_mut() {
             // Map of lines and stats, merge duplicated stats here
-            let mut lines: HashMap<u64, CoverageStat> = HashMap::new();
+            let mut lines: HashMap<u64, (CoverageStat, BTreeSet<String>)> = HashMap::new();
             // Duplicated traces need cleaning up. Maintain a list of them!
             let mut dirty: Vec<u64> = Vec::new();
             for v in values.iter() {
                 lines
                     .entry(v.line)
-                    .and_modify(|e| {
+                    .and_modify(|(stats, hit_by)| {
                         dirty.push(v.line);
-                        *e = e.clone() + v.stats.clone();
+                        *stats = stats.clone() + v.stats.clone();
+                        hit_by.extend(v.hit_by.iter().cloned());
                     })
-                    .or_insert_with(|| v.stats.clone());
+                    .or_insert_with(|| (v.stats.clone(), v.hit_by.clone()));
             }
             for d in &dirty {
                 let mut first = true;
                 values.retain(|x| {
                     let res = x.line != *d;
                     if !res {
-                        if first {
-                            first = false;
-                            true
-                        } else {
-                            false
-                        }
+                        std::mem::take(&mut first)
                     } else {
                         res
                     }
                 });
-                if let Some(new_stat) = lines.remove(d) {
+                if let Some((new_stat, hit_by)) = lines.remove(d) {
                     if let Some(ref mut t) = values.iter_mut().find(|x| x.line == *d) {
                         t.stats = new_stat;
+                        t.hit_by = hit_by;
                     }
                 }
             }
@@ -378,11 +1421,52 @@ impl TraceMap {
     pub fn coverage_percentage(&self) -> f64 {
         coverage_percentage(self.all_traces())
     }
+
+    /// Public functions which have coverable lines but none of them were hit
+    pub fn uncovered_public_functions(&self) -> Vec<(&Path, &Function)> {
+        let mut result = vec![];
+        for (file, traces) in &self.traces {
+            for function in self.get_functions(file).filter(|x| x.public) {
+                let mut lines = traces
+                    .iter()
+                    .filter(|x| (function.start..=function.end).contains(&x.line))
+                    .peekable();
+                if lines.peek().is_some() && amount_covered(lines) == 0 {
+                    result.push((file.as_path(), function));
+                }
+            }
+        }
+        result
+    }
+
+    /// Functions with a foreign ABI or unmangled symbol which have coverable lines, by whether
+    /// any of them were hit
+    pub fn ffi_functions(&self) -> Vec<FfiFunction> {
+        let mut result = vec![];
+        for (file, traces) in &self.traces {
+            for function in self.get_functions(file).filter(|x| x.ffi) {
+                let mut lines = traces
+                    .iter()
+                    .filter(|x| (function.start..=function.end).contains(&x.line))
+                    .peekable();
+                if lines.peek().is_some() {
+                    result.push(FfiFunction {
+                        path: file.clone(),
+                        name: function.name.clone(),
+                        line: function.start,
+                        covered: amount_covered(lines) > 0,
+                    });
+                }
+            }
+        }
+        result
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::source_analysis::BranchArmKind;
     use std::path::Path;
 
     #[test]
@@ -437,6 +1521,7 @@ mod tests {
             address,
             length: 0,
             stats: CoverageStat::Line(1),
+            hit_by: BTreeSet::new(),
         };
         t1.add_trace(Path::new("file.rs"), trace_1);
 
@@ -458,6 +1543,7 @@ mod tests {
             address,
             length: 0,
             stats: CoverageStat::Line(1),
+            hit_by: BTreeSet::new(),
         };
         t1.add_trace(Path::new("file.rs"), a_trace.clone());
         t2.add_trace(
@@ -467,6 +1553,7 @@ mod tests {
                 address: HashSet::new(),
                 length: 0,
                 stats: CoverageStat::Line(2),
+                hit_by: BTreeSet::new(),
             },
         );
 
@@ -491,6 +1578,7 @@ mod tests {
             address,
             length: 0,
             stats: CoverageStat::Line(1),
+            hit_by: BTreeSet::new(),
         };
         t1.add_trace(Path::new("file.rs"), a_trace.clone());
         t2.add_trace(
@@ -500,6 +1588,7 @@ mod tests {
                 address: HashSet::new(),
                 length: 0,
                 stats: CoverageStat::Line(2),
+                hit_by: BTreeSet::new(),
             },
         );
 
@@ -525,6 +1614,7 @@ mod tests {
                 address: address.clone(),
                 length: 0,
                 stats: CoverageStat::Line(5),
+                hit_by: BTreeSet::new(),
             },
         );
         t2.add_trace(
@@ -534,6 +1624,7 @@ mod tests {
                 address: address.clone(),
                 length: 0,
                 stats: CoverageStat::Line(2),
+                hit_by: BTreeSet::new(),
             },
         );
         t1.merge(&t2);
@@ -545,6 +1636,7 @@ mod tests {
                 address: address.clone(),
                 length: 0,
                 stats: CoverageStat::Line(7),
+                hit_by: BTreeSet::new(),
             })
         );
         // Deduplicating should have no effect.
@@ -557,7 +1649,703 @@ mod tests {
                 address,
                 length: 0,
                 stats: CoverageStat::Line(7),
+                hit_by: BTreeSet::new(),
             })
         );
     }
+
+    #[test]
+    fn merge_branches() {
+        let file = Path::new("foo.rs");
+        let mut t1 = TraceMap::new();
+        t1.add_branch(
+            file,
+            BranchTrace {
+                line: 4,
+                column: 20,
+                stats: LogicState {
+                    been_true: true,
+                    been_false: false,
+                },
+            },
+        );
+        let mut t2 = TraceMap::new();
+        t2.add_branch(
+            file,
+            BranchTrace {
+                line: 4,
+                column: 20,
+                stats: LogicState {
+                    been_true: false,
+                    been_false: true,
+                },
+            },
+        );
+        t2.add_branch(
+            file,
+            BranchTrace {
+                line: 4,
+                column: 31,
+                stats: LogicState::default(),
+            },
+        );
+        t1.merge(&t2);
+
+        let (_, branches) = t1.branches().next().unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(branches[0].stats.been_true && branches[0].stats.been_false);
+        assert_eq!(branches[1].column, 31);
+        // Branches don't count towards line coverage
+        assert!(t1.is_empty());
+    }
+
+    fn region(
+        line_start: usize,
+        column_start: usize,
+        line_end: usize,
+        column_end: usize,
+    ) -> Region {
+        Region {
+            line_start,
+            column_start,
+            line_end,
+            column_end,
+        }
+    }
+
+    #[test]
+    fn monomorphisations_summed() {
+        // A generic fn called once each with two types has two sets of counters for one region
+        let mut hits = RegionHits::new();
+        hits.add(region(1, 1, 3, 2), 1);
+        hits.add(region(1, 1, 3, 2), 1);
+        hits.add(region(2, 5, 2, 20), 1);
+        hits.add(region(2, 5, 2, 20), 2);
+
+        assert_eq!(hits.hits_for_line(1), Some(2));
+        assert_eq!(hits.hits_for_line(2), Some(3));
+        assert_eq!(hits.hits_for_line(3), Some(2));
+        assert_eq!(hits.hits_for_line(4), None);
+    }
+
+    #[test]
+    fn nested_regions_not_summed() {
+        let hits = vec![
+            (region(1, 1, 10, 2), 4),
+            (region(3, 9, 6, 6), 0),
+            (region(6, 6, 9, 6), 4),
+            (region(3, 16, 3, 30), 4),
+        ]
+        .into_iter()
+        .collect::<RegionHits>();
+
+        let lines = hits.line_hits();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[&1], 4);
+        assert_eq!(lines[&3], 4);
+        // Inside the if that is never entered
+        assert_eq!(lines[&4], 0);
+        assert_eq!(lines[&5], 0);
+        assert_eq!(lines[&7], 4);
+        assert_eq!(lines[&10], 4);
+    }
+
+    #[test]
+    fn unentered_body_keeps_condition_hits() {
+        // `if x {` on line 3 where the body starting after the brace never ran
+        let hits = vec![(region(1, 1, 6, 2), 3), (region(3, 10, 5, 6), 0)]
+            .into_iter()
+            .collect::<RegionHits>();
+
+        assert_eq!(hits.hits_for_line(3), Some(3));
+        assert_eq!(hits.hits_for_line(4), Some(0));
+        assert_eq!(hits.hits_for_line(5), Some(0));
+        assert_eq!(hits.hits_for_line(6), Some(3));
+        assert_eq!(hits.hits_for_line(7), None);
+    }
+
+    #[test]
+    fn hits_at_innermost_region() {
+        // `if c { Ok(1) } else { Err(2) }` on line 2 where only the first block ran
+        let hits = vec![
+            (region(1, 1, 3, 2), 1),
+            (region(2, 10, 2, 19), 1),
+            (region(2, 25, 2, 35), 0),
+        ]
+        .into_iter()
+        .collect::<RegionHits>();
+
+        assert_eq!(hits.hits_for_line(2), Some(1));
+        assert_eq!(hits.hits_at(2, 12), Some(1));
+        assert_eq!(hits.hits_at(2, 27), Some(0));
+        assert_eq!(hits.hits_at(2, 35), Some(1));
+        assert_eq!(hits.hits_at(4, 1), None);
+    }
+
+    #[test]
+    fn uncovered_public_functions() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 0), (3, 0), (6, 1), (7, 0), (10, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let function = |name: &str, start, end, public| Function {
+            name: name.to_string(),
+            start,
+            end,
+            public,
+            ffi: false,
+        };
+        let mut functions = HashMap::new();
+        functions.insert(
+            file.to_path_buf(),
+            vec![
+                function("never_called", 1, 4, true),
+                function("partly_covered", 5, 8, true),
+                function("private", 9, 11, false),
+                function("no_lines", 12, 14, true),
+            ],
+        );
+        map.set_functions(functions);
+
+        let uncovered = map.uncovered_public_functions();
+        assert_eq!(uncovered.len(), 1);
+        assert_eq!(uncovered[0].0, file);
+        assert_eq!(uncovered[0].1.name, "never_called");
+    }
+
+    #[test]
+    fn ffi_functions_listed_by_coverage() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 0), (3, 0), (6, 1), (7, 0), (10, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let function = |name: &str, start, end, ffi| Function {
+            name: name.to_string(),
+            start,
+            end,
+            public: true,
+            ffi,
+        };
+        let mut functions = HashMap::new();
+        functions.insert(
+            file.to_path_buf(),
+            vec![
+                function("never_called", 1, 4, true),
+                function("called", 5, 8, true),
+                function("rust_only", 9, 11, false),
+                function("no_lines", 12, 14, true),
+            ],
+        );
+        map.set_functions(functions);
+
+        let ffi = map
+            .ffi_functions()
+            .into_iter()
+            .map(|x| (x.name, x.line, x.covered))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ffi,
+            vec![
+                ("never_called".to_string(), 1, false),
+                ("called".to_string(), 5, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn portable_paths_merge_across_roots() {
+        // As written by a run on another OS with the paths already made portable
+        let mut windows = TraceMap::new();
+        windows.add_trace(Path::new("src/lib.rs"), Trace::new_stub(1));
+        windows.portable = true;
+        let json = serde_json::to_string(&windows).unwrap();
+        assert!(json.contains("\"portable\":true"));
+
+        let root = std::env::current_dir().unwrap();
+        let mut unix = TraceMap::new();
+        let mut trace = Trace::new_stub(2);
+        trace.stats = CoverageStat::Line(1);
+        unix.add_trace(&root.join("src").join("lib.rs"), trace);
+        unix.make_portable(&root);
+        assert_eq!(unix.files(), vec![Path::new("src/lib.rs")]);
+
+        let mut merged: TraceMap = serde_json::from_str(&json).unwrap();
+        merged.make_native(&root);
+        unix.make_native(&root);
+        merged.merge(&unix);
+        assert_eq!(merged.files(), vec![&root.join("src").join("lib.rs")]);
+        assert_eq!(merged.total_coverable(), 2);
+    }
+
+    #[test]
+    fn arms_without_hits_uncovered() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(3, 1), (5, 0), (7, 0), (8, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let arm = |line, start, end| BranchArm {
+            kind: BranchArmKind::MatchArm,
+            line,
+            start,
+            end,
+        };
+        let mut arms = HashMap::new();
+        arms.insert(
+            file.to_path_buf(),
+            vec![arm(3, 3, 3), arm(4, 4, 6), arm(7, 7, 8), arm(9, 9, 9)],
+        );
+        map.set_branch_arms(arms);
+
+        assert_eq!(map.uncovered_branch_arms(file), vec![&arm(4, 4, 6)]);
+        assert!(map.uncovered_branch_arms(Path::new("other.rs")).is_empty());
+    }
+
+    #[test]
+    fn coverage_of_feature_gated_code() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (6, 0), (7, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let gate = |feature: &str, start, end| FeatureGate {
+            feature: feature.to_string(),
+            start,
+            end,
+        };
+        let mut gates = HashMap::new();
+        gates.insert(
+            file.to_path_buf(),
+            vec![gate("std", 1, 4), gate("serde", 5, 8), gate("std", 2, 2)],
+        );
+        map.set_feature_gates(gates);
+
+        let features = map.feature_coverage();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0].feature, "serde");
+        assert_eq!((features[0].covered, features[0].coverable), (0, 2));
+        assert_eq!(features[1].feature, "std");
+        assert_eq!((features[1].covered, features[1].coverable), (1, 2));
+    }
+
+    #[test]
+    fn lines_classified_by_region() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (6, 1)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        assert!(map.line_classes(file).is_empty());
+
+        let region = |kind, start, end| CodeRegion {
+            kind,
+            start,
+            end,
+            column: 1,
+        };
+        let mut regions = HashMap::new();
+        regions.insert(
+            file.to_path_buf(),
+            vec![
+                region(RegionKind::Unsafe, 1, 3),
+                region(RegionKind::Branch, 3, 3),
+                region(RegionKind::Test, 5, 7),
+            ],
+        );
+        map.set_code_regions(regions);
+
+        let classes = map.line_classes(file);
+        assert_eq!(classes.len(), 3);
+        assert!(classes[0].covered && classes[0].unsafe_code);
+        assert!(!classes[0].branch && !classes[0].test);
+        assert!(!classes[1].covered && classes[1].branch);
+        assert!(classes[2].test && !classes[2].unsafe_code);
+    }
+
+    #[test]
+    fn unsafe_lines_outside_tests_counted() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (4, 0), (8, 1), (9, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        let region = |kind, start, end| CodeRegion {
+            kind,
+            start,
+            end,
+            column: 1,
+        };
+        let mut regions = HashMap::new();
+        regions.insert(file.to_path_buf(), vec![region(RegionKind::Branch, 2, 2)]);
+        map.set_code_regions(regions.clone());
+        assert_eq!(map.unsafe_coverage(), None);
+
+        regions.insert(
+            file.to_path_buf(),
+            vec![
+                region(RegionKind::Unsafe, 1, 3),
+                region(RegionKind::Unsafe, 3, 3),
+                region(RegionKind::Test, 7, 10),
+                region(RegionKind::Unsafe, 8, 9),
+            ],
+        );
+        map.set_code_regions(regions);
+        let stats = map.unsafe_coverage().unwrap();
+        assert_eq!((stats.covered, stats.coverable), (1, 2));
+        assert_eq!(stats.coverage, 50.0);
+    }
+
+    #[test]
+    fn tests_only_running_test_code_found() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        let mut add_test = |target: &str, test: &str, hits: &[u64], outcome| {
+            let mut run = TraceMap::new();
+            for line in [2, 3, 12, 13, 15] {
+                let mut trace = Trace::new_stub(line);
+                trace.stats = CoverageStat::Line(hits.contains(&line) as u64);
+                run.add_trace(file, trace);
+            }
+            run.tag_tests(&[format!("{target} {test}")]);
+            run.add_run(TestRun {
+                binary: PathBuf::from(format!("{target}-0123456789abcdef")),
+                target: target.to_string(),
+                package: None,
+                run_type: None,
+                duration: 0.0,
+                collection: 0.0,
+                size: None,
+                return_code: 0,
+                ignored: false,
+                tests: vec![test.to_string()],
+                results: vec![TestResult {
+                    name: test.to_string(),
+                    outcome,
+                    exec_time: None,
+                }],
+            });
+            map.merge(&run);
+        };
+        add_test("lib", "tests::calls_code", &[2, 12], TestOutcome::Passed);
+        add_test("lib", "tests::tautology", &[13], TestOutcome::Passed);
+        add_test("lib", "tests::failing", &[15], TestOutcome::Failed);
+        add_test("lib", "tests::runs_nothing", &[], TestOutcome::Passed);
+        // A test of the same name passing in another target doesn't make the failing one pass
+        add_test("other", "tests::failing", &[2, 12], TestOutcome::Passed);
+        map.set_code_regions(HashMap::from([(
+            file.to_path_buf(),
+            vec![CodeRegion {
+                kind: RegionKind::Test,
+                start: 10,
+                end: 20,
+                column: 1,
+            }],
+        )]));
+
+        assert_eq!(
+            map.tautological_tests(),
+            ["lib tests::runs_nothing", "lib tests::tautology"]
+        );
+    }
+
+    #[test]
+    fn error_paths_counted_outside_tests() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for (line, hits) in [(2, 1), (3, 0), (4, 0), (6, 1), (9, 0)] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        assert_eq!(map.error_path_coverage(), None);
+
+        let region = |kind, start, end| CodeRegion {
+            kind,
+            start,
+            end,
+            column: 1,
+        };
+        let mut regions = HashMap::new();
+        regions.insert(
+            file.to_path_buf(),
+            vec![
+                region(RegionKind::ErrorPath, 2, 2),
+                region(RegionKind::ErrorPath, 3, 4),
+                region(RegionKind::ErrorPath, 4, 4),
+                region(RegionKind::Panic, 6, 6),
+                region(RegionKind::Panic, 7, 7),
+                region(RegionKind::Test, 8, 10),
+                region(RegionKind::Panic, 9, 9),
+            ],
+        );
+        map.set_code_regions(regions);
+
+        let stats = map.error_path_coverage().unwrap();
+        assert_eq!(stats.error_paths, 2);
+        assert_eq!(stats.error_paths_hit, 1);
+        assert_eq!(stats.panics, 1);
+        assert_eq!(stats.panics_hit, 1);
+        assert!((stats.coverage - 66.666).abs() < 0.01);
+    }
+
+    #[test]
+    fn error_paths_judged_by_sites_and_try_branches() {
+        let file = Path::new("lib.rs");
+        let mut map = TraceMap::new();
+        for line in [2, 4, 5, 9] {
+            let mut trace = Trace::new_stub(line);
+            trace.stats = CoverageStat::Line(1);
+            map.add_trace(file, trace);
+        }
+        let region = |kind, start, end, column| CodeRegion {
+            kind,
+            start,
+            end,
+            column,
+        };
+        map.set_code_regions(HashMap::from([(
+            file.to_path_buf(),
+            vec![
+                region(RegionKind::ErrorPath, 2, 2, 27),
+                region(RegionKind::Test, 8, 10, 1),
+            ],
+        )]));
+        // The line ran but the `Err` sharing it with an `Ok` didn't
+        let site = |column, hit| SiteTrace {
+            line: 2,
+            column,
+            hit,
+        };
+        map.add_site(file, site(27, false));
+        map.add_site(file, site(12, true));
+        let branch = |line, been_false| BranchTrace {
+            line,
+            column: 10,
+            stats: LogicState {
+                been_true: true,
+                been_false,
+            },
+        };
+        map.add_branch(file, branch(4, true));
+        map.add_branch(file, branch(5, false));
+        map.add_branch(file, branch(9, false));
+
+        let stats = map.error_path_coverage().unwrap();
+        assert_eq!(stats.error_paths, 3);
+        assert_eq!(stats.error_paths_hit, 1);
+
+        let mut other = TraceMap::new();
+        other.add_site(file, site(27, true));
+        map.merge(&other);
+        assert_eq!(map.error_path_coverage().unwrap().error_paths_hit, 2);
+    }
+
+    #[test]
+    fn coverage_split_by_run_type() {
+        let file = Path::new("src/lib.rs");
+        let binary = |hits: &[u64]| {
+            let mut map = TraceMap::new();
+            for line in 1..=4 {
+                let mut trace = Trace::new_stub(line);
+                trace.stats = CoverageStat::Line(hits.contains(&line).into());
+                map.add_trace(file, trace);
+            }
+            map
+        };
+        let mut unit = binary(&[1, 2]);
+        unit.tag_run_type(RunType::Lib);
+        let mut integration = binary(&[2, 3]);
+        integration.tag_run_type(RunType::Tests);
+
+        let mut result = TraceMap::new();
+        result.merge(&unit);
+        result.merge(&integration);
+        result.dedup();
+
+        let stats = result.run_type_coverage();
+        assert_eq!(stats.len(), 2);
+        let lib = stats.iter().find(|x| x.run_type == RunType::Lib).unwrap();
+        assert_eq!(lib.covered, 2);
+        assert_eq!(lib.coverage, 50.0);
+        assert_eq!(lib.unique, 1);
+        let tests = stats.iter().find(|x| x.run_type == RunType::Tests).unwrap();
+        assert_eq!(tests.unique, 1);
+    }
+
+    #[test]
+    fn binaries_hitting_trace() {
+        let file = Path::new("src/lib.rs");
+        let binary = |name: &str, hits: u64| {
+            let mut map = TraceMap::new();
+            let mut hit = Trace::new_stub(1);
+            hit.stats = CoverageStat::Line(hits);
+            map.add_trace(file, hit);
+            map.add_trace(file, Trace::new_stub(2));
+            map.tag_binary(name);
+            map
+        };
+        let mut result = TraceMap::new();
+        result.merge(&binary("unit", 1));
+        result.merge(&binary("integration", 3));
+        result.merge(&binary("never", 0));
+        result.dedup();
+
+        let traces = result.get_child_traces(file).collect::<Vec<_>>();
+        assert_eq!(
+            traces[0].hit_by.iter().collect::<Vec<_>>(),
+            vec!["integration", "unit"]
+        );
+        assert!(traces[1].hit_by.is_empty());
+    }
+
+    #[test]
+    fn aliased_files_merged() {
+        // The workspace is reached through a symlink to /real/ws
+        let shared = Path::new("/ws/test-support/src/lib.rs");
+        let via_a = Path::new("/ws/a/../test-support/src/lib.rs");
+        let via_b = Path::new("/other/support/src/lib.rs");
+        let lib = Path::new("/ws/a/src/lib.rs");
+        let mut map = TraceMap::new();
+        for (file, hits) in [(via_b, 1), (via_a, 2), (shared, 0)] {
+            let mut trace = Trace::new_stub(3);
+            trace.stats = CoverageStat::Line(hits);
+            map.add_trace(file, trace);
+        }
+        map.add_trace(lib, Trace::new_stub(1));
+        let canonical = |path: &Path| {
+            if path.ends_with("src/lib.rs") && !path.starts_with("/ws/a/src") {
+                PathBuf::from("/real/ws/test-support/src/lib.rs")
+            } else {
+                Path::new("/real").join(path.strip_prefix("/").unwrap())
+            }
+        };
+
+        assert_eq!(map.merge_aliases(Path::new("/ws"), canonical), 1);
+        map.dedup();
+        assert_eq!(map.files(), vec![&lib.to_path_buf(), &shared.to_path_buf()]);
+        let traces = map.get_child_traces(shared).collect::<Vec<_>>();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].stats, CoverageStat::Line(3));
+        let aliases = map.aliases().collect::<Vec<_>>();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases[0].0, shared);
+        assert!(aliases[0].1.contains(via_a) && aliases[0].1.contains(via_b));
+    }
+
+    #[test]
+    fn overhead_added_up_per_binary() {
+        let run = |binary: &str, duration: f64, collection: f64, ignored: bool| TestRun {
+            binary: PathBuf::from(binary),
+            target: String::new(),
+            package: None,
+            run_type: None,
+            duration,
+            collection,
+            size: (!ignored).then_some(BinarySize {
+                total: 1000,
+                instrumentation: 100,
+                debug_info: 500,
+            }),
+            return_code: 0,
+            ignored,
+            tests: vec![],
+            results: vec![],
+        };
+        let mut map = TraceMap::new();
+        map.add_run(run("target/debug/deps/b", 2.0, 1.0, false));
+        map.add_run(run("target/debug/deps/a", 4.0, 1.0, false));
+        map.add_run(run("target/debug/deps/a", 1.0, 0.0, true));
+
+        let overheads = map.binary_overheads();
+        assert_eq!(overheads.len(), 2);
+        assert_eq!(overheads[0].binary, Path::new("target/debug/deps/a"));
+        assert_eq!(overheads[0].duration, 5.0);
+        assert_eq!(overheads[0].collection_percentage(), 20.0);
+        assert_eq!(overheads[0].size.unwrap().instrumentation, 100);
+        assert_eq!(overheads[1].collection_percentage(), 50.0);
+    }
+
+    #[test]
+    fn config_summary_splits_time() {
+        let mut map = TraceMap::new();
+        let mut trace = Trace::new_stub(1);
+        trace.stats = CoverageStat::Line(1);
+        map.add_trace(Path::new("src/lib.rs"), trace);
+        map.add_trace(Path::new("src/lib.rs"), Trace::new_stub(2));
+        map.add_run(TestRun {
+            binary: PathBuf::from("target/debug/deps/a"),
+            target: "a".to_string(),
+            package: None,
+            run_type: None,
+            duration: 3.0,
+            collection: 1.0,
+            size: None,
+            return_code: 0,
+            ignored: false,
+            tests: vec![],
+            results: vec![],
+        });
+
+        let summary = ConfigSummary::new("unit".to_string(), Duration::from_secs(2), &map);
+        assert_eq!(summary.build, 2.0);
+        assert_eq!(summary.test, 2.0);
+        assert_eq!(summary.collection, 1.0);
+        assert_eq!(summary.total(), 5.0);
+        assert_eq!(summary.coverage, 50.0);
+
+        let empty = ConfigSummary::new("empty".to_string(), Duration::ZERO, &TraceMap::new());
+        assert_eq!(empty.coverage, 0.0);
+
+        let mut merged = TraceMap::new();
+        map.add_config_summary(summary);
+        merged.merge(&map);
+        assert_eq!(merged.config_summaries().len(), 1);
+    }
+
+    #[test]
+    fn rerun_replaces_failed_runs() {
+        let run = |binary: &str, return_code: i32, tests: &[&str]| TestRun {
+            binary: PathBuf::from(binary),
+            target: String::new(),
+            package: None,
+            run_type: None,
+            duration: 1.0,
+            collection: 0.0,
+            size: None,
+            return_code,
+            ignored: false,
+            tests: tests.iter().map(|x| x.to_string()).collect(),
+            results: vec![],
+        };
+        let mut stored = TraceMap::new();
+        stored.add_trace(Path::new("src/lib.rs"), Trace::new_stub(1));
+        stored.add_run(run("target/debug/deps/a", 0, &[]));
+        stored.add_run(run("target/debug/deps/b", 101, &["b::one"]));
+        stored.add_run(run("target/debug/deps/b", 0, &["b::two"]));
+
+        let mut rerun = TraceMap::new();
+        let mut trace = Trace::new_stub(1);
+        trace.stats = CoverageStat::Line(1);
+        rerun.add_trace(Path::new("src/lib.rs"), trace);
+        rerun.add_run(run("target/debug/deps/b", 0, &["b::one"]));
+
+        stored.merge_rerun(&rerun);
+        assert_eq!(stored.runs().len(), 3);
+        assert!(stored.runs().iter().all(|x| x.return_code == 0));
+        assert_eq!(stored.total_covered(), 1);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::source_analysis::prelude::*;
+use crate::prelude::*;
 use syn::*;
 
 pub mod predicates {
@@ -18,13 +18,13 @@ impl SourceAnalysis {
         let mut check_cover = true;
         for attr in attrs {
             analysis.ignore_tokens(attr);
-            if check_cfg_attr(&attr.meta) {
+            if check_cfg_attr(&attr.meta, ctx.config) {
                 check_cover = false;
             } else if attr.meta.path().is_ident("cfg") {
                 let mut skip = false;
                 let _ = attr.parse_nested_meta(|meta| {
-                    skip |=
-                        predicates::is_test_attribute(&meta.path) && !ctx.config.include_tests();
+                    skip |= predicates::is_test_attribute(&meta.path)
+                        && !ctx.config.test_functions_included();
                     Ok(())
                 });
                 if skip {
@@ -39,7 +39,13 @@ impl SourceAnalysis {
     }
 }
 
-pub(crate) fn check_cfg_attr(attr: &Meta) -> bool {
+/// Whether the cfg is set when building the code to collect coverage
+fn is_coverage_cfg(path: &syn::Path, config: &AnalysisConfig) -> bool {
+    (path.is_ident("tarpaulin") && !config.avoid_cfg_tarpaulin)
+        || config.coverage_cfgs.iter().any(|x| path.is_ident(x))
+}
+
+pub(crate) fn check_cfg_attr(attr: &Meta, config: &AnalysisConfig) -> bool {
     tracing::trace!("cfg attr: {}", attr.to_token_stream());
     let mut ignore_span = false;
     let id = attr.path();
@@ -61,7 +67,7 @@ pub(crate) fn check_cfg_attr(attr: &Meta) -> bool {
                 if nested.path.is_ident("not") {
                     nested.parse_nested_meta(|meta| {
                         ignore_span |= meta.path.is_ident("tarpaulin_include")
-                            || meta.path.is_ident("tarpaulin");
+                            || is_coverage_cfg(&meta.path, config);
                         Ok(())
                     })
                 } else {
@@ -74,7 +80,7 @@ pub(crate) fn check_cfg_attr(attr: &Meta) -> bool {
             let mut first = true;
             let mut is_tarpaulin = false;
             let _ = ml.parse_nested_meta(|nested| {
-                if first && nested.path.is_ident("tarpaulin") {
+                if first && is_coverage_cfg(&nested.path, config) {
                     first = false;
                     is_tarpaulin = true;
                 } else if !first && is_tarpaulin {
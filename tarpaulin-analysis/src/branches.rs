@@ -0,0 +1,65 @@
+use crate::prelude::*;
+use syn::*;
+
+/// Which kind of branch a `BranchArm` is
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum BranchArmKind {
+    MatchArm,
+    Else,
+}
+
+/// An arm of a `match` or the `else` of an `if`. `line` is where the arm starts, the pattern of a
+/// match arm or the `else` keyword, and `start` to `end` are the lines of its body inclusive.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct BranchArm {
+    pub kind: BranchArmKind,
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Default)]
+struct ArmVisitor {
+    arms: Vec<BranchArm>,
+}
+
+impl<'ast> Visit<'ast> for ArmVisitor {
+    fn visit_expr_match(&mut self, i: &'ast ExprMatch) {
+        for arm in &i.arms {
+            let body = arm.body.span();
+            self.arms.push(BranchArm {
+                kind: BranchArmKind::MatchArm,
+                line: arm.pat.span().start().line,
+                start: body.start().line,
+                end: body.end().line,
+            });
+        }
+        visit::visit_expr_match(self, i);
+    }
+
+    fn visit_expr_if(&mut self, i: &'ast ExprIf) {
+        // An `else if` is visited as its own `if`, so only its condition belongs to this else
+        if let Some((else_token, expr)) = &i.else_branch {
+            let body = match &**expr {
+                Expr::If(nested) => nested.cond.span(),
+                expr => expr.span(),
+            };
+            self.arms.push(BranchArm {
+                kind: BranchArmKind::Else,
+                line: else_token.span.start().line,
+                start: body.start().line,
+                end: body.end().line,
+            });
+        }
+        visit::visit_expr_if(self, i);
+    }
+}
+
+impl SourceAnalysis {
+    /// Finds the arms of every `match` and the `else` branches of `if` expressions
+    pub(crate) fn find_branch_arms(&mut self, file: &syn::File, ctx: &Context) {
+        let mut visitor = ArmVisitor::default();
+        visitor.visit_file(file);
+        self.get_line_analysis(ctx.file.to_path_buf()).branch_arms = visitor.arms;
+    }
+}
@@ -0,0 +1,97 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Options controlling what source analysis treats as coverable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisConfig {
+    /// Include test functions, test modules and the `tests` directory
+    pub include_tests: bool,
+    /// Include the `tests` directory, test functions in it are only included along with
+    /// `include_test_functions`
+    pub include_test_helpers: bool,
+    /// Include test functions and test modules
+    pub include_test_functions: bool,
+    /// Include tests marked with `#[ignore]`
+    pub run_ignored: bool,
+    /// Include the `examples` directory
+    pub include_examples: bool,
+    /// Ignore panic macros in tests
+    pub ignore_panics: bool,
+    /// Ignore const blocks and const fns which are only used in const contexts
+    pub ignore_const_fns: bool,
+    /// Constructs which shouldn't have their physical lines merged into one logical line
+    pub no_logical_lines: Vec<LogicalLines>,
+    /// The code isn't built with `--cfg=tarpaulin`, so `cfg(tarpaulin)` doesn't affect coverage
+    pub avoid_cfg_tarpaulin: bool,
+    /// Other cfgs set when building for coverage, code gated on `not` one of these is excluded
+    /// the same as `cfg(not(tarpaulin))`
+    pub coverage_cfgs: Vec<String>,
+    /// Lines with source matching any of these are ignored
+    pub ignore_line_patterns: Vec<LinePattern>,
+    /// Files with more lines than this aren't analysed
+    pub max_file_lines: Option<usize>,
+    /// Files taking longer than this to analyse are skipped
+    pub analysis_timeout: Option<Duration>,
+    /// Keep files skipped because of the limits in coverage without any source analysis, by
+    /// default they're ignored
+    pub include_skipped_files: bool,
+}
+
+impl AnalysisConfig {
+    /// Whether the `tests` directory is analysed
+    pub fn test_helpers_included(&self) -> bool {
+        self.include_tests || self.include_test_helpers
+    }
+
+    /// Whether test functions and modules are coverable
+    pub fn test_functions_included(&self) -> bool {
+        self.include_tests || self.include_test_functions
+    }
+
+    /// Whether lines making up the given construct should be merged into one logical line
+    pub fn merge_logical_lines(&self, kind: LogicalLines) -> bool {
+        !self.no_logical_lines.contains(&kind)
+    }
+}
+
+/// A regex matched against the source of a line, compared by the pattern it was compiled from
+#[derive(Debug, Clone)]
+pub struct LinePattern(Regex);
+
+impl LinePattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.0.is_match(line)
+    }
+}
+
+impl PartialEq for LinePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for LinePattern {}
+
+/// Constructs where source analysis merges multiple physical lines into one logical line
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize, Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "clap", value(rename_all = "PascalCase"))]
+pub enum LogicalLines {
+    /// Lines in a method chain between the receiver and arguments
+    MethodChains,
+    /// `let` bindings where the pattern, `=` and expression are on different lines
+    LetBindings,
+    /// Match arm patterns split over multiple lines i.e. or-patterns
+    MatchPatterns,
+    /// Macro invocations over multiple lines
+    Macros,
+}
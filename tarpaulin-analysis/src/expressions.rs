@@ -1,4 +1,5 @@
-use crate::source_analysis::prelude::*;
+use crate::config::LogicalLines;
+use crate::prelude::*;
 use syn::{punctuated::Pair, punctuated::Punctuated, token::Comma, *};
 
 impl SourceAnalysis {
@@ -50,7 +51,8 @@ impl SourceAnalysis {
             }
             let spn = let_expr.span();
             let base_line = let_expr.let_token.span().start().line;
-            if base_line != spn.end().line {
+            let merge = ctx.config.merge_logical_lines(LogicalLines::LetBindings);
+            if base_line != spn.end().line && merge {
                 // Now check the other lines
                 let lhs = let_expr.pat.span();
                 if lhs.start().line != base_line {
@@ -138,10 +140,12 @@ impl SourceAnalysis {
             if self.check_attr_list(&arm.attrs, ctx) {
                 let reachable = self.process_expr(&arm.body, ctx);
                 if reachable.is_reachable() {
-                    let analysis = self.get_line_analysis(ctx.file.to_path_buf());
-                    let span = arm.pat.span();
-                    for line in span.start().line..span.end().line {
-                        analysis.logical_lines.insert(line + 1, span.start().line);
+                    if ctx.config.merge_logical_lines(LogicalLines::MatchPatterns) {
+                        let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+                        let span = arm.pat.span();
+                        for line in span.start().line..span.end().line {
+                            analysis.logical_lines.insert(line + 1, span.start().line);
+                        }
                     }
                     result = result.map(|x| x + reachable).or(Some(reachable));
                 }
@@ -252,12 +256,14 @@ impl SourceAnalysis {
     fn visit_methodcall(&mut self, meth: &ExprMethodCall, ctx: &Context) -> SubResult {
         if self.check_attr_list(&meth.attrs, ctx) {
             self.process_expr(&meth.receiver, ctx);
-            let start = meth.receiver.span().end().line + 1;
-            let range = get_line_range(meth);
-            let lines = get_coverable_args(&meth.args);
-            let lines = (start..range.end).filter(|x| !lines.contains(x));
-            let analysis = self.get_line_analysis(ctx.file.to_path_buf());
-            analysis.add_to_ignore(lines);
+            if ctx.config.merge_logical_lines(LogicalLines::MethodChains) {
+                let start = meth.receiver.span().end().line + 1;
+                let range = get_line_range(meth);
+                let lines = get_coverable_args(&meth.args);
+                let lines = (start..range.end).filter(|x| !lines.contains(x));
+                let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+                analysis.add_to_ignore(lines);
+            }
         } else {
             let analysis = self.get_line_analysis(ctx.file.to_path_buf());
             analysis.ignore_tokens(meth);
@@ -0,0 +1,131 @@
+use crate::prelude::*;
+use std::path::Path;
+use syn::meta::ParseNestedMeta;
+use syn::*;
+
+/// Code which is only compiled with a feature enabled, from a `cfg(feature = "...")` attribute.
+/// The lines are inclusive.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FeatureGate {
+    pub feature: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Features required by the `cfg` attributes, features under a `not` aren't required so are left
+/// out. Every feature in an `any` is included.
+fn required_features(attrs: &[Attribute]) -> Vec<String> {
+    let mut features = vec![];
+    for attr in attrs.iter().filter(|x| x.path().is_ident("cfg")) {
+        let _ = attr.parse_nested_meta(|meta| collect_features(meta, &mut features));
+    }
+    features
+}
+
+fn collect_features(meta: ParseNestedMeta, features: &mut Vec<String>) -> Result<()> {
+    if meta.path.is_ident("feature") {
+        let value: LitStr = meta.value()?.parse()?;
+        features.push(value.value());
+    } else if meta.path.is_ident("all") || meta.path.is_ident("any") {
+        meta.parse_nested_meta(|meta| collect_features(meta, features))?;
+    } else if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Lit>()?;
+    } else if meta.input.peek(token::Paren) {
+        meta.input.parse::<proc_macro2::Group>()?;
+    }
+    Ok(())
+}
+
+struct FeatureVisitor<'a> {
+    file: &'a Path,
+    gates: Vec<FeatureGate>,
+    modules: Vec<(PathBuf, String)>,
+}
+
+impl<'a> FeatureVisitor<'a> {
+    fn record(&mut self, attrs: &[Attribute], span: Span) {
+        for feature in required_features(attrs) {
+            self.gates.push(FeatureGate {
+                feature,
+                start: span.start().line,
+                end: span.end().line,
+            });
+        }
+    }
+}
+
+impl<'ast, 'a> Visit<'ast> for FeatureVisitor<'a> {
+    fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+        self.record(&i.attrs, i.span());
+        visit::visit_item_fn(self, i);
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        self.record(&i.attrs, i.span());
+        visit::visit_item_impl(self, i);
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast ItemTrait) {
+        self.record(&i.attrs, i.span());
+        visit::visit_item_trait(self, i);
+    }
+
+    fn visit_impl_item_fn(&mut self, i: &'ast ImplItemFn) {
+        self.record(&i.attrs, i.span());
+        visit::visit_impl_item_fn(self, i);
+    }
+
+    fn visit_item_mod(&mut self, i: &'ast ItemMod) {
+        if i.content.is_some() {
+            self.record(&i.attrs, i.span());
+        } else {
+            // Same resolution of the module file as used when ignoring modules
+            let mut path = match self.file.parent() {
+                Some(parent) => parent.join(i.ident.to_string()),
+                None => PathBuf::from(i.ident.to_string()),
+            };
+            if !path.exists() {
+                path.set_extension("rs");
+            }
+            for feature in required_features(&i.attrs) {
+                self.modules.push((path.clone(), feature));
+            }
+        }
+        visit::visit_item_mod(self, i);
+    }
+}
+
+impl SourceAnalysis {
+    /// Finds the code gated on features. Modules in other files which are gated are added to the
+    /// gated modules so every file in them can be tagged once all the files are analysed.
+    pub(crate) fn find_feature_gates(&mut self, file: &syn::File, ctx: &Context) {
+        let mut visitor = FeatureVisitor {
+            file: ctx.file,
+            gates: vec![],
+            modules: vec![],
+        };
+        for feature in required_features(&file.attrs) {
+            visitor.gates.push(FeatureGate {
+                feature,
+                start: 1,
+                end: usize::MAX,
+            });
+        }
+        visitor.visit_file(file);
+        self.gated_modules.extend(visitor.modules);
+        self.get_line_analysis(ctx.file.to_path_buf()).feature_gates = visitor.gates;
+    }
+
+    /// Tags the files in modules gated on a feature with the feature
+    pub(crate) fn apply_gated_modules(&mut self) {
+        for (module, feature) in &self.gated_modules {
+            for (_, analysis) in self.lines.iter_mut().filter(|(k, _)| k.starts_with(module)) {
+                analysis.feature_gates.push(FeatureGate {
+                    feature: feature.clone(),
+                    start: 1,
+                    end: usize::MAX,
+                });
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::source_analysis::prelude::*;
+use crate::prelude::*;
 use syn::*;
 
 impl SourceAnalysis {
@@ -30,10 +30,8 @@ impl SourceAnalysis {
                 }
                 Item::Trait(i) => self.visit_trait(i, ctx),
                 Item::Impl(i) => self.visit_impl(i, ctx),
-                Item::Macro(ref i) => {
-                    if self.visit_macro_call(&i.mac, ctx).is_unreachable() {
-                        res = SubResult::Unreachable;
-                    }
+                Item::Macro(ref i) if self.visit_macro_call(&i.mac, ctx).is_unreachable() => {
+                    res = SubResult::Unreachable;
                 }
                 Item::Const(c) => {
                     let analysis = self.get_line_analysis(ctx.file.to_path_buf());
@@ -52,7 +50,9 @@ impl SourceAnalysis {
         let check_insides = self.check_attr_list(&module.attrs, ctx);
         if check_insides {
             if let Some((_, ref items)) = module.content {
+                self.modules.push(module.ident.to_string());
                 self.process_items(items, ctx);
+                self.modules.pop();
             }
         } else {
             if let Some((ref braces, _)) = module.content {
@@ -77,10 +77,16 @@ impl SourceAnalysis {
         {
             let analysis = self.get_line_analysis(ctx.file.to_path_buf());
             let span = func.span();
-            analysis.functions.insert(
-                ctx.get_qualified_name(),
-                (func.sig.span().start().line, span.end().line),
-            );
+            let name = ctx.get_qualified_name();
+            if matches!(func.vis, Visibility::Public(_)) {
+                analysis.public_functions.insert(name.clone());
+            }
+            if is_ffi_boundary(func) {
+                analysis.ffi_functions.insert(name.clone());
+            }
+            analysis
+                .functions
+                .insert(name, (func.sig.span().start().line, span.end().line));
         }
         let mut test_func = false;
         let mut ignored_attr = false;
@@ -98,13 +104,13 @@ impl SourceAnalysis {
                 is_inline = true;
             } else if id.is_ident("ignore") {
                 ignored_attr = true;
-            } else if check_cfg_attr(&attr.meta) {
+            } else if check_cfg_attr(&attr.meta, ctx.config) {
                 ignore_span = true;
                 break;
             }
         }
         if ignore_span
-            || (test_func && !ctx.config.include_tests())
+            || (test_func && !ctx.config.test_functions_included())
             || (ignored_attr && !ctx.config.run_ignored)
         {
             let analysis = self.get_line_analysis(ctx.file.to_path_buf());
@@ -245,6 +251,36 @@ fn has_generic_arg<'a>(args: impl Iterator<Item = &'a FnArg>) -> bool {
     false
 }
 
+/// Whether the function can be called from other languages, either through a foreign ABI or an
+/// unmangled symbol
+fn is_ffi_boundary(func: &ItemFn) -> bool {
+    // `extern fn` without an ABI is `extern "C"`
+    let foreign_abi = func
+        .sig
+        .abi
+        .as_ref()
+        .is_some_and(|abi| abi.name.as_ref().map(LitStr::value).as_deref() != Some("Rust"));
+    foreign_abi || func.attrs.iter().any(exports_symbol)
+}
+
+/// Whether the attribute keeps the symbol unmangled or names it. Edition 2024 requires these to
+/// be wrapped in `#[unsafe(..)]`, so the attributes inside it are checked too.
+fn exports_symbol(attr: &Attribute) -> bool {
+    let is_export = |path: &syn::Path| path.is_ident("no_mangle") || path.is_ident("export_name");
+    if !attr.path().is_ident("unsafe") {
+        return is_export(attr.path());
+    }
+    let mut exported = false;
+    let _ = attr.parse_nested_meta(|meta| {
+        exported |= is_export(&meta.path);
+        if meta.input.peek(Token![=]) {
+            meta.value()?.parse::<Expr>()?;
+        }
+        Ok(())
+    });
+    exported
+}
+
 fn is_sig_generic(sig: &Signature) -> bool {
     !sig.generics.params.is_empty() || has_generic_arg(sig.inputs.iter())
 }
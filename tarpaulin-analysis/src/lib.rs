@@ -0,0 +1,1245 @@
+//! Source analysis used by cargo-tarpaulin to find which lines of a project are coverable,
+//! which lines should be ignored and which physical lines make up one logical line. This is
+//! published separately so other coverage and mutation testing tools can reuse the heuristics.
+use lazy_static::lazy_static;
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::ToTokens;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::*;
+use tracing::{debug, warn};
+use walkdir::{DirEntry, WalkDir};
+
+pub use crate::branches::{BranchArm, BranchArmKind};
+pub use crate::config::{AnalysisConfig, LinePattern, LogicalLines};
+pub use crate::features::FeatureGate;
+pub use crate::regions::{CodeRegion, RegionKind};
+
+mod attributes;
+mod branches;
+mod config;
+mod expressions;
+mod features;
+mod items;
+mod macros;
+mod regions;
+mod statements;
+#[cfg(test)]
+mod tests;
+
+pub(crate) mod prelude {
+    pub(crate) use super::*;
+    pub(crate) use attributes::*;
+    pub(crate) use macros::*;
+}
+
+/// Enumeration representing which lines to ignore
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Lines {
+    /// Ignore all lines in the file
+    All,
+    /// A single line to ignore in the file
+    Line(usize),
+}
+
+/// Represents the results of analysis of a single file. Does not store the file
+/// in question as this is expected to be maintained by the user.
+#[derive(Clone, Debug, Default)]
+pub struct LineAnalysis {
+    /// This represents lines that should be ignored in coverage
+    /// but may be identifed as coverable in the DWARF tables
+    pub ignore: HashSet<Lines>,
+    /// This represents lines that should be included in coverage
+    /// But may be ignored. Doesn't make sense to cover ALL the lines so this
+    /// is just an index.
+    pub cover: HashSet<usize>,
+    /// Some logical lines may be split between physical lines this shows the
+    /// mapping from physical line to logical line to prevent false positives
+    /// from expressions split across physical lines
+    pub logical_lines: HashMap<usize, usize>,
+    /// Shows the line length of the provided file
+    max_line: usize,
+    pub functions: HashMap<String, (usize, usize)>,
+    /// Names of the functions declared `pub` (or default methods of a `pub` trait)
+    pub public_functions: HashSet<String>,
+    /// Names of the functions other languages can call, with a foreign ABI or an unmangled symbol
+    pub ffi_functions: HashSet<String>,
+    /// Names and line ranges of const fns in the file
+    pub const_fns: Vec<(String, (usize, usize))>,
+    /// Line and column (1-indexed) of each `?` operator. These introduce an early return which
+    /// is measured as a branch
+    pub try_branches: HashSet<(usize, usize)>,
+    /// Rust code examples in the doc comments of the file
+    pub doc_examples: Vec<DocExample>,
+    /// Lines with a `cfg` or `cfg_attr` using `tarpaulin`
+    pub tarpaulin_cfgs: Vec<usize>,
+    /// Code only compiled with a feature enabled
+    pub feature_gates: Vec<FeatureGate>,
+    /// Arms of `match` expressions and `else` branches
+    pub branch_arms: Vec<BranchArm>,
+    /// Lines ignored because they matched one of the `ignore_line_patterns`, along with the
+    /// pattern which matched
+    pub pattern_ignored: Vec<(usize, String)>,
+    /// Test code, unsafe code and branches in the file
+    pub regions: Vec<CodeRegion>,
+}
+
+/// How rustdoc treats a code example in a doc comment
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+pub enum DocExampleKind {
+    /// Compiled and ran as a doctest
+    Run,
+    /// Compiled but never ran, from `no_run` or `compile_fail`
+    NoRun,
+    /// Neither compiled nor ran
+    Ignore,
+}
+
+/// A rust code block in a doc comment, the lines include the fences
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct DocExample {
+    pub start: usize,
+    pub end: usize,
+    pub kind: DocExampleKind,
+}
+
+/// Provides context to the source analysis stage including the analysis
+/// config and the source code being analysed.
+pub(crate) struct Context<'a> {
+    /// Analysis config
+    config: &'a AnalysisConfig,
+    /// Contents of the source file
+    file_contents: &'a str,
+    /// path to the file being analysed
+    file: &'a Path,
+    /// Other parts of context are immutable like tarpaulin config and users
+    /// source code. This is discovered during hence use of interior mutability
+    ignore_mods: RefCell<HashSet<PathBuf>>,
+    /// As we traverse the structures we want to grab module names etc so we can get proper names
+    /// for our functions etc
+    pub(crate) symbol_stack: RefCell<Vec<String>>,
+}
+
+pub(crate) struct StackGuard<'a>(&'a RefCell<Vec<String>>);
+
+impl<'a> Drop for StackGuard<'a> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().pop();
+    }
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn push_to_symbol_stack(&self, mut ident: String) -> StackGuard<'_> {
+        if !(ident.starts_with("<") && ident.ends_with(">")) {
+            ident = ident.replace(' ', "");
+        }
+        self.symbol_stack.borrow_mut().push(ident);
+        StackGuard(&self.symbol_stack)
+    }
+
+    pub(crate) fn get_qualified_name(&self) -> String {
+        let stack = self.symbol_stack.borrow();
+        let name = stack.join("::");
+        debug!("Found function: {}", name);
+        name
+    }
+}
+
+/// When the `LineAnalysis` results are mapped to their files there needs to be
+/// an easy way to get the information back. For the container used implement
+/// this trait
+pub trait SourceAnalysisQuery {
+    /// Returns true if the line in the given file should be ignored
+    fn should_ignore(&self, path: &Path, l: &usize) -> bool;
+    /// Takes a path and line number and normalises it to the logical line
+    /// that should be represented in the statistics
+    fn normalise(&self, path: &Path, l: usize) -> (PathBuf, usize);
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) enum SubResult {
+    /// Expression should be a reachable one (or we don't care to check further)
+    Ok,
+    /// Expression definitely reachable - reserved for early returns from functions to stop
+    /// unreachable expressions wiping them out
+    Definite,
+    /// Unreachable expression i.e. unreachable!()
+    Unreachable,
+}
+
+// Addition works for this by forcing anything + definite to definite, otherwise prioritising
+// unreachable.
+impl std::ops::AddAssign for SubResult {
+    fn add_assign(&mut self, other: Self) {
+        if *self == Self::Definite || other == Self::Definite {
+            *self = Self::Definite;
+        } else if *self == Self::Unreachable || other == Self::Unreachable {
+            *self = Self::Unreachable;
+        } else {
+            *self = Self::Ok;
+        }
+    }
+}
+
+impl std::ops::Add for SubResult {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl SubResult {
+    pub fn is_reachable(&self) -> bool {
+        *self != Self::Unreachable
+    }
+
+    pub fn is_unreachable(&self) -> bool {
+        !self.is_reachable()
+    }
+}
+
+impl SourceAnalysisQuery for HashMap<PathBuf, LineAnalysis> {
+    fn should_ignore(&self, path: &Path, l: &usize) -> bool {
+        if self.contains_key(path) {
+            self.get(path).unwrap().should_ignore(*l)
+        } else {
+            false
+        }
+    }
+
+    fn normalise(&self, path: &Path, l: usize) -> (PathBuf, usize) {
+        let pb = path.to_path_buf();
+        match self.get(path) {
+            Some(s) => match s.logical_lines.get(&l) {
+                Some(o) => (pb, *o),
+                _ => (pb, l),
+            },
+            _ => (pb, l),
+        }
+    }
+}
+
+impl LineAnalysis {
+    /// Creates a new LineAnalysis object
+    fn new() -> Self {
+        Default::default()
+    }
+
+    fn new_from_file(path: &Path) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(Self {
+            max_line: file.lines().count(),
+            ..Default::default()
+        })
+    }
+
+    /// Ignore all lines in the file
+    pub fn ignore_all(&mut self) {
+        self.ignore.clear();
+        self.cover.clear();
+        self.ignore.insert(Lines::All);
+    }
+
+    /// Ignore all tokens in the given token stream
+    pub fn ignore_tokens<T>(&mut self, tokens: T)
+    where
+        T: ToTokens,
+    {
+        for token in tokens.into_token_stream() {
+            self.ignore_span(token.span());
+        }
+    }
+
+    /// Adds the lines of the provided span to the ignore set
+    pub fn ignore_span(&mut self, span: Span) {
+        // If we're already ignoring everything no need to ignore this span
+        if !self.ignore.contains(&Lines::All) {
+            for i in span.start().line..=span.end().line {
+                self.ignore.insert(Lines::Line(i));
+                if self.cover.contains(&i) {
+                    self.cover.remove(&i);
+                }
+            }
+        }
+    }
+
+    /// Cover all tokens in the given tokenstream
+    pub fn cover_token_stream(&mut self, tokens: TokenStream, contents: Option<&str>) {
+        for token in tokens {
+            self.cover_span(token.span(), contents);
+        }
+    }
+
+    /// Adds the lines of the provided span to the cover set
+    pub fn cover_span(&mut self, span: Span, contents: Option<&str>) {
+        // Not checking for Lines::All because I trust we've called cover_span
+        // for a reason.
+        let mut useful_lines: HashSet<usize> = HashSet::new();
+        if let Some(c) = contents {
+            lazy_static! {
+                static ref SINGLE_LINE: Regex = Regex::new(r"\s*//").unwrap();
+            }
+            const MULTI_START: &str = "/*";
+            const MULTI_END: &str = "*/";
+            let len = span.end().line - span.start().line;
+            let mut is_comment = false;
+            for (i, line) in c.lines().enumerate().skip(span.start().line - 1).take(len) {
+                let is_code = if line.contains(MULTI_START) {
+                    if !line.contains(MULTI_END) {
+                        is_comment = true;
+                    }
+                    false
+                } else if is_comment {
+                    if line.contains(MULTI_END) {
+                        is_comment = false;
+                    }
+                    false
+                } else {
+                    true
+                };
+                if is_code && !SINGLE_LINE.is_match(line) {
+                    useful_lines.insert(i + 1);
+                }
+            }
+        }
+        for i in span.start().line..=span.end().line {
+            if !self.ignore.contains(&Lines::Line(i)) && useful_lines.contains(&i) {
+                self.cover.insert(i);
+            }
+        }
+    }
+
+    /// Shows whether the line should be ignored by tarpaulin
+    pub fn should_ignore(&self, line: usize) -> bool {
+        self.ignore.contains(&Lines::Line(line))
+            || self.ignore.contains(&Lines::All)
+            || (self.max_line > 0 && self.max_line < line)
+    }
+
+    /// Adds a line to the list of lines to ignore
+    fn add_to_ignore(&mut self, lines: impl IntoIterator<Item = usize>) {
+        if !self.ignore.contains(&Lines::All) {
+            for l in lines {
+                self.ignore.insert(Lines::Line(l));
+                if self.cover.contains(&l) {
+                    self.cover.remove(&l);
+                }
+            }
+        }
+    }
+}
+
+impl Function {
+    fn new(name: &str, span: (usize, usize), public: bool, ffi: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            start: span.0 as u64,
+            end: span.1 as u64,
+            public,
+            ffi,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+pub struct Function {
+    pub name: String,
+    pub start: u64,
+    pub end: u64,
+    /// Whether the function is part of the public API
+    #[serde(default)]
+    pub public: bool,
+    /// Whether the function is an FFI entry point, `extern` with a foreign ABI or exported with
+    /// `#[no_mangle]` or `#[export_name]`
+    #[serde(default)]
+    pub ffi: bool,
+}
+
+/// Decision from a custom line filter on whether a line is coverable
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FilterDecision {
+    /// Leave the line as the analysis found it
+    Keep,
+    /// Exclude the line from coverage
+    Ignore,
+    /// Count the line as coverable even if the analysis ignored it
+    Cover,
+}
+
+/// Why source analysis skipped a file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    /// The file has more lines than `max_file_lines`, with the number of lines
+    TooLarge(usize),
+    /// Analysing the file took longer than `analysis_timeout`
+    Timeout,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge(lines) => write!(f, "it has {lines} lines"),
+            Self::Timeout => write!(f, "analysing it timed out"),
+        }
+    }
+}
+
+/// Custom line filter taking the file, 1-indexed line number and the line's source. The filters
+/// need to be `Send + Sync` as files may be analysed in parallel.
+pub type LineFilter = Box<dyn Fn(&Path, usize, &str) -> FilterDecision + Send + Sync>;
+
+#[derive(Default)]
+pub struct SourceAnalysis {
+    pub lines: HashMap<PathBuf, LineAnalysis>,
+    ignored_modules: Vec<PathBuf>,
+    /// Module files or directories only compiled with the feature enabled
+    gated_modules: Vec<(PathBuf, String)>,
+    /// Functions returning `!` as `module::name`, where the module is the innermost module, type
+    /// or trait the function is defined in. Calls to these mean any following statements can't run
+    diverging_functions: HashSet<String>,
+    /// Statements following calls to functions which may return `!` but aren't defined in the file
+    /// being analysed, as the called function, file and first and last line of the statement.
+    /// These are ignored once every file is analysed if the function does diverge
+    deferred_diverging: Vec<(String, PathBuf, (usize, usize))>,
+    /// Inline modules enclosing the items currently being processed
+    modules: Vec<String>,
+    /// Names of functions or paths referred to outside of a const context
+    runtime_names: HashSet<String>,
+    /// Names referred to in the body of each const fn, these are used at runtime if the const fn
+    /// is called at runtime
+    const_fn_uses: HashMap<String, HashSet<String>>,
+    /// Filters applied to every line once the files have been analysed
+    filters: Vec<LineFilter>,
+    /// Files which weren't analysed because of the limits in the config, sorted by path
+    pub skipped_files: Vec<(PathBuf, SkipReason)>,
+    /// How long each analysed file took
+    pub analysis_times: Vec<(PathBuf, Duration)>,
+}
+
+impl SourceAnalysis {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn create_function_map(&self) -> HashMap<PathBuf, Vec<Function>> {
+        self.lines
+            .iter()
+            .map(|(file, analysis)| {
+                let mut functions: Vec<Function> = analysis
+                    .functions
+                    .iter()
+                    .map(|(function, span)| {
+                        let public = analysis.public_functions.contains(function);
+                        let ffi = analysis.ffi_functions.contains(function);
+                        Function::new(function, *span, public, ffi)
+                    })
+                    .collect();
+                functions.sort_unstable_by_key(|a| a.start);
+                (file.to_path_buf(), functions)
+            })
+            .collect()
+    }
+
+    /// The doc examples in each file, files without any are left out
+    pub fn create_doc_example_map(&self) -> HashMap<PathBuf, Vec<DocExample>> {
+        self.lines
+            .iter()
+            .filter(|(_, analysis)| !analysis.doc_examples.is_empty())
+            .map(|(file, analysis)| (file.clone(), analysis.doc_examples.clone()))
+            .collect()
+    }
+
+    /// The match arms and else branches in each file which aren't ignored, files without any are
+    /// left out
+    pub fn create_branch_arm_map(&self) -> HashMap<PathBuf, Vec<BranchArm>> {
+        self.lines
+            .iter()
+            .map(|(file, analysis)| {
+                let arms = analysis
+                    .branch_arms
+                    .iter()
+                    .filter(|x| !analysis.should_ignore(x.line))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (file.clone(), arms)
+            })
+            .filter(|(_, arms)| !arms.is_empty())
+            .collect()
+    }
+
+    /// The test code, unsafe code and branches in each file which isn't ignored entirely. Every
+    /// such file is included so files missing from the map weren't classified.
+    pub fn create_region_map(&self) -> HashMap<PathBuf, Vec<CodeRegion>> {
+        self.lines
+            .iter()
+            .filter(|(_, analysis)| !analysis.ignore.contains(&Lines::All))
+            .map(|(file, analysis)| (file.clone(), analysis.regions.clone()))
+            .collect()
+    }
+
+    /// The feature gated code in each file, files without any are left out
+    pub fn create_feature_gate_map(&self) -> HashMap<PathBuf, Vec<FeatureGate>> {
+        self.lines
+            .iter()
+            .filter(|(_, analysis)| !analysis.feature_gates.is_empty())
+            .map(|(file, analysis)| (file.clone(), analysis.feature_gates.clone()))
+            .collect()
+    }
+
+    pub fn get_line_analysis(&mut self, path: PathBuf) -> &mut LineAnalysis {
+        self.lines
+            .entry(path.clone())
+            .or_insert_with(|| LineAnalysis::new_from_file(&path).unwrap_or_default())
+    }
+
+    /// Adds a filter to decide per line whether it's coverable, these run after the built in
+    /// analysis so can override it. The first filter not returning `FilterDecision::Keep` decides
+    /// the line, files ignored entirely aren't passed to the filters.
+    pub fn with_filter(mut self, filter: LineFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Analyses the given source files of the project at `root`. Files in `tests` or `examples`
+    /// at the root are ignored unless enabled in the config.
+    pub fn get_analysis(
+        files: impl IntoIterator<Item = PathBuf>,
+        root: &Path,
+        config: &AnalysisConfig,
+    ) -> Self {
+        Self::new().analyse(files, root, config)
+    }
+
+    /// Analyses the given source files like `get_analysis`, applying any filters added with
+    /// `with_filter` to the results.
+    ///
+    /// With the `parallel` feature files are analysed on the rayon thread pool. Each file is
+    /// analysed independently and the results merged, calls to functions returning `!` defined in
+    /// another file and modules excluded via attributes are only applied after the merge.
+    ///
+    /// Files over the size limit or taking longer than the timeout in the config are skipped,
+    /// they're ignored entirely unless `include_skipped_files` is set.
+    pub fn analyse(
+        self,
+        files: impl IntoIterator<Item = PathBuf>,
+        root: &Path,
+        config: &AnalysisConfig,
+    ) -> Self {
+        let files = files.into_iter().collect::<Vec<_>>();
+
+        let analyses = map_files(&files, |path| {
+            if let Some(reason) = exceeds_size_limit(path, config) {
+                return Err(reason);
+            }
+            let start = Instant::now();
+            let (path, root, config) = (path.clone(), root.to_path_buf(), config.clone());
+            let res = with_timeout(config.analysis_timeout, move |cancelled| {
+                let mut analysis = Self::new();
+                let mut ignored_files = HashSet::new();
+                analysis.analyse_package(&path, &root, &config, &mut ignored_files, &cancelled);
+                (analysis, ignored_files)
+            });
+            res.map(|x| (x, start.elapsed())).ok_or(SkipReason::Timeout)
+        });
+
+        let mut result = Self {
+            filters: self.filters,
+            ..Default::default()
+        };
+        let mut skipped = HashMap::new();
+        let mut ignored_files: HashSet<PathBuf> = HashSet::new();
+        for (path, res) in files.iter().zip(analyses) {
+            let (analysis, ignored) = match res {
+                Ok((analysed, elapsed)) => {
+                    result.analysis_times.push((path.clone(), elapsed));
+                    analysed
+                }
+                Err(reason) => {
+                    skipped.insert(path.clone(), reason);
+                    continue;
+                }
+            };
+            result.lines.extend(analysis.lines);
+            result
+                .diverging_functions
+                .extend(analysis.diverging_functions);
+            result
+                .deferred_diverging
+                .extend(analysis.deferred_diverging);
+            result.ignored_modules.extend(analysis.ignored_modules);
+            result.gated_modules.extend(analysis.gated_modules);
+            result.runtime_names.extend(analysis.runtime_names);
+            for (name, uses) in analysis.const_fn_uses {
+                result.const_fn_uses.entry(name).or_default().extend(uses);
+            }
+            ignored_files.extend(ignored);
+        }
+        result.apply_deferred_diverging();
+        if !config.include_skipped_files {
+            ignored_files.extend(skipped.keys().cloned());
+        }
+        result.skipped_files = skipped.into_iter().collect();
+        result.skipped_files.sort_by(|a, b| a.0.cmp(&b.0));
+        for module in &result.ignored_modules {
+            result
+                .lines
+                .iter_mut()
+                .filter(|(k, _)| k.starts_with(module))
+                .for_each(|(_, v)| v.ignore_all());
+        }
+        result.apply_gated_modules();
+        for e in ignored_files {
+            let mut analysis = LineAnalysis::new();
+            analysis.ignore_all();
+            result.lines.insert(e, analysis);
+        }
+        if config.ignore_const_fns {
+            result.ignore_const_only_fns();
+        }
+        result.apply_filters();
+
+        result
+    }
+
+    /// Ignores the statements after calls to functions defined in another file once they're known
+    /// to return `!`
+    fn apply_deferred_diverging(&mut self) {
+        for (function, path, (start, end)) in std::mem::take(&mut self.deferred_diverging) {
+            if self.diverging_functions.contains(&function) {
+                self.get_line_analysis(path).add_to_ignore(start..=end);
+            }
+        }
+    }
+
+    /// Runs the custom line filters over every file which isn't ignored entirely
+    fn apply_filters(&mut self) {
+        if self.filters.is_empty() {
+            return;
+        }
+        for (path, analysis) in self
+            .lines
+            .iter_mut()
+            .filter(|(_, x)| !x.ignore.contains(&Lines::All))
+        {
+            let content = match std::fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            for (i, line) in content.lines().enumerate() {
+                let decision = self
+                    .filters
+                    .iter()
+                    .map(|filter| filter(path, i + 1, line))
+                    .find(|x| *x != FilterDecision::Keep);
+                match decision {
+                    Some(FilterDecision::Ignore) => analysis.add_to_ignore([i + 1]),
+                    Some(FilterDecision::Cover) => {
+                        analysis.ignore.remove(&Lines::Line(i + 1));
+                        analysis.cover.insert(i + 1);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Analyses a single source file of the target crate, any files or modules it excludes from
+    /// coverage are added to `filtered_files`. The analysis stops early once `cancelled` is set.
+    fn analyse_package(
+        &mut self,
+        path: &Path,
+        root: &Path,
+        config: &AnalysisConfig,
+        filtered_files: &mut HashSet<PathBuf>,
+        cancelled: &AtomicBool,
+    ) {
+        let is_cancelled = || cancelled.load(Ordering::Relaxed);
+        let skip_cause_test =
+            !config.test_helpers_included() && path.starts_with(root.join("tests"));
+        let skip_cause_example =
+            path.starts_with(root.join("examples")) && !config.include_examples;
+        if skip_cause_test || skip_cause_example {
+            let mut analysis = LineAnalysis::new();
+            analysis.ignore_all();
+            self.lines.insert(path.to_path_buf(), analysis);
+        } else if let Some((content, file)) = parse_source(path) {
+            let ctx = Context {
+                config,
+                file_contents: &content,
+                file: path,
+                ignore_mods: RefCell::new(HashSet::new()),
+                symbol_stack: RefCell::new(vec![]),
+            };
+            if is_cancelled() {
+                return;
+            }
+            self.find_diverging_functions(&file, path);
+            if self.check_attr_list(&file.attrs, &ctx) {
+                self.find_ignorable_lines(&ctx);
+                self.find_doc_examples(&ctx);
+                self.find_tarpaulin_cfgs(&ctx);
+                self.find_feature_gates(&file, &ctx);
+                self.find_const_usage(&file, &ctx);
+                if is_cancelled() {
+                    return;
+                }
+                self.process_items(&file.items, &ctx);
+                if is_cancelled() {
+                    return;
+                }
+                self.find_try_branches(&file, &ctx);
+                self.find_branch_arms(&file, &ctx);
+                self.find_code_regions(&file, &ctx);
+                if path.starts_with(root.join("tests")) {
+                    self.get_line_analysis(path.to_path_buf())
+                        .regions
+                        .push(CodeRegion {
+                            kind: RegionKind::Test,
+                            start: 1,
+                            end: usize::MAX,
+                            column: 1,
+                        });
+                }
+
+                let mut ignored_files = ctx.ignore_mods.into_inner();
+                for f in ignored_files.drain() {
+                    if f.is_file() {
+                        filtered_files.insert(f);
+                    } else {
+                        let walker = WalkDir::new(f).into_iter();
+                        for e in walker
+                            .filter_map(std::result::Result::ok)
+                            .filter(is_source_file)
+                        {
+                            filtered_files.insert(e.path().to_path_buf());
+                        }
+                    }
+                }
+                maybe_ignore_first_line(path, &mut self.lines);
+            } else {
+                // Now we need to ignore not only this file but if it is a lib.rs or
+                // mod.rs we need to get the others
+                let bad_module = match (path.parent(), path.file_name().map(OsStr::to_string_lossy))
+                {
+                    (Some(p), Some(n)) => {
+                        if n == "lib.rs" || n == "mod.rs" {
+                            Some(p.to_path_buf())
+                        } else {
+                            let ignore = p.join(n.trim_end_matches(".rs"));
+                            if ignore.exists() && ignore.is_dir() {
+                                Some(ignore)
+                            } else {
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                // Kill it with fire! This is applied to the other files in the module once
+                // every file has been analysed
+                if let Some(module) = bad_module {
+                    self.ignored_modules.push(module);
+                }
+                let analysis = self.get_line_analysis(path.to_path_buf());
+                analysis.ignore_span(file.span());
+            }
+        }
+    }
+
+    /// Finds lines from the raw string which are ignorable.
+    /// These are often things like close braces, semicolons that may register as
+    /// false positives.
+    pub(crate) fn find_ignorable_lines(&mut self, ctx: &Context) {
+        lazy_static! {
+            static ref IGNORABLE: Regex =
+                Regex::new(r"^((\s*//)|([\[\]\{\}\(\)\s;\?,/]*$))").unwrap();
+        }
+        let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+        let lines = ctx
+            .file_contents
+            .lines()
+            .enumerate()
+            .filter(|&(_, x)| IGNORABLE.is_match(x))
+            .map(|(i, _)| i + 1);
+        analysis.add_to_ignore(lines);
+
+        let lines = ctx
+            .file_contents
+            .lines()
+            .enumerate()
+            .filter(|&(_, x)| {
+                let mut x = x.to_string();
+                x.retain(|c| !c.is_whitespace());
+                x == "}else{"
+            })
+            .map(|(i, _)| i + 1);
+        analysis.add_to_ignore(lines);
+
+        if !ctx.config.ignore_line_patterns.is_empty() {
+            let matched = ctx
+                .file_contents
+                .lines()
+                .enumerate()
+                .filter_map(|(i, x)| {
+                    ctx.config
+                        .ignore_line_patterns
+                        .iter()
+                        .find(|pattern| pattern.is_match(x))
+                        .map(|pattern| (i + 1, pattern.as_str().to_string()))
+                })
+                .collect::<Vec<_>>();
+            analysis.add_to_ignore(matched.iter().map(|(line, _)| *line));
+            analysis.pattern_ignored = matched;
+        }
+    }
+
+    /// Finds the lines using `cfg(tarpaulin)`, these only have an effect when the code is built
+    /// with `--cfg=tarpaulin`
+    pub(crate) fn find_tarpaulin_cfgs(&mut self, ctx: &Context) {
+        lazy_static! {
+            static ref TARPAULIN_CFG: Regex =
+                Regex::new(r"^\s*#!?\[\s*cfg(_attr)?\s*\(.*\btarpaulin\b").unwrap();
+        }
+        let lines = ctx
+            .file_contents
+            .lines()
+            .enumerate()
+            .filter(|(_, x)| TARPAULIN_CFG.is_match(x))
+            .map(|(i, _)| i + 1)
+            .collect();
+        self.get_line_analysis(ctx.file.to_path_buf())
+            .tarpaulin_cfgs = lines;
+    }
+
+    /// Finds the rust code blocks in `///` and `//!` comments and how rustdoc treats them
+    pub(crate) fn find_doc_examples(&mut self, ctx: &Context) {
+        let mut examples = vec![];
+        let mut open: Option<(usize, Option<DocExampleKind>)> = None;
+        for (i, line) in ctx.file_contents.lines().enumerate() {
+            let line = line.trim_start();
+            let doc = line
+                .strip_prefix("///")
+                .or_else(|| line.strip_prefix("//!"))
+                .filter(|x| !x.starts_with('/'))
+                .map(str::trim);
+            match (doc, open) {
+                (Some(text), None) if text.starts_with("```") => {
+                    open = Some((i + 1, doc_example_kind(text.trim_start_matches('`'))));
+                }
+                (Some(text), Some((start, kind))) if text.starts_with("```") => {
+                    if let Some(kind) = kind {
+                        examples.push(DocExample {
+                            start,
+                            end: i + 1,
+                            kind,
+                        });
+                    }
+                    open = None;
+                }
+                // rustdoc closes a block left open at the end of the comment
+                (None, Some((start, kind))) => {
+                    if let Some(kind) = kind {
+                        examples.push(DocExample {
+                            start,
+                            end: i,
+                            kind,
+                        });
+                    }
+                    open = None;
+                }
+                _ => {}
+            }
+        }
+        self.get_line_analysis(ctx.file.to_path_buf()).doc_examples = examples;
+    }
+
+    /// Finds functions and methods in the file which return `!`
+    pub(crate) fn find_diverging_functions(&mut self, file: &syn::File, path: &Path) {
+        struct NeverVisitor<'a> {
+            scopes: Vec<String>,
+            functions: &'a mut HashSet<String>,
+        }
+
+        impl<'a> NeverVisitor<'a> {
+            fn in_scope<F: FnOnce(&mut Self)>(&mut self, scope: String, inner: F) {
+                self.scopes.push(scope);
+                inner(self);
+                self.scopes.pop();
+            }
+        }
+
+        impl<'ast, 'a> Visit<'ast> for NeverVisitor<'a> {
+            fn visit_item_mod(&mut self, module: &'ast ItemMod) {
+                self.in_scope(module.ident.to_string(), |v| {
+                    visit::visit_item_mod(v, module)
+                });
+            }
+
+            fn visit_item_impl(&mut self, impl_blk: &'ast ItemImpl) {
+                let self_ty = match &*impl_blk.self_ty {
+                    Type::Path(ty) => ty.path.segments.last().map(|x| x.ident.to_string()),
+                    _ => None,
+                };
+                let self_ty =
+                    self_ty.unwrap_or_else(|| impl_blk.self_ty.to_token_stream().to_string());
+                self.in_scope(self_ty, |v| visit::visit_item_impl(v, impl_blk));
+            }
+
+            fn visit_item_trait(&mut self, item: &'ast ItemTrait) {
+                self.in_scope(item.ident.to_string(), |v| visit::visit_item_trait(v, item));
+            }
+
+            fn visit_signature(&mut self, sig: &'ast Signature) {
+                if let ReturnType::Type(_, ty) = &sig.output {
+                    if matches!(**ty, Type::Never(_)) {
+                        let scope = self.scopes.last().expect("file module is always present");
+                        self.functions.insert(format!("{}::{}", scope, sig.ident));
+                    }
+                }
+                visit::visit_signature(self, sig);
+            }
+        }
+
+        NeverVisitor {
+            scopes: vec![file_module(path)],
+            functions: &mut self.diverging_functions,
+        }
+        .visit_file(file);
+    }
+
+    /// Finds const fns and every name used at runtime (outside of const fns, const/static items,
+    /// const blocks and array lengths). Const blocks are ignored here if requested, const fns can
+    /// only be ignored once every file has been seen.
+    pub(crate) fn find_const_usage(&mut self, file: &syn::File, ctx: &Context) {
+        struct ConstVisitor<'a> {
+            /// The const contexts being visited, innermost last. The name is set for const fn
+            /// bodies which run at runtime when the const fn is called at runtime
+            const_contexts: Vec<Option<String>>,
+            const_fns: Vec<(String, (usize, usize))>,
+            const_blocks: Vec<Span>,
+            runtime_names: &'a mut HashSet<String>,
+            const_fn_uses: &'a mut HashMap<String, HashSet<String>>,
+        }
+
+        impl<'a> ConstVisitor<'a> {
+            fn visit_fn<F: FnOnce(&mut Self)>(&mut self, sig: &Signature, span: Span, inner: F) {
+                if sig.constness.is_some() {
+                    let name = sig.ident.to_string();
+                    self.const_fns
+                        .push((name.clone(), (span.start().line, span.end().line)));
+                    self.const_contexts.push(Some(name));
+                    inner(self);
+                    self.const_contexts.pop();
+                } else {
+                    inner(self);
+                }
+            }
+
+            fn in_const<F: FnOnce(&mut Self)>(&mut self, inner: F) {
+                self.const_contexts.push(None);
+                inner(self);
+                self.const_contexts.pop();
+            }
+
+            /// Where a name used in the current context is recorded, `None` if it's only used at
+            /// compile time
+            fn uses(&mut self) -> Option<&mut HashSet<String>> {
+                match self.const_contexts.last() {
+                    None => Some(self.runtime_names),
+                    Some(Some(name)) => Some(self.const_fn_uses.entry(name.clone()).or_default()),
+                    Some(None) => None,
+                }
+            }
+        }
+
+        impl<'ast, 'a> Visit<'ast> for ConstVisitor<'a> {
+            fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+                self.visit_fn(&i.sig, i.span(), |v| visit::visit_item_fn(v, i));
+            }
+
+            fn visit_impl_item_fn(&mut self, i: &'ast ImplItemFn) {
+                self.visit_fn(&i.sig, i.span(), |v| visit::visit_impl_item_fn(v, i));
+            }
+
+            fn visit_item_const(&mut self, i: &'ast ItemConst) {
+                self.in_const(|v| visit::visit_item_const(v, i));
+            }
+
+            fn visit_item_static(&mut self, i: &'ast ItemStatic) {
+                self.in_const(|v| visit::visit_item_static(v, i));
+            }
+
+            fn visit_impl_item_const(&mut self, i: &'ast ImplItemConst) {
+                self.in_const(|v| visit::visit_impl_item_const(v, i));
+            }
+
+            fn visit_type_array(&mut self, i: &'ast TypeArray) {
+                self.in_const(|v| visit::visit_type_array(v, i));
+            }
+
+            fn visit_expr_repeat(&mut self, i: &'ast ExprRepeat) {
+                self.visit_expr(&i.expr);
+                self.in_const(|v| v.visit_expr(&i.len));
+            }
+
+            fn visit_expr_const(&mut self, i: &'ast ExprConst) {
+                self.const_blocks.push(i.span());
+                self.in_const(|v| visit::visit_expr_const(v, i));
+            }
+
+            fn visit_expr_path(&mut self, i: &'ast ExprPath) {
+                if let Some(segment) = i.path.segments.last() {
+                    if let Some(uses) = self.uses() {
+                        uses.insert(segment.ident.to_string());
+                    }
+                }
+                visit::visit_expr_path(self, i);
+            }
+
+            fn visit_expr_method_call(&mut self, i: &'ast ExprMethodCall) {
+                if let Some(uses) = self.uses() {
+                    uses.insert(i.method.to_string());
+                }
+                visit::visit_expr_method_call(self, i);
+            }
+
+            fn visit_macro(&mut self, i: &'ast Macro) {
+                // Macro arguments aren't parsed, so any identifier in them could be a runtime use
+                fn collect_idents(tokens: TokenStream, names: &mut HashSet<String>) {
+                    for token in tokens {
+                        match token {
+                            TokenTree::Ident(ident) => {
+                                names.insert(ident.to_string());
+                            }
+                            TokenTree::Group(group) => collect_idents(group.stream(), names),
+                            _ => {}
+                        }
+                    }
+                }
+
+                if let Some(uses) = self.uses() {
+                    collect_idents(i.tokens.clone(), uses);
+                }
+                visit::visit_macro(self, i);
+            }
+        }
+
+        let mut visitor = ConstVisitor {
+            const_contexts: vec![],
+            const_fns: vec![],
+            const_blocks: vec![],
+            runtime_names: &mut self.runtime_names,
+            const_fn_uses: &mut self.const_fn_uses,
+        };
+        visitor.visit_file(file);
+        let ConstVisitor {
+            const_fns,
+            const_blocks,
+            ..
+        } = visitor;
+
+        let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+        analysis.const_fns = const_fns;
+        if ctx.config.ignore_const_fns {
+            for block in const_blocks {
+                analysis.ignore_span(block);
+            }
+        }
+    }
+
+    /// Ignores const fns which have never been referred to outside of a const context, these are
+    /// evaluated at compile time so can never be covered. A const fn called at runtime runs its
+    /// body at runtime, so the names its body uses are runtime uses as well.
+    fn ignore_const_only_fns(&mut self) {
+        let mut runtime_names = self.runtime_names.clone();
+        let mut pending = runtime_names.iter().cloned().collect::<Vec<_>>();
+        while let Some(name) = pending.pop() {
+            for used in self.const_fn_uses.get(&name).into_iter().flatten() {
+                if runtime_names.insert(used.clone()) {
+                    pending.push(used.clone());
+                }
+            }
+        }
+        for analysis in self.lines.values_mut() {
+            let const_only = analysis
+                .const_fns
+                .iter()
+                .filter(|(name, _)| !runtime_names.contains(name))
+                .map(|(_, (start, end))| *start..=*end)
+                .collect::<Vec<_>>();
+            for lines in const_only {
+                analysis.add_to_ignore(lines);
+            }
+        }
+    }
+
+    /// Records the location of every `?` in the file so the early return can be measured
+    pub(crate) fn find_try_branches(&mut self, file: &syn::File, ctx: &Context) {
+        struct TryVisitor<'a>(&'a mut HashSet<(usize, usize)>);
+
+        impl<'ast, 'a> Visit<'ast> for TryVisitor<'a> {
+            fn visit_expr_try(&mut self, expr: &'ast ExprTry) {
+                let start = expr.question_token.span().start();
+                self.0.insert((start.line, start.column + 1));
+                visit::visit_expr_try(self, expr);
+            }
+        }
+
+        let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+        TryVisitor(&mut analysis.try_branches).visit_file(file);
+    }
+
+    pub(crate) fn visit_generics(&mut self, generics: &Generics, ctx: &Context) {
+        if let Some(ref wh) = generics.where_clause {
+            let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+            analysis.ignore_tokens(wh);
+        }
+    }
+}
+
+/// Reads and parses a source file, returning the contents alongside the parsed file
+fn parse_source(path: &Path) -> Option<(String, syn::File)> {
+    let mut content = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+        warn!(
+            "Unable to read file into string, skipping source analysis: {}",
+            e
+        );
+        return None;
+    }
+    let file = parse_file(&content).ok()?;
+    Some((content, file))
+}
+
+/// Name of the module a source file defines, the crate root is `crate`
+fn file_module(path: &Path) -> String {
+    let name = |p: Option<&OsStr>| p.and_then(OsStr::to_str).map(str::to_string);
+    match path.file_stem().and_then(OsStr::to_str) {
+        Some("mod") => name(path.parent().and_then(Path::file_name)),
+        Some("lib") | Some("main") | Some("") | None => None,
+        Some(stem) => Some(stem.to_string()),
+    }
+    .unwrap_or_else(|| "crate".to_string())
+}
+
+/// Whether the file has more lines than allowed by the config, files which can't be read are left
+/// for parsing to report
+fn exceeds_size_limit(path: &Path, config: &AnalysisConfig) -> Option<SkipReason> {
+    let max = config.max_file_lines?;
+    let lines = BufReader::new(File::open(path).ok()?).lines().count();
+    (lines > max).then_some(SkipReason::TooLarge(lines))
+}
+
+/// Runs `f` on its own thread when there's a timeout, giving up on it once the timeout passes.
+/// The flag passed to `f` is set when it's given up on, `f` should check it and return early so
+/// the thread doesn't keep running in the background.
+fn with_timeout<T, F>(timeout: Option<Duration>, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(Arc<AtomicBool>) -> T + Send + 'static,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    match timeout {
+        Some(timeout) => {
+            let (tx, rx) = mpsc::channel();
+            let flag = Arc::clone(&cancelled);
+            thread::spawn(move || {
+                let _ = tx.send(f(flag));
+            });
+            let res = rx.recv_timeout(timeout).ok();
+            if res.is_none() {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+            res
+        }
+        None => Some(f(cancelled)),
+    }
+}
+
+/// Runs `f` over every file on the rayon thread pool
+#[cfg(feature = "parallel")]
+fn map_files<T, F>(files: &[PathBuf], f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&PathBuf) -> T + Send + Sync,
+{
+    files.par_iter().map(f).collect()
+}
+
+/// Runs `f` over every file
+#[cfg(not(feature = "parallel"))]
+fn map_files<T, F>(files: &[PathBuf], f: F) -> Vec<T>
+where
+    F: Fn(&PathBuf) -> T,
+{
+    files.iter().map(f).collect()
+}
+
+/// The kind of doc example for the info string after the opening fence, `None` if the block isn't
+/// rust code
+fn doc_example_kind(info: &str) -> Option<DocExampleKind> {
+    let mut kind = DocExampleKind::Run;
+    let mut explicit_rust = false;
+    let mut other_language = false;
+    for token in info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+    {
+        match token {
+            "ignore" => kind = DocExampleKind::Ignore,
+            "no_run" | "compile_fail" if kind == DocExampleKind::Run => {
+                kind = DocExampleKind::NoRun
+            }
+            "rust" => explicit_rust = true,
+            "no_run" | "compile_fail" | "should_panic" | "test_harness" | "standalone_crate" => {}
+            x if x.starts_with("edition") || x.starts_with("ignore-") => {}
+            x if x.len() == 5
+                && x.starts_with('E')
+                && x[1..].chars().all(|c| c.is_ascii_digit()) => {}
+            _ => other_language = true,
+        }
+    }
+    if other_language && !explicit_rust {
+        None
+    } else {
+        Some(kind)
+    }
+}
+
+fn is_source_file(entry: &DirEntry) -> bool {
+    let p = entry.path();
+    p.is_file() && p.extension() == Some(OsStr::new("rs"))
+}
+
+/// lib.rs:1 can often show up as a coverable line when it's not. This ignores
+/// that line as long as it's not a real source line. This can also affect
+/// the main files for binaries in a project as well.
+fn maybe_ignore_first_line(file: &Path, result: &mut HashMap<PathBuf, LineAnalysis>) {
+    if let Ok(f) = File::open(file) {
+        let read_file = BufReader::new(f);
+        if let Some(Ok(first)) = read_file.lines().next() {
+            if !(first.starts_with("pub") || first.starts_with("fn")) {
+                let file = file.to_path_buf();
+                let line_analysis = result.entry(file).or_default();
+                line_analysis.add_to_ignore([1]);
+            }
+        }
+    }
+}
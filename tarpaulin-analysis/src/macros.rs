@@ -1,4 +1,4 @@
-use crate::source_analysis::prelude::*;
+use crate::prelude::*;
 use proc_macro2::TokenTree;
 use std::cmp::{max, min};
 use std::ops::Range;
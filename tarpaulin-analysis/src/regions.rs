@@ -0,0 +1,197 @@
+use crate::prelude::*;
+use syn::*;
+
+/// What kind of code a `CodeRegion` contains
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum RegionKind {
+    /// Test functions, `#[cfg(test)]` modules and files in the `tests` directory
+    Test,
+    /// Unsafe blocks and unsafe functions
+    Unsafe,
+    /// Where control flow branches, the conditions of `if` and `while`, patterns of match arms
+    /// and `?` operators
+    Branch,
+    /// Code handling or returning an error, match arms and `if let` bodies for `Err` and
+    /// expressions constructing an `Err`
+    ErrorPath,
+    /// `panic!` calls
+    Panic,
+}
+
+/// Lines of a source file containing a kind of code, the lines are inclusive. Regions can be
+/// nested or overlap, so a line can be in regions of more than one kind.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CodeRegion {
+    pub kind: RegionKind,
+    pub start: usize,
+    pub end: usize,
+    /// Column the region starts at on its first line, 1-indexed like the columns of llvm's
+    /// coverage regions
+    #[serde(default)]
+    pub column: usize,
+}
+
+impl CodeRegion {
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start..=self.end).contains(&line)
+    }
+}
+
+fn is_test_fn(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .any(|x| x.path().segments.last().is_some_and(|x| x.ident == "test"))
+}
+
+/// Whether the pattern matches `Err`, either on its own or as one of several alternatives
+fn is_err_pat(pat: &Pat) -> bool {
+    match pat {
+        Pat::TupleStruct(p) => is_err_path(&p.path),
+        Pat::Or(p) => p.cases.iter().any(is_err_pat),
+        Pat::Paren(p) => is_err_pat(&p.pat),
+        _ => false,
+    }
+}
+
+fn is_err_path(path: &syn::Path) -> bool {
+    path.segments.last().is_some_and(|x| x.ident == "Err")
+}
+
+/// Whether the attributes include a `cfg` requiring `test`
+fn is_test_cfg(attrs: &[Attribute]) -> bool {
+    let mut test = false;
+    for attr in attrs.iter().filter(|x| x.path().is_ident("cfg")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            test |= meta.path.is_ident("test");
+            Ok(())
+        });
+    }
+    test
+}
+
+#[derive(Default)]
+struct RegionVisitor {
+    regions: Vec<CodeRegion>,
+}
+
+impl RegionVisitor {
+    fn record(&mut self, kind: RegionKind, span: Span) {
+        self.regions.push(CodeRegion {
+            kind,
+            start: span.start().line,
+            end: span.end().line,
+            column: span.start().column + 1,
+        });
+    }
+
+    fn record_fn(&mut self, attrs: &[Attribute], sig: &Signature, span: Span) {
+        if is_test_fn(attrs) || is_test_cfg(attrs) {
+            self.record(RegionKind::Test, span);
+        }
+        if sig.unsafety.is_some() {
+            self.record(RegionKind::Unsafe, span);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for RegionVisitor {
+    fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+        self.record_fn(&i.attrs, &i.sig, i.span());
+        visit::visit_item_fn(self, i);
+    }
+
+    fn visit_impl_item_fn(&mut self, i: &'ast ImplItemFn) {
+        self.record_fn(&i.attrs, &i.sig, i.span());
+        visit::visit_impl_item_fn(self, i);
+    }
+
+    fn visit_trait_item_fn(&mut self, i: &'ast TraitItemFn) {
+        self.record_fn(&i.attrs, &i.sig, i.span());
+        visit::visit_trait_item_fn(self, i);
+    }
+
+    fn visit_item_mod(&mut self, i: &'ast ItemMod) {
+        if is_test_cfg(&i.attrs) {
+            self.record(RegionKind::Test, i.span());
+        }
+        visit::visit_item_mod(self, i);
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        if i.unsafety.is_some() {
+            self.record(RegionKind::Unsafe, i.span());
+        }
+        visit::visit_item_impl(self, i);
+    }
+
+    fn visit_expr_unsafe(&mut self, i: &'ast ExprUnsafe) {
+        self.record(RegionKind::Unsafe, i.span());
+        visit::visit_expr_unsafe(self, i);
+    }
+
+    fn visit_expr_if(&mut self, i: &'ast ExprIf) {
+        self.record(RegionKind::Branch, i.cond.span());
+        if let Expr::Let(cond) = i.cond.as_ref() {
+            if is_err_pat(&cond.pat) {
+                self.record(RegionKind::ErrorPath, i.then_branch.span());
+            }
+        }
+        visit::visit_expr_if(self, i);
+    }
+
+    fn visit_expr_while(&mut self, i: &'ast ExprWhile) {
+        self.record(RegionKind::Branch, i.cond.span());
+        visit::visit_expr_while(self, i);
+    }
+
+    fn visit_arm(&mut self, i: &'ast Arm) {
+        self.record(RegionKind::Branch, i.pat.span());
+        if is_err_pat(&i.pat) {
+            // The body is the code which only runs for the error, the pattern runs for every arm
+            self.record(RegionKind::ErrorPath, i.body.span());
+        }
+        if let Some((_, guard)) = &i.guard {
+            self.record(RegionKind::Branch, guard.span());
+        }
+        visit::visit_arm(self, i);
+    }
+
+    fn visit_expr_call(&mut self, i: &'ast ExprCall) {
+        if let Expr::Path(func) = i.func.as_ref() {
+            if is_err_path(&func.path) {
+                self.record(RegionKind::ErrorPath, i.span());
+            }
+        }
+        visit::visit_expr_call(self, i);
+    }
+
+    fn visit_macro(&mut self, i: &'ast Macro) {
+        if i.path.segments.last().is_some_and(|x| x.ident == "panic") {
+            self.record(RegionKind::Panic, i.span());
+        }
+        visit::visit_macro(self, i);
+    }
+
+    fn visit_expr_try(&mut self, i: &'ast ExprTry) {
+        self.record(RegionKind::Branch, i.question_token.span());
+        visit::visit_expr_try(self, i);
+    }
+}
+
+impl SourceAnalysis {
+    /// Finds the test code, unsafe code and branches in the file. A file which is only compiled
+    /// for tests is one test region.
+    pub(crate) fn find_code_regions(&mut self, file: &syn::File, ctx: &Context) {
+        let mut visitor = RegionVisitor::default();
+        if is_test_cfg(&file.attrs) {
+            visitor.regions.push(CodeRegion {
+                kind: RegionKind::Test,
+                start: 1,
+                end: usize::MAX,
+                column: 1,
+            });
+        }
+        visitor.visit_file(file);
+        self.get_line_analysis(ctx.file.to_path_buf()).regions = visitor.regions;
+    }
+}
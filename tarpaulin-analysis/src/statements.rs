@@ -0,0 +1,196 @@
+use crate::config::LogicalLines;
+use crate::prelude::*;
+use syn::*;
+
+impl SourceAnalysis {
+    pub(crate) fn process_statements(&mut self, stmts: &[Stmt], ctx: &Context) -> SubResult {
+        // in a list of statements, if any of them is unreachable, the whole list is
+        // unreachable
+        let mut unreachable = false;
+        let mut definite = false;
+        // Once a statement is guaranteed to diverge the rest of the list can't run, unlike
+        // unreachable the diverging statement itself is still reachable
+        let mut diverged = false;
+        // Functions called earlier in the list which may be found to return `!` in a file that
+        // hasn't been analysed yet
+        let mut maybe_diverged = vec![];
+        for stmt in stmts.iter() {
+            if !matches!(stmt, Stmt::Item(_)) {
+                if diverged {
+                    let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+                    analysis.ignore_tokens(stmt);
+                    continue;
+                }
+                let lines = (stmt.span().start().line, stmt.span().end().line);
+                for function in &maybe_diverged {
+                    self.deferred_diverging.push((
+                        String::clone(function),
+                        ctx.file.to_path_buf(),
+                        lines,
+                    ));
+                }
+            }
+            diverged = self.is_diverging(stmt, ctx);
+            if !diverged {
+                maybe_diverged.extend(self.called_function(stmt, ctx));
+            }
+            let res = match stmt {
+                Stmt::Item(i) => self.process_items(std::slice::from_ref(i), ctx),
+                Stmt::Expr(i, _) => self.process_expr(i, ctx),
+                Stmt::Local(i) => self.process_local(i, ctx),
+                Stmt::Macro(i) => self.process_macro(i, ctx),
+            };
+            unreachable |= res.is_unreachable();
+            if SubResult::Definite == res {
+                definite = true;
+            }
+        }
+        // We must be in a block, the parent will handle marking the span as unreachable
+        if unreachable && !definite {
+            SubResult::Unreachable
+        } else if definite {
+            SubResult::Definite
+        } else {
+            SubResult::Ok
+        }
+    }
+
+    /// Whether a statement is guaranteed to never complete e.g. `return`, `std::process::exit` or
+    /// a call to a function returning `!`
+    fn is_diverging(&self, stmt: &Stmt, ctx: &Context) -> bool {
+        const DIVERGING_FNS: &[&str] = &[
+            "std::process::exit",
+            "process::exit",
+            "std::process::abort",
+            "process::abort",
+            "std::panic::panic_any",
+            "panic::panic_any",
+            "panic_any",
+            "std::panic::resume_unwind",
+            "panic::resume_unwind",
+            "resume_unwind",
+        ];
+        let is_panic = |mac: &Macro| !ctx.config.ignore_panics && mac.path.is_ident("panic");
+        match stmt {
+            Stmt::Expr(Expr::Return(_), _) => true,
+            Stmt::Expr(Expr::Call(call), _) => match call.func.as_ref() {
+                Expr::Path(path) => {
+                    let name = path
+                        .path
+                        .segments
+                        .iter()
+                        .map(|x| x.ident.to_string())
+                        .collect::<Vec<_>>();
+                    DIVERGING_FNS.contains(&name.join("::").as_str())
+                        || self
+                            .called_function(stmt, ctx)
+                            .is_some_and(|function| self.diverging_functions.contains(&function))
+                }
+                _ => false,
+            },
+            Stmt::Expr(Expr::Macro(mac), _) => is_panic(&mac.mac),
+            Stmt::Macro(mac) => is_panic(&mac.mac),
+            _ => false,
+        }
+    }
+
+    /// The function a statement calls as `module::name`, to compare to the functions found to
+    /// return `!`. Only the module the function is defined in and its name are compared, paths
+    /// through `super`, `Self` or a `use` aren't resolved so calls via them are treated as
+    /// returning.
+    fn called_function(&self, stmt: &Stmt, ctx: &Context) -> Option<String> {
+        let path = match stmt {
+            Stmt::Expr(Expr::Call(call), _) => match call.func.as_ref() {
+                Expr::Path(path) => path
+                    .path
+                    .segments
+                    .iter()
+                    .map(|x| x.ident.to_string())
+                    .collect::<Vec<_>>(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let current_module = || {
+            self.modules
+                .last()
+                .cloned()
+                .unwrap_or_else(|| file_module(ctx.file))
+        };
+        let module = match path.as_slice() {
+            [_] => current_module(),
+            [.., module, _] if module == "self" => current_module(),
+            [.., module, _] => module.clone(),
+            [] => return None,
+        };
+        let name = &path[path.len() - 1];
+        Some(format!("{}::{}", module, name))
+    }
+
+    fn process_macro(&mut self, mac: &StmtMacro, ctx: &Context) -> SubResult {
+        let check_cover = self.check_attr_list(&mac.attrs, ctx);
+        if check_cover {
+            if let Some(macro_name) = mac.mac.path.segments.last() {
+                let (sub, should_ignore) = ignore_macro_name(&macro_name.ident, ctx);
+                let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+                if should_ignore {
+                    analysis.ignore_tokens(mac);
+                } else if ctx.config.merge_logical_lines(LogicalLines::Macros) {
+                    // lets just merge the macros into one big logical line
+                    let start = mac.span().start().line;
+                    for i in start..mac.span().end().line {
+                        analysis.logical_lines.insert(i + 1, start);
+                    }
+                }
+                sub
+            } else {
+                SubResult::Ok
+            }
+        } else {
+            let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+            analysis.ignore_tokens(mac);
+            SubResult::Ok
+        }
+    }
+
+    fn process_local(&mut self, local: &Local, ctx: &Context) -> SubResult {
+        let mut result = SubResult::Ok;
+        if let Some(init) = &local.init {
+            // Process if the local wasn't ignored with an attribute
+            let check_cover = self.check_attr_list(&local.attrs, ctx);
+            let analysis = self.get_line_analysis(ctx.file.to_path_buf());
+
+            if check_cover {
+                for a in &local.attrs {
+                    analysis.ignore_tokens(a);
+                }
+                let spn = local.span();
+                let base_line = local.let_token.span().start().line;
+                let merge = ctx.config.merge_logical_lines(LogicalLines::LetBindings);
+                if base_line != spn.end().line && merge {
+                    // Now check the other lines
+                    let lhs = local.pat.span();
+                    if lhs.start().line != base_line {
+                        analysis.logical_lines.insert(lhs.start().line, base_line);
+                    }
+                    let eq = init.eq_token.span();
+                    if eq.start().line != base_line {
+                        analysis.logical_lines.insert(eq.start().line, base_line);
+                    }
+                    if init.expr.span().start().line != base_line {
+                        analysis
+                            .logical_lines
+                            .insert(init.expr.span().start().line, base_line);
+                    }
+                    result += self.process_expr(&init.expr, ctx);
+                    if let Some((_, expr)) = &init.diverge {
+                        self.process_expr(expr, ctx);
+                    }
+                }
+            } else {
+                analysis.ignore_tokens(local);
+            }
+        }
+        result
+    }
+}
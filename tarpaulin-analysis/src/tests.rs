@@ -1,10 +1,11 @@
-use crate::source_analysis::prelude::*;
+use crate::config::LogicalLines;
+use crate::prelude::*;
 use syn::parse_file;
 use test_log::test;
 
 #[test]
 fn logical_lines_let_bindings() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let mut analysis = SourceAnalysis::new();
     let ctx = Context {
         config: &config,
@@ -58,7 +59,7 @@ fn logical_lines_let_bindings() {
 
 #[test]
 fn match_pattern_logical_lines() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn foo(num: i32) -> bool {
@@ -89,6 +90,37 @@ fn match_pattern_logical_lines() {
     assert_ne!(lines.logical_lines.get(&8), Some(&3));
 }
 
+#[test]
+fn disabled_logical_lines() {
+    let config = AnalysisConfig {
+        no_logical_lines: vec![LogicalLines::LetBindings, LogicalLines::MatchPatterns],
+        ..Default::default()
+    };
+    let ctx = Context {
+        config: &config,
+        file_contents: "fn foo(num: i32) -> bool {
+            let x
+                  =
+                    5;
+            match num {
+            1
+            | 3 => true,
+            _ => false,
+            }
+        }",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.process_items(&parser.items, &ctx);
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    assert!(!lines.logical_lines.contains_key(&3));
+    assert!(!lines.logical_lines.contains_key(&4));
+    assert!(!lines.logical_lines.contains_key(&7));
+}
+
 #[test]
 fn line_analysis_works() {
     let mut la = LineAnalysis::new();
@@ -104,7 +136,7 @@ fn line_analysis_works() {
 
 #[test]
 fn filter_str_literals() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn test() {
@@ -167,7 +199,7 @@ fn filter_str_literals() {
 
 #[test]
 fn filter_struct_members() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[derive(Debug)]\npub struct Struct {\npub i: i32,\nj:String,\n}",
@@ -203,7 +235,7 @@ fn filter_struct_members() {
 
 #[test]
 fn filter_enum_members() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[derive(Debug)]\npub enum E {\nI1,\nI2(u32),\nI3{\nx:u32,\n},\n}",
@@ -226,7 +258,7 @@ fn filter_enum_members() {
 
 #[test]
 fn filter_struct_consts() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "struct T{x:String, y:i32}
@@ -250,7 +282,7 @@ fn filter_struct_consts() {
 
 #[test]
 fn filter_unreachable_unchecked() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn test() {
@@ -269,7 +301,7 @@ fn filter_unreachable_unchecked() {
 
 #[test]
 fn filter_loop_attr() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn test() {
@@ -298,7 +330,7 @@ fn filter_loop_attr() {
 
 #[test]
 fn filter_mods() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "mod foo {\nfn double(x:i32)->i32 {\n x*2\n}\n}",
@@ -341,7 +373,7 @@ fn filter_mods() {
 
 #[test]
 fn filter_macros() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "\n\nfn unused() {\nunimplemented!();\n}",
@@ -406,10 +438,14 @@ fn filter_macros() {
 
 #[test]
 fn filter_tests() {
-    let mut config = Config::default();
-    config.set_include_tests(true);
-    let mut igconfig = Config::default();
-    igconfig.set_include_tests(false);
+    let config = AnalysisConfig {
+        include_tests: true,
+        ..Default::default()
+    };
+    let igconfig = AnalysisConfig {
+        include_tests: false,
+        ..Default::default()
+    };
 
     let ctx = Context {
         config: &config,
@@ -476,8 +512,10 @@ fn filter_tests() {
 
 #[test]
 fn filter_nonstd_tests() {
-    let mut igconfig = Config::default();
-    igconfig.set_include_tests(false);
+    let igconfig = AnalysisConfig {
+        include_tests: false,
+        ..Default::default()
+    };
 
     let ctx = Context {
         config: &igconfig,
@@ -549,8 +587,10 @@ fn filter_nonstd_tests() {
 
 #[test]
 fn include_nonstd_tests() {
-    let mut config = Config::default();
-    config.set_include_tests(true);
+    let config = AnalysisConfig {
+        include_tests: true,
+        ..Default::default()
+    };
 
     let ctx = Context {
         config: &config,
@@ -606,8 +646,10 @@ fn include_nonstd_tests() {
 
 #[test]
 fn filter_test_utilities() {
-    let mut config = Config::default();
-    config.set_include_tests(false);
+    let config = AnalysisConfig {
+        include_tests: false,
+        ..Default::default()
+    };
 
     let ctx = Context {
         config: &config,
@@ -629,8 +671,10 @@ fn filter_test_utilities() {
     assert!(lines.ignore.contains(&Lines::Line(3)));
     assert!(lines.ignore.contains(&Lines::Line(4)));
 
-    let mut config = Config::default();
-    config.set_include_tests(true);
+    let config = AnalysisConfig {
+        include_tests: true,
+        ..Default::default()
+    };
 
     let ctx = Context {
         config: &config,
@@ -654,7 +698,7 @@ fn filter_test_utilities() {
 
 #[test]
 fn filter_where() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn boop<T>() -> T  where T:Default {
@@ -707,7 +751,7 @@ fn filter_where() {
 
 #[test]
 fn filter_derives() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[derive(Debug)]\nstruct T;",
@@ -737,7 +781,7 @@ fn filter_derives() {
 
 #[test]
 fn filter_unsafe() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn unsafe_fn() {\n let x=1;\nunsafe {\nprintln!(\"{}\", x);\n}\n}",
@@ -768,7 +812,7 @@ fn filter_unsafe() {
 
 #[test]
 fn cover_generic_impl_methods() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "struct GenericStruct<T>(T);
@@ -811,7 +855,7 @@ fn cover_generic_impl_methods() {
 
 #[test]
 fn cover_default_trait_methods() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "trait Thing {
@@ -833,7 +877,7 @@ fn cover_default_trait_methods() {
 
 #[test]
 fn cover_impl_trait_generic_fns() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn bloop(t: impl std::io::Read) -> usize {
@@ -853,7 +897,7 @@ fn cover_impl_trait_generic_fns() {
 
 #[test]
 fn filter_method_args() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "struct Thing;
@@ -891,7 +935,7 @@ fn filter_method_args() {
 
 #[test]
 fn filter_use_statements() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "use std::collections::HashMap;
@@ -910,7 +954,7 @@ fn filter_use_statements() {
 
 #[test]
 fn include_inline_fns() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[inline]
@@ -940,7 +984,7 @@ fn include_inline_fns() {
 
 #[test]
 fn cover_callable_noargs() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn foo() {
@@ -959,7 +1003,7 @@ fn cover_callable_noargs() {
 
 #[test]
 fn filter_closure_contents() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn inline_func() {
@@ -980,7 +1024,7 @@ fn filter_closure_contents() {
 
 #[test]
 fn tarpaulin_skip_attr() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[cfg(not(tarpaulin_include))]
@@ -1078,7 +1122,7 @@ fn tarpaulin_skip_attr() {
 
 #[test]
 fn tarpaulin_skip_trait_attrs() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "#[cfg(not(tarpaulin_include))]
@@ -1135,7 +1179,7 @@ fn tarpaulin_skip_trait_attrs() {
 
 #[test]
 fn tarpaulin_skip_impl_attrs() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "struct Foo;
@@ -1195,7 +1239,7 @@ fn tarpaulin_skip_impl_attrs() {
 
 #[test]
 fn filter_block_contents() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn unreachable_match(x: u32) -> u32 {
@@ -1222,7 +1266,7 @@ fn filter_block_contents() {
 
 #[test]
 fn filter_consts() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn boo() {
@@ -1241,7 +1285,7 @@ fn filter_consts() {
 
 #[test]
 fn optional_panic_ignore() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn unreachable_match(x: u32) -> u32 {
@@ -1265,8 +1309,10 @@ fn optional_panic_ignore() {
     assert!(!lines.ignore.contains(&Lines::Line(3)));
     assert!(!lines.ignore.contains(&Lines::Line(7)));
 
-    let mut config = Config::default();
-    config.ignore_panics = true;
+    let config = AnalysisConfig {
+        ignore_panics: true,
+        ..Default::default()
+    };
     let ctx = Context {
         config: &config,
         file_contents: "fn unreachable_match(x: u32) -> u32 {
@@ -1294,7 +1340,7 @@ fn optional_panic_ignore() {
 
 #[test]
 fn filter_nested_blocks() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn block() {
@@ -1327,7 +1373,7 @@ fn filter_nested_blocks() {
 
 #[test]
 fn filter_multi_line_decls() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn print_it(x:u32,
@@ -1390,7 +1436,7 @@ fn filter_multi_line_decls() {
 
 #[test]
 fn unreachable_propagate() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "enum Void {}
@@ -1468,7 +1514,7 @@ fn unreachable_propagate() {
 
 #[test]
 fn unreachable_include_returns() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn test_not_unreachable() -> Result<(), Box<dyn std::error::Error>> {
@@ -1529,7 +1575,7 @@ fn unreachable_include_returns() {
 
 #[test]
 fn unreachable_include_loops() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn test_not_unreachable() {
@@ -1599,7 +1645,7 @@ fn unreachable_include_loops() {
 
 #[test]
 fn single_line_callables() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "struct A;
@@ -1634,7 +1680,7 @@ fn single_line_callables() {
 
 #[test]
 fn visit_generics() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "fn blah<T>(t: T)
@@ -1681,7 +1727,7 @@ fn visit_generics() {
 
 #[test]
 fn ignore_comment() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "/// I am a doc comment
@@ -1714,9 +1760,50 @@ fn ignore_comment() {
     assert!(lines.ignore.contains(&Lines::Line(12)));
 }
 
+#[test]
+fn ignore_line_patterns() {
+    let config = AnalysisConfig {
+        ignore_line_patterns: vec![
+            LinePattern::new(r"^\s*log::(trace|debug)!").unwrap(),
+            LinePattern::new(r"#\[derive").unwrap(),
+        ],
+        ..Default::default()
+    };
+    let ctx = Context {
+        config: &config,
+        file_contents: "#[derive(Debug)]
+        struct Foo;
+
+        fn foo() -> u32 {
+            log::debug!(\"foo\");
+            log::info!(\"foo\");
+            5
+        }",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_ignorable_lines(&ctx);
+    analysis.process_items(&parser.items, &ctx);
+    let lines = &analysis.lines[Path::new("")];
+    assert!(lines.ignore.contains(&Lines::Line(1)));
+    assert!(lines.ignore.contains(&Lines::Line(5)));
+    assert!(!lines.ignore.contains(&Lines::Line(6)));
+    assert!(!lines.cover.contains(&5));
+    assert_eq!(
+        lines.pattern_ignored,
+        vec![
+            (1, r"#\[derive".to_string()),
+            (5, r"^\s*log::(trace|debug)!".to_string())
+        ]
+    );
+}
+
 #[test]
 fn py_attr() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "use pyo3::prelude::{pyfunction, PyResult};
@@ -1753,7 +1840,7 @@ fn py_attr() {
 
 #[test]
 fn handle_c_strs() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: r#"fn main() {
@@ -1770,7 +1857,7 @@ fn handle_c_strs() {
 
 #[test]
 fn ignore_trait_types() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: "pub trait Foo { type X; }
@@ -1793,7 +1880,7 @@ fn ignore_trait_types() {
 
 #[test]
 fn get_function_names() {
-    let config = Config::default();
+    let config = AnalysisConfig::default();
     let ctx = Context {
         config: &config,
         file_contents: r#"use std::fmt;
@@ -1886,53 +1973,734 @@ fn get_function_names() {
             name: "add".to_string(),
             start: 3,
             end: 5,
+            public: true,
+            ffi: false,
         },
         Function {
             name: "Foo::five".to_string(),
             start: 10,
             end: 12,
+            public: true,
+            ffi: false,
         },
         Function {
             name: "<impl Foo for Marker>::four".to_string(),
             start: 19,
             end: 21,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "<impl Display for Wrapper<T>>::fmt".to_string(),
             start: 25,
             end: 27,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "Wrapper<T>::unwrap".to_string(),
             start: 31,
             end: 33,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "Marker::marked".to_string(),
             start: 37,
             end: 39,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "nonsense".to_string(),
             start: 42,
             end: 50,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "nonsense::inner".to_string(),
             start: 43,
             end: 45,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "beep::it_works".to_string(),
             start: 57,
             end: 60,
+            public: false,
+            ffi: false,
         },
         Function {
             name: "<impl Foo2 for Marker>::five".to_string(),
             start: 68,
             end: 70,
+            public: false,
+            ffi: false,
         },
     ];
 
     assert_eq!(functions, &expected_fns);
 }
+
+#[test]
+fn ffi_functions_found() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: r#"#[no_mangle]
+            pub extern "C" fn exported(x: u32) -> u32 {
+                x + 1
+            }
+
+            #[export_name = "renamed"]
+            fn exported_as() {
+                println!("renamed");
+            }
+
+            extern "C" fn callback() {
+                println!("callback");
+            }
+
+            extern "Rust" fn rust_abi() {
+                println!("rust");
+            }
+
+            pub fn plain() {
+                println!("plain");
+            }
+
+            #[unsafe(no_mangle)]
+            pub fn unsafe_exported() {
+                println!("unsafe");
+            }
+
+            #[unsafe(export_name = "unsafe_renamed")]
+            fn unsafe_exported_as() {
+                println!("unsafe renamed");
+            }
+
+            struct Handle;
+
+            impl Handle {
+                #[no_mangle]
+                pub extern "C" fn handle_method() {
+                    println!("method");
+                }
+
+                fn private() {
+                    println!("private");
+                }
+            }
+        "#,
+        file: Path::new("src.rs"),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.process_items(&parser.items, &ctx);
+
+    let function_map = analysis.create_function_map();
+    let ffi = function_map[Path::new("src.rs")]
+        .iter()
+        .filter(|x| x.ffi)
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ffi,
+        [
+            "exported",
+            "exported_as",
+            "callback",
+            "unsafe_exported",
+            "unsafe_exported_as",
+            "Handle::handle_method",
+        ]
+    );
+}
+
+#[test]
+fn find_try_branches() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "fn foo<T: ?Sized>(x: &T) -> Result<(), Error> {
+    let y = bar()?;
+    baz(y)?.qux()?;
+    Ok(())
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_try_branches(&parser, &ctx);
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    let mut branches = lines.try_branches.iter().copied().collect::<Vec<_>>();
+    branches.sort_unstable();
+    assert_eq!(branches, vec![(2, 18), (3, 11), (3, 18)]);
+}
+
+#[test]
+fn ignore_after_diverging_calls() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "fn fail() -> ! {
+    panic!(\"oh no\")
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!(\"{}\", e);
+        std::process::exit(1);
+        println!(\"never\");
+    }
+    fail();
+    cleanup();
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_diverging_functions(&parser, ctx.file);
+    analysis.process_items(&parser.items, &ctx);
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    assert!(!lines.ignore.contains(&Lines::Line(7)));
+    assert!(!lines.ignore.contains(&Lines::Line(8)));
+    assert!(lines.ignore.contains(&Lines::Line(9)));
+    assert!(!lines.ignore.contains(&Lines::Line(11)));
+    assert!(lines.ignore.contains(&Lines::Line(12)));
+}
+
+#[test]
+fn diverging_calls_matched_by_module() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "mod exit_codes {
+    pub fn fail() -> ! {
+        std::process::exit(2)
+    }
+
+    pub fn run() {
+        fail();
+        println!(\"never\");
+    }
+}
+
+mod checks {
+    pub fn fail() {}
+
+    pub fn run() {
+        fail();
+        println!(\"checked\");
+    }
+}
+
+fn main() {
+    checks::fail();
+    cleanup();
+    exit_codes::fail();
+    never();
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_diverging_functions(&parser, ctx.file);
+    analysis.process_items(&parser.items, &ctx);
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    assert!(lines.ignore.contains(&Lines::Line(8)));
+    assert!(!lines.ignore.contains(&Lines::Line(17)));
+    assert!(!lines.ignore.contains(&Lines::Line(23)));
+    assert!(lines.ignore.contains(&Lines::Line(25)));
+}
+
+#[test]
+fn ignore_const_only_fns() {
+    let config = AnalysisConfig {
+        ignore_const_fns: true,
+        ..Default::default()
+    };
+    let ctx = Context {
+        config: &config,
+        file_contents: "const fn size() -> usize {
+    4
+}
+
+const fn double(x: u32) -> u32 {
+    x * 2
+}
+
+const SIZE: usize = size();
+
+fn main() {
+    let buffer = [0u8; size()];
+    let x = double(2);
+    let y = const {
+        size() + 1
+    };
+    println!(\"{}\", triple(y));
+}
+
+const fn triple(x: u32) -> u32 {
+    x * 3 + offset()
+}
+
+const fn offset() -> u32 {
+    quarter(4)
+}
+
+const fn quarter(x: u32) -> u32 {
+    x / 4
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_const_usage(&parser, &ctx);
+    analysis.process_items(&parser.items, &ctx);
+    analysis.ignore_const_only_fns();
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    assert_eq!(lines.const_fns.len(), 5);
+    assert!(lines.ignore.contains(&Lines::Line(1)));
+    assert!(lines.ignore.contains(&Lines::Line(2)));
+    assert!(!lines.ignore.contains(&Lines::Line(5)));
+    assert!(!lines.ignore.contains(&Lines::Line(6)));
+    assert!(!lines.ignore.contains(&Lines::Line(13)));
+    assert!(lines.ignore.contains(&Lines::Line(15)));
+    assert!(!lines.ignore.contains(&Lines::Line(20)));
+    assert!(!lines.ignore.contains(&Lines::Line(21)));
+    // Only called from the body of a const fn called at runtime
+    assert!(!lines.ignore.contains(&Lines::Line(25)));
+    assert!(!lines.ignore.contains(&Lines::Line(29)));
+}
+
+#[test]
+fn analysis_merged_across_files() {
+    let root = std::env::temp_dir().join(format!("tarpaulin-analysis-{}", std::process::id()));
+    let src = root.join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let lib = src.join("lib.rs");
+    let util = src.join("util.rs");
+    let skipped = src.join("skipped.rs");
+    std::fs::write(
+        &lib,
+        "mod util;
+#[cfg(not(tarpaulin_include))]
+mod skipped;
+
+pub fn check(x: u32) {
+    if x > 3 {
+        util::fatal();
+        println!(\"unreachable\");
+    }
+}
+",
+    )
+    .unwrap();
+    std::fs::write(
+        &util,
+        "pub fn fatal() -> ! {\n    std::process::exit(1)\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &skipped,
+        "pub fn skipped() {\n    println!(\"skipped\");\n}\n",
+    )
+    .unwrap();
+
+    // The excluded module and the diverging function are seen after the files using them
+    let files = vec![skipped.clone(), lib.clone(), util];
+    let analysis = SourceAnalysis::get_analysis(files, &root, &AnalysisConfig::default());
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert!(analysis.lines[&skipped].ignore.contains(&Lines::All));
+    assert!(analysis.lines[&lib].ignore.contains(&Lines::Line(8)));
+    assert!(!analysis.lines[&lib].ignore.contains(&Lines::Line(7)));
+}
+
+#[test]
+fn doc_examples_classified() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "/// ```
+/// assert!(true);
+/// ```
+///
+/// ```no_run
+/// loop {}
+/// ```
+/// ```rust,ignore
+/// not rust
+/// ```
+/// ```text
+/// just text
+/// ```
+//! ```compile_fail,E0308
+//! let x: u8 = \"\";
+fn foo() {}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_doc_examples(&ctx);
+    let lines = &analysis.lines[Path::new("")];
+    assert_eq!(
+        lines.doc_examples,
+        vec![
+            DocExample {
+                start: 1,
+                end: 3,
+                kind: DocExampleKind::Run
+            },
+            DocExample {
+                start: 5,
+                end: 7,
+                kind: DocExampleKind::NoRun
+            },
+            DocExample {
+                start: 8,
+                end: 10,
+                kind: DocExampleKind::Ignore
+            },
+            DocExample {
+                start: 14,
+                end: 15,
+                kind: DocExampleKind::NoRun
+            },
+        ]
+    );
+}
+
+#[test]
+fn custom_line_filters() {
+    let dir = std::env::temp_dir().join(format!("tarpaulin-filter-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("lib.rs");
+    std::fs::write(
+        &file,
+        "pub fn generated() -> u32 {
+    let x = 5; // @generated
+    x * 2
+}
+",
+    )
+    .unwrap();
+
+    let analysis = SourceAnalysis::new()
+        .with_filter(Box::new(|_, _, line| {
+            if line.contains("@generated") {
+                FilterDecision::Ignore
+            } else {
+                FilterDecision::Keep
+            }
+        }))
+        .with_filter(Box::new(|_, line_no, _| {
+            if line_no == 2 || line_no == 4 {
+                FilterDecision::Cover
+            } else {
+                FilterDecision::Keep
+            }
+        }))
+        .analyse(vec![file.clone()], &dir, &AnalysisConfig::default());
+    let lines = &analysis.lines[&file];
+    // The first filter with a decision wins
+    assert!(lines.should_ignore(2));
+    assert!(!lines.should_ignore(3));
+    assert!(!lines.should_ignore(4));
+    assert!(lines.cover.contains(&4));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_helpers_without_test_functions() {
+    let root = std::env::temp_dir().join(format!("tarpaulin-helpers-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("tests/common")).unwrap();
+    let helper = root.join("tests/common/mod.rs");
+    std::fs::write(&helper, "pub fn setup() -> u32 {\n    5\n}\n").unwrap();
+    let test = root.join("tests/api.rs");
+    std::fs::write(&test, "#[test]\nfn api() {\n    assert!(true);\n}\n").unwrap();
+    let files = vec![helper.clone(), test.clone()];
+
+    let config = AnalysisConfig {
+        include_test_helpers: true,
+        ..Default::default()
+    };
+    let analysis = SourceAnalysis::get_analysis(files.clone(), &root, &config);
+    assert!(!analysis.lines[&helper].should_ignore(2));
+    assert!(analysis.lines[&test].should_ignore(3));
+
+    let analysis = SourceAnalysis::get_analysis(files, &root, &AnalysisConfig::default());
+    assert!(analysis.lines[&helper].should_ignore(2));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn user_coverage_cfgs() {
+    let config = AnalysisConfig {
+        avoid_cfg_tarpaulin: true,
+        coverage_cfgs: vec!["coverage_run".to_string()],
+        ..Default::default()
+    };
+    let ctx = Context {
+        config: &config,
+        file_contents: "#[cfg(not(coverage_run))]
+fn skipped() {
+    println!(\"skipped\");
+}
+
+#[cfg(not(tarpaulin))]
+fn covered() {
+    println!(\"covered\");
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_tarpaulin_cfgs(&ctx);
+    analysis.process_items(&parser.items, &ctx);
+    let lines = analysis.get_line_analysis(ctx.file.to_path_buf());
+    assert!(lines.ignore.contains(&Lines::Line(3)));
+    assert!(!lines.ignore.contains(&Lines::Line(8)));
+    assert_eq!(lines.tarpaulin_cfgs, vec![6]);
+}
+
+#[test]
+fn feature_gated_code() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "#[cfg(feature = \"serde\")]
+fn serialise() {
+    println!(\"serde\");
+}
+
+#[cfg(all(unix, any(feature = \"a\", feature = \"b\")))]
+impl Foo {
+    #[cfg(not(feature = \"c\"))]
+    fn bar() {}
+}
+
+#[cfg(feature = \"extra\")]
+mod extra;",
+        file: Path::new("src/lib.rs"),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_feature_gates(&parser, &ctx);
+    let lines = &analysis.lines[Path::new("src/lib.rs")];
+    let gate = |feature: &str, start, end| FeatureGate {
+        feature: feature.to_string(),
+        start,
+        end,
+    };
+    assert_eq!(
+        lines.feature_gates,
+        vec![gate("serde", 1, 4), gate("a", 6, 10), gate("b", 6, 10)]
+    );
+    assert_eq!(
+        analysis.gated_modules,
+        vec![(PathBuf::from("src/extra.rs"), "extra".to_string())]
+    );
+}
+
+#[test]
+fn match_arms_and_else_branches() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "fn foo(x: Option<u32>) -> u32 {
+    let y = match x {
+        Some(0) => 1,
+        Some(y) => {
+            y + 1
+        }
+        None => 0,
+    };
+    if y > 4 {
+        1
+    } else if y > 2 {
+        2
+    } else {
+        3
+    }
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_branch_arms(&parser, &ctx);
+    let lines = &analysis.lines[Path::new("")];
+    let arm = |kind, line, start, end| BranchArm {
+        kind,
+        line,
+        start,
+        end,
+    };
+    assert_eq!(
+        lines.branch_arms,
+        vec![
+            arm(BranchArmKind::MatchArm, 3, 3, 3),
+            arm(BranchArmKind::MatchArm, 4, 4, 6),
+            arm(BranchArmKind::MatchArm, 7, 7, 7),
+            arm(BranchArmKind::Else, 11, 11, 11),
+            arm(BranchArmKind::Else, 13, 13, 15),
+        ]
+    );
+}
+
+#[test]
+fn test_unsafe_and_branch_regions() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "unsafe fn raw(x: *const u32) -> u32 {
+    *x
+}
+
+fn checked(x: Option<u32>) -> Option<u32> {
+    let y = x?;
+    if y > 2 {
+        return Some(unsafe { raw(&y) });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_works() {}
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_code_regions(&parser, &ctx);
+    let lines = &analysis.lines[Path::new("")];
+    let region = |kind, start, end, column| CodeRegion {
+        kind,
+        start,
+        end,
+        column,
+    };
+    assert_eq!(
+        lines.regions,
+        vec![
+            region(RegionKind::Unsafe, 1, 3, 1),
+            region(RegionKind::Branch, 6, 6, 14),
+            region(RegionKind::Branch, 7, 7, 8),
+            region(RegionKind::Unsafe, 8, 8, 21),
+            region(RegionKind::Test, 13, 17, 1),
+            region(RegionKind::Test, 15, 16, 5),
+        ]
+    );
+}
+
+#[test]
+fn error_path_and_panic_regions() {
+    let config = AnalysisConfig::default();
+    let ctx = Context {
+        config: &config,
+        file_contents: "fn parse(x: &str) -> Result<u32, String> {
+    match x.parse::<u32>() {
+        Ok(x) if x > 0 => Ok(x),
+        Ok(_) => panic!(\"zero\"),
+        Err(e) => {
+            return Err(e.to_string());
+        }
+    }
+}
+
+fn check(x: Result<u32, String>) {
+    if let Err(e) = x {
+        std::panic!(\"{}\", e);
+    }
+}",
+        file: Path::new(""),
+        ignore_mods: RefCell::new(HashSet::new()),
+        symbol_stack: RefCell::new(Vec::new()),
+    };
+    let parser = parse_file(ctx.file_contents).unwrap();
+    let mut analysis = SourceAnalysis::new();
+    analysis.find_code_regions(&parser, &ctx);
+    let lines = &analysis.lines[Path::new("")];
+    let region = |kind, start, end, column| CodeRegion {
+        kind,
+        start,
+        end,
+        column,
+    };
+    let regions = lines
+        .regions
+        .iter()
+        .filter(|x| x.kind != RegionKind::Branch)
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        regions,
+        vec![
+            region(RegionKind::Panic, 4, 4, 18),
+            region(RegionKind::ErrorPath, 5, 7, 19),
+            region(RegionKind::ErrorPath, 6, 6, 20),
+            region(RegionKind::ErrorPath, 12, 14, 23),
+            region(RegionKind::Panic, 13, 13, 9),
+        ]
+    );
+}
+
+#[test]
+fn files_over_limits_skipped() {
+    let root = std::env::temp_dir().join(format!("tarpaulin-limits-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    let small = root.join("src/lib.rs");
+    std::fs::write(&small, "pub fn small() -> u32 {\n    5\n}\n").unwrap();
+    let large = root.join("src/bindings.rs");
+    std::fs::write(&large, "pub const X: u32 = 1;\n".repeat(20)).unwrap();
+    let files = vec![small.clone(), large.clone()];
+
+    let config = AnalysisConfig {
+        max_file_lines: Some(10),
+        analysis_timeout: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let analysis = SourceAnalysis::get_analysis(files.clone(), &root, &config);
+    assert_eq!(
+        analysis.skipped_files,
+        vec![(large.clone(), SkipReason::TooLarge(20))]
+    );
+    assert!(analysis.lines[&large].should_ignore(1));
+    assert!(!analysis.lines[&small].should_ignore(2));
+    assert_eq!(analysis.analysis_times.len(), 1);
+    assert_eq!(analysis.analysis_times[0].0, small);
+
+    let config = AnalysisConfig {
+        include_skipped_files: true,
+        ..config
+    };
+    let analysis = SourceAnalysis::get_analysis(files, &root, &config);
+    assert_eq!(analysis.skipped_files.len(), 1);
+    assert!(!analysis.lines.contains_key(&large));
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+pub fn describe<T: Display>(value: T) -> String {
+    let text = value.to_string();
+    if text.is_empty() {
+        "empty".to_string()
+    } else {
+        text
+    }
+}
+
+pub fn doubled(values: impl Iterator<Item = u32>) -> impl Iterator<Item = u32> {
+    values.map(|x| x * 2)
+}
+
+pub struct Wrapper<T> {
+    inner: T,
+}
+
+impl<T: Clone> Wrapper<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    pub fn get(&self) -> T {
+        self.inner.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn many_instantiations() {
+        assert_eq!(describe(1), "1");
+        assert_eq!(describe("hello"), "hello");
+        assert_eq!(describe(String::new()), "empty");
+        assert_eq!(describe(2.5), "2.5");
+
+        let v = doubled(vec![1, 2].into_iter()).collect::<Vec<_>>();
+        assert_eq!(v, vec![2, 4]);
+        let v = doubled(Some(3).into_iter()).collect::<Vec<_>>();
+        assert_eq!(v, vec![6]);
+
+        assert_eq!(Wrapper::new(1u8).get(), 1);
+        assert_eq!(Wrapper::new("a").get(), "a");
+    }
+}
@@ -1,7 +1,8 @@
 use crate::utils::get_test_path;
-use cargo_tarpaulin::event_log::EventLog;
+use cargo_tarpaulin::event_log::EventLogFile;
 use cargo_tarpaulin::path_utils::*;
-use cargo_tarpaulin::traces::TraceMap;
+use cargo_tarpaulin::test_utils;
+use cargo_tarpaulin::traces::{CoverageStat, TraceMap};
 use cargo_tarpaulin::{
     args::TarpaulinCli,
     config::{Config, ConfigWrapper, Mode, OutputFile, RunType, TraceEngine},
@@ -78,51 +79,21 @@ pub fn check_percentage_with_config(
     project_name: &str,
     minimum_coverage: f64,
     has_lines: bool,
-    mut config: Config,
+    config: Config,
 ) -> TraceMap {
-    config.test_timeout = Duration::from_secs(60);
-    let restore_dir = env::current_dir().unwrap();
-    let test_dir = get_test_path(project_name);
-    env::set_current_dir(&test_dir).unwrap();
-    let mut manifest = test_dir;
-    manifest.push("Cargo.toml");
-    config.set_manifest(manifest);
-    config.set_clean(false);
-
     // Note to contributors. If an integration test fails, uncomment this to be able to see the
     // tarpaulin logs
     //cargo_tarpaulin::setup_logging(true, true, false);
-    let event_log = if config.dump_traces {
-        let mut paths = HashSet::new();
-        paths.insert(config.manifest());
-        Some(EventLog::new(paths, &config))
-    } else {
-        None
-    };
-
-    let (res, ret) = launch_tarpaulin(&config, &event_log).unwrap();
-    assert_eq!(ret, 0);
-
-    env::set_current_dir(restore_dir).unwrap();
-    if has_lines {
-        assert!(res.total_coverable() > 0);
-        assert!(
-            res.coverage_percentage() >= minimum_coverage,
-            "Assertion failed {} >= {}",
-            res.coverage_percentage(),
-            minimum_coverage
-        );
-    } else {
-        assert_eq!(res.total_coverable(), 0);
-    }
-    res
+    test_utils::check_percentage_with_config(
+        &get_test_path(project_name),
+        minimum_coverage,
+        has_lines,
+        config,
+    )
 }
 
 pub fn check_percentage(project_name: &str, minimum_coverage: f64, has_lines: bool) -> TraceMap {
-    let mut config = Config::default();
-    config.set_include_tests(true);
-    config.set_clean(false);
-    check_percentage_with_config(project_name, minimum_coverage, has_lines, config)
+    test_utils::check_percentage(&get_test_path(project_name), minimum_coverage, has_lines)
 }
 
 rusty_fork_test! {
@@ -188,6 +159,25 @@ fn llvm_sanity_test() {
     check_percentage_with_config("follow_exe", 1.0f64, true, config);
 }
 
+#[test]
+fn generic_monomorphisations_merged() {
+    let mut config = Config::default();
+    config.set_engine(TraceEngine::Llvm);
+    config.set_include_tests(true);
+    config.set_clean(false);
+
+    let res = check_percentage_with_config("generic_tester", 1.0f64, true, config);
+    assert!(res.coverage_percentage() <= 1.0);
+    for (file, traces) in res.iter() {
+        let lines = traces.iter().map(|x| x.line).collect::<HashSet<_>>();
+        assert_eq!(lines.len(), traces.len(), "duplicate lines in {}", file.display());
+    }
+    // describe is instantiated for 4 types and called once with each
+    let lib = res.files().into_iter().find(|x| x.ends_with("src/lib.rs")).unwrap().clone();
+    let body = res.get_child_traces(&lib).find(|x| x.line == 4).unwrap();
+    assert_eq!(body.stats, CoverageStat::Line(4));
+}
+
 #[cfg_attr(not(ptrace_supported), test)]
 #[should_panic]
 fn ptrace_not_unsupported_system() {
@@ -601,14 +591,20 @@ fn output_dir_workspace() {
     assert!(output.remove("cobertura.xml"));
     assert!(output.remove("lcov.info"));
     assert!(output.remove("tarpaulin-report.html"));
+    assert!(output.remove("tarpaulin-index.html"));
     assert!(output.remove("tarpaulin-report.json"));
-    assert_eq!(output.len(), 1);
+    assert!(output.remove("tarpaulin-latest.json"));
+    assert_eq!(output.len(), 2);
 
     for event_log in &output {
         let events = report_dir.join(event_log);
         let log = fs::read(events).unwrap();
         // We can deserialize event log so it must be good
-        serde_json::from_slice::<EventLog>(log.as_slice()).unwrap();
+        if event_log.ends_with(".jsonl") {
+            EventLogFile::from_jsonl(log.as_slice()).unwrap();
+        } else {
+            serde_json::from_slice::<EventLogFile>(log.as_slice()).unwrap();
+        }
     }
 }
 